@@ -0,0 +1,86 @@
+//! `--copy`: put a resolved path on the system clipboard by piping it to
+//! whichever clipboard utility the platform has, so pathranger needs no
+//! display server libraries of its own.
+
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use pathranger::{debug, Error, Result};
+
+/// A clipboard utility and the arguments that make it read stdin into the
+/// clipboard
+struct Backend {
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+const PBCOPY: Backend = Backend { program: "pbcopy", args: &[] };
+const CLIP: Backend = Backend { program: "clip.exe", args: &[] };
+const WL_COPY: Backend = Backend { program: "wl-copy", args: &[] };
+const XCLIP: Backend = Backend { program: "xclip", args: &["-selection", "clipboard"] };
+const XSEL: Backend = Backend { program: "xsel", args: &["--clipboard", "--input"] };
+
+/// The utilities worth trying here, most fitting first: the session's own
+/// display server before the other, and Windows' clipboard from WSL last
+fn candidates() -> Vec<Backend> {
+    if cfg!(target_os = "macos") {
+        return vec![PBCOPY];
+    }
+    if cfg!(windows) {
+        return vec![CLIP];
+    }
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty());
+    let mut backends = if wayland { vec![WL_COPY, XCLIP, XSEL] } else { vec![XCLIP, XSEL, WL_COPY] };
+    backends.push(CLIP);
+    backends
+}
+
+/// Whether `program` is an executable file in a directory on $PATH
+fn on_path(program: &str) -> bool {
+    let Some(path) = std::env::var_os("PATH") else {
+        return false;
+    };
+    std::env::split_paths(&path).any(|dir| is_executable(&dir.join(program)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    
+    path.metadata().is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Copy `text` to the clipboard with the first utility found, without a
+/// trailing newline. Fails with what to install when there is none.
+pub fn copy(text: &str) -> Result<()> {
+    let Some(backend) = candidates().into_iter().find(|backend| on_path(backend.program)) else {
+        return Err(Error::InvalidInput(String::from(
+            "No clipboard utility found\n\
+             Install wl-clipboard (Wayland), xclip or xsel (X11), or use pbcopy (macOS) or clip.exe (Windows and WSL)",
+        )));
+    };
+    debug!("copying with {}", backend.program);
+    
+    let io_error = |e| Error::Io(format!("Could not copy with {}", backend.program), e);
+    let mut child = Command::new(backend.program)
+        .args(backend.args.iter().map(OsStr::new))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(io_error)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).map_err(io_error)?;
+    }
+    let status = child.wait().map_err(io_error)?;
+    if !status.success() {
+        return Err(io_error(io::Error::other(format!("it exited with {}", status))));
+    }
+    Ok(())
+}
@@ -0,0 +1,614 @@
+//! Settings from `config.toml` in pathranger's config directory, each of
+//! which a `PATHRANGER_<SECTION>_<KEY>` environment variable can override
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use shellexpand::tilde;
+
+use crate::display::{format_path, TimeFormat, DEFAULT_TIME_FORMAT};
+use crate::project::{CwdProject, DEFAULT_ROOT_MARKERS};
+use crate::tagfile;
+use crate::{debug, warn, Error, PathForm, Result, SortKey};
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    Always,
+    Auto,
+    Never,
+}
+
+/// Persistent settings read from `config.toml` in the pathranger config directory
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default color mode when neither a flag nor NO_COLOR decides it
+    pub color: Option<ColorChoice>,
+    
+    /// Ask before a destructive command changes more than this many rows (default 20)
+    pub confirm_threshold: Option<usize>,
+    
+    /// Database file to use instead of the default location, overridden by
+    /// --db and the environment
+    pub database: Option<String>,
+    
+    /// Which convention picks the data directory (default "platform")
+    pub data_dir_style: Option<DataDirStyle>,
+    
+    /// Read-only tags shared by everyone on the machine (default
+    /// /etc/pathranger/tags.toml)
+    pub system_tags: Option<String>,
+    
+    /// Glob patterns for directories that are never recorded, never offered
+    /// by `query` or `suggest`, and are left out of listings unless --all is
+    /// given
+    pub exclude: Vec<String>,
+    
+    /// Record visits to a local journal, replayed into the database by
+    /// later commands, when the database is locked or can't be opened
+    /// (default false)
+    pub journal: Option<bool>,
+    
+    /// How search and query treat the project the current directory is in
+    /// (default "auto")
+    pub cwd_project: Option<CwdProject>,
+    
+    /// Let --parent climb out of the home directory (default false)
+    pub parent_above_home: Option<bool>,
+    
+    /// Treat Windows paths differing only in case as one directory, as
+    /// NTFS does (default true)
+    pub fold_case: Option<bool>,
+    
+    /// Record directories reached through symlinks as the shell spells them
+    /// ("logical") or with symlinks resolved ("physical") (default "logical")
+    pub paths: Option<PathForm>,
+    
+    /// Assume directories on network filesystems exist rather than asking
+    /// the server (default false)
+    pub trust_network_paths: Option<bool>,
+    
+    pub display: DisplayConfig,
+    pub top: CountConfig,
+    pub recent: CountConfig,
+    pub list: ListConfig,
+    pub search: SearchConfig,
+    pub query: QueryConfig,
+    pub root: RootConfig,
+    pub daemon: DaemonConfig,
+    pub hooks: HooksConfig,
+    
+    /// Keys set in the config file, as `section.key` inside sections
+    #[serde(skip)]
+    pub from_file: BTreeSet<String>,
+    
+    /// Keys set by `PATHRANGER_<SECTION>_<KEY>` environment variables
+    #[serde(skip)]
+    pub from_env: BTreeSet<String>,
+}
+
+impl Config {
+    /// Compile the config's `exclude` patterns. A bad pattern fails every
+    /// command that opens the database, since they all apply them.
+    pub fn exclude_patterns(&self) -> Result<Option<GlobSet>> {
+        if self.exclude.is_empty() {
+            return Ok(None);
+        }
+        
+        let mut builder = GlobSetBuilder::new();
+        for pattern in &self.exclude {
+            // `*` stays within one component; `**` crosses them
+            let glob = GlobBuilder::new(&tilde(pattern))
+                .literal_separator(true)
+                .build()
+                .map_err(|e| Error::InvalidInput(format!("Invalid exclude pattern '{}' in the config file: {}", pattern, e.kind())))?;
+            builder.add(glob);
+        }
+        let patterns = builder
+            .build()
+            .map_err(|e| Error::InvalidInput(format!("Invalid exclude patterns in the config file: {}", e)))?;
+        debug!("excluding {} pattern(s)", self.exclude.len());
+        Ok(Some(patterns))
+    }
+}
+
+/// Where the data directory is when nothing overrides it. They differ on
+/// macOS, where the platform convention is ~/Library/Application Support.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataDirStyle {
+    /// The platform's data directory
+    #[default]
+    Platform,
+    /// $XDG_DATA_HOME or ~/.local/share, as on Linux
+    Xdg,
+}
+
+impl DataDirStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            DataDirStyle::Platform => "platform",
+            DataDirStyle::Xdg => "xdg",
+        }
+    }
+    
+    /// The pathranger directory under this convention's data directory
+    pub fn data_dir(self) -> Option<PathBuf> {
+        let base = match self {
+            DataDirStyle::Platform => dirs::data_dir(),
+            DataDirStyle::Xdg => env_value("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute())
+                .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("share"))),
+        };
+        base.map(|dir| dir.join("pathranger"))
+    }
+}
+
+/// How paths are displayed in listings. Output meant for the shell to act on
+/// (goto, --paths-only, --print0) always uses absolute paths.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    /// Relative to the current directory when inside it, `~`-folded otherwise
+    #[default]
+    Auto,
+    /// Always relative to the current directory, using `..` where needed
+    Relative,
+    /// Always `~`-folded
+    Tilde,
+}
+
+/// Default number of rows for `top`, `recent`, `list` and `search`
+pub const DEFAULT_COUNT: usize = 10;
+
+/// Default for `confirm_threshold`
+pub const DEFAULT_CONFIRM_THRESHOLD: usize = 20;
+
+/// Default for `daemon.idle_timeout`, in seconds
+pub const DEFAULT_IDLE_TIMEOUT: u64 = 30 * 60;
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CountConfig {
+    /// Number of directories to show when -c isn't given (default 10)
+    pub count: Option<usize>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListConfig {
+    /// Number of directories to show when -c isn't given (default 10)
+    pub count: Option<usize>,
+    
+    /// Order when --sort isn't given (default "visits")
+    pub sort: Option<SortKey>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Number of matches to show when --limit isn't given (default 10)
+    pub limit: Option<usize>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueryConfig {
+    /// Look inside frequent directories when no tracked one clearly
+    /// matches (default false)
+    pub explore: Option<bool>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RootConfig {
+    /// Files or directories besides .git, .hg and .svn that mark a project
+    /// root for `root` (default Cargo.toml, package.json and .project)
+    pub markers: Option<Vec<String>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DaemonConfig {
+    /// Seconds without a request before the daemon exits, 0 for never
+    /// (default 1800)
+    pub idle_timeout: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Shell commands the integration runs after `goto` changes directory
+    pub after_goto: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+    /// Show last-visited times as "3 hours ago" rather than a timestamp (default true)
+    pub relative_time: Option<bool>,
+    
+    /// A strftime pattern for times in listings, or "relative"; overrides
+    /// `relative_time` (default "%Y-%m-%d %H:%M" for absolute times)
+    pub time_format: Option<TimeFormat>,
+    
+    /// Merge listing entries that are symlinks to the same directory (default false)
+    pub resolve_symlinks: Option<bool>,
+    
+    /// How merged symlinked entries are shown: "logical" or "physical"
+    pub path_form: Option<PathForm>,
+    
+    /// Leave directories inside dot directories out of listings (default false)
+    pub hide_hidden: Option<bool>,
+    
+    /// "auto" (relative inside the current directory, `~`-folded elsewhere),
+    /// "relative" or "tilde"
+    pub path_style: Option<PathStyle>,
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("pathranger").join("config.toml"))
+}
+
+/// Read the config file, then apply `PATHRANGER_<SECTION>_<KEY>` variables
+/// over it. An invalid file is reported and ignored; an invalid variable is
+/// an error, since it was set for this run.
+pub fn load() -> Result<Config> {
+    let file = path().and_then(|path| Some((fs::read_to_string(&path).ok()?, path)));
+    let mut document = toml_edit::DocumentMut::new();
+    let mut from_file = BTreeSet::new();
+    if let Some((contents, path)) = file {
+        debug!("config: {}", path.display());
+        match parse(&contents) {
+            Ok(config) => {
+                document = contents.parse().unwrap_or_default();
+                from_file = config.from_file;
+            }
+            Err(e) => warn!("Ignoring invalid config file {}: {}", path.display(), e),
+        }
+    }
+    
+    let mut from_env = BTreeSet::new();
+    for key in KEYS {
+        let var = env_var(key);
+        let Some(raw) = env_value(&var) else {
+            continue;
+        };
+        let value = env_value_of(key, &raw);
+        
+        // Check each variable alone, so the error names the one at fault
+        let mut single = toml_edit::DocumentMut::new();
+        set_key(&mut single, key, value.clone());
+        if let Err(e) = parse(&single.to_string()) {
+            return Err(Error::InvalidInput(format!("Invalid value '{}' in ${}: {}", raw, var, e.message())));
+        }
+        debug!("{} set by ${}", key, var);
+        set_key(&mut document, key, value);
+        from_env.insert(key.to_string());
+    }
+    
+    let mut config = parse(&document.to_string())
+        .map_err(|e| Error::InvalidInput(format!("Invalid configuration: {}", e.message())))?;
+    config.from_file = from_file;
+    config.from_env = from_env;
+    Ok(config)
+}
+
+/// Parse config TOML, noting which keys it sets in `from_file`
+fn parse(contents: &str) -> std::result::Result<Config, toml::de::Error> {
+    let table = toml::from_str::<toml::Table>(contents)?;
+    let from_file = keys(&table);
+    let mut config: Config = toml::Value::Table(table).try_into()?;
+    config.from_file = from_file;
+    Ok(config)
+}
+
+/// The environment variable that overrides `key`: `top.count` is
+/// `PATHRANGER_TOP_COUNT`
+pub fn env_var(key: &str) -> String {
+    format!("PATHRANGER_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Read a variable's value as TOML, like `config set`. A list can also be
+/// given colon-separated, as in $PATH, or for hooks as a single command.
+fn env_value_of(key: &str, raw: &str) -> toml_edit::Value {
+    let value = parse_value(raw);
+    if (key == "exclude" || key == "root.markers") && !value.is_array() {
+        return toml_edit::Value::Array(raw.split(':').filter(|pattern| !pattern.is_empty()).collect());
+    }
+    if key == "hooks.after_goto" && !value.is_array() {
+        return toml_edit::Value::Array([raw].into_iter().collect());
+    }
+    value
+}
+
+/// Read a value given on the command line or in the environment as TOML,
+/// taking anything that isn't valid TOML as a string
+fn parse_value(raw: &str) -> toml_edit::Value {
+    let mut value = raw.parse::<toml_edit::Value>().unwrap_or_else(|_| toml_edit::Value::from(raw));
+    value.decor_mut().clear();
+    value
+}
+
+fn set_key(document: &mut toml_edit::DocumentMut, key: &str, value: toml_edit::Value) {
+    match key.split_once('.') {
+        Some((section, name)) => {
+            // A new section gets a `[section]` header rather than an inline table
+            if !document.contains_key(section) {
+                document[section] = toml_edit::table();
+            }
+            document[section][name] = toml_edit::value(value);
+        }
+        None => document[key] = toml_edit::value(value),
+    }
+}
+
+/// The keys set in a config table, as `section.key` inside sections
+fn keys(table: &toml::Table) -> BTreeSet<String> {
+    let mut keys = BTreeSet::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Table(section) => keys.extend(section.keys().map(|name| format!("{}.{}", key, name))),
+            _ => {
+                keys.insert(key.clone());
+            }
+        }
+    }
+    keys
+}
+
+/// Every config key, in the order `config show` prints them
+pub const KEYS: &[&str] = &[
+    "color",
+    "confirm_threshold",
+    "database",
+    "data_dir_style",
+    "system_tags",
+    "exclude",
+    "journal",
+    "cwd_project",
+    "parent_above_home",
+    "fold_case",
+    "paths",
+    "trust_network_paths",
+    "display.relative_time",
+    "display.time_format",
+    "display.resolve_symlinks",
+    "display.path_form",
+    "display.hide_hidden",
+    "display.path_style",
+    "top.count",
+    "recent.count",
+    "list.count",
+    "list.sort",
+    "search.limit",
+    "query.explore",
+    "root.markers",
+    "daemon.idle_timeout",
+    "hooks.after_goto",
+];
+
+/// The value in effect for `key`, falling back to the built-in default.
+/// None for keys that have no default and aren't set.
+pub fn value(config: &Config, key: &str) -> Option<toml::Value> {
+    fn value(value: impl Serialize) -> Option<toml::Value> {
+        toml::Value::try_from(value).ok()
+    }
+    
+    let display = &config.display;
+    match key {
+        "color" => value(config.color.unwrap_or(ColorChoice::Auto)),
+        "confirm_threshold" => value(config.confirm_threshold.unwrap_or(DEFAULT_CONFIRM_THRESHOLD)),
+        "database" => config.database.as_deref().and_then(value),
+        "data_dir_style" => value(config.data_dir_style.unwrap_or_default()),
+        "system_tags" => value(config.system_tags.as_deref().unwrap_or(tagfile::SYSTEM_FILE)),
+        "exclude" => value(&config.exclude),
+        "journal" => value(config.journal.unwrap_or(false)),
+        "cwd_project" => value(config.cwd_project.unwrap_or_default()),
+        "parent_above_home" => value(config.parent_above_home.unwrap_or(false)),
+        "fold_case" => value(config.fold_case.unwrap_or(true)),
+        "paths" => value(config.paths.unwrap_or_default()),
+        "trust_network_paths" => value(config.trust_network_paths.unwrap_or(false)),
+        "display.relative_time" => value(display.relative_time.unwrap_or(true)),
+        "display.time_format" => match &display.time_format {
+            Some(format) => value(format),
+            None if display.relative_time.unwrap_or(true) => value(TimeFormat::Relative),
+            None => value(DEFAULT_TIME_FORMAT),
+        },
+        "display.resolve_symlinks" => value(display.resolve_symlinks.unwrap_or(false)),
+        "display.path_form" => value(display.path_form.unwrap_or_default()),
+        "display.hide_hidden" => value(display.hide_hidden.unwrap_or(false)),
+        "display.path_style" => value(display.path_style.unwrap_or_default()),
+        "top.count" => value(config.top.count.unwrap_or(DEFAULT_COUNT)),
+        "recent.count" => value(config.recent.count.unwrap_or(DEFAULT_COUNT)),
+        "list.count" => value(config.list.count.unwrap_or(DEFAULT_COUNT)),
+        "list.sort" => value(config.list.sort.unwrap_or(SortKey::Visits)),
+        "search.limit" => value(config.search.limit.unwrap_or(DEFAULT_COUNT)),
+        "query.explore" => value(config.query.explore.unwrap_or(false)),
+        "root.markers" => match &config.root.markers {
+            Some(markers) => value(markers),
+            None => value(DEFAULT_ROOT_MARKERS),
+        },
+        "daemon.idle_timeout" => value(config.daemon.idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT)),
+        "hooks.after_goto" => value(&config.hooks.after_goto),
+        _ => None,
+    }
+}
+
+/// Where the value in effect for `key` came from
+pub fn source(config: &Config, key: &str) -> String {
+    if config.from_env.contains(key) {
+        format!("${}", env_var(key))
+    } else if config.from_file.contains(key) {
+        String::from("config file")
+    } else {
+        String::from("default")
+    }
+}
+
+/// Fail unless `key` is one `value` knows, so typos are caught
+/// before they reach the file
+pub fn check_key(key: &str) -> Result<()> {
+    if KEYS.contains(&key) {
+        return Ok(());
+    }
+    Err(Error::InvalidInput(format!("Unknown config key '{}'\nKnown keys: {}", key, KEYS.join(", "))))
+}
+
+/// What `config init` writes: every setting, commented out at its default
+const TEMPLATE: &str = r#"# pathranger settings. Uncomment a line to change it, or use
+# `pathranger config set KEY VALUE`; `pathranger config show` prints the
+# values in effect.
+
+# Default color mode: "always", "auto" or "never"
+#color = "auto"
+# Ask before a destructive command changes more rows than this
+#confirm_threshold = 20
+# Database file to use instead of the default location
+#database = "~/sync/pathranger.db"
+# Where the data directory is: "platform" (~/Library/Application Support on
+# macOS) or "xdg" ($XDG_DATA_HOME or ~/.local/share everywhere)
+#data_dir_style = "platform"
+# Read-only tags shared by all users of the machine
+#system_tags = "/etc/pathranger/tags.toml"
+# Directories that are never recorded, jumped to or listed
+#exclude = ["**/node_modules/**", "**/.git/**"]
+# When the database is locked or can't be opened, record visits to a local
+# journal that later commands replay into it, for databases on slow or
+# contended network filesystems
+#journal = false
+# Inside a git, Mercurial or Subversion checkout, have search and query
+# "auto" prefer its directories, consider "only" them, or ignore it ("off")
+#cwd_project = "auto"
+# Let `--parent` go above your home directory from a match inside it
+#parent_above_home = false
+# Count Windows paths that differ only in case as one directory; turn off if
+# you make directories case-sensitive
+#fold_case = true
+# Record a directory reached through a symlink as the shell spells it
+# ("logical") or with symlinks resolved ("physical")
+#paths = "logical"
+# Assume directories on NFS, SMB and FUSE mounts exist rather than checking,
+# which can hang on an automounter; checks otherwise give up after 200ms
+#trust_network_paths = false
+
+[display]
+# Show last-visited times as "3 hours ago"
+#relative_time = true
+# How times are shown: a strftime pattern such as "%Y-%m-%dT%H:%M:%S" or
+# "%d/%m/%Y", or "relative"; overrides relative_time when set
+#time_format = "%Y-%m-%d %H:%M"
+# Merge listing entries that are symlinks to the same directory
+#resolve_symlinks = false
+# Show merged entries as recorded ("logical") or fully resolved ("physical")
+#path_form = "logical"
+# Leave directories inside dot directories out of listings
+#hide_hidden = false
+# "auto" (relative inside the current directory), "relative" or "tilde"
+#path_style = "auto"
+
+[top]
+#count = 10
+
+[recent]
+#count = 10
+
+[list]
+#count = 10
+# "visits", "recent", "alpha" or "frecency"
+#sort = "visits"
+
+[search]
+#limit = 10
+
+[query]
+# When no tracked directory clearly matches, try the unvisited directories
+# inside your most frequent ones
+#explore = false
+
+[root]
+# Files or directories that mark a project root for `pathranger root`, as well
+# as .git, .hg and .svn
+#markers = ["Cargo.toml", "package.json", ".project"]
+
+[daemon]
+# Seconds without a request before `pathranger daemon` exits; 0 for never
+#idle_timeout = 1800
+
+[hooks]
+# Shell commands run after the shell integration's goto changes directory,
+# skipped with `goto --no-hooks`
+#after_goto = ["ls --color=auto", "git status -sb 2>/dev/null"]
+"#;
+
+fn required_path() -> Result<PathBuf> {
+    path().ok_or_else(|| Error::InvalidInput(String::from("Could not determine the config directory")))
+}
+
+/// Write `contents` to the config file, creating its directory
+fn write(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| Error::Io(format!("Could not create {}", dir.display()), e))?;
+    }
+    fs::write(path, contents).map_err(|e| Error::Io(format!("Could not write {}", path.display()), e))
+}
+
+/// Write the commented-out template of every setting to the config file,
+/// unless one exists and `force` is false. Returns where it went.
+pub fn init(force: bool) -> Result<PathBuf> {
+    let path = required_path()?;
+    if path.exists() && !force {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists\nPass --force to replace it",
+            format_path(&path.to_string_lossy())
+        )));
+    }
+    
+    write(&path, TEMPLATE)?;
+    Ok(path)
+}
+
+/// Set `key` in the config file, editing it in place so comments and layout
+/// survive. The result is checked against the schema before it is written.
+/// Returns the file and the value as written.
+pub fn set(key: &str, raw: &str) -> Result<(PathBuf, String)> {
+    check_key(key)?;
+    let path = required_path()?;
+    
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(Error::Io(format!("Could not read {}", path.display()), e)),
+    };
+    let mut document: toml_edit::DocumentMut = contents.parse().map_err(|e| {
+        Error::InvalidInput(format!(
+            "Can't edit {}, which isn't valid TOML: {}",
+            format_path(&path.to_string_lossy()),
+            e
+        ))
+    })?;
+    
+    let value = parse_value(raw);
+    let shown_value = value.to_string();
+    set_key(&mut document, key, value);
+    
+    let edited = document.to_string();
+    if let Err(e) = parse(&edited) {
+        return Err(Error::InvalidInput(format!("Invalid value '{}' for {}: {}", raw, key, e.message())));
+    }
+    
+    write(&path, &edited)?;
+    Ok((path, shown_value))
+}
+
+/// An environment variable's value, treating an empty one as unset
+pub fn env_value(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
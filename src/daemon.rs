@@ -0,0 +1,325 @@
+//! `pathranger daemon`: keeps one database connection open behind a Unix
+//! socket so prompt hooks don't pay for process setup and opening SQLite on
+//! every prompt. Clients send one request per line and read one response
+//! line back:
+//!
+//! ```text
+//! record<TAB>/abs/path[<TAB>/abs/from]
+//!                           ok | missing<TAB>path | error<TAB>message
+//! query<TAB>cwd<TAB>name[<TAB>auto|only|off]
+//!                           ok<TAB>path | none | error<TAB>message
+//! resolve-tag<TAB>name      ok<TAB>path | none | error<TAB>message
+//! status                    ok<TAB>pid<TAB>uptime secs<TAB>requests<TAB>database
+//! stop                      ok<TAB>pid
+//! ```
+//!
+//! Each database gets its own socket, so profiles and `--db` never reach a
+//! daemon serving another file. Without a daemon, or on platforms without
+//! Unix sockets, callers open the database themselves.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(unix)]
+use std::sync::Arc;
+#[cfg(unix)]
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(unix)]
+use clap::ValueEnum;
+use globset::GlobSet;
+
+use crate::project::CwdProject;
+use crate::record::{from_to_record, path_to_record};
+#[cfg(unix)]
+use crate::note;
+use crate::{database_id, debug, warn, Database, Error, Result};
+
+/// How long a client waits for the daemon to answer before giving up
+#[cfg(unix)]
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The directory sockets live in: $XDG_RUNTIME_DIR/pathranger, or a
+/// per-user directory under the system temp directory
+#[cfg(unix)]
+fn socket_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(runtime) => PathBuf::from(runtime).join("pathranger"),
+        // SAFETY: getuid has no preconditions and cannot fail
+        None => std::env::temp_dir().join(format!("pathranger-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// The socket a daemon serving `db` listens on, one per database file. None
+/// if the socket directory doesn't exist or isn't private to us, since then
+/// anyone could have put a socket there.
+#[cfg(unix)]
+pub fn socket_path(db: &Path) -> Option<PathBuf> {
+    let dir = socket_dir();
+    let metadata = fs::symlink_metadata(&dir).ok()?;
+    // SAFETY: getuid has no preconditions and cannot fail
+    let private = metadata.is_dir() && metadata.uid() == unsafe { libc::getuid() } && metadata.mode() & 0o077 == 0;
+    private.then(|| dir.join(format!("{}.sock", database_id(db))))
+}
+
+#[cfg(not(unix))]
+pub fn socket_path(_db: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Create the socket directory readable only by us, then find the socket
+/// for `db` in it
+#[cfg(unix)]
+pub fn create_socket_path(db: &Path) -> Result<PathBuf> {
+    let dir = socket_dir();
+    if !dir.exists() {
+        fs::create_dir_all(&dir).map_err(|e| Error::Io(format!("Could not create {}", dir.display()), e))?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .map_err(|e| Error::Io(format!("Could not restrict {}", dir.display()), e))?;
+    }
+    socket_path(db).ok_or_else(|| {
+        Error::InvalidInput(format!("{} must be a directory only you can access", dir.display()))
+    })
+}
+
+#[cfg(not(unix))]
+pub fn create_socket_path(_db: &Path) -> Result<PathBuf> {
+    Err(Error::InvalidInput(String::from("The daemon needs Unix domain sockets, which this platform lacks")))
+}
+
+/// Send one request and return the daemon's response line, or None if no
+/// daemon is listening or it didn't answer
+#[cfg(unix)]
+pub fn request(socket: &Path, line: &str) -> Option<String> {
+    let mut stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT)).ok()?;
+    writeln!(stream, "{}", line).ok()?;
+    
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).ok()?;
+    let response = response.trim_end_matches('\n');
+    (!response.is_empty()).then(|| response.to_string())
+}
+
+#[cfg(not(unix))]
+pub fn request(_socket: &Path, _line: &str) -> Option<String> {
+    None
+}
+
+/// Whether `text` can travel in one field of a request
+fn fits_field(text: &str) -> bool {
+    !text.contains(['\t', '\n'])
+}
+
+/// Hand a visit to the daemon serving `db_path`, if one is running.
+/// Returns false when the caller has to record it itself: no daemon
+/// answered, or the daemon hit an error worth reporting.
+pub fn record(db_path: &Path, path: &str, from: Option<&str>, exclude: Option<&GlobSet>) -> bool {
+    let Some(socket) = socket_path(db_path) else {
+        return false;
+    };
+    let Some(expanded) = path_to_record(path, exclude) else {
+        return true;
+    };
+    // The daemon runs elsewhere, so it can only be given absolute paths
+    if !Path::new(expanded.as_ref()).is_absolute() || !fits_field(&expanded) {
+        return false;
+    }
+    let request_line = match from_to_record(from, exclude).filter(|from| fits_field(from)) {
+        Some(from) => format!("record\t{}\t{}", expanded, from),
+        None => format!("record\t{}", expanded),
+    };
+    match request(&socket, &request_line) {
+        Some(response) if response == "ok" => true,
+        Some(response) if response.starts_with("missing\t") => {
+            warn!("Directory does not exist: {}", expanded);
+            true
+        }
+        response => {
+            debug!("daemon did not record the visit: {:?}", response);
+            false
+        }
+    }
+}
+
+/// Ask the daemon serving `db_path` for the `query` answer from `cwd`.
+/// None when no daemon answered, so the caller queries the database itself.
+pub fn query(db_path: &Path, cwd: &Path, name: &str, cwd_project: CwdProject) -> Option<Option<String>> {
+    let socket = socket_path(db_path)?;
+    let cwd = cwd.to_str().filter(|cwd| fits_field(cwd))?;
+    if !fits_field(name) {
+        return None;
+    }
+    let request_line = format!("query\t{}\t{}\t{}", cwd, name, cwd_project.name());
+    let response = request(&socket, &request_line)?;
+    if response == "none" {
+        return Some(None);
+    }
+    match response.strip_prefix("ok\t") {
+        Some(path) => Some(Some(path.to_string())),
+        None => {
+            debug!("daemon could not answer the query: {}", response);
+            None
+        }
+    }
+}
+
+/// What a running daemon reports about itself
+pub struct Status {
+    pub pid: String,
+    pub uptime: Duration,
+    pub requests: u64,
+}
+
+/// The `status` answer of the daemon serving `db_path`, or None if no
+/// daemon is running
+pub fn status(db_path: &Path) -> Option<Status> {
+    let response = request(&socket_path(db_path)?, "status")?;
+    let mut fields = response.strip_prefix("ok\t")?.splitn(4, '\t');
+    let pid = fields.next()?.to_string();
+    let uptime = Duration::from_secs(fields.next()?.parse().ok()?);
+    let requests = fields.next()?.parse().ok()?;
+    Some(Status { pid, uptime, requests })
+}
+
+/// Ask the daemon serving `db_path` to exit. Returns its pid, or None if
+/// none was running.
+pub fn stop(db_path: &Path) -> Option<String> {
+    let response = request(&socket_path(db_path)?, "stop")?;
+    response.strip_prefix("ok\t").map(ToString::to_string)
+}
+
+/// Serve `db` on `socket` until a `stop` request, or until no request has
+/// arrived for `idle_timeout` (None to run until stopped)
+#[cfg(unix)]
+pub fn serve(db: Database, db_path: &Path, socket: &Path, idle_timeout: Option<Duration>) -> Result<()> {
+    if request(socket, "status").is_some() {
+        return Err(Error::InvalidInput(format!("A daemon is already serving {}", db_path.display())));
+    }
+    // Nothing answered, so any socket file is left over from a daemon that died
+    let _ = fs::remove_file(socket);
+    
+    // Create the socket owner-only from the start, not chmod it afterwards.
+    // SAFETY: umask has no preconditions; nothing else creates files meanwhile.
+    let old_umask = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(socket);
+    unsafe { libc::umask(old_umask) };
+    let listener = bound.map_err(|e| Error::Io(format!("Could not listen on {}", socket.display()), e))?;
+    note!("Serving {} on {}", db_path.display(), socket.display());
+    
+    let started = Instant::now();
+    let last_request = Arc::new(AtomicU64::new(now_secs()));
+    if let Some(timeout) = idle_timeout {
+        let last_request = Arc::clone(&last_request);
+        let socket = socket.to_path_buf();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(Duration::from_secs(1).min(timeout));
+            if now_secs().saturating_sub(last_request.load(Ordering::Relaxed)) >= timeout.as_secs() {
+                debug!("idle for {:?}, stopping", timeout);
+                let _ = fs::remove_file(&socket);
+                std::process::exit(0);
+            }
+        });
+    }
+    
+    let mut requests: u64 = 0;
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        last_request.store(now_secs(), Ordering::Relaxed);
+        requests += 1;
+        
+        // One slow client mustn't hold up everyone's prompt for long
+        let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+        let mut line = String::new();
+        let mut reader = BufReader::new(&stream);
+        if reader.read_line(&mut line).is_err() {
+            continue;
+        }
+        let line = line.trim_end_matches('\n');
+        debug!("request: {}", line);
+        
+        let (command, argument) = line.split_once('\t').unwrap_or((line, ""));
+        let response = match command {
+            "record" => {
+                let (path, from) = match argument.split_once('\t') {
+                    Some((path, from)) => (path, Some(from)),
+                    None => (argument, None),
+                };
+                recorded(db.record_visit_from(path, from))
+            }
+            "query" => {
+                let (cwd, name) = argument.split_once('\t').unwrap_or(("", argument));
+                // Clients that predate the project field want the old behaviour
+                let (name, mode) = match name.split_once('\t') {
+                    Some((name, mode)) => (name, CwdProject::from_str(mode, false).unwrap_or(CwdProject::Off)),
+                    None => (name, CwdProject::Off),
+                };
+                let cwd = Some(Path::new(cwd)).filter(|cwd| cwd.is_absolute());
+                let root = mode.root(cwd);
+                found(db.query_in(name, cwd, mode.scope(root.as_deref()), &[]))
+            }
+            "resolve-tag" => found(db.resolve_tag(argument)),
+            "status" => format!(
+                "ok\t{}\t{}\t{}\t{}",
+                std::process::id(),
+                started.elapsed().as_secs(),
+                requests,
+                db_path.display()
+            ),
+            "stop" => format!("ok\t{}", std::process::id()),
+            _ => format!("error\tunknown request '{}'", command),
+        };
+        let _ = writeln!(&stream, "{}", response);
+        
+        if command == "stop" {
+            break;
+        }
+    }
+    
+    let _ = fs::remove_file(socket);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve(_db: Database, _db_path: &Path, _socket: &Path, _idle_timeout: Option<Duration>) -> Result<()> {
+    Err(Error::InvalidInput(String::from("The daemon needs Unix domain sockets, which this platform lacks")))
+}
+
+/// Respond with a visit's outcome
+#[cfg(unix)]
+fn recorded(result: Result<()>) -> String {
+    match result {
+        Ok(()) => String::from("ok"),
+        Err(Error::DirectoryMissing(path)) => format!("missing\t{}", path),
+        Err(e) => format!("error\t{}", e),
+    }
+}
+
+/// Respond with a lookup's result
+#[cfg(unix)]
+fn found(result: Result<Option<String>>) -> String {
+    match result {
+        Ok(Some(path)) => format!("ok\t{}", path),
+        Ok(None) => String::from("none"),
+        Err(e) => format!("error\t{}", e),
+    }
+}
+
+#[cfg(unix)]
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,529 @@
+//! Turning paths and timestamps into what listings show
+
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, TimeZone};
+use dirs::home_dir;
+use crate::paths::normalize_windows_path;
+use serde::{Deserialize, Serialize};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Timestamps older than this are shown as a date even in relative mode
+pub const RELATIVE_TIME_MAX_DAYS: i64 = 30;
+
+/// The timestamp pattern listings use unless `display.time_format` or
+/// `--time-format` gives another
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+/// How listings show when something happened
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TimeFormat {
+    /// "3 hours ago", falling back to a date after `RELATIVE_TIME_MAX_DAYS`
+    Relative,
+    /// A chrono strftime pattern, checked to be valid
+    Pattern(String),
+}
+
+impl TimeFormat {
+    pub fn default_pattern() -> Self {
+        TimeFormat::Pattern(String::from(DEFAULT_TIME_FORMAT))
+    }
+    
+    /// The cells a time in this format takes at most, give or take a
+    /// pattern's variable-width names, for sizing table columns
+    pub fn width(&self) -> usize {
+        match self {
+            TimeFormat::Relative => "59 minutes ago".width(),
+            // A date with long month and day names and two-digit fields
+            TimeFormat::Pattern(pattern) => match Local.with_ymd_and_hms(2000, 9, 27, 23, 59, 59).single() {
+                Some(sample) => sample.format(pattern).to_string().width(),
+                None => pattern.width(),
+            },
+        }
+    }
+}
+
+impl FromStr for TimeFormat {
+    type Err = String;
+    
+    /// "relative", or a strftime pattern such as "%Y-%m-%dT%H:%M:%S"
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if text == "relative" {
+            return Ok(TimeFormat::Relative);
+        }
+        if let Some(specifier) = invalid_specifier(text) {
+            return Err(format!(
+                "'{}' is not a strftime specifier; use \"relative\" or a pattern like \"%Y-%m-%d %H:%M\"",
+                specifier
+            ));
+        }
+        Ok(TimeFormat::Pattern(text.to_string()))
+    }
+}
+
+impl TryFrom<String> for TimeFormat {
+    type Error = String;
+    
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<TimeFormat> for String {
+    fn from(format: TimeFormat) -> Self {
+        match format {
+            TimeFormat::Relative => String::from("relative"),
+            TimeFormat::Pattern(pattern) => pattern,
+        }
+    }
+}
+
+/// The first specifier in `pattern` that chrono can't format, such as `%Q`
+/// or a `%` at the end, if there is one. Formatting with one would panic.
+fn invalid_specifier(pattern: &str) -> Option<String> {
+    if !StrftimeItems::new(pattern).any(|item| matches!(item, Item::Error)) {
+        return None;
+    }
+    let mut rest = pattern;
+    while let Some(start) = rest.find('%') {
+        let tail = &rest[start + 1..];
+        // A specifier runs up to its letter (or `%`), after flags such as `-`, `.3` or `:`
+        let end = tail.find(|c: char| c.is_ascii_alphabetic() || c == '%').map_or(tail.len(), |i| i + 1);
+        let specifier = &rest[start..start + 1 + end];
+        if StrftimeItems::new(specifier).any(|item| matches!(item, Item::Error)) {
+            return Some(specifier.to_string());
+        }
+        rest = &rest[start + 1 + end..];
+    }
+    Some(pattern.to_string())
+}
+
+pub fn format_last_visited(time: Option<DateTime<Local>>, format: &TimeFormat) -> String {
+    match (time, format) {
+        (None, _) => String::from("unknown"),
+        (Some(time), TimeFormat::Relative) => format_time_ago(time, Local::now()),
+        (Some(time), TimeFormat::Pattern(pattern)) => time.format(pattern).to_string(),
+    }
+}
+
+/// A file size in the largest binary unit that keeps it at least 1, with one
+/// decimal: "512 B", "48.0 KiB", "1.2 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Humanize a timestamp relative to `now`: "just now", "5 minutes ago",
+/// "3 hours ago", "2 days ago", falling back to "Oct 12" (or "Oct 12 2023"
+/// for another year) beyond `RELATIVE_TIME_MAX_DAYS`.
+pub fn format_time_ago(time: DateTime<Local>, now: DateTime<Local>) -> String {
+    let elapsed = now.signed_duration_since(time);
+    
+    let plural = |n: i64, unit: &str| {
+        if n == 1 {
+            format!("1 {} ago", unit)
+        } else {
+            format!("{} {}s ago", n, unit)
+        }
+    };
+    
+    if elapsed.num_days() > RELATIVE_TIME_MAX_DAYS || elapsed.num_seconds() < 0 {
+        if time.year() == now.year() {
+            time.format("%b %d").to_string()
+        } else {
+            time.format("%b %d %Y").to_string()
+        }
+    } else if elapsed.num_minutes() < 1 {
+        "just now".to_string()
+    } else if elapsed.num_hours() < 1 {
+        plural(elapsed.num_minutes(), "minute")
+    } else if elapsed.num_days() < 1 {
+        plural(elapsed.num_hours(), "hour")
+    } else {
+        plural(elapsed.num_days(), "day")
+    }
+}
+
+/// The width in cells of each table column but the last, which isn't
+/// padded: the widest of its header, its minimum and its cells in `rows`
+pub fn column_widths(headers: &[&str], min_widths: &[usize], rows: &[Vec<&str>]) -> Vec<usize> {
+    (0..headers.len().saturating_sub(1))
+        .map(|col| {
+            rows.iter()
+                .map(|row| row[col].width())
+                .chain([headers[col].width(), min_widths[col]])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Left-align `text` in `width` terminal cells
+pub fn pad_to_width(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.width());
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// Never truncate a path below this many cells, however narrow the terminal
+pub const MIN_PATH_WIDTH: usize = 12;
+
+/// Shorten `path` to at most `max_width` display cells by replacing the middle
+/// with `…`. The final component is kept whole whenever it fits, since it is
+/// the most informative part; otherwise it is cut from the left. With no
+/// room at all, not even the `…` is left.
+pub fn truncate_middle(path: &str, max_width: usize) -> String {
+    if path.width() <= max_width {
+        return path.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+    
+    let ellipsis = '…';
+    let budget = max_width.saturating_sub(1);
+    let tail = match path.trim_end_matches('/').rfind('/') {
+        Some(idx) => &path[idx..],
+        None => path,
+    };
+    
+    if tail.width() >= budget {
+        // Not even the basename fits: keep as much of its end as possible
+        let mut kept = Vec::new();
+        let mut used = 0;
+        for c in tail.chars().rev() {
+            let w = c.width().unwrap_or(0);
+            if used + w > budget {
+                break;
+            }
+            used += w;
+            kept.push(c);
+        }
+        // A combining mark whose base was cut off would land on the `…`
+        while kept.last().is_some_and(|c| c.width() == Some(0)) {
+            kept.pop();
+        }
+        let kept: String = kept.into_iter().rev().collect();
+        return format!("{}{}", ellipsis, kept);
+    }
+    
+    let head_budget = budget - tail.width();
+    let mut head = String::new();
+    let mut used = 0;
+    for c in path.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > head_budget {
+            break;
+        }
+        used += w;
+        head.push(c);
+    }
+    
+    format!("{}{}{}", head, ellipsis, tail)
+}
+
+/// The current directory as the shell sees it ($PWD, which keeps symlinks)
+/// and as the kernel does. $PWD is only trusted if it is the same directory.
+pub fn current_dir_forms() -> Vec<PathBuf> {
+    let mut forms = Vec::new();
+    let physical = std::env::current_dir().ok();
+    
+    if let Some(pwd) = std::env::var_os("PWD").map(PathBuf::from) {
+        let same = physical.as_ref().is_some_and(|physical| {
+            fs::canonicalize(&pwd).ok() == fs::canonicalize(physical).ok()
+        });
+        if pwd.is_absolute() && same {
+            forms.push(pwd);
+        }
+    }
+    if let Some(physical) = physical
+        && !forms.contains(&physical)
+    {
+        forms.push(physical);
+    }
+    
+    forms
+}
+
+/// `path` relative to `base`, climbing out with `..` as needed. Both must be
+/// absolute; returns None if they share no root (e.g. different drives).
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    if path.first() != base.first() {
+        return None;
+    }
+    
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..base.len() {
+        relative.push("..");
+    }
+    for component in &path[common..] {
+        relative.push(component);
+    }
+    
+    Some(relative)
+}
+
+/// Show the home directory as `~`: `/home/alex/src` becomes `~/src` and
+/// `/home/alex` becomes `~`, while a sibling such as `/home/alexx` is left
+/// alone
+pub fn format_path(path: &str) -> String {
+    match home_dir() {
+        Some(home) => fold_home_in(path, &home.to_string_lossy(), cfg!(windows)),
+        None => path.to_string(),
+    }
+}
+
+/// `format_path` against a given home directory. With `windows`, both are
+/// compared in their `normalize_windows_path` form and ignoring ASCII case,
+/// as NTFS does, so `c:/users/alex/src` still shows as `~\src`; a path on
+/// another drive or share, or a home that is a bare drive, is never folded.
+pub fn fold_home_in(path: &str, home: &str, windows: bool) -> String {
+    let (normalized, home) = if windows {
+        (normalize_windows_path(path), normalize_windows_path(home))
+    } else {
+        (path.to_string(), home.to_string())
+    };
+    let home = home.trim_end_matches(|c| c == '/' || (windows && c == '\\'));
+    // A home of / (or C:\) would turn every path into ~something
+    if home.is_empty() || (windows && home.ends_with(':')) {
+        return path.to_string();
+    }
+    
+    let Some(head) = normalized.get(..home.len()) else {
+        return path.to_string();
+    };
+    let rest = &normalized[home.len()..];
+    let same_home = if windows { head.eq_ignore_ascii_case(home) } else { head == home };
+    let separated = rest.is_empty() || rest.starts_with('/') || (windows && rest.starts_with('\\'));
+    if same_home && separated {
+        format!("~{}", rest)
+    } else {
+        path.to_string()
+    }
+}
+
+/// `text` with control characters written out the way `ls` quotes them,
+/// so a directory named with a newline or an escape sequence can't break
+/// up a listing or drive the terminal: `\n`, `\t`, `\r` and `\x1b` style
+/// escapes. Only for what people read; paths meant for the shell keep
+/// their bytes.
+pub fn escape_control(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(char::is_control) {
+        return Cow::Borrowed(text);
+    }
+    let mut escaped = String::with_capacity(text.len() + 8);
+    for c in text.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", u32::from(c))),
+            c => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// `text` as a field of `--porcelain` output: `escape_control` with
+/// backslashes doubled as well, so every escape can be undone exactly
+pub fn escape_field(text: &str) -> Cow<'_, str> {
+    if text.contains('\\') {
+        Cow::Owned(escape_control(&text.replace('\\', "\\\\")).into_owned())
+    } else {
+        escape_control(text)
+    }
+}
+
+/// The inverse of `format_path`: expand a leading `~` (alone or followed by
+/// a separator) to the home directory. Anything else, including `~user`, is
+/// returned unchanged.
+pub fn expand_display_path(path: &str) -> String {
+    let Some(rest) = path.strip_prefix('~') else {
+        return path.to_string();
+    };
+    if !(rest.is_empty() || rest.starts_with(std::path::is_separator)) {
+        return path.to_string();
+    }
+    
+    match home_dir() {
+        Some(home) => format!("{}{}", home.to_string_lossy().trim_end_matches(std::path::is_separator), rest),
+        None => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Rows mixing CJK, emoji, combining accents and ASCII, as listings lay
+    /// them out: rank, visits and a path
+    const ROWS: [[&str; 3]; 4] = [
+        ["1", "12", "/srv/東京/プロジェクト"],
+        ["2", "3", "/home/e\u{301}cole"],
+        ["10", "1", "/tmp/📁 notes"],
+        ["100", "1234567", "/plain"],
+    ];
+    
+    #[test]
+    fn columns_are_padded_by_cells() {
+        let headers = ["", "VISITS", "PATH"];
+        let rows: Vec<Vec<&str>> = ROWS.iter().map(|row| row.to_vec()).collect();
+        let widths = column_widths(&headers, &[4, 0, 0], &rows);
+        assert_eq!(widths, [4, 7]);
+        
+        for row in &rows {
+            for (cell, width) in row.iter().zip(&widths) {
+                assert_eq!(pad_to_width(cell, *width).width(), *width, "{:?}", cell);
+            }
+        }
+        // So the path column starts at the same cell on every row
+        let starts: Vec<usize> = rows
+            .iter()
+            .map(|row| row.iter().zip(&widths).map(|(cell, width)| pad_to_width(cell, *width) + " ").collect::<String>())
+            .map(|prefix| prefix.width())
+            .collect();
+        assert_eq!(starts, [13; 4]);
+    }
+    
+    #[test]
+    fn wide_cells_widen_their_column() {
+        let rows = vec![vec!["東京都", "x"], vec!["e\u{301}e\u{301}e\u{301}", "y"]];
+        assert_eq!(column_widths(&["TAG", "PATH"], &[0, 0], &rows), [6]);
+        assert_eq!(pad_to_width("e\u{301}e\u{301}e\u{301}", 6), "e\u{301}e\u{301}e\u{301}   ");
+        // Text wider than the column is left as it is
+        assert_eq!(pad_to_width("東京都", 2), "東京都");
+    }
+    
+    #[test]
+    fn truncate_middle_counts_cells() {
+        let path = "/srv/東京/プロジェクト/資料";
+        assert_eq!(truncate_middle(path, path.width()), path);
+        
+        let truncated = truncate_middle(path, 12);
+        assert_eq!(truncated, "/srv/…/資料");
+        assert!(truncated.width() <= 12);
+        
+        // The basename is cut from the left a whole character at a time,
+        // never splitting a double-width one
+        let truncated = truncate_middle("/srv/プロジェクト", 6);
+        assert_eq!(truncated, "…クト");
+        assert!(truncated.width() <= 6);
+    }
+    
+    #[test]
+    fn truncate_middle_keeps_combining_marks_with_their_base() {
+        let path = "/home/alex/de\u{301}ja\u{300}-vu";
+        let truncated = truncate_middle(path, 13);
+        assert_eq!(truncated, "/hom…/de\u{301}ja\u{300}-vu");
+        assert_eq!(truncated.width(), 13);
+        
+        // Cut between the e and its accent, the mark goes with the e
+        let truncated = truncate_middle("/srv/e\u{301}tude", 5);
+        assert_eq!(truncated, "…tude");
+        assert!(!truncated.contains('\u{301}'));
+    }
+    
+    #[test]
+    fn truncate_middle_with_no_room() {
+        assert_eq!(truncate_middle("/srv/東京", 0), "");
+        assert_eq!(truncate_middle("/srv/東京", 1), "…");
+        // A double-width character doesn't fit beside the … in 2 cells
+        assert_eq!(truncate_middle("/srv/東京", 2), "…");
+        assert_eq!(truncate_middle("/srv/東京", 3), "…京");
+    }
+    
+    #[test]
+    fn folds_home_itself_and_below() {
+        assert_eq!(fold_home_in("/home/alex", "/home/alex", false), "~");
+        assert_eq!(fold_home_in("/home/alex/src/api", "/home/alex", false), "~/src/api");
+        assert_eq!(fold_home_in("/home/alex/", "/home/alex", false), "~/");
+    }
+    
+    #[test]
+    fn leaves_siblings_of_home_alone() {
+        assert_eq!(fold_home_in("/home/alexx", "/home/alex", false), "/home/alexx");
+        assert_eq!(fold_home_in("/home/alexx/stuff", "/home/alex", false), "/home/alexx/stuff");
+        assert_eq!(fold_home_in("/home", "/home/alex", false), "/home");
+        assert_eq!(fold_home_in("/srv/home/alex", "/home/alex", false), "/srv/home/alex");
+    }
+    
+    #[test]
+    fn home_with_trailing_slash() {
+        assert_eq!(fold_home_in("/home/alex", "/home/alex/", false), "~");
+        assert_eq!(fold_home_in("/home/alex/src", "/home/alex/", false), "~/src");
+        assert_eq!(fold_home_in("/home/alexx", "/home/alex/", false), "/home/alexx");
+        // A home of / would fold everything
+        assert_eq!(fold_home_in("/etc", "/", false), "/etc");
+    }
+    
+    #[test]
+    fn unix_paths_compare_case() {
+        assert_eq!(fold_home_in("/home/Alex/src", "/home/alex", false), "/home/Alex/src");
+    }
+    
+    #[test]
+    fn windows_home_ignores_case_and_separators() {
+        assert_eq!(fold_home_in(r"C:\Users\Alex\src", r"C:\Users\Alex", true), r"~\src");
+        assert_eq!(fold_home_in("c:/users/alex/src", r"C:\Users\Alex", true), r"~\src");
+        assert_eq!(fold_home_in(r"\\?\C:\USERS\ALEX", r"C:\Users\Alex\", true), "~");
+        assert_eq!(fold_home_in(r"C:\Users\Alexx", r"C:\Users\Alex", true), r"C:\Users\Alexx");
+        // Another drive with the same directories, and a home that is a drive
+        assert_eq!(fold_home_in(r"D:\Users\Alex\src", r"C:\Users\Alex", true), r"D:\Users\Alex\src");
+        assert_eq!(fold_home_in(r"C:\src", r"C:\", true), r"C:\src");
+    }
+    
+    #[test]
+    fn control_characters_are_written_out() {
+        assert_eq!(escape_control("/tmp/a\nb"), r"/tmp/a\nb");
+        assert_eq!(escape_control("tab\there\rback"), r"tab\there\rback");
+        assert_eq!(escape_control("\x1b[31mred\x1b[0m"), r"\x1b[31mred\x1b[0m");
+        assert_eq!(escape_control("\x1b]0;title\x07"), r"\x1b]0;title\x07");
+        assert_eq!(escape_control("del\x7f"), r"del\x7f");
+        assert_eq!(escape_control("nul\0"), r"nul\x00");
+    }
+    
+    #[test]
+    fn c1_controls_are_written_out() {
+        // U+009B is a one-character CSI in terminals that take 8-bit controls
+        assert_eq!(escape_control("\u{9b}31mred"), r"\x9b31mred");
+        assert_eq!(escape_control("next\u{85}line"), r"next\x85line");
+    }
+    
+    #[test]
+    fn printable_text_is_borrowed() {
+        for text in ["/home/alex", r"C:\src", "/srv/東京/e\u{301}cole 📁", ""] {
+            assert!(matches!(escape_control(text), Cow::Borrowed(borrowed) if borrowed == text), "{:?}", text);
+            if !text.contains('\\') {
+                assert!(matches!(escape_field(text), Cow::Borrowed(_)), "{:?}", text);
+            }
+        }
+    }
+    
+    #[test]
+    fn fields_escape_backslashes_too() {
+        assert_eq!(escape_field("tab\there"), r"tab\there");
+        assert_eq!(escape_field("a\nb"), r"a\nb");
+        assert_eq!(escape_field(r"C:\src"), r"C:\\src");
+        // A backslash followed by n stays apart from a newline
+        assert_eq!(escape_field("a\\nb"), r"a\\nb");
+        assert_eq!(escape_field("a\\\nb"), r"a\\\nb");
+        assert_eq!(escape_field("\x1b\\"), r"\x1b\\");
+    }
+}
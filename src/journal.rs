@@ -0,0 +1,201 @@
+//! The visit journal: with `journal = true`, `record` appends a line to a
+//! local file instead of writing to a database that may sit on a slow or
+//! contended network filesystem. Other commands replay it into the database
+//! in one transaction before they run.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use crate::{
+    create_private_dir, database_id, debug, private_file_options, Database, Error, Result, Transition, Visit, VisitLog,
+    VisitTime,
+};
+
+/// How long a command waits for the database lock before turning to the
+/// journal: to replay it, or to record a visit to it
+pub const BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// The journal for one database, on local disk
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// The journal for the database at `db_path`, in the local state (or
+    /// cache) directory, named after the database so profiles keep theirs apart
+    pub fn for_database(db_path: &Path) -> Option<Self> {
+        let dir = dirs::state_dir().or_else(dirs::cache_dir)?.join("pathranger");
+        Some(Journal { path: dir.join(format!("journal-{}", database_id(db_path))) })
+    }
+    
+    /// Where the journal is kept
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    
+    /// Whether `path` can be written as one field of a journal line
+    pub fn accepts(path: &str) -> bool {
+        !path.contains(['\t', '\n'])
+    }
+    
+    /// Append one visit, made from the directory `from` if known, with a
+    /// single O_APPEND write, so concurrent shells never interleave their lines
+    pub fn append(&self, path: &str, from: Option<&str>) -> Result<()> {
+        let io_error = |e| Error::Io(format!("Could not write {}", self.path.display()), e);
+        if let Some(dir) = self.path.parent() {
+            create_private_dir(dir).map_err(io_error)?;
+        }
+        let line = match from.filter(|from| Journal::accepts(from)) {
+            Some(from) => format!("{}\t{}\t{}\n", Local::now().to_rfc3339(), path, from),
+            None => format!("{}\t{}\n", Local::now().to_rfc3339(), path),
+        };
+        let mut file = private_file_options().create(true).append(true).open(&self.path).map_err(io_error)?;
+        file.write_all(line.as_bytes()).map_err(io_error)
+    }
+    
+    /// Where a flush moves the journal while replaying it, so new visits go
+    /// to a fresh file meanwhile
+    fn flushing_path(&self) -> PathBuf {
+        self.path.with_extension("flushing")
+    }
+    
+    /// Visits not yet in the database, one entry per directory
+    pub fn pending(&self) -> Vec<Visit> {
+        let mut contents = String::new();
+        for file in [self.flushing_path(), self.path.clone()] {
+            contents.push_str(&fs::read_to_string(file).unwrap_or_default());
+        }
+        aggregate(&contents).visits
+    }
+    
+    /// Replay the journal into `db` in one transaction and remove it.
+    /// Returns the visits that were added, or None if another process is
+    /// flushing it right now.
+    pub fn flush(&self, db: &Database) -> Result<Option<Vec<Visit>>> {
+        let flushing = self.flushing_path();
+        if !flushing.exists() && !self.path.exists() {
+            return Ok(Some(Vec::new()));
+        }
+        let Some(_lock) = FlushLock::take(&self.path.with_extension("lock")) else {
+            debug!("another process is flushing {}", self.path.display());
+            return Ok(None);
+        };
+        
+        let mut flushed = Vec::new();
+        // A journal left by a failed flush goes first, then the current one
+        for _ in 0..2 {
+            if !flushing.exists() {
+                match fs::rename(&self.path, &flushing) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => break,
+                    Err(e) => return Err(Error::Io(format!("Could not move {}", self.path.display()), e)),
+                }
+            }
+            let contents = fs::read_to_string(&flushing)
+                .map_err(|e| Error::Io(format!("Could not read {}", flushing.display()), e))?;
+            let log = aggregate(&contents);
+            db.record_visits(&log)?;
+            debug!("flushed {} directories from {}", log.visits.len(), flushing.display());
+            fs::remove_file(&flushing).map_err(|e| Error::Io(format!("Could not remove {}", flushing.display()), e))?;
+            flushed.extend(log.visits);
+        }
+        Ok(Some(flushed))
+    }
+    
+    /// Bring `db` up to date with the journal before a command uses it. If
+    /// that can't happen now, e.g. because the database is locked, and the
+    /// command lists visits (`fold_in`), the journal is folded into what it
+    /// sees so recent visits still show.
+    pub fn replay(&self, db: &Database, fold_in: bool) {
+        // Don't hold the command up for long behind another writer
+        let _ = db.set_busy_timeout(BUSY_TIMEOUT);
+        let flushed = self.flush(db);
+        let _ = db.set_busy_timeout(Duration::from_secs(5));
+        match flushed {
+            Ok(Some(_)) => return,
+            Ok(None) => {}
+            Err(e) => debug!("could not flush the journal: {}", e),
+        }
+        if !fold_in {
+            return;
+        }
+        let pending = self.pending();
+        if !pending.is_empty()
+            && let Err(e) = db.overlay_visits(&pending)
+        {
+            debug!("could not fold in the journal: {}", e);
+        }
+    }
+}
+
+/// Sum journal lines per directory, and per pair of directories for lines
+/// that say where the visit came from, keeping each one's latest time, and
+/// list every line for the visit history. Lines that aren't complete, such
+/// as a final line cut off by a crash, are skipped.
+fn aggregate(contents: &str) -> VisitLog {
+    let mut visits: HashMap<&str, Visit> = HashMap::new();
+    let mut transitions: HashMap<(&str, &str), Transition> = HashMap::new();
+    let mut history = Vec::new();
+    let complete = &contents[..contents.rfind('\n').map_or(0, |end| end + 1)];
+    for line in complete.lines() {
+        let Some((time, path)) = line.split_once('\t') else {
+            debug!("skipping malformed journal line: {}", line);
+            continue;
+        };
+        let Ok(time) = DateTime::parse_from_rfc3339(time) else {
+            debug!("skipping journal line with a bad time: {}", line);
+            continue;
+        };
+        let (path, from) = match path.split_once('\t') {
+            Some((path, from)) => (path, Some(from)),
+            None => (path, None),
+        };
+        history.push(VisitTime { path: path.to_string(), visited_at: time });
+        let time = time.with_timezone(&Local);
+        if let Some(from) = from {
+            let transition = transitions.entry((from, path)).or_insert_with(|| Transition {
+                from: from.to_string(),
+                to: path.to_string(),
+                count: 0,
+                last_moved: time,
+            });
+            transition.count += 1;
+            transition.last_moved = transition.last_moved.max(time);
+        }
+        let visit = visits.entry(path).or_insert_with(|| Visit { path: path.to_string(), count: 0, last_visited: time });
+        visit.count += 1;
+        visit.last_visited = visit.last_visited.max(time);
+    }
+    let mut visits: Vec<Visit> = visits.into_values().collect();
+    visits.sort_by(|a, b| a.path.cmp(&b.path));
+    VisitLog { visits, transitions: transitions.into_values().collect(), history }
+}
+
+/// An exclusive lock so only one process replays a journal at a time;
+/// released when dropped
+struct FlushLock {
+    #[cfg(unix)]
+    _file: fs::File,
+}
+
+impl FlushLock {
+    #[cfg(unix)]
+    fn take(path: &Path) -> Option<Self> {
+        use std::os::fd::AsRawFd;
+        
+        let file = private_file_options().create(true).truncate(false).write(true).open(path).ok()?;
+        // SAFETY: the descriptor is open for as long as `file` lives; the
+        // lock goes with it
+        let locked = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+        locked.then_some(FlushLock { _file: file })
+    }
+    
+    #[cfg(not(unix))]
+    fn take(_path: &Path) -> Option<Self> {
+        Some(FlushLock {})
+    }
+}
@@ -0,0 +1,1738 @@
+//! PathRanger's directory database: visit tracking, tags, listings and
+//! search. The `pathranger` binary is a thin CLI over this crate.
+
+pub mod config;
+pub mod daemon;
+pub mod display;
+pub mod journal;
+pub mod mounts;
+pub mod output;
+pub mod paths;
+pub mod project;
+pub mod record;
+pub mod stats;
+pub mod tagfile;
+pub mod workspace;
+
+use std::cell::{Cell, RefCell};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Instant;
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime, Timelike};
+use clap::ValueEnum;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use globset::GlobSet;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, CachedStatement, Connection, DatabaseName, ErrorCode, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use mounts::Probes;
+use paths::is_windows_path;
+
+/// Everything that can go wrong in pathranger. The CLI prints these and
+/// maps each kind to its own exit code.
+#[derive(Debug)]
+pub enum Error {
+    /// No tag has this name
+    TagNotFound(String),
+    /// No workspace has this name
+    WorkspaceNotFound(String),
+    /// A listing rank past the end of the listing
+    RankOutOfRange { rank: usize, available: usize },
+    /// A path that must be an existing directory isn't one
+    DirectoryMissing(String),
+    /// A path that must be an existing file isn't one
+    FileMissing(String),
+    /// The platform has no data directory to keep the database in
+    NoDataDir,
+    /// SQLite reported an error
+    Database(rusqlite::Error),
+    /// The database (or the directory it goes in) can't be written
+    ReadOnly { path: PathBuf, source: io::Error },
+    /// A filesystem operation failed; the string says what was being done
+    Io(String, io::Error),
+    /// An argument the command can't use
+    InvalidInput(String),
+    /// A shell the command can't produce code for
+    UnsupportedShell { shell: String, feature: &'static str, supported: &'static str },
+    /// The user declined to go ahead
+    Cancelled,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TagNotFound(tag) => write!(f, "Tag '{}' not found", tag),
+            Error::WorkspaceNotFound(name) => write!(f, "Workspace '{}' not found", name),
+            Error::RankOutOfRange { rank, available } => {
+                write!(f, "There is no entry {}: only {} directories are listed", rank, available)
+            }
+            Error::DirectoryMissing(path) => write!(f, "Directory does not exist: {}", path),
+            Error::FileMissing(path) => write!(f, "File does not exist: {}", path),
+            Error::NoDataDir => write!(f, "Could not determine data directory"),
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::ReadOnly { path, source } => write!(f, "Cannot write to {}: {}", path.display(), source),
+            Error::Io(context, e) => write!(f, "{}: {}", context, e),
+            Error::InvalidInput(message) => write!(f, "{}", message),
+            Error::UnsupportedShell { shell, feature, supported } => {
+                write!(f, "{} supports {}, not {}", feature, supported, shell)
+            }
+            Error::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(_, e) | Error::ReadOnly { source: e, .. } => Some(e),
+            Error::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Whether this is a failure to write rather than a broken database, so
+    /// callers that only read can carry on
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Error::ReadOnly { .. } => true,
+            Error::Database(rusqlite::Error::SqliteFailure(e, _)) => {
+                matches!(e.code, ErrorCode::ReadOnly | ErrorCode::CannotOpen | ErrorCode::PermissionDenied)
+            }
+            _ => false,
+        }
+    }
+    
+    /// Whether another connection holds the lock the database needed, so
+    /// trying again later may work
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            Error::Database(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(e.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        Error::Database(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A tracked directory as stored in the `directories` table, or a file from
+/// the `files` table with its opens as visits
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub path: String,
+    pub visit_count: i64,
+    /// None when the stored value isn't a timestamp pathranger can read
+    pub last_visited: Option<DateTime<Local>>,
+}
+
+/// Visits to one directory gathered outside the database, such as in a
+/// journal, to be added in one go
+#[derive(Clone, Debug)]
+pub struct Visit {
+    pub path: String,
+    pub count: i64,
+    pub last_visited: DateTime<Local>,
+}
+
+/// Moves from one directory straight to another, gathered outside the
+/// database like `Visit`
+#[derive(Clone, Debug)]
+pub struct Transition {
+    pub from: String,
+    pub to: String,
+    pub count: i64,
+    pub last_moved: DateTime<Local>,
+}
+
+/// One visit as it happened, kept in the visit history. The time keeps the
+/// UTC offset it was recorded with, so reports bucket it by the local time
+/// of the visit.
+#[derive(Clone, Debug)]
+pub struct VisitTime {
+    pub path: String,
+    pub visited_at: DateTime<FixedOffset>,
+}
+
+/// Visits gathered outside the database, such as in a journal, to be added
+/// in one go
+#[derive(Clone, Debug, Default)]
+pub struct VisitLog {
+    /// Totals per directory
+    pub visits: Vec<Visit>,
+    /// Totals per move from one directory straight to another
+    pub transitions: Vec<Transition>,
+    /// Every visit, for the visit history
+    pub history: Vec<VisitTime>,
+}
+
+/// A directory `suggest` expects to be visited next, and why
+#[derive(Clone, Debug)]
+pub struct Suggestion {
+    pub path: String,
+    pub score: f64,
+    /// Moves recorded from the current directory straight to this one
+    pub moves: i64,
+    /// Where it sits relative to the current directory, if nearby
+    pub nearby: Option<Nearby>,
+    /// Share of its visits made within an hour of the current time of day
+    pub hour_share: f64,
+}
+
+/// How a suggested directory relates to the current one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nearby {
+    Child,
+    Sibling,
+}
+
+/// A named bookmark for a directory
+#[derive(Clone, Debug, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub path: String,
+    /// When the tag was first made
+    pub created_at: Option<DateTime<Local>>,
+    /// When the tag was last marked, which may have moved it
+    pub updated_at: Option<DateTime<Local>>,
+}
+
+/// A tag whose directory no longer exists
+#[derive(Clone, Debug)]
+pub struct BrokenTag {
+    pub tag: Tag,
+    /// When its directory was last visited, if it is still tracked
+    pub last_visited: Option<DateTime<Local>>,
+    /// The one existing tracked directory with its directory's name, where
+    /// it has probably moved
+    pub moved_to: Option<String>,
+}
+
+/// What to do with a broken tag
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TagFix {
+    Remove,
+    Keep,
+    /// Point it at this directory instead
+    Move(String),
+}
+
+/// A fuzzy search hit; higher scores are better matches
+#[derive(Clone, Debug)]
+pub struct SearchMatch {
+    pub entry: DirEntry,
+    pub score: i64,
+}
+
+/// Orderings offered by `list`. Ties are always broken by path ascending, so
+/// output is deterministic; `--reverse` flips only the primary key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// Most visited first
+    Visits,
+    /// Most recently visited first
+    Recent,
+    /// Alphabetical by path
+    Alpha,
+    /// Visit count weighted by recency
+    Frecency,
+}
+
+/// Which spelling to show or record for directories reached through
+/// symlinks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathForm {
+    /// The most visited path as it was recorded (symlinks kept)
+    #[default]
+    Logical,
+    /// The canonical path with symlinks resolved
+    Physical,
+}
+
+/// Which directories a listing selects
+#[derive(Clone, Debug)]
+pub struct ListOptions {
+    pub sort: SortKey,
+    pub reverse: bool,
+    pub count: usize,
+    pub filter: Filter,
+}
+
+/// Restrictions on which tracked directories listings and search consider
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Absolute directory that results must be equal to or inside of
+    pub under: Option<String>,
+    /// Merge rows that are symlinks to the same directory, shown in this form
+    pub resolve_symlinks: Option<PathForm>,
+    /// Exclude directories that have a tag
+    pub untagged: bool,
+    /// Exclude paths with a component starting with '.'
+    pub hide_hidden: bool,
+    /// Window on last_visited: inclusive start, exclusive end. Until per-visit
+    /// history is recorded, `top` ranks by total visits of the directories
+    /// last visited in the window rather than by visits within it.
+    pub since: Option<DateTime<Local>>,
+    pub until: Option<DateTime<Local>>,
+    /// Keep directories matching the database's exclude patterns, which are
+    /// otherwise left out (see `Database::set_exclude`)
+    pub show_excluded: bool,
+}
+
+/// Which part of the tree `search` and `query` draw candidates from
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Scope<'a> {
+    /// Every tracked directory
+    #[default]
+    Everywhere,
+    /// Only directories inside this one, or every directory if none of
+    /// those match
+    Prefer(&'a Path),
+    /// Only directories inside this one
+    Only(&'a Path),
+}
+
+impl Scope<'_> {
+    /// Keep the candidates this scope allows
+    fn narrow<T>(self, candidates: Vec<T>, path: impl Fn(&T) -> &str) -> Vec<T> {
+        let (dir, fallback) = match self {
+            Scope::Everywhere => return candidates,
+            Scope::Prefer(dir) => (dir, true),
+            Scope::Only(dir) => (dir, false),
+        };
+        let (inside, outside): (Vec<T>, Vec<T>) =
+            candidates.into_iter().partition(|candidate| Path::new(path(candidate)).starts_with(dir));
+        if inside.is_empty() && fallback {
+            debug!("nothing matched inside {}, looking everywhere", dir.display());
+            return outside;
+        }
+        inside
+    }
+}
+
+/// Whether `path` or anything under it matches one of `patterns`, so
+/// `/a/**` leaves out `/a` itself as well
+pub fn is_excluded(patterns: &GlobSet, path: &str) -> bool {
+    patterns.is_match(path) || patterns.is_match(format!("{}/", path))
+}
+
+impl Filter {
+    /// SQL condition (with its parameters) selecting matching directories
+    fn where_clause(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        
+        if let Some(under) = &self.under {
+            // Component-aware prefix match: /a/b matches /a/b and /a/b/c, not
+            // /a/bc. Windows paths are stored with backslashes.
+            let separator = if is_windows_path(under) { '\\' } else { '/' };
+            values.push(Value::Text(under.clone()));
+            values.push(Value::Text(format!("{}{}", under.trim_end_matches(separator), separator)));
+            let (n, prefix) = (values.len() - 1, values.len());
+            conditions.push(format!(
+                "(path = ?{n} OR substr(path, 1, length(?{prefix})) = ?{prefix})"
+            ));
+        }
+        
+        if self.untagged {
+            conditions.push("path NOT IN (SELECT path FROM tags)".to_string());
+        }
+        
+        if self.hide_hidden {
+            // Stored paths are normalized, so "/." only appears at the start of
+            // a dot component. Only Unix paths start with '/'; elsewhere a
+            // backslash is the separator.
+            conditions.push("path NOT LIKE '%/.%' AND (path LIKE '/%' OR path NOT LIKE '%\\.%')".to_string());
+        }
+        
+        // julianday() normalizes the stored offsets before comparing
+        if let Some(since) = self.since {
+            values.push(Value::Text(since.to_rfc3339()));
+            conditions.push(format!("{} >= julianday(?{})", VISITED_JULIANDAY, values.len()));
+        }
+        if let Some(until) = self.until {
+            values.push(Value::Text(until.to_rfc3339()));
+            conditions.push(format!("{} < julianday(?{})", VISITED_JULIANDAY, values.len()));
+        }
+        
+        (conditions.join(" AND "), values)
+    }
+}
+
+/// Where listings and search read directories from
+const DIRECTORY_ROWS: &str = "directories";
+
+/// The `files` table under the column names listings and filters use
+const FILE_ROWS: &str = "(SELECT path, open_count AS visit_count, last_opened AS last_visited FROM files)";
+
+/// last_visited as a Julian day for SQL comparisons, understanding the same
+/// formats as `parse_timestamp`. NULL for values that aren't timestamps.
+const VISITED_JULIANDAY: &str = "(CASE typeof(last_visited) \
+    WHEN 'integer' THEN julianday(last_visited, 'unixepoch') \
+    ELSE julianday(last_visited) END)";
+
+/// `frecency` in SQL, for rows with their `age` in days, so they can be
+/// ranked without loading them. Unknown ages compare false: old visits.
+const FRECENCY_RANK: &str =
+    "visit_count * CASE WHEN age < 1.0 / 24 THEN 4.0 WHEN age < 1 THEN 2.0 WHEN age < 7 THEN 0.5 ELSE 0.25 END";
+
+/// How far the best `query` match must outscore the runner-up to be used
+pub const QUERY_CONFIDENCE: f64 = 2.0;
+
+/// How many of the most frecent directories `explore` looks inside
+pub const EXPLORE_PARENTS: usize = 10;
+
+/// How many entries `explore` reads from each directory at most
+pub const EXPLORE_ENTRIES: usize = 256;
+
+/// How long `explore` spends reading directories before it gives up on the
+/// rest, so a slow network mount can't hold up the shell
+pub const EXPLORE_BUDGET: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// How much `suggest` multiplies the score of children and siblings of the
+/// current directory by, less one
+pub const SUGGEST_NEARBY_BOOST: f64 = 0.5;
+
+/// How much `suggest` multiplies a score by, less one, for a directory only
+/// ever visited around the current time of day
+pub const SUGGEST_HOUR_BOOST: f64 = 0.5;
+
+/// What `suggest` adds for a directory every recorded move out of the
+/// current directory went to; a normalized frecency is at most 1
+pub const SUGGEST_MOVE_WEIGHT: f64 = 4.0;
+
+/// An open PathRanger database
+pub struct Database {
+    conn: Connection,
+    /// SQL statements run so far, for `--verbose` and profiling
+    statements: Cell<usize>,
+    /// Whether Windows paths match tracked directories ignoring case; see
+    /// `set_fold_case`
+    fold_case: Cell<bool>,
+    /// Which spelling of a directory reached through symlinks is recorded;
+    /// see `set_path_form`
+    path_form: Cell<PathForm>,
+    /// How directories are checked for existence; see `set_path_checks`
+    probes: Probes,
+    /// Directories never recorded or listed; see `set_exclude`
+    exclude: RefCell<Option<GlobSet>>,
+}
+
+impl Database {
+    /// Where the CLI keeps its database, under the platform data directory
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("pathranger").join("pathranger.db"))
+    }
+    
+    /// Open the database at `path`, creating it and its parent directories
+    /// if needed. New files and directories are private to the user.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        debug!("database: {}", path.display());
+        if !path.exists() {
+            if let Some(data_dir) = path.parent() {
+                create_private_dir(data_dir).map_err(|e| match e.kind() {
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                        Error::ReadOnly { path: data_dir.to_path_buf(), source: e }
+                    }
+                    _ => Error::Io(String::from("Could not create data directory"), e),
+                })?;
+            }
+            // SQLite would create it with the umask's permissions; its
+            // journal files copy whatever the database has. Failures show up
+            // when SQLite tries the same.
+            let _ = private_file_options().write(true).create_new(true).open(path);
+        }
+        
+        let conn = Connection::open(path).map_err(|e| {
+            let e = Error::from(e);
+            if e.is_read_only() { read_only_error(path) } else { e }
+        })?;
+        // SQLite quietly opens files it can't write read-only, which would
+        // only surface on the first write
+        if conn.is_readonly(DatabaseName::Main)? {
+            return Err(read_only_error(path));
+        }
+        
+        // Reading user_version is one page read; the DDL below costs far more,
+        // and `record` runs on every prompt
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        let db = Database::with_connection(conn);
+        if version < SCHEMA_VERSION {
+            // Under the write lock, so shells opening a new or old database at
+            // once upgrade it one at a time, and the later ones find it done
+            db.transaction(false, |db| {
+                let version: i64 = db.conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+                if version >= SCHEMA_VERSION {
+                    return Ok(());
+                }
+                debug!("upgrading schema from version {} to {}", version, SCHEMA_VERSION);
+                create_schema(&db.conn)?;
+                // Version 6 stores Windows paths in one spelling; rows recorded
+                // under several become one
+                if version > 0 && version < 6 {
+                    db.merge_variants(false)?;
+                }
+                Ok(())
+            })?;
+        }
+        
+        Ok(db)
+    }
+    
+    fn with_connection(conn: Connection) -> Self {
+        Database {
+            conn,
+            statements: Cell::new(0),
+            fold_case: Cell::new(true),
+            path_form: Cell::new(PathForm::Logical),
+            probes: Probes::default(),
+            exclude: RefCell::new(None),
+        }
+    }
+    
+    /// Open an existing database without creating or changing anything
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Database::with_connection(conn))
+    }
+    
+    /// Open an existing database for writing, failing if it doesn't exist,
+    /// and check that a write lock can be taken
+    pub fn open_existing(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        conn.execute_batch("BEGIN IMMEDIATE; ROLLBACK;")?;
+        Ok(Database::with_connection(conn))
+    }
+    
+    /// Whether the `directories` and `tags` tables both exist
+    pub fn has_schema(&self) -> Result<bool> {
+        let count: i64 = self
+            .statement("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('directories', 'tags')")?
+            .query_row([], |row| row.get(0))?;
+        Ok(count == 2)
+    }
+    
+    /// Write a consistent copy of the database to `path`, which must not exist
+    pub fn copy_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.statement("VACUUM INTO ?1")?.execute([path.as_ref().to_string_lossy()])?;
+        Ok(())
+    }
+    
+    /// Whether SQLite's integrity check finds nothing wrong with the file
+    pub fn integrity_check(&self) -> Result<bool> {
+        let result: String = self.statement("PRAGMA integrity_check")?.query_row([], |row| row.get(0))?;
+        Ok(result == "ok")
+    }
+    
+    /// Number of tracked directories
+    pub fn directory_count(&self) -> Result<usize> {
+        let count: i64 = self.statement("SELECT COUNT(*) FROM directories")?.query_row([], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+    
+    /// Number of tags
+    pub fn tag_count(&self) -> Result<usize> {
+        let count: i64 = self.statement("SELECT COUNT(*) FROM tags")?.query_row([], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+    
+    /// Prepare `sql` through the connection's statement cache, so commands
+    /// that run the same statement repeatedly only compile it once
+    fn statement(&self, sql: &str) -> Result<CachedStatement<'_>> {
+        self.statements.set(self.statements.get() + 1);
+        Ok(self.conn.prepare_cached(sql)?)
+    }
+    
+    /// How many SQL statements this connection has run
+    pub fn statement_count(&self) -> usize {
+        self.statements.get()
+    }
+    
+    /// How long to wait for another process's lock before failing with
+    /// "database is locked" (SQLite's default here is 5 seconds)
+    pub fn set_busy_timeout(&self, timeout: std::time::Duration) -> Result<()> {
+        Ok(self.conn.busy_timeout(timeout)?)
+    }
+    
+    /// Never record visits to directories matching `patterns`, and leave
+    /// them out of listings, search, `query` and `suggest`. None by default.
+    pub fn set_exclude(&self, patterns: Option<GlobSet>) {
+        self.exclude.replace(patterns);
+    }
+    
+    /// Whether `path` matches the patterns set with `set_exclude`
+    pub fn excludes(&self, path: &str) -> bool {
+        self.exclude.borrow().as_ref().is_some_and(|patterns| is_excluded(patterns, path))
+    }
+    
+    /// Run `f` in a transaction. With `dry_run` the transaction is rolled back
+    /// afterwards, so `f` does everything a real run would, including
+    /// reporting what it changed, but the database is left untouched.
+    pub fn transaction<T>(&self, dry_run: bool, f: impl FnOnce(&Database) -> Result<T>) -> Result<T> {
+        self.statement("BEGIN IMMEDIATE")?.execute([])?;
+        let result = f(self);
+        if result.is_ok() && !dry_run {
+            self.statement("COMMIT")?.execute([])?;
+        } else {
+            debug!("rolling back{}", if dry_run { " (dry run)" } else { "" });
+            self.statement("ROLLBACK")?.execute([])?;
+        }
+        result
+    }
+    
+    /// Directories whose last_visited isn't stored as RFC 3339, with the time
+    /// it was read as (None if it couldn't be read at all)
+    pub fn irregular_timestamps(&self) -> Result<Vec<(String, Option<DateTime<Local>>)>> {
+        let mut stmt = self.statement("SELECT path, last_visited FROM directories ORDER BY path")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, Value>(1)?)))?;
+        
+        let mut irregular = Vec::new();
+        for row in rows {
+            let (path, value) = row?;
+            let is_rfc3339 = matches!(&value, Value::Text(text) if DateTime::parse_from_rfc3339(text).is_ok());
+            if !is_rfc3339 {
+                irregular.push((path, parse_timestamp(&value)));
+            }
+        }
+        Ok(irregular)
+    }
+    
+    /// Rewrite every irregular last_visited as RFC 3339. Values that can't be
+    /// read become the Unix epoch, so they rank as the oldest visits. Returns
+    /// the number of rows rewritten.
+    pub fn repair_timestamps(&self) -> Result<usize> {
+        let irregular = self.irregular_timestamps()?;
+        self.transaction(false, |db| {
+            let mut update = db.statement("UPDATE directories SET last_visited = ?1 WHERE path = ?2")?;
+            for (path, time) in &irregular {
+                let time = time.unwrap_or_else(|| DateTime::UNIX_EPOCH.with_timezone(&Local));
+                update.execute(params![time.to_rfc3339(), path])?;
+                debug!("rewrote last_visited for {}", path);
+            }
+            Ok(irregular.len())
+        })
+    }
+    
+    /// Count a visit to `path`, which must be an existing directory
+    pub fn record_visit(&self, path: &str) -> Result<()> {
+        self.record_visit_from(path, None)
+    }
+    
+    /// Count a visit to `path` made straight from the directory `from`, so
+    /// `suggest` learns where you tend to go next. Excluded directories
+    /// aren't counted.
+    pub fn record_visit_from(&self, path: &str, from: Option<&str>) -> Result<()> {
+        if self.excludes(path) {
+            debug!("not recording {}: it matches an exclude pattern", path);
+            return Ok(());
+        }
+        if !self.dir_exists(Path::new(path)) {
+            return Err(Error::DirectoryMissing(path.to_string()));
+        }
+        
+        let now = Local::now();
+        self.transaction(false, |db| {
+            let path = db.canonical_path(path)?;
+            db.count_visit(&path, now)?;
+            if let Some(from) = from.filter(|from| !db.excludes(from)) {
+                let transition = Transition { from: db.canonical_path(from)?, to: path, count: 1, last_moved: now };
+                db.apply_transitions(&[transition])?;
+            }
+            Ok(())
+        })
+    }
+    
+    /// Count an open of the file `path`, which must exist, and a visit to
+    /// the directory it is in. Files in excluded directories aren't counted.
+    pub fn record_file(&self, path: &str) -> Result<()> {
+        if self.excludes(path) {
+            debug!("not recording {}: it matches an exclude pattern", path);
+            return Ok(());
+        }
+        let file = Path::new(path);
+        if !file.is_file() {
+            return Err(Error::FileMissing(path.to_string()));
+        }
+        let parent = file
+            .parent()
+            .and_then(Path::to_str)
+            .filter(|parent| !parent.is_empty() && !self.excludes(parent));
+        
+        let now = Local::now();
+        self.transaction(false, |db| {
+            db.statement(
+                "INSERT INTO files (path, open_count, last_opened) VALUES (?1, 1, ?2)
+                ON CONFLICT (path) DO UPDATE SET open_count = open_count + 1, last_opened = excluded.last_opened",
+            )?
+            .execute(params![path, now.to_rfc3339()])?;
+            match parent {
+                Some(parent) => db.count_visit(&db.canonical_path(parent)?, now),
+                None => Ok(()),
+            }
+        })
+    }
+    
+    /// Add one visit to `path` at `now`, to its total and to the history
+    fn count_visit(&self, path: &str, now: DateTime<Local>) -> Result<()> {
+        // Try to update existing entry
+        let rows_affected = self
+            .statement("UPDATE directories SET visit_count = visit_count + 1, last_visited = ?1 WHERE path = ?2")?
+            .execute(params![now.to_rfc3339(), path])?;
+        
+        // If no rows were affected, insert a new entry
+        if rows_affected == 0 {
+            self.statement("INSERT INTO directories (path, visit_count, last_visited) VALUES (?1, 1, ?2)")?
+                .execute(params![path, now.to_rfc3339()])?;
+            debug!("inserted new directory {}", path);
+        } else {
+            debug!("updated {} row(s) for {}", rows_affected, path);
+        }
+        
+        self.apply_history(&[VisitTime { path: path.to_string(), visited_at: now.fixed_offset() }])
+    }
+    
+    /// Add everything in `log` in a single transaction. Unlike
+    /// `record_visit`, the directories aren't checked, since they existed
+    /// when visited; excluded ones are still left out.
+    pub fn record_visits(&self, log: &VisitLog) -> Result<()> {
+        self.transaction(false, |db| {
+            db.apply_visits(&log.visits)?;
+            db.apply_history(&log.history)?;
+            db.apply_transitions(&log.transitions)
+        })
+    }
+    
+    /// Start tracking the directories in `visits` that aren't tracked yet,
+    /// leaving existing rows alone. Returns how many were new. Run it inside
+    /// `transaction` when adding many.
+    pub fn add_directories(&self, visits: &[Visit]) -> Result<usize> {
+        let mut insert =
+            self.statement("INSERT OR IGNORE INTO directories (path, visit_count, last_visited) VALUES (?1, ?2, ?3)")?;
+        let mut added = 0;
+        for visit in visits.iter().filter(|visit| !self.excludes(&visit.path)) {
+            let path = self.canonical_path(&visit.path)?;
+            added += insert.execute(params![path, visit.count, visit.last_visited.to_rfc3339()])?;
+        }
+        Ok(added)
+    }
+    
+    /// Show `visits` to this connection's listings without writing them: a
+    /// temporary copy of the directories table shadows the real one. Only
+    /// for connections that won't write, since writes would land in the copy.
+    pub fn overlay_visits(&self, visits: &[Visit]) -> Result<()> {
+        debug!("folding {} unrecorded directories into listings", visits.len());
+        self.statement("CREATE TEMP TABLE directories AS SELECT * FROM main.directories")?.execute([])?;
+        self.apply_visits(visits)
+    }
+    
+    fn apply_visits(&self, visits: &[Visit]) -> Result<()> {
+        // A newer visit recorded directly, e.g. from another machine, keeps its time
+        let mut update = self.statement(&format!(
+            "UPDATE directories SET visit_count = visit_count + ?1,
+                last_visited = CASE WHEN {0} >= julianday(?2) THEN last_visited ELSE ?2 END
+            WHERE path = ?3",
+            VISITED_JULIANDAY
+        ))?;
+        let mut insert = self.statement("INSERT INTO directories (path, visit_count, last_visited) VALUES (?1, ?2, ?3)")?;
+        for visit in visits.iter().filter(|visit| !self.excludes(&visit.path)) {
+            let path = self.canonical_path(&visit.path)?;
+            let last_visited = visit.last_visited.to_rfc3339();
+            if update.execute(params![visit.count, last_visited, path])? == 0 {
+                insert.execute(params![path, visit.count, last_visited])?;
+            }
+        }
+        Ok(())
+    }
+    
+    fn apply_history(&self, history: &[VisitTime]) -> Result<()> {
+        let mut insert = self.statement("INSERT INTO visits (path, visited_at) VALUES (?1, ?2)")?;
+        let mut upsert = self.statement(
+            "INSERT INTO visit_hours (path, hour, count) VALUES (?1, ?2, 1)
+            ON CONFLICT (path, hour) DO UPDATE SET count = count + 1",
+        )?;
+        for visit in history.iter().filter(|visit| !self.excludes(&visit.path)) {
+            let path = self.canonical_path(&visit.path)?;
+            insert.execute(params![path, visit.visited_at.to_rfc3339()])?;
+            upsert.execute(params![path, visit.visited_at.hour()])?;
+        }
+        Ok(())
+    }
+    
+    fn apply_transitions(&self, transitions: &[Transition]) -> Result<()> {
+        let mut upsert = self.statement(
+            "INSERT INTO transitions (from_path, to_path, count, last_moved) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (from_path, to_path) DO UPDATE SET
+                count = count + excluded.count,
+                last_moved = max(last_moved, excluded.last_moved)",
+        )?;
+        for transition in transitions.iter().filter(|moved| !self.excludes(&moved.from) && !self.excludes(&moved.to)) {
+            let (from, to) = (self.canonical_path(&transition.from)?, self.canonical_path(&transition.to)?);
+            if from != to {
+                upsert.execute(params![from, to, transition.count, transition.last_moved.to_rfc3339()])?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Point `tag` at `path` and record a visit there, both or neither.
+    /// Returns true if the tag already existed and was moved.
+    pub fn mark(&self, tag: &str, path: &str) -> Result<bool> {
+        if !self.dir_exists(Path::new(path)) {
+            return Err(Error::DirectoryMissing(path.to_string()));
+        }
+        
+        let now = Local::now();
+        self.transaction(false, |db| {
+            let path = db.canonical_path(path)?;
+            let exists = db.statement("SELECT id FROM tags WHERE name = ?1")?.exists(params![tag])?;
+            
+            if exists {
+                db.statement("UPDATE tags SET path = ?1, updated_at = ?2 WHERE name = ?3")?
+                    .execute(params![path, now.to_rfc3339(), tag])?;
+                debug!("re-pointed existing tag '{}' to {}", tag, path);
+            } else {
+                db.statement("INSERT INTO tags (name, path, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)")?
+                    .execute(params![tag, path, now.to_rfc3339()])?;
+                debug!("inserted new tag '{}' for {}", tag, path);
+            }
+            
+            db.count_visit(&path, now)?;
+            Ok(exists)
+        })
+    }
+    
+    /// Create `tag` for `path` unless a tag by that name exists, which is
+    /// left as it is. Returns whether the tag was created.
+    pub fn add_tag(&self, tag: &str, path: &str) -> Result<bool> {
+        let now = Local::now().to_rfc3339();
+        let inserted = self
+            .statement("INSERT OR IGNORE INTO tags (name, path, created_at, updated_at) VALUES (?1, ?2, ?3, ?3)")?
+            .execute(params![tag, self.canonical_path(path)?, now])?;
+        Ok(inserted > 0)
+    }
+    
+    /// The directory `tag` points at, if the tag exists
+    pub fn resolve_tag(&self, tag: &str) -> Result<Option<String>> {
+        let path = self.statement("SELECT path FROM tags WHERE name = ?1")?.query_row(params![tag], |row| row.get(0));
+        match path {
+            Ok(path) => Ok(Some(path)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    /// The tag on `path`, or on the nearest directory above it, and the
+    /// directory it points at; of several tags on one directory, the first by
+    /// name. Compares spellings only, without touching the filesystem.
+    pub fn innermost_tag(&self, path: &str) -> Result<Option<(String, String)>> {
+        // Older SQLite builds allow at most 999 parameters
+        let ancestors: Vec<String> =
+            Path::new(path).ancestors().take(500).map(|dir| dir.to_string_lossy().into_owned()).collect();
+        let placeholders = vec!["?"; ancestors.len()].join(", ");
+        let tag = self
+            .statement(&format!(
+                "SELECT name, path FROM tags WHERE path IN ({}) ORDER BY length(path) DESC, name LIMIT 1",
+                placeholders
+            ))?
+            .query_row(params_from_iter(&ancestors), |row| Ok((row.get(0)?, row.get(1)?)));
+        match tag {
+            Ok(tag) => Ok(Some(tag)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+    
+    /// Point `tag` at `path` without counting a visit there, as when its
+    /// directory has moved. Returns false if there was no such tag.
+    pub fn move_tag(&self, tag: &str, path: &str) -> Result<bool> {
+        let rows_affected = self
+            .statement("UPDATE tags SET path = ?1, updated_at = ?2 WHERE name = ?3")?
+            .execute(params![self.canonical_path(path)?, Local::now().to_rfc3339(), tag])?;
+        Ok(rows_affected > 0)
+    }
+    
+    /// Delete `tag`. Returns false if there was no such tag.
+    pub fn remove_tag(&self, tag: &str) -> Result<bool> {
+        let rows_affected = self.statement("DELETE FROM tags WHERE name = ?1")?.execute(params![tag])?;
+        debug!("deleted {} tag row(s)", rows_affected);
+        Ok(rows_affected > 0)
+    }
+    
+    /// The tags whose directories no longer exist, sorted by name. A tag
+    /// has moved when exactly one existing tracked directory has its
+    /// directory's name.
+    pub fn broken_tags(&self) -> Result<Vec<BrokenTag>> {
+        let broken: Vec<Tag> = self.tags()?.into_iter().filter(|tag| !Path::new(&tag.path).is_dir()).collect();
+        if broken.is_empty() {
+            return Ok(Vec::new());
+        }
+        
+        let options = ListOptions { sort: SortKey::Alpha, reverse: false, count: i64::MAX as usize, filter: Filter::default() };
+        let tracked = self.list(&options)?;
+        let last_visits: HashMap<&str, Option<DateTime<Local>>> =
+            tracked.iter().map(|entry| (entry.path.as_str(), entry.last_visited)).collect();
+        let mut by_name: HashMap<&OsStr, Vec<&str>> = HashMap::new();
+        for entry in &tracked {
+            if let Some(name) = Path::new(&entry.path).file_name() {
+                by_name.entry(name).or_default().push(&entry.path);
+            }
+        }
+        let moved_to = |tag: &Tag| {
+            let candidates = by_name.get(Path::new(&tag.path).file_name()?)?;
+            match candidates.iter().filter(|path| Path::new(path).is_dir()).collect::<Vec<_>>().as_slice() {
+                [only] => Some(only.to_string()),
+                _ => None,
+            }
+        };
+        Ok(broken
+            .into_iter()
+            .map(|tag| BrokenTag {
+                last_visited: last_visits.get(tag.path.as_str()).copied().flatten(),
+                moved_to: moved_to(&tag),
+                tag,
+            })
+            .collect())
+    }
+    
+    /// Apply a fix to each tag named. Returns how many were removed and how
+    /// many moved.
+    pub fn fix_tags(&self, fixes: &[(&str, TagFix)]) -> Result<(usize, usize)> {
+        let (mut removed, mut moved) = (0, 0);
+        for (tag, fix) in fixes {
+            match fix {
+                TagFix::Remove => removed += usize::from(self.remove_tag(tag)?),
+                TagFix::Move(to) => moved += usize::from(self.move_tag(tag, to)?),
+                TagFix::Keep => {}
+            }
+        }
+        Ok((removed, moved))
+    }
+    
+    /// Every tag, sorted by name
+    pub fn tags(&self) -> Result<Vec<Tag>> {
+        let mut stmt = self.statement("SELECT name, path, created_at, updated_at FROM tags ORDER BY name")?;
+        let tags = stmt.query_map([], |row| {
+            Ok(Tag {
+                name: row.get(0)?,
+                path: row.get(1)?,
+                created_at: parse_timestamp(&row.get(2)?),
+                updated_at: parse_timestamp(&row.get(3)?),
+            })
+        })?;
+        Ok(tags.collect::<rusqlite::Result<_>>()?)
+    }
+    
+    /// Names of the tags pointing at each path, sorted by name
+    pub fn tags_by_path(&self) -> Result<HashMap<String, Vec<String>>> {
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        for tag in self.tags()? {
+            tags.entry(tag.path).or_default().push(tag.name);
+        }
+        Ok(tags)
+    }
+    
+    /// Names of the tags pointing at each of `paths`, sorted by name. One
+    /// query per few hundred paths, however many are listed.
+    pub fn tags_for_paths<'a>(&self, paths: impl IntoIterator<Item = &'a str>) -> Result<HashMap<String, Vec<String>>> {
+        let paths: Vec<&str> = paths.into_iter().collect();
+        let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+        // Older SQLite builds allow at most 999 parameters
+        for chunk in paths.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(", ");
+            let mut stmt = self.statement(&format!(
+                "SELECT path, name FROM tags WHERE path IN ({}) ORDER BY name",
+                placeholders
+            ))?;
+            let rows = stmt.query_map(params_from_iter(chunk), |row| Ok((row.get(0)?, row.get(1)?)))?;
+            for row in rows {
+                let (path, name): (String, String) = row?;
+                tags.entry(path).or_default().push(name);
+            }
+        }
+        Ok(tags)
+    }
+    
+    /// Tag names starting with `prefix`, sorted
+    pub fn tags_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        // A range rather than LIKE so the unique index on name is used; U+10FFFF
+        // sorts after every character that can follow the prefix
+        let upper = format!("{}\u{10FFFF}", prefix);
+        let mut stmt = self.statement("SELECT name FROM tags WHERE name >= ?1 AND name < ?2 ORDER BY name")?;
+        let names = stmt.query_map(params![prefix, upper], |row| row.get(0))?;
+        Ok(names.collect::<rusqlite::Result<_>>()?)
+    }
+    
+    /// Every tracked directory, sorted by path. Rows that fail to load are
+    /// reported on stderr and skipped.
+    pub fn entries(&self) -> Result<Vec<DirEntry>> {
+        self.query_entries("SELECT path, visit_count, last_visited FROM directories ORDER BY path", [])
+    }
+    
+    /// The `count` most visited directories
+    pub fn top(&self, count: usize) -> Result<Vec<DirEntry>> {
+        self.list(&ListOptions { sort: SortKey::Visits, reverse: false, count, filter: Filter::default() })
+    }
+    
+    /// The `count` most recently visited directories
+    pub fn recent(&self, count: usize) -> Result<Vec<DirEntry>> {
+        self.list(&ListOptions { sort: SortKey::Recent, reverse: false, count, filter: Filter::default() })
+    }
+    
+    /// The directories a listing shows, in order
+    pub fn list(&self, options: &ListOptions) -> Result<Vec<DirEntry>> {
+        self.list_rows(DIRECTORY_ROWS, options)
+    }
+    
+    /// The files a listing shows, in order
+    pub fn files(&self, options: &ListOptions) -> Result<Vec<DirEntry>> {
+        self.list_rows(FILE_ROWS, options)
+    }
+    
+    /// Whether `filter` leaves out the directories matching the exclude
+    /// patterns, and there are any
+    fn applies_exclude(&self, filter: &Filter) -> bool {
+        !filter.show_excluded && self.exclude.borrow().is_some()
+    }
+    
+    fn list_rows(&self, rows: &str, options: &ListOptions) -> Result<Vec<DirEntry>> {
+        let (condition, mut values) = options.filter.where_clause();
+        
+        // Frecency is computed here, merged symlink rows have new counts and
+        // excludes are globs, so all need every candidate row ranked in Rust
+        let exclude = self.applies_exclude(&options.filter);
+        if options.sort == SortKey::Frecency || options.filter.resolve_symlinks.is_some() || exclude {
+            debug!("loading every matching row to rank in Rust");
+            let mut entries = self.query_entries(
+                &format!(
+                    "SELECT path, visit_count, last_visited FROM {} WHERE {} ORDER BY path ASC",
+                    rows, condition
+                ),
+                params_from_iter(values),
+            )?;
+            if exclude {
+                entries.retain(|entry| !self.excludes(&entry.path));
+            }
+            
+            if let Some(form) = options.filter.resolve_symlinks {
+                entries = merge_symlinked(entries, form);
+            }
+            sort_entries(&mut entries, options.sort, options.reverse);
+            entries.truncate(options.count);
+            return Ok(entries);
+        }
+        
+        let direction = if options.reverse { "ASC" } else { "DESC" };
+        let order_by = match options.sort {
+            SortKey::Visits => format!("visit_count {}, path ASC", direction),
+            // Unreadable timestamps sort last whichever way the list goes
+            SortKey::Recent => format!(
+                "{0} IS NULL, {0} {1}, path ASC",
+                VISITED_JULIANDAY, direction
+            ),
+            SortKey::Alpha => format!("path {}", if options.reverse { "DESC" } else { "ASC" }),
+            SortKey::Frecency => unreachable!("frecency is ranked above"),
+        };
+        
+        values.push(Value::Integer(options.count as i64));
+        self.query_entries(
+            &format!(
+                "SELECT path, visit_count, last_visited FROM {} WHERE {} ORDER BY {} LIMIT ?{}",
+                rows,
+                condition,
+                order_by,
+                values.len()
+            ),
+            params_from_iter(values),
+        )
+    }
+    
+    /// Directories whose paths fuzzy-match `query`, best match first
+    pub fn search(&self, query: &str, filter: &Filter) -> Result<Vec<SearchMatch>> {
+        self.search_in(query, filter, Scope::Everywhere)
+    }
+    
+    /// `search` among the directories `scope` allows
+    pub fn search_in(&self, query: &str, filter: &Filter, scope: Scope) -> Result<Vec<SearchMatch>> {
+        self.search_rows(DIRECTORY_ROWS, query, filter, scope)
+    }
+    
+    /// Files whose paths fuzzy-match `query` among those `scope` allows,
+    /// best match first
+    pub fn search_files(&self, query: &str, filter: &Filter, scope: Scope) -> Result<Vec<SearchMatch>> {
+        self.search_rows(FILE_ROWS, query, filter, scope)
+    }
+    
+    fn search_rows(&self, rows: &str, query: &str, filter: &Filter, scope: Scope) -> Result<Vec<SearchMatch>> {
+        let (condition, values) = filter.where_clause();
+        let mut entries = self.query_entries(
+            &format!("SELECT path, visit_count, last_visited FROM {} WHERE {} ORDER BY path", rows, condition),
+            params_from_iter(values),
+        )?;
+        if self.applies_exclude(filter) {
+            entries.retain(|entry| !self.excludes(&entry.path));
+        }
+        if let Some(form) = filter.resolve_symlinks {
+            entries = merge_symlinked(entries, form);
+        }
+        
+        // Paths under the home directory also match in their ~ form, so a
+        // query can be written either way
+        let home = dirs::home_dir().filter(|home| home.parent().is_some());
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<SearchMatch> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                let folded = home.as_deref().and_then(|home| fold_home(&entry.path, home));
+                let score = [Some(entry.path.as_str()), folded.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|path| matcher.fuzzy_match(path, query))
+                    .max()?;
+                Some(SearchMatch { entry, score })
+            })
+            .collect();
+        matches = scope.narrow(matches, |m| &m.entry.path);
+        
+        // Sort by score (descending)
+        matches.sort_by_key(|m| Reverse(m.score));
+        Ok(matches)
+    }
+    
+    /// Up to `count` tracked directories starting with `prefix` (ignoring
+    /// ASCII case) that `filter` keeps, by frecency
+    pub fn dirs_matching(&self, prefix: &str, filter: &Filter, count: usize) -> Result<Vec<String>> {
+        let (condition, mut values) = filter.where_clause();
+        // The whole pattern is one parameter so the NOCASE index serves LIKE
+        let escaped = prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        values.push(Value::Text(format!("{}%", escaped)));
+        let pattern = values.len();
+        // Excludes are globs, checked here, so rows are read until enough pass
+        let exclude = self.applies_exclude(filter);
+        values.push(Value::Integer(if exclude { -1 } else { count as i64 }));
+        
+        let mut stmt = self.statement(&format!(
+            "SELECT path, {} AS rank FROM (
+                SELECT path, visit_count, julianday('now') - {} AS age FROM directories
+                WHERE path LIKE ?{} ESCAPE '\\' AND {}
+            )
+            ORDER BY rank DESC, path ASC LIMIT ?{}",
+            FRECENCY_RANK,
+            VISITED_JULIANDAY,
+            pattern,
+            condition,
+            values.len()
+        ))?;
+        let rows = stmt.query_map(params_from_iter(values), |row| row.get::<_, String>(0))?;
+        let mut paths = Vec::new();
+        for path in rows {
+            let path = path?;
+            if exclude && self.excludes(&path) {
+                continue;
+            }
+            paths.push(path);
+            if paths.len() == count {
+                break;
+            }
+        }
+        Ok(paths)
+    }
+    
+    /// Find the directory `name` most likely means: its last component must
+    /// contain `name` (ignoring case), an exact match counts double, and the
+    /// winner must lead the runner-up by QUERY_CONFIDENCE in frecency. The
+    /// current directory is never a candidate, so asking again from the
+    /// winner gives the runner-up, if it in turn clearly leads the rest.
+    /// Returns None when no candidate is that clear, so shells can fall back
+    /// safely.
+    pub fn query(&self, name: &str) -> Result<Option<String>> {
+        self.query_in(name, std::env::current_dir().ok().as_deref(), Scope::Everywhere, &[])
+    }
+    
+    /// `query` on behalf of a process whose current directory is `cwd`,
+    /// among the directories `scope` allows, leaving out `exclude` as well
+    pub fn query_in(&self, name: &str, cwd: Option<&Path>, scope: Scope, exclude: &[&Path]) -> Result<Option<String>> {
+        let entries = self.candidates()?;
+        let needle = name.to_lowercase();
+        let now = Local::now();
+        
+        let mut scored: Vec<(f64, String)> = entries
+            .into_iter()
+            .filter_map(|entry| {
+                let base = Path::new(&entry.path).file_name()?.to_string_lossy().to_lowercase();
+                if !base.contains(&needle) {
+                    return None;
+                }
+                let weight = if base == needle { 2.0 } else { 1.0 };
+                Some((frecency(&entry, now) * weight, entry.path))
+            })
+            .filter(|(_, path)| {
+                let path = Path::new(path);
+                cwd != Some(path) && !exclude.contains(&path) && self.dir_exists(path)
+            })
+            .collect();
+        scored = scope.narrow(scored, |(_, path)| path);
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        
+        Ok(match scored.as_slice() {
+            [] => None,
+            [(_, path)] => Some(path.clone()),
+            [(best, path), (second, _), ..] => (*best >= second * QUERY_CONFIDENCE).then(|| path.clone()),
+        })
+    }
+    
+    /// Look for `name` among the directories never visited that sit right
+    /// inside the EXPLORE_PARENTS most frecent ones, such as a repository
+    /// just cloned into ~/work. Their names are fuzzy-matched against `name`,
+    /// an exact name counts double, and the best must lead the runner-up by
+    /// QUERY_CONFIDENCE. Reads are bounded by EXPLORE_ENTRIES and
+    /// EXPLORE_BUDGET, so this is for when `query_in` found nothing.
+    pub fn explore(&self, name: &str, cwd: Option<&Path>, scope: Scope, exclude: &[&Path]) -> Result<Option<String>> {
+        let entries = self.candidates()?;
+        let tracked: HashSet<String> = entries.iter().map(|entry| entry.path.clone()).collect();
+        let now = Local::now();
+        let mut parents: Vec<(f64, String)> =
+            entries.into_iter().map(|entry| (frecency(&entry, now), entry.path)).collect();
+        parents.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let parents = parents.into_iter().take(EXPLORE_PARENTS).map(|(_, path)| PathBuf::from(path)).collect();
+        
+        let needle = name.to_lowercase();
+        let matcher = SkimMatcherV2::default();
+        let mut scored: Vec<(i64, String)> = read_children(parents)
+            .into_iter()
+            .filter(|child| {
+                let path = Path::new(child);
+                !tracked.contains(child) && cwd != Some(path) && !exclude.contains(&path) && !self.excludes(child)
+            })
+            .filter_map(|child| {
+                let base = Path::new(&child).file_name()?.to_string_lossy().to_lowercase();
+                let score = matcher.fuzzy_match(&base, &needle)?;
+                Some((if base == needle { score * 2 } else { score }, child))
+            })
+            .collect();
+        scored = scope.narrow(scored, |(_, path)| path);
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        debug!("{} unvisited directories match '{}'", scored.len(), name);
+        
+        Ok(match scored.as_slice() {
+            [] => None,
+            [(_, path)] => Some(path.clone()),
+            [(best, path), (second, _), ..] => (*best as f64 >= *second as f64 * QUERY_CONFIDENCE).then(|| path.clone()),
+        })
+    }
+    
+    /// The `count` directories most likely to be visited next from `cwd`,
+    /// best first. Overall frecency is the base, boosted for children and
+    /// siblings of `cwd` and for directories usually visited around this
+    /// hour; moves recorded from `cwd` add on top. `cwd` itself and
+    /// directories that no longer exist are left out.
+    pub fn suggest(&self, cwd: &Path, count: usize) -> Result<Vec<Suggestion>> {
+        let entries = self.candidates()?;
+        let now = Local::now();
+        
+        let mut moves: HashMap<String, i64> = HashMap::new();
+        let mut stmt = self.statement("SELECT to_path, count FROM transitions WHERE from_path = ?1")?;
+        for row in stmt.query_map(params![cwd.to_string_lossy()], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (to, count) = row?;
+            moves.insert(to, count);
+        }
+        let moves_total: i64 = moves.values().sum();
+        
+        let hour = now.hour() as i64;
+        let mut hours: HashMap<String, f64> = HashMap::new();
+        let mut stmt = self.statement(
+            "SELECT path, SUM(CASE WHEN hour IN (?1, ?2, ?3) THEN count ELSE 0 END), SUM(count)
+            FROM visit_hours GROUP BY path",
+        )?;
+        let window = params![(hour + 23) % 24, hour, (hour + 1) % 24];
+        for row in stmt.query_map(window, |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, i64>(2)?)))? {
+            let (path, near, total) = row?;
+            if total > 0 {
+                hours.insert(path, near as f64 / total as f64);
+            }
+        }
+        
+        let frecencies: Vec<f64> = entries.iter().map(|entry| frecency(entry, now)).collect();
+        let max_frecency = frecencies.iter().copied().fold(0.0, f64::max).max(f64::MIN_POSITIVE);
+        let parent = cwd.parent();
+        let mut suggestions: Vec<Suggestion> = entries
+            .into_iter()
+            .zip(frecencies)
+            .filter(|(entry, _)| Path::new(&entry.path) != cwd)
+            .map(|(entry, frecency)| {
+                let entry_parent = Path::new(&entry.path).parent();
+                let nearby = if entry_parent == Some(cwd) {
+                    Some(Nearby::Child)
+                } else if entry_parent.is_some() && entry_parent == parent {
+                    Some(Nearby::Sibling)
+                } else {
+                    None
+                };
+                let moves = moves.get(&entry.path).copied().unwrap_or(0);
+                let hour_share = hours.get(&entry.path).copied().unwrap_or(0.0);
+                
+                let mut score = frecency / max_frecency;
+                if nearby.is_some() {
+                    score *= 1.0 + SUGGEST_NEARBY_BOOST;
+                }
+                score *= 1.0 + SUGGEST_HOUR_BOOST * hour_share;
+                if moves_total > 0 {
+                    score += SUGGEST_MOVE_WEIGHT * moves as f64 / moves_total as f64;
+                }
+                Suggestion { path: entry.path, score, moves, nearby, hour_share }
+            })
+            .collect();
+        suggestions.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.path.cmp(&b.path)));
+        
+        Ok(suggestions.into_iter().filter(|suggestion| self.dir_exists(Path::new(&suggestion.path))).take(count).collect())
+    }
+    
+    /// The `count` parent directories whose direct children carry the most
+    /// frecency. Parents as broad as / or the home directory, and paths that
+    /// can't be written in a CDPATH (containing ':'), are left out.
+    pub fn frequent_parents(&self, count: usize) -> Result<Vec<String>> {
+        let entries = self.candidates()?;
+        let now = Local::now();
+        let mut weights: HashMap<&Path, f64> = HashMap::new();
+        for entry in &entries {
+            if let Some(parent) = Path::new(&entry.path).parent() {
+                *weights.entry(parent).or_default() += frecency(entry, now);
+            }
+        }
+        
+        let home = dirs::home_dir();
+        let mut parents: Vec<(&Path, f64)> = weights
+            .into_iter()
+            .filter(|(parent, _)| parent.parent().is_some())
+            .filter(|(parent, _)| !home.as_deref().is_some_and(|home| home.starts_with(parent)))
+            .filter(|(parent, _)| !parent.to_string_lossy().contains(':'))
+            .collect();
+        parents.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        
+        Ok(parents
+            .into_iter()
+            .filter(|(parent, _)| self.dir_exists(parent))
+            .take(count)
+            .map(|(parent, _)| parent.to_string_lossy().into_owned())
+            .collect())
+    }
+    
+    /// Every tracked directory `query`, `explore`, `suggest` and `cdpath`
+    /// may offer: all but the excluded ones, in no particular order
+    fn candidates(&self) -> Result<Vec<DirEntry>> {
+        let mut entries = self.query_entries("SELECT path, visit_count, last_visited FROM directories", [])?;
+        if self.exclude.borrow().is_some() {
+            entries.retain(|entry| !self.excludes(&entry.path));
+        }
+        Ok(entries)
+    }
+    
+    /// Run a `SELECT path, visit_count, last_visited ...` query, reporting and
+    /// skipping rows that fail to load
+    fn query_entries(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<DirEntry>> {
+        debug!("query: {}", sql);
+        let mut stmt = self.statement(sql)?;
+        let rows = stmt.query_map(params, dir_entry_from_row)?;
+        
+        let mut entries = Vec::new();
+        for row in rows {
+            match row {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Error: {}", e),
+            }
+        }
+        
+        debug!("loaded {} row(s)", entries.len());
+        Ok(entries)
+    }
+}
+
+/// A short name for the database at `db_path` that is the same in every
+/// build, for files kept per database elsewhere (daemon sockets, journals)
+pub fn database_id(db_path: &Path) -> String {
+    // 64-bit FNV-1a, since std's hasher may change between releases
+    let hash = db_path
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+/// Permissions for pathranger's own directories on Unix: the history in
+/// them is nobody else's business
+#[cfg(unix)]
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
+
+/// Permissions for pathranger's own files on Unix
+#[cfg(unix)]
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Create `dir` and any missing parents, the new ones accessible only to
+/// the user
+pub fn create_private_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, PRIVATE_DIR_MODE);
+    builder.create(dir)
+}
+
+/// Options that create files readable and writable only by the user
+pub fn private_file_options() -> fs::OpenOptions {
+    let mut options = fs::OpenOptions::new();
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, PRIVATE_FILE_MODE);
+    options
+}
+
+/// Whether the group or others can get at `path` at all
+#[cfg(unix)]
+pub fn is_shared(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    
+    Ok(fs::metadata(path)?.permissions().mode() & 0o077 != 0)
+}
+
+/// Take the group's and others' permissions off `path`. Returns false if
+/// it had none to begin with.
+#[cfg(unix)]
+pub fn make_private(path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+    
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 == 0 {
+        return Ok(false);
+    }
+    fs::set_permissions(path, fs::Permissions::from_mode(mode & !0o077))?;
+    Ok(true)
+}
+
+/// Describe why `path` can't be written. SQLite doesn't keep the errno, so
+/// ask the filesystem directly.
+fn read_only_error(path: &Path) -> Error {
+    let probe = if path.exists() {
+        fs::OpenOptions::new().append(true).open(path)
+    } else {
+        fs::OpenOptions::new().append(true).create(true).open(path)
+    };
+    let source = probe
+        .err()
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "the database is read-only"));
+    Error::ReadOnly { path: path.to_path_buf(), source }
+}
+
+/// Bump whenever `create_schema` changes, so existing databases pick it up
+const SCHEMA_VERSION: i64 = 7;
+
+/// Create the tables and bring older databases up to date, then record
+/// SCHEMA_VERSION. Every step must be safe to repeat.
+fn create_schema(conn: &Connection) -> Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS directories (
+            id INTEGER PRIMARY KEY,
+            path TEXT UNIQUE NOT NULL,
+            visit_count INTEGER NOT NULL DEFAULT 1,
+            last_visited DATETIME NOT NULL
+        )",
+        [],
+    )?;
+    
+    // Windows paths are looked up ignoring case with `set_fold_case`
+    conn.execute("CREATE INDEX IF NOT EXISTS directories_by_folded_path ON directories (path COLLATE NOCASE)", [])?;
+    
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            path TEXT NOT NULL,
+            created_at DATETIME NOT NULL,
+            updated_at DATETIME
+        )",
+        [],
+    )?;
+    // `prompt` looks up the tags on a directory and its parents at every prompt
+    conn.execute("CREATE INDEX IF NOT EXISTS tags_by_path ON tags (path)", [])?;
+    
+    // Where each directory was reached from, for `suggest`
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS transitions (
+            from_path TEXT NOT NULL,
+            to_path TEXT NOT NULL,
+            count INTEGER NOT NULL DEFAULT 1,
+            last_moved DATETIME NOT NULL,
+            PRIMARY KEY (from_path, to_path)
+        )",
+        [],
+    )?;
+    
+    // Visits per directory and hour of day (0-23), for `suggest`
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS visit_hours (
+            path TEXT NOT NULL,
+            hour INTEGER NOT NULL,
+            count INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (path, hour)
+        )",
+        [],
+    )?;
+    
+    // Every visit with the time it happened, recorded from schema version 3
+    // on; earlier visits only exist in the directories totals
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS visits (
+            id INTEGER PRIMARY KEY,
+            path TEXT NOT NULL,
+            visited_at DATETIME NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS visits_by_time ON visits (julianday(visited_at));",
+    )?;
+    
+    // Files opened, kept apart from directories so they are never jump
+    // candidates
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS files (
+            id INTEGER PRIMARY KEY,
+            path TEXT UNIQUE NOT NULL,
+            open_count INTEGER NOT NULL DEFAULT 1,
+            last_opened DATETIME NOT NULL
+        )",
+        [],
+    )?;
+    
+    // Workspaces list tags, in order. The trigger drops a deleted tag from
+    // every workspace, whichever program deletes it.
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS workspaces (
+            id INTEGER PRIMARY KEY,
+            name TEXT UNIQUE NOT NULL,
+            created_at DATETIME NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS workspace_tags (
+            workspace_id INTEGER NOT NULL REFERENCES workspaces (id),
+            tag_id INTEGER NOT NULL REFERENCES tags (id),
+            position INTEGER NOT NULL,
+            PRIMARY KEY (workspace_id, tag_id)
+        );
+        CREATE TRIGGER IF NOT EXISTS workspace_tags_cleanup AFTER DELETE ON tags BEGIN
+            DELETE FROM workspace_tags WHERE tag_id = old.id;
+        END;",
+    )?;
+    
+    // Databases from before updated_at existed get it, starting out equal
+    // to created_at
+    let has_updated_at = conn
+        .prepare("SELECT 1 FROM pragma_table_info('tags') WHERE name = 'updated_at'")?
+        .exists([])?;
+    if !has_updated_at {
+        debug!("adding tags.updated_at");
+        conn.execute_batch(
+            "ALTER TABLE tags ADD COLUMN updated_at DATETIME;
+            UPDATE tags SET updated_at = created_at;",
+        )?;
+    }
+    
+    conn.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+    Ok(())
+}
+
+fn dir_entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<DirEntry> {
+    let path: String = row.get(0)?;
+    let visit_count: i64 = row.get(1)?;
+    let last_visited = parse_timestamp(&row.get::<_, Value>(2)?);
+    if last_visited.is_none() {
+        debug!("unreadable last_visited for {}", path);
+    }
+    
+    Ok(DirEntry { path, visit_count, last_visited })
+}
+
+/// Read a stored timestamp: RFC 3339 as pathranger writes it, SQLite's
+/// CURRENT_TIMESTAMP format (UTC), or Unix seconds. None for anything else.
+pub fn parse_timestamp(value: &Value) -> Option<DateTime<Local>> {
+    match value {
+        Value::Text(text) => {
+            let text = text.trim();
+            if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+                return Some(time.with_timezone(&Local));
+            }
+            ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+                .map(|time| time.and_utc().with_timezone(&Local))
+        }
+        Value::Integer(seconds) => DateTime::from_timestamp(*seconds, 0).map(|time| time.with_timezone(&Local)),
+        _ => None,
+    }
+}
+
+/// `path` with `home` replaced by `~`, if it is inside `home`
+fn fold_home(path: &str, home: &Path) -> Option<String> {
+    let rest = Path::new(path).strip_prefix(home).ok()?;
+    if rest.as_os_str().is_empty() {
+        return Some(String::from("~"));
+    }
+    Some(format!("~{}{}", std::path::MAIN_SEPARATOR, rest.to_string_lossy()))
+}
+
+/// The subdirectories of `parents`, reading at most EXPLORE_ENTRIES entries
+/// of each and skipping hidden ones, gathered for no longer than
+/// EXPLORE_BUDGET. The reads run on a thread that is left behind when time
+/// runs out, since a read from a hung network mount can't be interrupted.
+fn read_children(parents: Vec<PathBuf>) -> Vec<String> {
+    let deadline = Instant::now() + EXPLORE_BUDGET;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for parent in parents {
+            let Ok(dir) = fs::read_dir(&parent) else {
+                continue;
+            };
+            let children: Vec<String> = dir
+                .take(EXPLORE_ENTRIES)
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+                .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                .map(|entry| entry.path().to_string_lossy().into_owned())
+                .collect();
+            if sender.send(children).is_err() {
+                return;
+            }
+        }
+    });
+    
+    let mut children = Vec::new();
+    loop {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(found) => children.extend(found),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                debug!("stopped exploring after {:?}", EXPLORE_BUDGET);
+                break;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    children
+}
+
+/// Visit count weighted by how recently the directory was used, so a burst of
+/// visits long ago ranks below steady recent use
+pub fn frecency(entry: &DirEntry, now: DateTime<Local>) -> f64 {
+    // A visit of unknown age counts as an old one
+    let age = entry.last_visited.map_or(Duration::MAX, |time| now.signed_duration_since(time));
+    let weight = if age < Duration::hours(1) {
+        4.0
+    } else if age < Duration::days(1) {
+        2.0
+    } else if age < Duration::weeks(1) {
+        0.5
+    } else {
+        0.25
+    };
+    
+    entry.visit_count as f64 * weight
+}
+
+/// Rust equivalent of the listing ORDER BY clauses, for rows that are ranked
+/// after loading. Entries must arrive sorted by path so the stable sort breaks
+/// ties by path ascending.
+fn sort_entries(entries: &mut [DirEntry], sort: SortKey, reverse: bool) {
+    let now = Local::now();
+    entries.sort_by(|a, b| {
+        let ordering = match sort {
+            SortKey::Visits => b.visit_count.cmp(&a.visit_count),
+            // Unknown times last, even when reversed
+            SortKey::Recent => match (a.last_visited, b.last_visited) {
+                (Some(a), Some(b)) => b.cmp(&a),
+                (a, b) => return a.is_none().cmp(&b.is_none()),
+            },
+            SortKey::Alpha => a.path.cmp(&b.path),
+            SortKey::Frecency => frecency(b, now).total_cmp(&frecency(a, now)),
+        };
+        if reverse { ordering.reverse() } else { ordering }
+    });
+}
+
+/// Merge rows whose paths resolve to the same directory, summing their visit
+/// counts and keeping the latest visit. Only affects what is displayed; the
+/// database keeps the separate rows. Paths that can't be resolved (e.g. no
+/// longer exist) are kept as they are. The result is sorted by path.
+fn merge_symlinked(entries: Vec<DirEntry>, form: PathForm) -> Vec<DirEntry> {
+    let mut groups: HashMap<PathBuf, Vec<DirEntry>> = HashMap::new();
+    for entry in entries {
+        let target = fs::canonicalize(&entry.path).unwrap_or_else(|_| PathBuf::from(&entry.path));
+        groups.entry(target).or_default().push(entry);
+    }
+    
+    let mut merged: Vec<DirEntry> = groups
+        .into_iter()
+        .map(|(target, mut group)| {
+            // Most visited spelling first, by path among equals
+            group.sort_by(|a, b| b.visit_count.cmp(&a.visit_count).then_with(|| a.path.cmp(&b.path)));
+            let visit_count = group.iter().map(|entry| entry.visit_count).sum();
+            let last_visited = group.iter().filter_map(|entry| entry.last_visited).max();
+            let path = match form {
+                PathForm::Logical => group.swap_remove(0).path,
+                PathForm::Physical => target.to_string_lossy().into_owned(),
+            };
+            DirEntry { path, visit_count, last_visited }
+        })
+        .collect();
+    
+    merged.sort_by(|a, b| a.path.cmp(&b.path));
+    merged
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    //! What the unit tests share
+    
+    use chrono::Local;
+    use tempfile::TempDir;
+    
+    use crate::{Database, Visit, VisitLog};
+    
+    /// A fresh database in a temporary directory, removed with the directory
+    pub fn fixture() -> (TempDir, Database) {
+        let dir = tempfile::tempdir().expect("create a temporary directory");
+        let db = Database::open(dir.path().join("pathranger.db")).expect("open the fixture database");
+        (dir, db)
+    }
+    
+    /// Make each of `repositories` under the fixture directory `dir` a
+    /// repository, holding `.git`, and record `visits` to directories under
+    /// it as of now. Returns the full path of a name under `dir`.
+    pub fn repositories(
+        dir: &TempDir,
+        db: &Database,
+        repositories: &[&str],
+        visits: &[(&str, i64)],
+    ) -> impl Fn(&str) -> String {
+        let root = dir.path().canonicalize().expect("resolve the fixture directory");
+        for repository in repositories {
+            std::fs::create_dir_all(root.join(repository).join(".git")).expect("make a fixture repository");
+        }
+        let path = move |name: &str| root.join(name).to_string_lossy().into_owned();
+        let now = Local::now();
+        let visits = visits.iter().map(|(name, count)| Visit { path: path(name), count: *count, last_visited: now }).collect();
+        db.record_visits(&VisitLog { visits, ..VisitLog::default() }).expect("seed the fixture database");
+        path
+    }
+}
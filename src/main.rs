@@ -1,540 +1,5837 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
 use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process;
-
-use ansi_term::Colour::{Blue, Green, Yellow};
-use chrono::{DateTime, Local};
-use clap::{Parser, Subcommand};
-use dirs::home_dir;
-use fuzzy_matcher::FuzzyMatcher;
-use fuzzy_matcher::skim::SkimMatcherV2;
-use rusqlite::{params, Connection, Result};
+use std::process::{Child, Command, ExitCode, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use ansi_term::Colour::{Blue, Fixed, Green, Red, Yellow};
+use ansi_term::Style;
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use clap::{ArgGroup, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use scan::ScanOptions;
+use pathranger::config::{
+    self, env_value, ColorChoice, Config, DataDirStyle, PathStyle, DEFAULT_CONFIRM_THRESHOLD, DEFAULT_COUNT, DEFAULT_IDLE_TIMEOUT,
+};
+use pathranger::daemon;
+use pathranger::display::{
+    current_dir_forms, escape_control, escape_field, format_last_visited, format_path, format_size, relative_path,
+    column_widths, pad_to_width, truncate_middle, TimeFormat, MIN_PATH_WIDTH,
+};
+use pathranger::journal::{self, Journal};
+use pathranger::mounts::PathChecks;
+use pathranger::output::{self, Verbosity};
+use pathranger::project::{self, CwdProject, DEFAULT_ROOT_MARKERS};
+use pathranger::paths::{
+    is_windows_path, normalize_windows_path, resolve_user_path, stored_path, windows_to_wsl, wsl_to_windows,
+};
+use pathranger::record::{self, recording_disabled};
+use pathranger::stats::{DirectoryVisits, Heatmap, Report};
+use pathranger::tagfile::{Origin, TagFiles};
+use pathranger::{
+    debug, note, warn, BrokenTag, Database, DirEntry, Error, Filter, ListOptions, Nearby, Result, SearchMatch, SortKey, TagFix,
+};
+use serde::Serialize;
 use shellexpand::tilde;
+use terminal_size::{terminal_size, Width};
+use unicode_width::UnicodeWidthStr;
+
+mod clipboard;
+mod rpc;
+mod scan;
+#[cfg(feature = "serve")]
+mod serve;
 
+/// A file system navigation enhancement tool
+///
+/// pathranger records the directories you visit through its shell
+/// integration (see `pathranger init`) and ranks them by how often and how
+/// recently you went there. Tag a directory with `mark` and jump back with
+/// `goto`, or let `query` find a visited directory from part of its name;
+/// `top`, `recent` and `search` list what it knows.
+///
+/// Settings are read from config.toml in pathranger's config directory
+/// (`pathranger config show` prints them all), and each can be overridden
+/// with a PATHRANGER_<SECTION>_<KEY> environment variable.
+///
+/// Exit status: 0 on success; 1 when nothing was found, such as an unknown
+/// tag or no confident match; 2 when the database can't be opened, read or
+/// written; 3 when a directory that must exist doesn't; 64 for invalid
+/// arguments; 74 when reading or writing a file failed.
 #[derive(Parser)]
 #[command(name = "pathranger")]
-#[command(about = "A file system navigation enhancement tool", long_about = None)]
 #[command(version = "0.1.0")]
 struct Cli {
+    /// When to use colors: auto colors only when stdout is a terminal
+    #[arg(long, global = true, value_enum)]
+    color: Option<ColorChoice>,
+    
+    /// Disable colors (same as --color never)
+    #[arg(long, global = true, conflicts_with = "color")]
+    no_color: bool,
+    
+    /// Show paths relative to the current directory
+    #[arg(long, global = true)]
+    relative: bool,
+    
+    /// Print full paths even when they are wider than the terminal
+    #[arg(long, global = true)]
+    no_truncate: bool,
+    
+    /// Show times with this strftime pattern, or "relative" for "3 hours ago"
+    #[arg(long, global = true, value_name = "FORMAT")]
+    time_format: Option<TimeFormat>,
+    
+    /// Never pipe long listings through $PAGER
+    #[arg(long, global = true)]
+    no_pager: bool,
+    
+    /// Print only the command's output and errors, no confirmations or warnings
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    
+    /// Explain what pathranger is doing on stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    
+    /// Use this database file instead of the default location
+    #[arg(long, global = true, value_name = "FILE")]
+    db: Option<PathBuf>,
+    
+    /// Use the named profile's database, kept apart from all others
+    #[arg(long, global = true, value_name = "NAME", conflicts_with = "db")]
+    profile: Option<String>,
+    
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+/// Print the settings in effect as TOML, optionally noting where each one
+/// came from
+fn show_config(config: &Config, sources: bool) {
+    for key in config::KEYS {
+        let line = match config::value(config, key) {
+            Some(value) => format!("{} = {}", key, value),
+            None => format!("# {} is not set", key),
+        };
+        if sources {
+            println!("{:<48} # {}", line, config::source(config, key));
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Print the value in effect for `key`, with its source on a terminal.
+/// Returns false if the key has no value.
+fn get_config(config: &Config, theme: &Theme, key: &str) -> Result<bool> {
+    config::check_key(key)?;
+    let Some(value) = config::value(config, key) else {
+        warn!("{} is not set", key);
+        return Ok(false);
+    };
+    
+    // Print strings bare, so `$(pathranger config get database)` works
+    let value = match value {
+        toml::Value::String(text) => text,
+        other => other.to_string(),
+    };
+    if theme.decorated {
+        println!("{} {}", value, theme.paint(Style::new().dimmed(), format!("({})", config::source(config, key))));
+    } else {
+        println!("{}", value);
+    }
+    Ok(true)
+}
+
+fn init_config(theme: &Theme, force: bool) -> Result<()> {
+    let path = config::init(force)?;
+    note!("Wrote {}", theme.path(format_path(&path.to_string_lossy())));
+    Ok(())
+}
+
+/// Set `key` in the config file and say where it went
+fn set_config(theme: &Theme, key: &str, raw: &str) -> Result<()> {
+    let (path, value) = config::set(key, raw)?;
+    note!("Set {} = {} in {}", key, value, theme.path(format_path(&path.to_string_lossy())));
+    let var = config::env_var(key);
+    if env_value(&var).is_some() {
+        warn!("${} is set and overrides this setting", var);
+    }
+    Ok(())
+}
+
+/// All terminal styling goes through here, so color decisions live in one place
+struct Theme {
+    /// Emit ANSI colors
+    color: bool,
+    /// Print titles, column headers and aligned columns. When stdout is not a
+    /// terminal, listings fall back to tab-separated rows without headers.
+    decorated: bool,
+    /// Terminal width in cells that decorated paths are truncated to, if any
+    width: Option<usize>,
+    /// How listed paths are spelled
+    path_style: PathStyle,
+    /// The logical ($PWD) and physical forms of the current directory, which
+    /// relative paths are computed against
+    cwd: Vec<PathBuf>,
+    /// How times are shown, from --time-format or the config
+    time_format: TimeFormat,
+    /// The pattern --absolute uses in place of relative times
+    absolute_format: TimeFormat,
+}
+
+impl Theme {
+    /// Resolve the color mode: `--no-color`/`--color` flags first, then the
+    /// NO_COLOR environment variable, then the config file, then TTY detection.
+    fn new(cli: &Cli, config: &Config) -> Self {
+        let tty = io::stdout().is_terminal();
+        let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        
+        let choice = if cli.no_color {
+            ColorChoice::Never
+        } else if let Some(choice) = cli.color {
+            choice
+        } else if no_color_env {
+            ColorChoice::Never
+        } else {
+            config.color.unwrap_or(ColorChoice::Auto)
+        };
+        
+        let color = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => tty,
+        };
+        
+        let width = if tty && !cli.no_truncate {
+            terminal_size().map(|(Width(w), _)| w as usize)
+        } else {
+            None
+        };
+        
+        let path_style = if cli.relative {
+            PathStyle::Relative
+        } else {
+            config.display.path_style.unwrap_or_default()
+        };
+        
+        let time_format = match (&cli.time_format, &config.display.time_format) {
+            (Some(format), _) | (None, Some(format)) => format.clone(),
+            (None, None) if config.display.relative_time.unwrap_or(true) => TimeFormat::Relative,
+            (None, None) => TimeFormat::default_pattern(),
+        };
+        // What --absolute shows: the first pattern given, or the default
+        let absolute_format = [&cli.time_format, &config.display.time_format]
+            .into_iter()
+            .flatten()
+            .find(|format| matches!(format, TimeFormat::Pattern(_)))
+            .cloned()
+            .unwrap_or_else(TimeFormat::default_pattern);
+        
+        Theme { color, decorated: tty, width, path_style, cwd: current_dir_forms(), time_format, absolute_format }
+    }
+    
+    /// How a listing shows times; its --absolute swaps relative times for a
+    /// pattern
+    fn time_format(&self, absolute: bool) -> &TimeFormat {
+        if absolute && self.time_format == TimeFormat::Relative { &self.absolute_format } else { &self.time_format }
+    }
+    
+    /// Spell a stored absolute path for display according to the path style
+    fn display_path(&self, path: &str) -> String {
+        let path = Path::new(path);
+        let inside = || self.cwd.iter().find_map(|cwd| path.strip_prefix(cwd).ok().map(Path::to_path_buf));
+        let relative = match self.path_style {
+            PathStyle::Tilde => None,
+            PathStyle::Auto => inside(),
+            PathStyle::Relative => inside().or_else(|| self.cwd.first().and_then(|cwd| relative_path(path, cwd))),
+        };
+        
+        let shown = match relative {
+            Some(relative) if relative.as_os_str().is_empty() => ".".to_string(),
+            Some(relative) => relative.to_string_lossy().into_owned(),
+            None => format_path(&path.to_string_lossy()),
+        };
+        escape_control(&shown).into_owned()
+    }
+    
+    /// Fit a path into what is left of the terminal line after `used` cells of
+    /// fixed columns. Paths are only shortened when a terminal width is known.
+    fn fit_path(&self, path: &str, used: usize) -> String {
+        match self.width {
+            Some(width) => truncate_middle(path, width.saturating_sub(used).max(MIN_PATH_WIDTH)),
+            None => path.to_string(),
+        }
+    }
+    
+    fn paint(&self, style: Style, text: impl AsRef<str>) -> String {
+        if self.color {
+            style.paint(text.as_ref()).to_string()
+        } else {
+            text.as_ref().to_string()
+        }
+    }
+    
+    fn path(&self, text: impl AsRef<str>) -> String {
+        self.paint(Blue.normal(), escape_control(text.as_ref()))
+    }
+    
+    fn tag(&self, text: impl AsRef<str>) -> String {
+        self.paint(Green.bold(), text)
+    }
+    
+    fn count(&self, text: impl AsRef<str>) -> String {
+        self.paint(Yellow.normal(), text)
+    }
+    
+    fn cell(&self, kind: CellKind, text: &str) -> String {
+        match kind {
+            CellKind::Plain => text.to_string(),
+            CellKind::Count => self.count(text),
+            CellKind::Tag => self.tag(text),
+            CellKind::Path => self.path(text),
+        }
+    }
+}
+
+/// Result filters shared by the listing commands and search
+#[derive(Args, Default)]
+struct FilterArgs {
+    /// Only include this directory and its descendants ("." for the current directory)
+    #[arg(long, value_name = "PATH")]
+    under: Option<String>,
+    
+    /// Only include directories last visited at or after this time: a
+    /// duration ago ("7d", "3h") or a date/time ("2025-01-01", "2025-01-01T09:00")
+    #[arg(long, value_name = "WHEN", value_parser = parse_since)]
+    since: Option<DateTime<Local>>,
+    
+    /// Only include directories last visited before this time (same formats
+    /// as --since; a bare date includes that whole day)
+    #[arg(long, value_name = "WHEN", value_parser = parse_until)]
+    until: Option<DateTime<Local>>,
+    
+    /// Merge entries that are symlinks to the same directory (display only)
+    #[arg(long)]
+    resolve_symlinks: bool,
+    
+    /// Show symlinked entries separately even if the config merges them
+    #[arg(long, conflicts_with = "resolve_symlinks")]
+    no_resolve: bool,
+    
+    /// Only include directories that no tag points at
+    #[arg(long)]
+    untagged: bool,
+    
+    /// Hide directories inside hidden (dot) directories, like ~/.cache/...
+    #[arg(long)]
+    no_hidden: bool,
+    
+    /// Show everything, including directories hidden by --no-hidden, exclude
+    /// patterns or the config
+    #[arg(long)]
+    all: bool,
+}
+
+const TIME_FORMATS_HELP: &str =
+    "expected a duration like 7d, 12h or 30min, or a date like 2025-01-15 or 2025-01-15T09:30";
+
+/// Parse a --since value; bare dates mean the start of that day
+fn parse_since(value: &str) -> std::result::Result<DateTime<Local>, String> {
+    parse_time_bound(value, false)
+}
+
+/// Parse an --until value; bare dates mean the end of that day
+fn parse_until(value: &str) -> std::result::Result<DateTime<Local>, String> {
+    parse_time_bound(value, true)
+}
+
+fn parse_time_bound(value: &str, end_of_day: bool) -> std::result::Result<DateTime<Local>, String> {
+    if let Ok(duration) = humantime::parse_duration(value) {
+        let duration = Duration::from_std(duration).map_err(|_| "duration is too large".to_string())?;
+        return Ok(Local::now() - duration);
+    }
+    
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Local));
+    }
+    
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(value, format) {
+            return local_time(time).ok_or_else(|| format!("{} does not exist in the local timezone", value));
+        }
+    }
+    
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let date = if end_of_day { date.succ_opt().unwrap_or(date) } else { date };
+        return local_time(date.and_time(NaiveTime::MIN))
+            .ok_or_else(|| format!("{} does not exist in the local timezone", value));
+    }
+    
+    Err(TIME_FORMATS_HELP.to_string())
+}
+
+fn local_time(time: NaiveDateTime) -> Option<DateTime<Local>> {
+    time.and_local_timezone(Local).earliest()
+}
+
+/// Machine-friendly output flags shared by the listing commands
+#[derive(Args)]
+struct OutputArgs {
+    /// Print only paths, NUL-separated (for xargs -0 and fzf --read0)
+    #[arg(short = '0', long)]
+    print0: bool,
+    
+    /// Print only absolute paths, one per line
+    #[arg(long, conflicts_with = "print0")]
+    paths_only: bool,
+    
+    /// Print each result using a template, e.g. '{rank}\t{count}\t{path}'.
+    /// Fields: {rank} {path} {path_abs} {path_tilde} {count} {last_visited}
+    /// {last_visited_relative} {score} (search only) {tags}
+    #[arg(long, conflicts_with_all = ["print0", "paths_only"])]
+    format: Option<String>,
+    
+    /// Print a format for scripts that stays the same within a version
+    ///
+    /// Version 1 prints one line per result, with tab-separated fields and no
+    /// header or colors: RANK, VISITS, LAST_VISITED in Unix seconds ("-" if
+    /// unknown) and PATH, and in search SCORE before PATH. Backslashes,
+    /// tabs, newlines and other control characters are written as \\, \t,
+    /// \n and \xNN. Fields are only ever added in a new version.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "VERSION",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "v1",
+        conflicts_with_all = ["print0", "paths_only", "format"]
+    )]
+    porcelain: Option<Porcelain>,
+}
+
+impl OutputArgs {
+    fn is_human(&self) -> bool {
+        !self.print0 && !self.paths_only && self.format.is_none() && self.porcelain.is_none()
+    }
+}
+
+/// Versions of the `--porcelain` format. A version never changes once
+/// released; new fields go in a new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Porcelain {
+    V1,
+}
+
+/// Shared by commands that delete or overwrite data
+#[derive(Args)]
+struct DestructiveArgs {
+    /// Show what would change without changing anything
+    #[arg(long)]
+    dry_run: bool,
+    
+    /// Don't ask for confirmation, however much would change
+    #[arg(short, long)]
+    yes: bool,
+}
+
+#[derive(Subcommand)]
+enum HooksAction {
+    /// Print the after_goto commands as code for `shell` to evaluate
+    Print {
+        /// Shell to print code for
+        #[arg(short, long, value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum WorkspaceAction {
+    /// Create a workspace from tags, in the order given
+    Create {
+        /// Workspace name
+        name: String,
+        
+        /// Tags of the member directories
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    
+    /// List the workspaces and their tags
+    List,
+    
+    /// Show a workspace's tags and the directories they point at
+    Show {
+        /// Workspace name
+        name: String,
+    },
+    
+    /// Print the member directories one per line, e.g. to open a tmux pane
+    /// or editor window for each
+    Open {
+        /// Workspace name
+        name: String,
+    },
+    
+    /// Add a tag to the end of a workspace
+    Add {
+        /// Workspace name
+        name: String,
+        
+        /// Tag to add
+        tag: String,
+    },
+    
+    /// Take a tag out of a workspace, keeping the tag
+    Remove {
+        /// Workspace name
+        name: String,
+        
+        /// Tag to take out
+        tag: String,
+    },
+    
+    /// Delete a workspace, keeping its tags
+    Delete {
+        /// Workspace name
+        name: String,
+    },
+}
+
+impl WorkspaceAction {
+    fn is_mutating(&self) -> bool {
+        !matches!(self, WorkspaceAction::List | WorkspaceAction::Show { .. } | WorkspaceAction::Open { .. })
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every setting in effect, including built-in defaults, as TOML
+    Show {
+        /// Note after each setting whether it came from the environment, the
+        /// config file or the defaults
+        #[arg(long)]
+        sources: bool,
+    },
+    
+    /// Write a commented config file listing every setting
+    Init {
+        /// Replace an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Print the value in effect for a setting, e.g. `top.count`
+    Get {
+        key: String,
+    },
+    
+    /// Change a setting in the config file, keeping its comments
+    Set {
+        key: String,
+        
+        /// A TOML value; anything that isn't valid TOML is taken as a string
+        value: String,
+    },
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Mark current directory with a tag
+    ///
+    /// Marking a tag that already exists moves it here, keeping its creation
+    /// date. Without a tag, one is suggested from the directory name, lower
+    /// case with spaces turned into dashes and numbered if taken; a terminal
+    /// is asked to confirm or edit it, and elsewhere the suggestion is
+    /// printed and nothing is created unless --auto is given.
     Mark {
-        /// Tag name
-        tag: String,
+        /// Tag name [default: suggested from the directory name]
+        tag: Option<String>,
+        
+        /// Take the suggested name without asking, numbering it if taken
+        #[arg(long, conflicts_with = "tag")]
+        auto: bool,
     },
     
     /// Jump to a tagged directory
+    ///
+    /// Prints the directory and counts a visit to it; the `pr` wrapper from
+    /// `pathranger init` changes to it and runs the after_goto hooks. An
+    /// argument written like a path (starting with `.`, `/` or `~`, or
+    /// containing a `/`) that names an existing directory is used as it is,
+    /// ahead of a tag with the same name. --top N and --recent N go to an
+    /// entry of those listings instead, as `pr N` does for --top.
+    ///
+    /// A tag whose directory no longer exists fails with exit status 3, and
+    /// an unknown tag with exit status 1.
+    #[command(group(ArgGroup::new("target").required(true).args(["tag", "top", "recent"])))]
     Goto {
-        /// Tag name
-        tag: String,
+        /// Tag name, or a path to an existing directory
+        tag: Option<String>,
+        
+        /// Go to the Nth entry of `pathranger top` instead of a tag
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+        
+        /// Go to the Nth entry of `pathranger recent` instead of a tag
+        #[arg(long, value_name = "N")]
+        recent: Option<usize>,
+        
+        /// Go N directories above the target instead [N: 1]
+        #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "1")]
+        parent: Option<usize>,
+        
+        /// Always read the argument as a tag, even when it looks like a path
+        #[arg(long)]
+        no_passthrough: bool,
+        
+        /// Don't run the after_goto hooks (read by the shell integration)
+        #[arg(long)]
+        no_hooks: bool,
+        
+        /// Also copy the directory to the system clipboard
+        #[arg(long)]
+        copy: bool,
+        
+        /// Print the directory as Windows or WSL sees it, for scripts that
+        /// hand it to the other side; the visit is recorded as usual
+        #[arg(long = "as", value_enum, value_name = "FORM")]
+        as_form: Option<PathSystem>,
+        
+        /// Answer from the database alone, without checking that
+        /// directories still exist
+        #[arg(long)]
+        offline: bool,
     },
     
     /// Add current directory to tracked paths
     Add,
     
+    /// Track every directory under ROOT, so jumps and search have
+    /// candidates before you have visited them. Respects .gitignore and
+    /// the exclude patterns, and skips hidden directories.
+    Scan {
+        /// Directory to walk
+        root: PathBuf,
+        
+        /// Don't descend more than this many levels below ROOT
+        #[arg(long, value_name = "N")]
+        max_depth: Option<usize>,
+        
+        /// Only add directories at least this many levels below ROOT (1
+        /// skips ROOT itself)
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        min_depth: usize,
+        
+        /// Tag each git, Mercurial or Subversion root found after its
+        /// directory name, unless it already has a tag
+        #[arg(long)]
+        autotag: bool,
+        
+        /// Show what would be added and tagged without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
     /// List your most visited directories
+    ///
+    /// Ranked by visit count, ties broken by path. Directories matching the
+    /// `exclude` patterns are left out unless --all is given. Listings longer
+    /// than the screen go through $PATHRANGER_PAGER, $PAGER or less; when
+    /// stdout isn't a terminal, rows are printed tab-separated without
+    /// headers or colors.
     Top {
-        /// Number of directories to show
-        #[arg(short, long, default_value_t = 10)]
-        count: usize,
+        /// Number of directories to show [default: 10, or `count` in the [top] config section]
+        #[arg(short, long)]
+        count: Option<usize>,
+        
+        /// Show absolute timestamps instead of "3 hours ago"
+        #[arg(long)]
+        absolute: bool,
+        
+        /// Group results under their common parent directories
+        #[arg(long, conflicts_with_all = ["print0", "paths_only", "format", "porcelain"])]
+        tree: bool,
+        
+        /// Draw the tree with ASCII instead of box-drawing characters
+        #[arg(long, requires = "tree")]
+        ascii: bool,
+        
+        #[command(flatten)]
+        filter: FilterArgs,
+        
+        #[command(flatten)]
+        output: OutputArgs,
     },
     
     /// Show recently visited directories
+    ///
+    /// Ordered by last visit, newest first, and filtered and printed the same
+    /// way as `top`.
     Recent {
-        /// Number of directories to show
-        #[arg(short, long, default_value_t = 10)]
-        count: usize,
+        /// Number of directories to show [default: 10, or `count` in the [recent] config section]
+        #[arg(short, long)]
+        count: Option<usize>,
+        
+        /// Show absolute timestamps instead of "3 hours ago"
+        #[arg(long)]
+        absolute: bool,
+        
+        #[command(flatten)]
+        filter: FilterArgs,
+        
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    
+    /// Show files recorded with record-file, most recently opened first
+    #[command(group(ArgGroup::new("order").args(["recent", "top"])))]
+    Files {
+        /// List the most recently opened files (the default)
+        #[arg(long)]
+        recent: bool,
+        
+        /// List the most often opened files
+        #[arg(long)]
+        top: bool,
+        
+        /// Number of files to show
+        #[arg(short, long)]
+        count: Option<usize>,
+        
+        /// Show absolute timestamps instead of "3 hours ago"
+        #[arg(long)]
+        absolute: bool,
+        
+        #[command(flatten)]
+        filter: FilterArgs,
+        
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    
+    /// List tracked directories in any order
+    List {
+        /// Order to list directories in (ties are broken by path) [default: visits, or `sort` in
+        /// the [list] config section]
+        #[arg(short, long, value_enum)]
+        sort: Option<SortKey>,
+        
+        /// Reverse the sort order
+        #[arg(short, long)]
+        reverse: bool,
+        
+        /// Number of directories to show [default: 10, or `count` in the [list] config section]
+        #[arg(short, long)]
+        count: Option<usize>,
+        
+        /// Show absolute timestamps instead of "3 hours ago"
+        #[arg(long)]
+        absolute: bool,
+        
+        /// Group results under their common parent directories
+        #[arg(long, conflicts_with_all = ["print0", "paths_only", "format", "porcelain"])]
+        tree: bool,
+        
+        /// Draw the tree with ASCII instead of box-drawing characters
+        #[arg(long, requires = "tree")]
+        ascii: bool,
+        
+        #[command(flatten)]
+        filter: FilterArgs,
+        
+        #[command(flatten)]
+        output: OutputArgs,
     },
     
     /// Search across your visited directories
+    ///
+    /// Matches QUERY against the paths of tracked directories, including
+    /// their ~/... form, and lists the matches by score. A query starting
+    /// with `~` or holding $VAR or ${VAR} is expanded as the shell would.
+    /// Inside a git, Mercurial or Subversion checkout, directories in it are
+    /// preferred (see --cwd-project).
     Search {
         /// Text to search for
         query: String,
+        
+        /// Number of matches to show [default: 10, or `limit` in the [search] config section]
+        #[arg(short, long)]
+        limit: Option<usize>,
+        
+        /// Inside a version control checkout, prefer its directories
+        /// ("auto"), consider only them ("only") or ignore it ("off")
+        /// [default: "auto", or `cwd_project` in the config]
+        #[arg(long, value_enum, value_name = "MODE")]
+        cwd_project: Option<CwdProject>,
+        
+        /// Search the files recorded with record-file instead of directories
+        #[arg(long)]
+        files: bool,
+        
+        #[command(flatten)]
+        filter: FilterArgs,
+        
+        #[command(flatten)]
+        output: OutputArgs,
     },
     
     /// List all tags
+    ///
+    /// Lists your own tags, those shared through a `.pathranger.toml` in the
+    /// current repository and the read-only `system_tags`, with the
+    /// directories they point at. The export flags print the tags as shell
+    /// aliases, variables or fish abbreviations to source instead.
+    #[command(group(ArgGroup::new("format").args(["paths_only", "porcelain", "zsh_hash", "export_aliases", "export_vars", "fish_abbr"])))]
+    #[command(group(ArgGroup::new("export_kind").args(["export_aliases", "export_vars", "fish_abbr"]).multiple(false)))]
+    Tags {
+        /// Print only the tag target paths, one per line
+        #[arg(long)]
+        paths_only: bool,
+        
+        /// Print a format for scripts that stays the same within a version
+        ///
+        /// Version 1 prints one line per tag, with tab-separated fields and no
+        /// header or colors: NAME, SOURCE ("user", "project" or "system") and
+        /// PATH. Backslashes, tabs, newlines and other control characters are
+        /// written as \\, \t, \n and \xNN. Fields are only ever added in a new
+        /// version.
+        #[arg(long, value_enum, value_name = "VERSION", num_args = 0..=1, require_equals = true, default_missing_value = "v1")]
+        porcelain: Option<Porcelain>,
+        
+        /// Group tags under the directories their targets have in common
+        #[arg(long, conflicts_with_all = ["paths_only", "porcelain", "zsh_hash", "export_aliases", "export_vars", "fish_abbr"])]
+        tree: bool,
+        
+        /// Draw the tree with ASCII instead of box-drawing characters
+        #[arg(long, requires = "tree")]
+        ascii: bool,
+        
+        /// List tags whose directories no longer exist, then remove, keep or
+        /// move them
+        #[arg(long, conflicts_with_all = ["paths_only", "porcelain", "tree", "zsh_hash", "export_aliases", "export_vars", "fish_abbr"])]
+        clean: bool,
+        
+        /// With --clean, remove every broken tag without asking
+        #[arg(short, long, requires = "clean")]
+        yes: bool,
+        
+        /// With --clean, show what --yes would remove without changing anything
+        #[arg(long, requires = "clean")]
+        dry_run: bool,
+        
+        /// Print `hash -d` lines that make each tag a zsh named directory (~tag)
+        #[arg(long)]
+        zsh_hash: bool,
+        
+        /// Print an alias per tag that cds to it (e.g. cdwork)
+        #[arg(long)]
+        export_aliases: bool,
+        
+        /// Print an exported variable per tag holding its path (e.g. WORK)
+        #[arg(long)]
+        export_vars: bool,
+        
+        /// Print a fish abbreviation per tag that expands to cd (e.g. cdwork)
+        #[arg(long)]
+        fish_abbr: bool,
+        
+        /// Prefix for exported names [default: "cd" for aliases and abbreviations, none for variables]
+        #[arg(long, requires = "export_kind")]
+        prefix: Option<String>,
+        
+        /// Shell syntax for exported aliases and variables (bash, zsh or fish)
+        #[arg(long, value_enum, default_value_t = Shell::Bash, requires = "export_kind")]
+        shell: Shell,
+    },
+    
+    /// Print the best tracked directory named like NAME, if one clearly wins
+    ///
+    /// Used by `cd` fallback in the shell integration and by scripts. Exits 1
+    /// without printing anything when no tracked directory stands out from
+    /// the other matches. The current directory is never the answer, so
+    /// asking again from the first match gives the runner-up, and directories
+    /// that no longer exist are skipped. Inside a version control checkout,
+    /// its directories are preferred (see --cwd-project).
+    Query {
+        /// Directory name, or part of one
+        name: String,
+        
+        /// Inside a version control checkout, prefer its directories
+        /// ("auto"), consider only them ("only") or ignore it ("off")
+        /// [default: "auto", or `cwd_project` in the config]
+        #[arg(long, value_enum, value_name = "MODE")]
+        cwd_project: Option<CwdProject>,
+        
+        /// Never answer with this directory, like the current one (repeatable)
+        #[arg(long, value_name = "PATH")]
+        exclude: Vec<String>,
+        
+        /// When no tracked directory clearly matches, try the unvisited
+        /// directories inside the most frecent ones [default: `query.explore`]
+        #[arg(long)]
+        explore: bool,
+        
+        /// Print the directory N levels above the match instead [N: 1]
+        #[arg(long, value_name = "N", num_args = 0..=1, require_equals = true, default_missing_value = "1")]
+        parent: Option<usize>,
+        
+        /// Also copy the match to the system clipboard
+        #[arg(long)]
+        copy: bool,
+        
+        /// Print the match as Windows or WSL sees it
+        #[arg(long = "as", value_enum, value_name = "FORM")]
+        as_form: Option<PathSystem>,
+        
+        /// End the match with a NUL instead of a newline (for xargs -0)
+        #[arg(short = '0', long)]
+        print0: bool,
+        
+        /// Answer from the database alone, without checking that
+        /// directories still exist or exploring
+        #[arg(long)]
+        offline: bool,
+    },
+    
+    /// Suggest a CDPATH made of the parents of your most frecent directories
+    Cdpath {
+        /// Number of parent directories to include
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+        
+        /// Print a line setting CDPATH in this shell's syntax (bash, zsh or fish)
+        #[arg(long, value_enum)]
+        shell: Option<Shell>,
+    },
+    
+    /// Remove a tag
+    Untag {
+        /// Tag to remove
+        tag: String,
+        
+        #[command(flatten)]
+        destructive: DestructiveArgs,
+    },
+    
+    /// Record a visit to a directory (usually called from shell integration)
+    ///
+    /// Counts one visit and updates the last-visited time. Directories
+    /// matching the `exclude` patterns aren't recorded, and nothing is when
+    /// PATHRANGER_DISABLE is set.
+    Record {
+        /// Directory path
+        path: OsString,
+        
+        /// The directory the shell was in before, so `suggest` learns where
+        /// you go from there
+        #[arg(long, value_name = "DIR")]
+        from: Option<OsString>,
+        
+        /// Write the visit from a detached background process and return at
+        /// once; failures go to a log file next to the database
+        #[arg(long = "async")]
+        background: bool,
+        
+        /// Set on the background process: append failures to this file
+        #[arg(long, hide = true, value_name = "FILE")]
+        log: Option<PathBuf>,
+    },
+    
+    /// Record that a file was opened, also counting a visit to its
+    /// directory (for editor and shell plugins)
+    RecordFile {
+        /// File path, relative to the current directory or absolute
+        path: OsString,
+    },
+    
+    /// Generate shell integration code
+    ///
+    /// Prints code that records each directory you change to and defines the
+    /// `pr` wrapper command (see --cmd), to be evaluated from your shell's
+    /// startup file; --help-snippet prints the line to add. For bash, zsh
+    /// and fish, visits are recorded from a prompt hook by default, or from a
+    /// wrapper around cd with --hook cd.
+    ///
+    /// Supports bash, zsh, fish, nu, xonsh and cmd (through
+    /// Clink). Without --shell, the shell is detected from $SHELL or the
+    /// parent process.
+    Init {
+        /// Shell to generate code for; detected from $SHELL if omitted
+        #[arg(short, long, value_enum)]
+        shell: Option<Shell>,
+        
+        /// How bash, zsh and fish detect directory changes
+        #[arg(long, value_enum, default_value_t = InitHook::Prompt)]
+        hook: InitHook,
+        
+        /// Name of the shell command wrapping pathranger
+        #[arg(long, default_value = "pr", value_parser = parse_cmd_name)]
+        cmd: String,
+        
+        /// When cd fails on a bare name, go to pathranger's best match instead (bash, zsh, fish)
+        #[arg(long)]
+        fallback: bool,
+        
+        /// Print the line to add to your shell's startup file instead of the code
+        #[arg(long, conflicts_with = "write")]
+        help_snippet: bool,
+        
+        /// Write the code to a file to source at startup instead of printing it
+        #[arg(long, value_name = "PATH")]
+        write: Option<PathBuf>,
+    },
+    
+    /// Show which database is in use, why, and what it holds
+    Info,
+    
+    /// List the profile databases with their sizes and contents
+    Profiles,
+    
+    /// Move the database in use to another file or directory, e.g. after
+    /// changing data_dir_style
+    MigrateData {
+        /// Destination file, or directory to move the file into
+        dest: PathBuf,
+    },
+    
+    /// Serve record and query requests over a Unix socket, keeping the
+    /// database open so prompt hooks start faster
+    #[command(group(ArgGroup::new("control").args(["stop", "status"])))]
+    Daemon {
+        /// Stop the daemon serving this database
+        #[arg(long)]
+        stop: bool,
+        
+        /// Show whether a daemon is serving this database
+        #[arg(long)]
+        status: bool,
+        
+        /// Seconds without a request before exiting, 0 for never (default
+        /// from the config, or 1800)
+        #[arg(long, value_name = "SECONDS", conflicts_with = "control")]
+        idle_timeout: Option<u64>,
+    },
+    
+    /// Print the project root a directory is in: the nearest directory above
+    /// it holding .git, .hg, .svn or one of `root.markers`; exits 1 if none
+    /// is found below the home directory
+    Root {
+        /// Directory to start from (default: the current directory)
+        path: Option<String>,
+        
+        /// Also count a visit to the root
+        #[arg(long)]
+        record: bool,
+    },
+    
+    /// Print the tag on the current directory, or on the nearest tagged
+    /// directory above it, for a prompt segment; prints nothing without one
+    Prompt {
+        /// What to print: {tag} is the tag's name and {path} the directory
+        /// it points at
+        #[arg(long, default_value = "{tag}")]
+        format: String,
+        
+        /// Directory to look up instead of $PWD
+        #[arg(long, value_name = "DIR")]
+        path: Option<PathBuf>,
+        
+        /// Print nothing rather than wait longer than this for the database
+        #[arg(long, value_name = "MS")]
+        timeout_ms: Option<u64>,
+    },
+    
+    /// Answer JSON-RPC requests read from stdin one per line, for editor
+    /// plugins that keep one process running; stops when stdin closes
+    Rpc,
+    
+    /// Answer searches, listings and visits as JSON over HTTP, for editor
+    /// and launcher integrations; prints the address it listens on
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Address to listen on; port 0 picks a free one. Addresses other
+        /// than loopback need PATHRANGER_SERVE_TOKEN set.
+        #[arg(long, default_value = "127.0.0.1:0")]
+        addr: String,
+    },
+    
+    /// Show or change the settings in the config file
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    
+    /// Print the configured hooks as shell code
+    Hooks {
+        #[command(subcommand)]
+        action: HooksAction,
+    },
+    
+    /// Group tags you use together into named workspaces
+    Workspace {
+        #[command(subcommand)]
+        action: WorkspaceAction,
+    },
+    
+    /// Replay visits recorded to the journal into the database
+    Flush,
+    
+    /// Show usage statistics: visits, busiest directories, database size
+    Stats {
+        /// Print the statistics as a JSON object
+        #[arg(long)]
+        json: bool,
+        
+        /// Show when you change directory, as a grid of weekdays by hours
+        #[arg(long)]
+        heatmap: bool,
+        
+        /// Limit the heatmap to visits in and under a directory or tag
+        #[arg(long = "for", value_name = "PATH_OR_TAG", requires = "heatmap")]
+        scope: Option<String>,
+    },
+    
+    /// Show what is known about one directory: visits, rank, tags, recent
+    /// weeks and where you usually come from and go to
+    Stat {
+        /// Directory or tag to describe [default: the current directory]
+        #[arg(value_name = "PATH_OR_TAG")]
+        path: Option<String>,
+        
+        /// Print the details as a JSON object
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Summarize a week, or another window, of visits: the most visited
+    /// directories, new ones, ones you stopped visiting and new tags
+    #[command(group(ArgGroup::new("window").args(["week", "since"])))]
+    Report {
+        /// Cover the 7 days up to now, or up to --until (the default)
+        #[arg(long)]
+        week: bool,
+        
+        /// Start of the window: a duration ago ("30d") or a date/time
+        /// ("2025-01-01", "2025-01-01T09:00")
+        #[arg(long, value_name = "WHEN", value_parser = parse_since)]
+        since: Option<DateTime<Local>>,
+        
+        /// End of the window (same formats as --since; a bare date includes
+        /// that whole day) [default: now]
+        #[arg(long, value_name = "WHEN", value_parser = parse_until)]
+        until: Option<DateTime<Local>>,
+        
+        /// Print the report as a JSON object
+        #[arg(long)]
+        json: bool,
+        
+        /// Print the report as Markdown, for pasting into notes
+        #[arg(long, conflicts_with = "json")]
+        markdown: bool,
+    },
+    
+    /// Predict the directories you are likely to go to next from here
+    Suggest {
+        /// Number of suggestions
+        #[arg(short = 'n', long, default_value_t = 5)]
+        count: usize,
+        
+        /// Print the suggestions as a JSON array
+        #[arg(long)]
+        json: bool,
+        
+        /// Print only the suggested paths, one per line
+        #[arg(long, conflicts_with = "json")]
+        paths_only: bool,
+    },
+    
+    /// List the version control roots of the directories you visit, by
+    /// frecency, with their visits summed
+    Projects {
+        /// Number of projects to show (default all)
+        #[arg(short, long)]
+        count: Option<usize>,
+        
+        /// Show timestamps instead of relative times
+        #[arg(long)]
+        absolute: bool,
+        
+        /// Print the projects as a JSON array
+        #[arg(long)]
+        json: bool,
+        
+        /// Print only the project roots, one per line
+        #[arg(long, conflicts_with = "json")]
+        paths_only: bool,
+    },
+    
+    /// Roll visits up into the subtrees they happen in and list the
+    /// heaviest, with their share of all visits
+    Hotspots {
+        /// Path components below / that a subtree root has, so 3 groups
+        /// /home/me/src/api under /home/me/src
+        #[arg(short, long, default_value_t = 3, value_parser = clap::value_parser!(u16).range(1..))]
+        depth: u16,
+        
+        /// Group directories inside a repository under its root instead,
+        /// falling back to --depth outside one
+        #[arg(long)]
+        projects: bool,
+        
+        /// Number of subtrees to show
+        #[arg(short, long, default_value_t = 10)]
+        count: usize,
+        
+        /// Print the subtrees as a JSON array
+        #[arg(long)]
+        json: bool,
+    },
+    
+    /// Check that the database and shell integration are working
+    Verify {
+        /// Repair problems that can be fixed automatically
+        #[arg(long)]
+        fix: bool,
+        
+        /// With --fix, don't ask for confirmation, however much would change
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+    },
+    
+    /// Print completion candidates for shell completion scripts
+    #[command(hide = true)]
+    #[command(group(ArgGroup::new("kind").required(true).args(["tags", "dirs"])))]
+    Complete {
+        /// Complete tag names starting with PREFIX
+        #[arg(long)]
+        tags: bool,
+        
+        /// Complete tracked directories matching PREFIX, most frecent first
+        #[arg(long)]
+        dirs: bool,
+        
+        /// What has been typed so far
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        prefix: String,
+    },
+    
+    /// Generate a tab completion script
+    ///
+    /// Prints the script to stdout, to be saved where the shell looks for
+    /// completions. The bash, zsh and fish scripts complete tag names and
+    /// tracked directories from the database.
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+    
+    /// Generate manual pages
+    ///
+    /// Prints the roff source of pathranger(1), or with COMMAND the page for
+    /// that subcommand, such as pathranger-goto(1), to stdout. With --dir,
+    /// writes pathranger.1 and a page for every subcommand into DIR instead,
+    /// creating it if needed, for packaging:
+    ///
+    ///     pathranger man --dir /usr/local/share/man/man1
+    Man {
+        /// Subcommand to print the page for, e.g. `goto` or `config set`
+        #[arg(value_name = "COMMAND", conflicts_with = "dir")]
+        command: Vec<String>,
+        
+        /// Write every page into this directory
+        #[arg(long, value_name = "DIR")]
+        dir: Option<PathBuf>,
+    },
+}
+
+/// Shells `init` can generate integration code for
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(alias = "nushell")]
+    Nu,
+    Xonsh,
+    /// cmd.exe with Clink
+    #[value(alias = "clink")]
+    Cmd,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nu => "nu",
+            Shell::Xonsh => "xonsh",
+            Shell::Cmd => "cmd",
+        }
+    }
+    
+    /// The `completions` shell for this `init` shell, if there is one
+    fn completions(self) -> Option<CompletionShell> {
+        match self {
+            Shell::Bash => Some(CompletionShell::Bash),
+            Shell::Zsh => Some(CompletionShell::Zsh),
+            Shell::Fish => Some(CompletionShell::Fish),
+            Shell::Nu => Some(CompletionShell::Nu),
+            Shell::Xonsh | Shell::Cmd => None,
+        }
+    }
+}
+
+/// Shells `completions` can generate scripts for: every `init` shell that
+/// has a completion system clap can target, plus PowerShell
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    #[value(alias = "nushell")]
+    Nu,
+    #[value(name = "powershell")]
+    PowerShell,
+}
+
+/// Check that an `init --cmd` name is usable as a function name in every shell
+fn parse_cmd_name(value: &str) -> std::result::Result<String, String> {
+    let mut chars = value.chars();
+    let valid_start = chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(String::from(
+            "must start with a letter or '_' and contain only letters, digits, '_' or '-'",
+        ));
+    }
+    Ok(value.to_string())
+}
+
+/// Directory change detection used by the generated shell code.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum InitHook {
+    /// Record from the prompt hook (PROMPT_COMMAND, chpwd, PWD events)
+    Prompt,
+    /// Wrap the cd builtin, recording only directories changed to with cd
+    Cd,
+}
+
+/// Which side of WSL a path is printed for with `--as`
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PathSystem {
+    /// Drive paths, as in C:\Users\alex
+    Windows,
+    /// Drive mounts, as in /mnt/c/Users/alex
+    Wsl,
+}
+
+impl PathSystem {
+    fn name(self) -> &'static str {
+        match self {
+            PathSystem::Windows => "Windows",
+            PathSystem::Wsl => "WSL",
+        }
+    }
+}
+
+impl Commands {
+    /// Whether this command's purpose is to change the database. Others only
+    /// read it, apart from goto counting a visit, and can run read-only.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Commands::Workspace { action } => action.is_mutating(),
+            Commands::Tags { clean, .. } => *clean,
+            _ => matches!(
+                self,
+                Commands::Mark { .. } | Commands::Add | Commands::Scan { .. } | Commands::Untag { .. } | Commands::Flush
+            ),
+        }
+    }
+    
+    /// Whether this command only reads the directories table, so unflushed
+    /// journal entries can be folded into what it sees
+    fn lists_visits(&self) -> bool {
+        matches!(
+            self,
+            Commands::Top { .. }
+                | Commands::Recent { .. }
+                | Commands::List { .. }
+                | Commands::Search { .. }
+                | Commands::Query { .. }
+                | Commands::Cdpath { .. }
+                | Commands::Projects { .. }
+                | Commands::Hotspots { .. }
+                | Commands::Suggest { .. }
+                | Commands::Stats { .. }
+                | Commands::Stat { .. }
+                | Commands::Report { .. }
+        )
+    }
+    
+    /// Whether this command prints a human-readable listing that may be paged
+    fn is_human_listing(&self) -> bool {
+        match self {
+            Commands::Top { output, .. }
+            | Commands::Recent { output, .. }
+            | Commands::List { output, .. }
+            | Commands::Files { output, .. }
+            | Commands::Search { output, .. } => output.is_human(),
+            // --clean asks questions, which a pager would hide
+            Commands::Tags { paths_only, porcelain, clean, zsh_hash, export_aliases, export_vars, fish_abbr, .. } => {
+                !(*paths_only || porcelain.is_some() || *clean || *zsh_hash || *export_aliases || *export_vars || *fish_abbr)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An optional path argument as text, or None if it isn't valid UTF-8 and
+/// so can't be recorded
+fn utf8_arg(arg: Option<&OsStr>) -> Option<&str> {
+    arg.and_then(OsStr::to_str)
+}
+
+fn flush_journal(db: &Database, journal: Option<&Journal>) -> Result<()> {
+    let Some(journal) = journal else {
+        return Err(Error::InvalidInput(String::from("Could not determine the journal directory")));
+    };
+    match journal.flush(db)? {
+        Some(visits) if visits.is_empty() => note!("The journal is empty"),
+        Some(visits) => {
+            let count: i64 = visits.iter().map(|visit| visit.count).sum();
+            let directories = if visits.len() == 1 { "directory" } else { "directories" };
+            let plural = if count == 1 { "" } else { "s" };
+            note!("Recorded {} visit{} to {} {} from the journal", count, plural, visits.len(), directories);
+        }
+        None => note!("Another process is flushing the journal"),
+    }
+    Ok(())
+}
+
+/// pathranger's own files for this database that exist: the database, the
+/// background recorder's log and the journal with its directory, plus the
+/// data directory when pathranger chose it rather than being handed a file
+fn state_files(location: &DbLocation) -> Vec<PathBuf> {
+    let mut files = vec![location.path.clone(), record::log_path(&location.path)];
+    if let Some(journal) = Journal::for_database(&location.path) {
+        files.extend(journal.path().parent().map(Path::to_path_buf));
+        files.push(journal.path().to_path_buf());
+    }
+    let chosen_dir = matches!(
+        location.source,
+        DbSource::Default | DbSource::ProfileFlag | DbSource::EnvProfile | DbSource::EnvDataDir
+    );
+    if chosen_dir && let Some(dir) = location.path.parent() {
+        files.push(dir.to_path_buf());
+    }
+    files.retain(|path| path.exists());
+    files
+}
+
+/// Take other users' access off the state files, saying so for each one
+/// that had it
+#[cfg(unix)]
+fn make_state_private(location: &DbLocation) {
+    for path in state_files(location) {
+        match pathranger::make_private(&path) {
+            Ok(true) => warn!("Made {} private; other users could read it", format_path(&path.to_string_lossy())),
+            Ok(false) => {}
+            Err(e) => debug!("could not restrict {}: {}", path.display(), e),
+        }
+    }
+}
+
+/// The current directory as the shell spells it, which is how the prompt
+/// hook recorded the paths `query` and project detection compare it with
+fn query_cwd() -> Option<PathBuf> {
+    current_dir_forms().into_iter().next()
+}
+
+/// The best match for `name` other than `cwd` and `exclude`, if one clearly
+/// wins. With `explore`, unvisited directories get a chance when no tracked
+/// one does.
+fn query_directory(
+    db: &Database,
+    name: &str,
+    cwd: Option<&Path>,
+    cwd_project: CwdProject,
+    exclude: &[String],
+    explore: bool,
+) -> Result<Option<String>> {
+    let root = cwd_project.root(cwd);
+    if let Some(root) = &root {
+        debug!("in project {}", root.display());
+    }
+    let exclude: Vec<String> = exclude.iter().map(|path| resolve_user_path(path)).collect();
+    let exclude: Vec<&Path> = exclude.iter().map(Path::new).collect();
+    let scope = cwd_project.scope(root.as_deref());
+    let found = db.query_in(name, cwd, scope, &exclude)?;
+    if found.is_some() || !explore {
+        return Ok(found);
+    }
+    debug!("no tracked directory clearly matches, exploring");
+    db.explore(name, cwd, scope, &exclude)
+}
+
+/// Print a `query` match, or the directory `parent` levels above it, as
+/// `as_form` sees it
+fn print_query_match(
+    config: &Config,
+    path: &str,
+    parent: Option<usize>,
+    copy: bool,
+    as_form: Option<PathSystem>,
+    print0: bool,
+) -> Result<()> {
+    let path = match parent {
+        Some(levels) => path_as(&parent_directory(config, path, levels)?, as_form),
+        None => path_as(path, as_form),
+    };
+    if print0 {
+        print!("{}\0", path);
+    } else {
+        println!("{}", path);
+    }
+    if copy {
+        clipboard::copy(&path)?;
+    }
+    Ok(())
+}
+
+/// The directory `levels` above `path`, for `--parent`. Going above the
+/// filesystem root is an error, and so is leaving the home directory from
+/// inside it unless `parent_above_home` allows it.
+fn parent_directory(config: &Config, path: &str, levels: usize) -> Result<String> {
+    let start = Path::new(path);
+    let Some(parent) = start.ancestors().nth(levels) else {
+        return Err(Error::InvalidInput(format!(
+            "Can't go {} level{} above {}: that's past the filesystem root",
+            levels,
+            if levels == 1 { "" } else { "s" },
+            format_path(path)
+        )));
+    };
+    if !config.parent_above_home.unwrap_or(false)
+        && let Some(home) = dirs::home_dir()
+        && start.starts_with(&home)
+        && !parent.starts_with(&home)
+    {
+        return Err(Error::InvalidInput(format!(
+            "Going {} level{} above {} leaves your home directory\n\
+             Set parent_above_home = true to allow it",
+            levels,
+            if levels == 1 { "" } else { "s" },
+            format_path(path)
+        )));
+    }
+    debug!("going {} level(s) above {}", levels, path);
+    Ok(parent.to_string_lossy().into_owned())
+}
+
+/// How the database checks that directories exist: not at all for a jump
+/// with --offline, and not on network filesystems with `trust_network_paths`
+fn path_checks(config: &Config, command: Option<&Commands>) -> PathChecks {
+    let offline = matches!(
+        command,
+        Some(Commands::Goto { offline: true, .. }) | Some(Commands::Query { offline: true, .. })
+    );
+    if offline {
+        PathChecks::Offline
+    } else if config.trust_network_paths.unwrap_or(false) {
+        PathChecks::TrustNetwork
+    } else {
+        PathChecks::Bounded
+    }
+}
+
+/// Serve this database until stopped or idle for `idle_timeout` seconds
+fn run_daemon(db_path: &Path, idle_timeout: u64, config: &Config) -> Result<()> {
+    let socket = daemon::create_socket_path(db_path)?;
+    let db = Database::open(db_path)?;
+    db.set_fold_case(config.fold_case.unwrap_or(true));
+    db.set_path_form(config.paths.unwrap_or_default());
+    db.set_path_checks(path_checks(config, None));
+    db.set_exclude(config.exclude_patterns()?);
+    let idle_timeout = (idle_timeout > 0).then(|| std::time::Duration::from_secs(idle_timeout));
+    daemon::serve(db, db_path, &socket, idle_timeout)
+}
+
+/// The project root `root` prints for `path`, by default the current
+/// directory, or None if it isn't inside one
+fn project_root(config: &Config, path: Option<&str>) -> Result<Option<String>> {
+    let dir = match path {
+        Some(path) => resolve_user_path(path),
+        None => stored_path(&current_dir()?.to_string_lossy()).into_owned(),
+    };
+    if !Path::new(&dir).is_dir() {
+        return Err(Error::DirectoryMissing(dir));
+    }
+    let markers: Vec<String> = match &config.root.markers {
+        Some(markers) => markers.clone(),
+        None => DEFAULT_ROOT_MARKERS.iter().map(ToString::to_string).collect(),
+    };
+    let root = project::find_root(Path::new(&dir), &markers);
+    debug!("project root of {}: {:?}", dir, root);
+    Ok(root.map(|root| root.to_string_lossy().into_owned()))
+}
+
+/// Print `format` for the tag on `path`, by default $PWD, or on the nearest
+/// tagged directory above it. Prompts redraw constantly, so this is one
+/// indexed query on a read-only connection with no filesystem checks, and
+/// prints nothing at all when there is no tag, on errors, or after
+/// `timeout_ms`.
+fn print_prompt_segment(db_path: &Path, format: &str, path: Option<&Path>, timeout_ms: Option<u64>) {
+    let dir = match path {
+        Some(path) => std::path::absolute(path).ok(),
+        None => std::env::var_os("PWD")
+            .map(PathBuf::from)
+            .filter(|pwd| pwd.is_absolute())
+            .or_else(|| std::env::current_dir().ok()),
+    };
+    let Some(dir) = dir else {
+        return;
+    };
+    let dir = stored_path(&dir.to_string_lossy()).into_owned();
+    let db_path = db_path.to_path_buf();
+    let lookup = move || Database::open_read_only(&db_path)?.innermost_tag(&dir);
+    
+    let found = match timeout_ms {
+        Some(ms) => {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            std::thread::spawn(move || {
+                let _ = sender.send(lookup());
+            });
+            receiver.recv_timeout(std::time::Duration::from_millis(ms)).unwrap_or_else(|_| {
+                debug!("no answer within {}ms", ms);
+                Ok(None)
+            })
+        }
+        None => lookup(),
+    };
+    match found {
+        Ok(Some((tag, path))) => {
+            let path = format_path(&path);
+            let pieces: Vec<String> = format.split("{tag}").map(|piece| piece.replace("{path}", &path)).collect();
+            println!("{}", escape_control(&pieces.join(&tag)));
+        }
+        Ok(None) => {}
+        Err(e) => debug!("no prompt segment: {}", e),
+    }
+}
+
+/// Report whether a daemon serves this database; false if none does
+fn daemon_status(theme: &Theme, db_path: &Path) -> bool {
+    let database = theme.path(format_path(&db_path.to_string_lossy()));
+    match daemon::status(db_path) {
+        Some(status) => {
+            let uptime = humantime::format_duration(status.uptime);
+            let plural = if status.requests == 1 { "" } else { "s" };
+            println!(
+                "Daemon running for {} (pid {}, up {}, {} request{})",
+                database, status.pid, uptime, status.requests, plural
+            );
+            true
+        }
+        None => {
+            println!("No daemon is running for {}", database);
+            false
+        }
+    }
+}
+
+/// Ask the daemon serving this database to exit; false if none was running
+fn stop_daemon(theme: &Theme, db_path: &Path) -> bool {
+    let database = theme.path(format_path(&db_path.to_string_lossy()));
+    match daemon::stop(db_path) {
+        Some(pid) => {
+            note!("Stopped the daemon for {} (pid {})", database, pid);
+            true
+        }
+        None => {
+            note!("No daemon is running for {}", database);
+            false
+        }
+    }
+}
+
+fn mark_directory(db: &Database, theme: &Theme, tag: &str, path: Option<&str>) -> Result<()> {
+    let path = match path {
+        Some(p) => stored_path(&shellexpand::tilde(p)).into_owned(),
+        None => stored_path(&current_dir()?.to_string_lossy()).into_owned(),
+    };
+    debug!("marking {}", path);
+    
+    let updated = db.mark(tag, &path)?;
+    let path = db.canonical_path(&path)?;
+    if updated {
+        note!("Updated tag '{}' to point to '{}'", theme.tag(tag), theme.path(&path));
+    } else {
+        note!("Created tag '{}' for '{}'", theme.tag(tag), theme.path(&path));
+    }
+    
+    Ok(())
+}
+
+/// Mark the current directory with a tag named after it. On a terminal the
+/// suggestion is offered for confirming or editing first; elsewhere it is
+/// only printed unless `auto` accepts it. Returns whether a tag was made.
+fn mark_suggested(db: &Database, theme: &Theme, auto: bool) -> Result<bool> {
+    let dir = db.canonical_path(&current_dir()?.to_string_lossy())?;
+    let Some(base) = Path::new(&dir).file_name().map(|name| suggested_tag_name(&name.to_string_lossy())) else {
+        return Err(Error::InvalidInput(format!("Can't suggest a tag for {}; pass one", dir)));
+    };
+    if db.resolve_tag(&base)?.as_deref() == Some(dir.as_str()) {
+        note!("'{}' is already tagged '{}'", theme.path(format_path(&dir)), theme.tag(&base));
+        return Ok(true);
+    }
+    let taken: HashSet<String> = db.tags()?.into_iter().map(|tag| tag.name).collect();
+    let numbered = (2..).map(|n| format!("{}-{}", base, n));
+    let Some(suggestion) = std::iter::once(base.clone()).chain(numbered).find(|tag| !taken.contains(tag)) else {
+        unreachable!("the numbered names never run out");
+    };
+    debug!("suggesting '{}' for {}", suggestion, dir);
+    
+    let tag = if auto {
+        suggestion
+    } else if io::stdin().is_terminal() && io::stderr().is_terminal() {
+        eprint!("Create tag '{}' for {}? [Y/n/edit] ", theme.tag(&suggestion), format_path(&dir));
+        match read_answer()?.as_str() {
+            "" | "y" | "Y" | "yes" => suggestion,
+            "e" | "edit" => {
+                eprint!("Tag name: ");
+                match read_answer()? {
+                    name if name.is_empty() => return Err(Error::Cancelled),
+                    name => name,
+                }
+            }
+            _ => return Err(Error::Cancelled),
+        }
+    } else {
+        // Scripts get the name to pass back, and a status saying nothing was made
+        println!("{}", suggestion);
+        warn!("No tag given; pass --auto to create '{}'", suggestion);
+        return Ok(false);
+    };
+    mark_directory(db, theme, &tag, None)?;
+    Ok(true)
+}
+
+/// A tag name made from a directory name: lowercase, with runs of
+/// whitespace turned into single dashes
+fn suggested_tag_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase()
+}
+
+/// One line typed at a prompt, trimmed
+fn read_answer() -> Result<String> {
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|e| Error::Io(String::from("Could not read the answer"), e))?;
+    Ok(answer.trim().to_string())
+}
+
+/// A search query with a leading `~` and any `$VAR` or `${VAR}` expanded, for
+/// when the shell left them alone, as it does inside quotes. A `~` anywhere
+/// else is literal, and a query naming an unset variable is used as typed.
+fn expand_search_query(query: &str) -> String {
+    match shellexpand::full(query) {
+        Ok(expanded) => {
+            if expanded != query {
+                debug!("expanded query {} to {}", query, expanded);
+            }
+            expanded.into_owned()
+        }
+        Err(e) => {
+            debug!("searching for {} as typed: {}", query, e);
+            query.to_string()
+        }
+    }
+}
+
+/// Tags from the project file in or above the current directory and the
+/// system-wide file, read once by the commands that use them
+fn tag_files(config: &Config) -> TagFiles {
+    let system = config.system_tags.as_deref().map_or(Cow::Borrowed(pathranger::tagfile::SYSTEM_FILE), tilde);
+    TagFiles::load(std::env::current_dir().ok().as_deref(), Path::new(system.as_ref()))
+}
+
+/// The absolute path `arg` names, if it is written like a path (starting
+/// with `.`, `/` or `~`, or containing a separator) and is an existing
+/// directory, so `goto ./src` goes there rather than looking for a tag
+fn passthrough_path(arg: &str) -> Option<String> {
+    if !(arg.starts_with(['.', '~']) || arg.contains(std::path::is_separator)) {
+        return None;
+    }
+    let path = resolve_user_path(arg);
+    if !Path::new(&path).is_dir() {
+        return None;
+    }
+    debug!("{} is a directory, going there directly", arg);
+    Some(path)
+}
+
+/// Where `goto` takes a tag, preferring the user's own tags over project and
+/// system ones
+fn tag_target(db: &Database, files: &TagFiles, tag: &str) -> Result<String> {
+    match (db.resolve_tag(tag)?, files.resolve(tag)) {
+        (Some(path), shadowed) => {
+            // Shadowing a system tag is the documented way to replace it
+            if let Some((Origin::Project, file, _)) = shadowed {
+                warn!("Using your own tag '{}' rather than the one in {}", tag, format_path(&file.path.to_string_lossy()));
+            }
+            debug!("tag '{}' points to {}", tag, path);
+            Ok(path)
+        }
+        (None, Some((_, file, path))) => {
+            debug!("tag '{}' points to {} (from {})", tag, path, file.path.display());
+            Ok(path.to_string())
+        }
+        (None, None) => match db.workspace(tag)? {
+            // A workspace stands for its first member
+            Some(workspace) => {
+                let Some(first) = workspace.members.first() else {
+                    return Err(Error::InvalidInput(format!("Workspace '{}' has no members", tag)));
+                };
+                debug!("workspace '{}' starts with tag '{}'", tag, first.name);
+                Ok(first.path.clone())
+            }
+            None => Err(Error::TagNotFound(tag.to_string())),
+        },
+    }
+}
+
+/// The directory at 1-based `rank` of the `top` or `recent` listing. The
+/// listing query is re-run, so this doesn't depend on earlier output.
+fn rank_target(db: &Database, config: &Config, sort: SortKey, rank: usize) -> Result<String> {
+    if rank == 0 {
+        return Err(Error::InvalidInput(String::from("Ranks start at 1")));
+    }
+    
+    let options = ListOptions {
+        sort,
+        reverse: false,
+        count: rank,
+        filter: filter_from_args(&FilterArgs::default(), config)?,
+    };
+    let mut entries = db.list(&options)?;
+    
+    if entries.len() < rank {
+        return Err(Error::RankOutOfRange { rank, available: entries.len() });
+    }
+    
+    Ok(entries.swap_remove(rank - 1).path)
+}
+
+fn goto_path(db: &Database, path: &str, shown: &str) -> Result<()> {
+    // Print the path for the shell wrapper to cd into
+    println!("{}", shown);
+    record::visit(db, path, None)
+}
+
+/// `path` as `form` sees it, for `--as`. A path already in that form is
+/// kept; one that has none, such as a share seen from WSL, is printed
+/// unchanged with a warning.
+fn path_as(path: &str, form: Option<PathSystem>) -> String {
+    let Some(form) = form else {
+        return path.to_string();
+    };
+    let translated = match form {
+        PathSystem::Windows if is_windows_path(path) => Some(normalize_windows_path(path)),
+        PathSystem::Windows => wsl_to_windows(path),
+        PathSystem::Wsl => windows_to_wsl(path).or_else(|| wsl_to_windows(path).map(|_| path.to_string())),
+    };
+    translated.unwrap_or_else(|| {
+        warn!("{} has no {} form; printing it unchanged", path, form.name());
+        path.to_string()
+    })
+}
+
+/// Heading for a listing in the given order
+fn sort_title(sort: SortKey) -> &'static str {
+    match sort {
+        SortKey::Visits => "Your most frequently visited directories",
+        SortKey::Recent => "Your recently visited directories",
+        SortKey::Alpha => "Your tracked directories",
+        SortKey::Frecency => "Your top directories by frecency",
+    }
+}
+
+/// Build a listing filter from the command line, falling back to the config
+fn filter_from_args(args: &FilterArgs, config: &Config) -> Result<Filter> {
+    let under = args.under.as_deref().map(resolve_user_path).and_then(|path| {
+        let path = path.trim_end_matches('/').to_string();
+        // Everything is under the root
+        (!path.is_empty()).then_some(path)
+    });
+    if let (Some(given), Some(under)) = (&args.under, &under) {
+        debug!("--under {} resolved to {}", given, under);
+    }
+    
+    let resolve = if args.no_resolve {
+        false
+    } else {
+        args.resolve_symlinks || config.display.resolve_symlinks.unwrap_or(false)
+    };
+    let resolve_symlinks = resolve.then(|| config.display.path_form.unwrap_or_default());
+    
+    let hide_hidden = !args.all && (args.no_hidden || config.display.hide_hidden.unwrap_or(false));
+    
+    Ok(Filter {
+        under,
+        resolve_symlinks,
+        untagged: args.untagged,
+        hide_hidden,
+        since: args.since,
+        until: args.until,
+        show_excluded: args.all,
+    })
+}
+
+/// Suffix for listing titles, e.g. " without tags under ~/work"
+fn describe_filter(filter: &Filter) -> String {
+    let mut description = String::new();
+    if filter.untagged {
+        description.push_str(" without tags");
+    }
+    if let Some(under) = &filter.under {
+        description.push_str(&format!(" under {}", format_path(under)));
+    }
+    if let Some(since) = filter.since {
+        description.push_str(&format!(" since {}", since.format("%Y-%m-%d %H:%M")));
+    }
+    if let Some(until) = filter.until {
+        description.push_str(&format!(" before {}", until.format("%Y-%m-%d %H:%M")));
+    }
+    description
+}
+
+fn list_directories(
+    db: &Database,
+    theme: &Theme,
+    mode: &OutputMode,
+    options: &ListOptions,
+    time_format: &TimeFormat,
+) -> Result<()> {
+    let entries = db.list(options)?;
+    let title = format!("{}{}:", sort_title(options.sort), describe_filter(&options.filter));
+    print_entries(db, theme, mode, &title, &entries, time_format)
+}
+
+/// Shared rendering for directory listings
+fn print_entries(
+    db: &Database,
+    theme: &Theme,
+    mode: &OutputMode,
+    title: &str,
+    entries: &[DirEntry],
+    time_format: &TimeFormat,
+) -> Result<()> {
+    match mode {
+        OutputMode::Print0 => {
+            let paths: Vec<String> = entries.iter().map(|entry| entry.path.clone()).collect();
+            print_paths_nul(&paths);
+        }
+        OutputMode::PathsOnly => {
+            for entry in entries {
+                println!("{}", entry.path);
+            }
+        }
+        OutputMode::Template(template) => {
+            let tags = template.load_tags(db, entries.iter().map(|entry| entry.path.as_str()))?;
+            for (i, entry) in entries.iter().enumerate() {
+                println!("{}", template.render(theme, i + 1, entry, None, &tags));
+            }
+        }
+        OutputMode::Porcelain(Porcelain::V1) => {
+            for (i, entry) in entries.iter().enumerate() {
+                let last_visited = porcelain_time(entry.last_visited);
+                println!("{}\t{}\t{}\t{}", i + 1, entry.visit_count, last_visited, escape_field(&entry.path));
+            }
+        }
+        OutputMode::Tree(glyphs) => {
+            if theme.decorated {
+                println!("{}", title);
+            }
+            for line in render_tree(entries, glyphs, theme) {
+                println!("{}", line);
+            }
+        }
+        OutputMode::Human => {
+            let tags = db.tags_for_paths(entries.iter().map(|entry| entry.path.as_str()))?;
+            let mut table = Table::new(&[("", 4), ("VISITS", 8), ("LAST VISITED", time_format.width()), ("PATH", 0)]);
+            for (i, entry) in entries.iter().enumerate() {
+                table.push(vec![
+                    Cell::plain((i + 1).to_string()),
+                    Cell::count(entry.visit_count.to_string()),
+                    Cell::plain(format_last_visited(entry.last_visited, time_format)),
+                    Cell::tagged_path(theme.display_path(&entry.path), tags.get(&entry.path)),
+                ]);
+            }
+            
+            if theme.decorated {
+                println!("{}", title);
+            }
+            table.print(theme);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print `Stats` as aligned label/value rows, or as JSON
+fn print_stats(db: &Database, theme: &Theme, json: bool) -> Result<()> {
+    let stats = db.stats()?;
+    if json {
+        return print_json(&stats);
+    }
+    
+    let shown = |path: &str| if theme.decorated { format_path(path) } else { path.to_string() };
+    let busiest = |busiest: &Option<DirectoryVisits>| match busiest {
+        Some(busiest) => {
+            let plural = if busiest.visits == 1 { "" } else { "s" };
+            format!("{} ({} visit{})", shown(&busiest.path), busiest.visits, plural)
+        }
+        None => String::from("none"),
+    };
+    // Windowed numbers can only be estimated from the per-directory totals
+    let estimate = if stats.estimated && theme.decorated { "~" } else { "" };
+    let mut rows = vec![
+        ("directories", stats.directories.to_string()),
+        ("visits", stats.visits.to_string()),
+        ("last 7 days", format!("{}{}", estimate, stats.visits_last_7_days)),
+        ("last 30 days", format!("{}{}", estimate, stats.visits_last_30_days)),
+        ("busiest week", busiest(&stats.busiest_this_week)),
+        ("busiest ever", busiest(&stats.busiest_all_time)),
+        ("tags", stats.tags.to_string()),
+        ("size", format_size(stats.database_bytes)),
+    ];
+    if let (Some(first), Some(per_day)) = (stats.first_record, stats.visits_per_day) {
+        rows.push(("since", first.format("%Y-%m-%d").to_string()));
+        rows.push(("per day", format!("{:.1}", per_day)));
+    }
+    
+    for (label, value) in rows {
+        if theme.decorated {
+            println!("{} {}", theme.paint(Style::new().bold(), format!("{:<13}", label)), value);
+        } else {
+            println!("{}\t{}", label, value);
+        }
+    }
+    if stats.estimated && theme.decorated {
+        println!();
+        println!(
+            "{}",
+            theme.paint(
+                Style::new().dimmed(),
+                "~ Only each directory's total and last visit are stored, so a directory last visited\n  in the window counts with all its visits"
+            )
+        );
+    }
+    Ok(())
+}
+
+/// The directory `text` names: a tag when it is a bare name that one of
+/// the user's or the tag files' tags has, otherwise a path
+fn resolve_path_or_tag(db: &Database, config: &Config, text: &str) -> Result<String> {
+    let bare = !text.contains('/') && !matches!(text, "." | "..") && !text.starts_with('~');
+    if bare {
+        if let Some(path) = db.resolve_tag(text)? {
+            return Ok(path);
+        }
+        if let Some((_, _, path)) = tag_files(config).resolve(text) {
+            return Ok(path.to_string());
+        }
+    }
+    Ok(resolve_user_path(text).trim_end_matches('/').to_string())
+}
+
+/// Directories moved to and from that `stat` lists
+const STAT_NEIGHBOURS: usize = 3;
+
+/// Sparkline bars from fewest to most visits
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Print `DirectoryStats` for `path` as aligned label/value rows, or as
+/// JSON. An untracked directory is reported as such, not as an error.
+fn print_directory_stats(db: &Database, theme: &Theme, path: &str, json: bool, time_format: &TimeFormat) -> Result<()> {
+    let stats = db.directory_stats(path, STAT_NEIGHBOURS)?;
+    if json {
+        let value = match &stats {
+            Some(stats) => {
+                let mut value = serde_json::to_value(stats)
+                    .map_err(|e| Error::Io(String::from("Could not write JSON"), e.into()))?;
+                value["tracked"] = serde_json::Value::Bool(true);
+                value
+            }
+            None => serde_json::json!({ "path": path, "tracked": false }),
+        };
+        return print_json(&value);
+    }
+    let Some(stats) = stats else {
+        note!("{} is not tracked", if theme.decorated { format_path(path) } else { path.to_string() });
+        return Ok(());
+    };
+    
+    let shown = |path: &str| if theme.decorated { format_path(path) } else { path.to_string() };
+    let first_visit = match stats.first_visit {
+        // Visits older than the history only show up in the total
+        Some(first) if stats.history_visits < stats.visits => format!("before {}", first.format("%Y-%m-%d")),
+        first => format_last_visited(first.map(|first| first.with_timezone(&Local)), theme.time_format(true)),
+    };
+    let weeks = if theme.decorated {
+        let busiest = stats.weekly_visits.iter().copied().max().unwrap_or(0).max(1);
+        let bars: String = stats
+            .weekly_visits
+            .iter()
+            .map(|&count| match count {
+                0 => theme.paint(Style::new().dimmed(), "·"),
+                _ => SPARKS[((count * SPARKS.len() as i64 - 1) / busiest) as usize].to_string(),
+            })
+            .collect();
+        let total: i64 = stats.weekly_visits.iter().sum();
+        format!("{} {} visit{}", bars, total, if total == 1 { "" } else { "s" })
+    } else {
+        let counts: Vec<String> = stats.weekly_visits.iter().map(i64::to_string).collect();
+        counts.join("\t")
+    };
+    let mut rows = vec![
+        ("path", shown(&stats.path)),
+        ("visits", stats.visits.to_string()),
+        ("rank", format!("{} of {}", stats.rank, stats.directories)),
+        ("first visit", first_visit),
+        ("last visit", format_last_visited(stats.last_visit, time_format)),
+        ("tags", stats.tags.join(", ")),
+        ("last 8 weeks", weeks),
+    ];
+    rows.retain(|(_, value)| !value.is_empty() || !theme.decorated);
+    
+    let neighbours = [("came from", &stats.came_from), ("went to", &stats.went_to)];
+    if theme.decorated {
+        for (label, entries) in neighbours {
+            let moves: Vec<String> =
+                entries.iter().map(|entry| format!("{} ({})", shown(&entry.path), entry.visits)).collect();
+            rows.push((label, if moves.is_empty() { String::from("none recorded") } else { moves.join(", ") }));
+        }
+        for (label, value) in rows {
+            println!("{} {}", theme.paint(Style::new().bold(), format!("{:<13}", label)), value);
+        }
+    } else {
+        for (label, value) in rows {
+            println!("{}\t{}", label, value);
+        }
+        for (label, entries) in neighbours {
+            for entry in entries {
+                println!("{}\t{}\t{}", label, entry.visits, entry.path);
+            }
+        }
+    }
+    Ok(())
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Heatmap shades from least to most visits, with the 256-color greens
+/// they are painted in
+const HEAT_LEVELS: [(char, u8); 4] = [('░', 22), ('▒', 28), ('▓', 34), ('█', 40)];
+
+/// Print the visit heatmap as a grid of shaded cells on a terminal, or its
+/// raw counts otherwise
+fn print_heatmap(heatmap: &Heatmap, theme: &Theme, json: bool) -> Result<()> {
+    if json {
+        return print_json(heatmap);
+    }
+    if !theme.decorated {
+        for (day, counts) in WEEKDAYS.iter().zip(&heatmap.counts) {
+            let counts: Vec<String> = counts.iter().map(i64::to_string).collect();
+            println!("{}\t{}", day, counts.join("\t"));
+        }
+        return Ok(());
+    }
+    
+    let under = heatmap.under.as_deref().map(|under| format!(" under {}", format_path(under))).unwrap_or_default();
+    let Some(since) = heatmap.since else {
+        println!("No visits{} in the history yet", under);
+        return Ok(());
+    };
+    let plural = if heatmap.visits == 1 { "" } else { "s" };
+    println!("{} visit{}{} since {}, by local time:", heatmap.visits, plural, under, since.format("%Y-%m-%d"));
+    
+    let hours: String = (0..24).step_by(3).map(|hour| format!("{:<6}", hour)).collect();
+    println!("    {}", hours.trim_end());
+    let busiest = heatmap.counts.iter().flatten().copied().max().unwrap_or(0).max(1);
+    for (day, counts) in WEEKDAYS.iter().zip(&heatmap.counts) {
+        let mut row = format!("{} ", day);
+        for &count in counts {
+            if count == 0 {
+                row.push_str(&theme.paint(Style::new().dimmed(), "· "));
+                continue;
+            }
+            // Any visit at all shows, however quiet the hour
+            let level = ((count * HEAT_LEVELS.len() as i64 + busiest - 1) / busiest) as usize;
+            let (shade, color) = HEAT_LEVELS[level.clamp(1, HEAT_LEVELS.len()) - 1];
+            row.push_str(&theme.paint(Fixed(color).normal(), format!("{}{}", shade, shade)));
+        }
+        println!("{}", row);
+    }
+    
+    let legend: String = HEAT_LEVELS.iter().map(|&(shade, color)| theme.paint(Fixed(color).normal(), shade.to_string())).collect();
+    println!("    less {} more (busiest hour: {})", legend, busiest);
+    Ok(())
+}
+
+/// Most visited directories a report lists
+const REPORT_TOP: usize = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Human,
+    Json,
+    Markdown,
+}
+
+/// The report's window as dates, or with times when it spans less than a
+/// couple of days
+fn describe_window(report: &Report) -> String {
+    let pattern = if report.until - report.since < Duration::days(2) { "%Y-%m-%d %H:%M" } else { "%Y-%m-%d" };
+    format!("{} to {}", report.since.format(pattern), report.until.format(pattern))
+}
+
+/// Print a report for a terminal, as tab-separated rows for scripts, as
+/// Markdown or as JSON
+fn print_report(db: &Database, theme: &Theme, report: &Report, format: ReportFormat) -> Result<()> {
+    let visits = |count: i64| format!("{} visit{}", count, if count == 1 { "" } else { "s" });
+    // The window before this one must be in the history for "stopped" to be complete
+    let partial = report.history_since.is_none_or(|first| first > report.since - (report.until - report.since));
+    let history_note = match report.history_since {
+        Some(first) if partial => Some(format!(
+            "The visit history starts on {}, so earlier visits aren't counted",
+            first.format("%Y-%m-%d")
+        )),
+        None => Some(String::from("No visits are in the history yet")),
+        Some(_) => None,
+    };
+    
+    match format {
+        ReportFormat::Json => print_json(report)?,
+        ReportFormat::Markdown => {
+            let code = |path: &str| format!("`{}`", format_path(path));
+            println!("## Directories, {}", describe_window(report));
+            println!();
+            println!("{} to {} {}.", visits(report.visits), report.directories, if report.directories == 1 { "directory" } else { "directories" });
+            if let Some(note) = &history_note {
+                println!();
+                println!("_{}._", note);
+            }
+            if !report.top.is_empty() {
+                println!();
+                println!("### Most visited");
+                println!();
+                println!("| # | Directory | Visits |");
+                println!("|--:|-----------|-------:|");
+                for (i, entry) in report.top.iter().enumerate() {
+                    println!("| {} | {} | {} |", i + 1, code(&entry.path), entry.visits);
+                }
+            }
+            if !report.new.is_empty() {
+                println!();
+                println!("### New");
+                println!();
+                for entry in &report.new {
+                    println!("- {} ({})", code(&entry.path), visits(entry.visits));
+                }
+            }
+            if !report.stopped.is_empty() {
+                println!();
+                println!("### Stopped visiting");
+                println!();
+                for entry in &report.stopped {
+                    println!("- {} ({} the period before)", code(&entry.path), visits(entry.visits));
+                }
+            }
+            if !report.tags.is_empty() {
+                println!();
+                println!("### New tags");
+                println!();
+                for tag in &report.tags {
+                    println!("- `{}` → {}", tag.name, code(&tag.path));
+                }
+            }
+        }
+        ReportFormat::Human if !theme.decorated => {
+            let sections = [("top", &report.top), ("new", &report.new), ("stopped", &report.stopped)];
+            for (section, entries) in sections {
+                for entry in entries {
+                    println!("{}\t{}\t{}", section, entry.visits, entry.path);
+                }
+            }
+            for tag in &report.tags {
+                println!("tag\t{}\t{}", tag.name, tag.path);
+            }
+        }
+        ReportFormat::Human => {
+            let heading = |text: &str| theme.paint(Style::new().bold(), text);
+            let directories = if report.directories == 1 { "directory" } else { "directories" };
+            println!(
+                "{} {} to {} {}",
+                heading(&format!("{}:", describe_window(report))),
+                visits(report.visits),
+                report.directories,
+                directories
+            );
+            if let Some(note) = &history_note {
+                println!("{}", theme.paint(Style::new().dimmed(), note));
+            }
+            
+            if !report.top.is_empty() {
+                println!();
+                println!("{}", heading("Most visited"));
+                let tags = db.tags_for_paths(report.top.iter().map(|entry| entry.path.as_str()))?;
+                let mut table = Table::new(&[("", 4), ("VISITS", 8), ("PATH", 0)]);
+                for (i, entry) in report.top.iter().enumerate() {
+                    table.push(vec![
+                        Cell::plain((i + 1).to_string()),
+                        Cell::count(entry.visits.to_string()),
+                        Cell::tagged_path(theme.display_path(&entry.path), tags.get(&entry.path)),
+                    ]);
+                }
+                table.print(theme);
+            }
+            let lists = [("New", &report.new, ""), ("Stopped visiting", &report.stopped, " the period before")];
+            for (title, entries, suffix) in lists {
+                if entries.is_empty() {
+                    continue;
+                }
+                println!();
+                println!("{}", heading(title));
+                for entry in entries {
+                    println!("  {} ({}{})", theme.path(theme.display_path(&entry.path)), visits(entry.visits), suffix);
+                }
+            }
+            if !report.tags.is_empty() {
+                println!();
+                println!("{}", heading("New tags"));
+                for tag in &report.tags {
+                    println!("  {} → {}", theme.paint(Green.bold(), &tag.name), theme.path(theme.display_path(&tag.path)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A `suggest` result as `--json` prints it
+#[derive(Serialize)]
+struct SuggestionJson<'a> {
+    path: &'a str,
+    score: f64,
+    /// Moves recorded from the current directory straight to this one
+    moves: i64,
+    /// "child" or "sibling" of the current directory, if either
+    nearby: Option<&'static str>,
+    /// Share of visits made within an hour of the current time of day
+    hour_share: f64,
+    tags: &'a [String],
+}
+
+/// Suggestions visited at least this share of the time around the current
+/// hour say so
+const USUAL_HOUR_SHARE: f64 = 0.25;
+
+/// Print the directories most likely to be visited next from the current one
+fn suggest_directories(db: &Database, theme: &Theme, count: usize, json: bool, paths_only: bool) -> Result<()> {
+    let cwd = query_cwd().ok_or_else(|| Error::InvalidInput(String::from("Could not determine the current directory")))?;
+    let suggestions = db.suggest(&cwd, count)?;
+    let tags = db.tags_for_paths(suggestions.iter().map(|suggestion| suggestion.path.as_str()))?;
+    
+    if json {
+        let items: Vec<SuggestionJson> = suggestions
+            .iter()
+            .map(|suggestion| SuggestionJson {
+                path: &suggestion.path,
+                score: suggestion.score,
+                moves: suggestion.moves,
+                nearby: suggestion.nearby.map(|nearby| match nearby {
+                    Nearby::Child => "child",
+                    Nearby::Sibling => "sibling",
+                }),
+                hour_share: suggestion.hour_share,
+                tags: tags.get(&suggestion.path).map_or(&[], Vec::as_slice),
+            })
+            .collect();
+        return print_json(&items);
+    }
+    if paths_only {
+        for suggestion in &suggestions {
+            println!("{}", suggestion.path);
+        }
+        return Ok(());
+    }
+    if suggestions.is_empty() {
+        if theme.decorated {
+            println!("Nothing to suggest yet; visit a few directories first");
+        }
+        return Ok(());
+    }
+    
+    let mut table = Table::new(&[("", 4), ("SCORE", 8), ("WHY", 28), ("PATH", 0)]);
+    for (i, suggestion) in suggestions.iter().enumerate() {
+        let mut reasons = Vec::new();
+        if suggestion.moves > 0 {
+            let plural = if suggestion.moves == 1 { "" } else { "s" };
+            reasons.push(format!("{} move{} from here", suggestion.moves, plural));
+        }
+        match suggestion.nearby {
+            Some(Nearby::Child) => reasons.push(String::from("child")),
+            Some(Nearby::Sibling) => reasons.push(String::from("sibling")),
+            None => {}
+        }
+        if suggestion.hour_share >= USUAL_HOUR_SHARE {
+            reasons.push(String::from("this time of day"));
+        }
+        if reasons.is_empty() {
+            reasons.push(String::from("frecent"));
+        }
+        table.push(vec![
+            Cell::plain((i + 1).to_string()),
+            Cell::count(format!("{:.2}", suggestion.score)),
+            Cell::plain(reasons.join(", ")),
+            Cell::tagged_path(theme.display_path(&suggestion.path), tags.get(&suggestion.path)),
+        ]);
+    }
+    if theme.decorated {
+        println!("Likely next directories:");
+    }
+    table.print(theme);
+    Ok(())
+}
+
+fn list_projects(
+    db: &Database,
+    theme: &Theme,
+    count: Option<usize>,
+    time_format: &TimeFormat,
+    json: bool,
+    paths_only: bool,
+) -> Result<()> {
+    let mut projects = db.projects()?;
+    projects.truncate(count.unwrap_or(usize::MAX));
+    
+    if json {
+        return print_json(&projects);
+    }
+    if paths_only {
+        for project in &projects {
+            println!("{}", project.path);
+        }
+        return Ok(());
+    }
+    if projects.is_empty() {
+        if theme.decorated {
+            println!("No tracked directories are inside a git, Mercurial or Subversion repository");
+        }
+        return Ok(());
+    }
+    
+    let mut table = Table::new(&[("", 4), ("VISITS", 8), ("DIRS", 6), ("LAST VISITED", time_format.width()), ("PROJECT", 0)]);
+    for (i, project) in projects.iter().enumerate() {
+        let tags = Some(&project.tags).filter(|tags| !tags.is_empty());
+        table.push(vec![
+            Cell::plain((i + 1).to_string()),
+            Cell::count(project.visits.to_string()),
+            Cell::count(project.directories.to_string()),
+            Cell::plain(format_last_visited(project.last_visited, time_format)),
+            Cell::tagged_path(theme.display_path(&project.path), tags),
+        ]);
+    }
+    if theme.decorated {
+        println!("Projects by frecency:");
+    }
+    table.print(theme);
+    Ok(())
+}
+
+/// Cells the share bar takes at 100%
+const SHARE_BAR_WIDTH: usize = 20;
+
+/// A bar `SHARE_BAR_WIDTH` cells wide at 100%, drawn in eighths of a cell
+fn share_bar(share: f64) -> String {
+    const EIGHTHS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+    // Any share at all gets a sliver, so small subtrees still show
+    let eighths = ((share / 100.0 * (SHARE_BAR_WIDTH * 8) as f64).round() as usize).max(1);
+    let mut bar = "█".repeat(eighths / 8);
+    if let Some(partial) = (eighths % 8).checked_sub(1) {
+        bar.push(EIGHTHS[partial]);
+    }
+    bar
+}
+
+fn list_hotspots(db: &Database, theme: &Theme, depth: usize, projects: bool, count: usize, json: bool) -> Result<()> {
+    let mut hotspots = db.hotspots(depth, projects)?;
+    hotspots.truncate(count);
+    
+    if json {
+        return print_json(&hotspots);
+    }
+    if hotspots.is_empty() {
+        if theme.decorated {
+            println!("No visits yet");
+        }
+        return Ok(());
+    }
+    
+    let tags = db.tags_for_paths(hotspots.iter().map(|hotspot| hotspot.path.as_str()))?;
+    let mut columns = vec![("", 4), ("VISITS", 8), ("SHARE", 7)];
+    if theme.decorated {
+        columns.push(("", SHARE_BAR_WIDTH + 1));
+    }
+    columns.extend([("DIRS", 6), ("SUBTREE", 0)]);
+    let mut table = Table::new(&columns);
+    for (i, hotspot) in hotspots.iter().enumerate() {
+        let mut row = vec![
+            Cell::plain((i + 1).to_string()),
+            Cell::count(hotspot.visits.to_string()),
+            Cell::count(format!("{:.1}%", hotspot.share)),
+        ];
+        if theme.decorated {
+            row.push(Cell::count(share_bar(hotspot.share)));
+        }
+        row.extend([
+            Cell::count(hotspot.directories.to_string()),
+            Cell::tagged_path(theme.display_path(&hotspot.path), tags.get(&hotspot.path)),
+        ]);
+        table.push(row);
+    }
+    if theme.decorated {
+        let grouping = if projects { format!("project, or depth {}", depth) } else { format!("depth {}", depth) };
+        println!("Busiest subtrees by {}:", grouping);
+    }
+    table.print(theme);
+    Ok(())
+}
+
+/// Print `value` as indented JSON, for the commands' `--json` output
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let text = serde_json::to_string_pretty(value).map_err(|e| Error::Io(String::from("Could not write JSON"), e.into()))?;
+    println!("{}", text);
+    Ok(())
+}
+
+/// A time as `--porcelain` writes it: Unix seconds, or "-" if unknown
+fn porcelain_time(time: Option<DateTime<Local>>) -> String {
+    time.map_or_else(|| String::from("-"), |time| time.timestamp().to_string())
+}
+
+/// Print search results, which are files rather than directories if `files`
+fn print_matches(
+    db: &Database,
+    theme: &Theme,
+    mode: &OutputMode,
+    query: &str,
+    filter: &Filter,
+    matches: Vec<SearchMatch>,
+    files: bool,
+) -> Result<()> {
+    match mode {
+        OutputMode::Print0 => {
+            let paths: Vec<String> = matches.into_iter().map(|m| m.entry.path).collect();
+            print_paths_nul(&paths);
+        }
+        OutputMode::PathsOnly => {
+            for m in &matches {
+                println!("{}", m.entry.path);
+            }
+        }
+        OutputMode::Template(template) => {
+            let tags = template.load_tags(db, matches.iter().map(|m| m.entry.path.as_str()))?;
+            for (i, m) in matches.iter().enumerate() {
+                println!("{}", template.render(theme, i + 1, &m.entry, Some(m.score), &tags));
+            }
+        }
+        OutputMode::Porcelain(Porcelain::V1) => {
+            for (i, m) in matches.iter().enumerate() {
+                let last_visited = porcelain_time(m.entry.last_visited);
+                let path = escape_field(&m.entry.path);
+                println!("{}\t{}\t{}\t{}\t{}", i + 1, m.entry.visit_count, last_visited, m.score, path);
+            }
+        }
+        OutputMode::Human | OutputMode::Tree(_) => {
+            if matches.is_empty() {
+                if theme.decorated {
+                    let kind = if files { "files" } else { "directories" };
+                    println!("No matching {} found for '{}'{}", kind, query, describe_filter(filter));
+                }
+                return Ok(());
+            }
+            
+            let tags = db.tags_for_paths(matches.iter().map(|m| m.entry.path.as_str()))?;
+            let mut table = Table::new(&[("", 4), ("SCORE", 8), ("PATH", 0)]);
+            for (i, m) in matches.iter().enumerate() {
+                table.push(vec![
+                    Cell::plain((i + 1).to_string()),
+                    Cell::count(m.score.to_string()),
+                    Cell::tagged_path(theme.display_path(&m.entry.path), tags.get(&m.entry.path)),
+                ]);
+            }
+            
+            if theme.decorated {
+                println!("Search results for '{}'{}:", query, describe_filter(filter));
+            }
+            table.print(theme);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Print the `top` parent directories whose direct children carry the most
+/// frecency, as a CDPATH that starts with "."
+fn print_cdpath(db: &Database, top: usize, shell: Option<Shell>) -> Result<()> {
+    if let Some(shell) = shell.filter(|shell| !matches!(shell, Shell::Bash | Shell::Zsh | Shell::Fish)) {
+        return Err(Error::UnsupportedShell {
+            shell: shell.name().to_string(),
+            feature: "cdpath --shell",
+            supported: "bash, zsh and fish",
+        });
+    }
+    
+    let mut cdpath = vec![String::from(".")];
+    cdpath.extend(db.frequent_parents(top)?);
+    
+    match shell {
+        Some(Shell::Fish) => {
+            let quoted: Vec<String> = cdpath.iter().map(|dir| fish_quote(dir)).collect();
+            println!("set -gx CDPATH {}", quoted.join(" "));
+        }
+        Some(_) => println!("export CDPATH={}", shell_quote(&cdpath.join(":"))),
+        None => println!("{}", cdpath.join(":")),
+    }
+    
+    Ok(())
+}
+
+fn list_tags(
+    db: &Database,
+    theme: &Theme,
+    files: &TagFiles,
+    paths_only: bool,
+    porcelain: Option<Porcelain>,
+    tree: Option<TreeGlyphs>,
+    time_format: &TimeFormat,
+) -> Result<()> {
+    let tags = db.tags()?;
+    
+    // File tags follow the user's own, which hide any of the same name
+    let own: HashSet<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+    let mut file_tags = Vec::new();
+    for (origin, file, name, path) in files.tags() {
+        if !own.contains(name) {
+            file_tags.push((origin, name, path));
+        } else if origin == Origin::Project {
+            warn!("Project tag '{}' in {} is hidden by your own tag", name, format_path(&file.path.to_string_lossy()));
+        }
+    }
+    
+    if paths_only {
+        for tag in &tags {
+            println!("{}", tag.path);
+        }
+        for (_, _, path) in &file_tags {
+            println!("{}", path);
+        }
+        return Ok(());
+    }
+    
+    if let Some(Porcelain::V1) = porcelain {
+        for tag in &tags {
+            println!("{}\tuser\t{}", escape_field(&tag.name), escape_field(&tag.path));
+        }
+        for (origin, name, path) in &file_tags {
+            println!("{}\t{}\t{}", escape_field(name), origin.badge(), escape_field(path));
+        }
+        return Ok(());
+    }
+    
+    if let Some(glyphs) = tree {
+        let mut entries: Vec<(&str, Option<&'static str>, &str)> =
+            tags.iter().map(|tag| (tag.name.as_str(), None, tag.path.as_str())).collect();
+        entries.extend(file_tags.iter().map(|(origin, name, path)| (*name, Some(origin.badge()), *path)));
+        if theme.decorated {
+            println!("Your tags:");
+        }
+        for line in render_tag_tree(&entries, &glyphs, theme) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    
+    // --verbose adds when each tag was made and last moved
+    let dated = output::verbosity() == Verbosity::Verbose;
+    let mut table = if dated {
+        Table::new(&[("TAG", 20), ("CREATED", time_format.width()), ("UPDATED", time_format.width()), ("PATH", 0)])
+    } else {
+        Table::new(&[("TAG", 20), ("PATH", 0)])
+    };
+    for tag in tags {
+        let mut row = vec![Cell::tag(tag.name)];
+        if dated {
+            row.push(Cell::plain(format_last_visited(tag.created_at, time_format)));
+            row.push(Cell::plain(format_last_visited(tag.updated_at, time_format)));
+        }
+        row.push(Cell::path(theme.display_path(&tag.path)));
+        table.push(row);
+    }
+    for (origin, name, path) in file_tags {
+        let mut row = vec![Cell { badge: Some(String::from(origin.badge())), ..Cell::tag(name.to_string()) }];
+        if dated {
+            row.push(Cell::plain(String::from("-")));
+            row.push(Cell::plain(String::from("-")));
+        }
+        row.push(Cell::path(theme.display_path(path)));
+        table.push(row);
+    }
+    
+    if theme.decorated {
+        println!("Your tags:");
+    }
+    table.print(theme);
+    
+    Ok(())
+}
+
+/// Ways `tags` can print tags as shell code, so they work without pathranger
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TagExport {
+    /// zsh named directories (`hash -d`), so `~tag` works and prompts shorten
+    ZshHash,
+    /// An alias per tag that cds to it
+    Aliases,
+    /// An exported variable per tag holding its path
+    Vars,
+    /// A fish abbreviation per tag that expands to cd
+    FishAbbr,
+}
+
+/// Environment variables a tag must never overwrite
+const RESERVED_VARS: &[&str] = &[
+    "CDPATH", "HOME", "IFS", "LANG", "OLDPWD", "PATH", "PS1", "PS2", "PWD", "SHELL", "TERM", "USER",
+];
+
+/// Print every tag as shell code. Names that aren't valid identifiers are
+/// sanitized, and collisions and skipped tags are reported on stderr.
+fn export_tags(db: &Database, export: TagExport, prefix: Option<&str>, shell: Shell) -> Result<()> {
+    let shell = match export {
+        TagExport::ZshHash => Shell::Zsh,
+        TagExport::FishAbbr => Shell::Fish,
+        TagExport::Aliases | TagExport::Vars => shell,
+    };
+    if !matches!(shell, Shell::Bash | Shell::Zsh | Shell::Fish) {
+        return Err(Error::UnsupportedShell {
+            shell: shell.name().to_string(),
+            feature: "Exporting tags",
+            supported: "bash, zsh and fish",
+        });
+    }
+    
+    let tags: Vec<(String, String)> = db.tags()?.into_iter().map(|tag| (tag.name, tag.path)).collect();
+    
+    let prefix = prefix.unwrap_or(match export {
+        TagExport::Aliases | TagExport::FishAbbr => "cd",
+        TagExport::ZshHash | TagExport::Vars => "",
+    });
+    let wanted = |name: &str| {
+        let wanted = format!("{}{}", prefix, name);
+        if export == TagExport::Vars { wanted.to_uppercase() } else { wanted }
+    };
+    let ident = |name: &str| {
+        if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        shell_identifier(&wanted(name))
+    };
+    
+    // Tags that are already valid names keep them; renamed ones give way
+    let mut used: HashSet<String> = tags
+        .iter()
+        .filter(|(name, _)| ident(name) == Some(wanted(name)))
+        .map(|(name, _)| wanted(name))
+        .collect();
+    let mut seen = HashSet::new();
+    for (name, path) in &tags {
+        let Some(ident) = ident(name) else {
+            warn!("Skipping tag '{}': it has no characters usable in a shell name", name);
+            continue;
+        };
+        if ident != wanted(name) && !used.insert(ident.clone()) {
+            warn!("Skipping tag '{}': {} is already used by another tag", name, ident);
+            continue;
+        }
+        // Uppercasing can make two valid names collide ("work" and "WORK")
+        if !seen.insert(ident.clone()) {
+            warn!("Skipping tag '{}': {} is already used by another tag", name, ident);
+            continue;
+        }
+        if export == TagExport::Vars && RESERVED_VARS.contains(&ident.as_str()) {
+            warn!("Skipping tag '{}': it would overwrite ${}", name, ident);
+            continue;
+        }
+        if ident != wanted(name) {
+            warn!("Exporting tag '{}' as {}", name, ident);
+        }
+        
+        let quote = if shell == Shell::Fish { fish_quote } else { shell_quote };
+        match (export, shell) {
+            (TagExport::ZshHash, _) => println!("hash -d {}={}", ident, shell_quote(path)),
+            (TagExport::Aliases, Shell::Fish) => println!("alias {} {}", ident, quote(&format!("cd {}", quote(path)))),
+            (TagExport::Aliases, _) => println!("alias {}={}", ident, quote(&format!("cd {}", quote(path)))),
+            (TagExport::Vars, Shell::Fish) => println!("set -gx {} {}", ident, quote(path)),
+            (TagExport::Vars, _) => println!("export {}={}", ident, quote(path)),
+            // Global abbreviations last only for the session, so regenerating
+            // at startup never leaves stale ones behind
+            (TagExport::FishAbbr, _) => println!("abbr -a -g {} {}", ident, quote(&format!("cd {}", quote(path)))),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Turn a tag name into a shell identifier: anything but letters, digits and
+/// `_` becomes `_`, and a leading digit gets a `_` prefix (zsh reads `~1` as
+/// a directory stack entry). Names with nothing usable give None.
+fn shell_identifier(name: &str) -> Option<String> {
+    if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    
+    let mut ident: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+    Some(ident)
+}
+
+/// Single-quote a string for POSIX shells, zsh and bash
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Single-quote a string for fish, where backslashes in quotes are escapes
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Double-quote a string for nushell, whose single quotes can't be escaped
+fn nu_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Double-quote an argument for cmd.exe, which has no escape inside quotes
+/// but reads a doubled quote as one
+fn cmd_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Double-quote a string literal for Lua
+fn lua_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+/// Quote a string literal in `shell`'s own syntax
+fn quote_for(shell: Shell, value: &str) -> String {
+    match shell {
+        Shell::Bash | Shell::Zsh => shell_quote(value),
+        // Python's single-quoted strings escape the same way fish's do
+        Shell::Fish | Shell::Xonsh => fish_quote(value),
+        Shell::Nu => nu_quote(value),
+        Shell::Cmd => cmd_quote(value),
+    }
+}
+
+fn remove_tag(
+    db: &Database,
+    config: &Config,
+    files: &TagFiles,
+    tag: &str,
+    args: &DestructiveArgs,
+) -> Result<()> {
+    let affected: Vec<String> = db.resolve_tag(tag)?.into_iter().collect();
+    if affected.is_empty()
+        && let Some((origin, file, _)) = files.resolve(tag)
+    {
+        let file = format_path(&file.path.to_string_lossy());
+        return Err(Error::InvalidInput(match origin {
+            Origin::Project => format!(
+                "Tag '{}' is defined in {}, which pathranger doesn't change\nEdit that file to remove it",
+                tag, file
+            ),
+            Origin::System => format!(
+                "Tag '{}' is provided system-wide by {} and can't be removed\n\
+                 To replace it, mark a directory with the same name: pathranger mark {}",
+                tag, file, tag
+            ),
+        }));
+    }
+    confirm_destructive(config, args, "remove", "tag", &affected)?;
+    
+    let removed = db.transaction(args.dry_run, |db| db.remove_tag(tag))?;
+    if removed && args.dry_run {
+        note!("Would remove tag '{}'", tag);
+    } else if removed {
+        note!("Tag '{}' removed", tag);
+    } else {
+        note!("Tag '{}' not found", tag);
+    }
+    if args.dry_run {
+        warn!("Dry run: nothing was changed");
+    }
+    
+    Ok(())
+}
+
+/// List the tags whose directories no longer exist, then ask whether to
+/// remove them all, keep them all or decide one by one. A tag can be moved
+/// instead when exactly one existing tracked directory has its directory's
+/// name. With `yes`, or without a terminal to ask on, they are all removed,
+/// subject to `confirm_destructive`. Every change is made in one transaction.
+fn clean_tags(db: &Database, theme: &Theme, config: &Config, yes: bool, dry_run: bool) -> Result<()> {
+    let broken = db.broken_tags()?;
+    if broken.is_empty() {
+        note!("Every tag points at an existing directory");
+        return Ok(());
+    }
+    
+    let time_format = theme.time_format(false);
+    let mut table = Table::new(&[("TAG", 20), ("CREATED", time_format.width()), ("LAST VISITED", time_format.width()), ("PATH", 0)]);
+    for BrokenTag { tag, last_visited, .. } in &broken {
+        table.push(vec![
+            Cell::tag(tag.name.clone()),
+            Cell::plain(format_last_visited(tag.created_at, time_format)),
+            Cell::plain(format_last_visited(*last_visited, time_format)),
+            Cell::path(theme.display_path(&tag.path)),
+        ]);
+    }
+    if theme.decorated {
+        println!("Tags pointing at missing directories:");
+    }
+    table.print(theme);
+    for BrokenTag { tag, moved_to, .. } in &broken {
+        if let Some(moved) = moved_to {
+            note!("'{}' may have moved to {}", theme.tag(&tag.name), theme.path(format_path(moved)));
+        }
+    }
+    
+    let fixes: Vec<TagFix> = if yes || dry_run || !io::stdin().is_terminal() {
+        let paths: Vec<String> = broken.iter().map(|broken| broken.tag.path.clone()).collect();
+        confirm_destructive(config, &DestructiveArgs { yes, dry_run }, "remove", "tag", &paths)?;
+        broken.iter().map(|_| TagFix::Remove).collect()
+    } else {
+        let count = format!("{} tag{}", broken.len(), if broken.len() == 1 { "" } else { "s" });
+        eprint!("Remove all {} (a), keep them (k) or choose for each (c)? [a/K/c] ", count);
+        match read_answer()?.as_str() {
+            "a" | "all" => broken.iter().map(|_| TagFix::Remove).collect(),
+            "" | "k" | "K" | "keep" => broken.iter().map(|_| TagFix::Keep).collect(),
+            "c" | "choose" => {
+                let mut fixes = Vec::new();
+                for BrokenTag { tag, moved_to, .. } in &broken {
+                    let shown = format_path(&tag.path);
+                    match moved_to {
+                        Some(to) => eprint!("{} ({}): remove, keep or move to {}? [r/K/m] ", tag.name, shown, format_path(to)),
+                        None => eprint!("{} ({}): remove or keep? [r/K] ", tag.name, shown),
+                    }
+                    fixes.push(match (read_answer()?.as_str(), moved_to) {
+                        ("r" | "remove", _) => TagFix::Remove,
+                        ("m" | "move", Some(to)) => TagFix::Move(to.clone()),
+                        _ => TagFix::Keep,
+                    });
+                }
+                fixes
+            }
+            _ => return Err(Error::Cancelled),
+        }
+    };
+    
+    let fixes: Vec<(&str, TagFix)> = broken.iter().map(|broken| broken.tag.name.as_str()).zip(fixes).collect();
+    let (removed, moved) = db.transaction(dry_run, |db| db.fix_tags(&fixes))?;
+    let kept = broken.len() - removed - moved;
+    let verb = if dry_run { "Would remove" } else { "Removed" };
+    note!("{} {} tag{}, moved {} and kept {}", verb, removed, if removed == 1 { "" } else { "s" }, moved, kept);
+    if dry_run {
+        warn!("Dry run: nothing was changed");
+    }
+    Ok(())
+}
+
+fn manage_workspace(db: &Database, theme: &Theme, action: WorkspaceAction) -> Result<()> {
+    let found = |name: &str| db.workspace(name)?.ok_or_else(|| Error::WorkspaceNotFound(name.to_string()));
+    match action {
+        WorkspaceAction::Create { name, tags } => {
+            db.transaction(false, |db| db.create_workspace(&name, &tags))?;
+            let plural = if tags.len() == 1 { "" } else { "s" };
+            note!("Created workspace '{}' with {} tag{}", name, tags.len(), plural);
+        }
+        WorkspaceAction::List => {
+            let workspaces = db.workspaces()?;
+            if workspaces.is_empty() {
+                if theme.decorated {
+                    println!("No workspaces yet; create one with `pathranger workspace create NAME TAG...`");
+                }
+                return Ok(());
+            }
+            let mut table = Table::new(&[("WORKSPACE", 20), ("TAGS", 0)]);
+            for workspace in workspaces {
+                let tags: Vec<&str> = workspace.members.iter().map(|tag| tag.name.as_str()).collect();
+                table.push(vec![Cell::tag(workspace.name), Cell::plain(tags.join(", "))]);
+            }
+            table.print(theme);
+        }
+        WorkspaceAction::Show { name } => {
+            let workspace = found(&name)?;
+            let mut table = Table::new(&[("TAG", 20), ("PATH", 0)]);
+            for tag in workspace.members {
+                let missing = !Path::new(&tag.path).is_dir();
+                let path = Cell::path(theme.display_path(&tag.path));
+                let path = if missing { Cell { badge: Some(String::from("missing")), ..path } } else { path };
+                table.push(vec![Cell::tag(tag.name), path]);
+            }
+            if theme.decorated {
+                println!("Workspace '{}':", name);
+            }
+            table.print(theme);
+        }
+        WorkspaceAction::Open { name } => {
+            // Opening a pane in a directory that is gone would only fail later
+            for tag in found(&name)?.members {
+                if Path::new(&tag.path).is_dir() {
+                    println!("{}", tag.path);
+                } else {
+                    warn!("Skipping tag '{}': directory does not exist: {}", tag.name, tag.path);
+                }
+            }
+        }
+        WorkspaceAction::Add { name, tag } => {
+            if db.add_to_workspace(&name, &tag)? {
+                note!("Added '{}' to workspace '{}'", tag, name);
+            } else {
+                note!("'{}' is already in workspace '{}'", tag, name);
+            }
+        }
+        WorkspaceAction::Remove { name, tag } => {
+            if db.remove_from_workspace(&name, &tag)? {
+                note!("Removed '{}' from workspace '{}'", tag, name);
+            } else {
+                note!("'{}' is not in workspace '{}'", tag, name);
+            }
+        }
+        WorkspaceAction::Delete { name } => {
+            if !db.transaction(false, |db| db.delete_workspace(&name))? {
+                return Err(Error::WorkspaceNotFound(name));
+            }
+            note!("Workspace '{}' deleted", name);
+        }
+    }
+    Ok(())
+}
+
+/// Paths listed when asking for confirmation
+const CONFIRM_SAMPLE: usize = 5;
+
+/// Ask before a destructive command touches more than the configured number
+/// of rows. `affected` is the paths that would change. Without a terminal to
+/// ask on, the command is refused unless --yes was given.
+fn confirm_destructive(
+    config: &Config,
+    args: &DestructiveArgs,
+    verb: &str,
+    noun: &str,
+    affected: &[String],
+) -> Result<()> {
+    let threshold = config.confirm_threshold.unwrap_or(DEFAULT_CONFIRM_THRESHOLD);
+    if args.yes || args.dry_run || affected.len() <= threshold {
+        return Ok(());
+    }
+    
+    let count = format!("{} {}{}", affected.len(), noun, if affected.len() == 1 { "" } else { "s" });
+    if !io::stdin().is_terminal() {
+        return Err(Error::InvalidInput(format!(
+            "This would {} {}; pass --yes to confirm, or --dry-run to preview",
+            verb, count
+        )));
+    }
+    
+    eprintln!("This will {} {}:", verb, count);
+    for path in affected.iter().take(CONFIRM_SAMPLE) {
+        eprintln!("  {}", format_path(path));
+    }
+    if affected.len() > CONFIRM_SAMPLE {
+        eprintln!("  ... and {} more", affected.len() - CONFIRM_SAMPLE);
+    }
+    eprint!("Continue? [y/N] ");
+    
+    if matches!(read_answer()?.as_str(), "y" | "Y" | "yes") {
+        Ok(())
+    } else {
+        Err(Error::Cancelled)
+    }
+}
+
+/// The current directory as the shell spells it when $PWD can be trusted,
+/// as the prompt hook records it; the database resolves it if `paths` says
+fn current_dir() -> Result<PathBuf> {
+    match current_dir_forms().into_iter().next() {
+        Some(dir) => Ok(dir),
+        None => std::env::current_dir().map_err(|e| Error::Io(String::from("Could not get current directory"), e)),
+    }
+}
+
+fn add_current_directory(db: &Database, theme: &Theme) -> Result<()> {
+    let current_dir = current_dir()?.to_string_lossy().into_owned();
+    
+    record::visit(db, &current_dir, None)?;
+    note!("Added '{}' to tracked directories", theme.path(format_path(&db.canonical_path(&current_dir)?)));
+    
+    Ok(())
+}
+
+/// Walk `root` and start tracking the directories found, committing what was
+/// gathered even if Ctrl-C stops the walk early
+fn scan_directories(
+    db: &Database,
+    theme: &Theme,
+    config: &Config,
+    root: &Path,
+    options: &ScanOptions,
+    dry_run: bool,
+) -> Result<()> {
+    let Some(root) = root.to_str() else {
+        return Err(Error::InvalidInput(format!(
+            "Can't scan {}: paths that aren't valid UTF-8 can't be tracked",
+            root.display()
+        )));
+    };
+    let root = resolve_user_path(root);
+    if !Path::new(&root).is_dir() {
+        return Err(Error::DirectoryMissing(root));
+    }
+    let exclude = config.exclude_patterns()?;
+    
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&interrupted);
+    if let Err(e) = ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed)) {
+        debug!("could not catch Ctrl-C: {}", e);
+    }
+    
+    let result = scan::scan(Path::new(&root), options, exclude.as_ref(), &interrupted);
+    if result.interrupted {
+        warn!("Interrupted; adding the {} directories found so far", result.directories.len());
+    }
+    if result.unreadable > 0 {
+        let entries = if result.unreadable == 1 { "entry" } else { "entries" };
+        warn!("Skipped {} {} that could not be read (use --verbose to list them)", result.unreadable, entries);
+    }
+    
+    let (added, tagged) = db.transaction(dry_run, |db| {
+        let added = db.add_directories(&result.directories)?;
+        let mut tagged = Vec::new();
+        for (tag, path) in db.autotag_names(&result.repositories)? {
+            if db.add_tag(&tag, &path)? {
+                tagged.push((tag, path));
+            }
+        }
+        Ok((added, tagged))
+    })?;
+    
+    let (tag_verb, add_verb) = if dry_run { ("Would tag", "would add") } else { ("Tagged", "added") };
+    for (tag, path) in &tagged {
+        note!("{} '{}' for '{}'", tag_verb, theme.tag(tag), theme.path(format_path(path)));
+    }
+    note!(
+        "Scanned {} directories under {}; {} {} new",
+        result.scanned,
+        theme.path(format_path(&root)),
+        add_verb,
+        added
+    );
+    if options.find_repositories {
+        let skipped = result.repositories.len() - tagged.len();
+        let repositories = if tagged.len() == 1 { "repository" } else { "repositories" };
+        note!("{} {} {} ({} already tagged)", tag_verb, tagged.len(), repositories, skipped);
+    }
+    Ok(())
+}
+
+/// Guess the user's shell from $SHELL, falling back to the parent process
+fn detect_shell() -> Option<Shell> {
+    std::env::var_os("SHELL")
+        .and_then(|shell| shell_name(&PathBuf::from(shell).to_string_lossy()))
+        .or_else(|| parent_process_name().and_then(|name| shell_name(&name)))
+}
+
+/// Map a shell executable path or process name to a supported shell
+fn shell_name(program: &str) -> Option<Shell> {
+    let name = Path::new(program.trim()).file_name()?.to_string_lossy();
+    // Login shells show up as "-zsh"
+    Shell::from_str(name.trim_start_matches('-'), false).ok()
+}
+
+#[cfg(unix)]
+fn parent_process_name() -> Option<String> {
+    // SAFETY: getppid has no preconditions and cannot fail
+    let ppid = unsafe { libc::getppid() };
+    
+    if let Ok(comm) = fs::read_to_string(format!("/proc/{}/comm", ppid)) {
+        return Some(comm);
+    }
+    
+    let output = Command::new("ps")
+        .args(["-o", "comm=", "-p", &ppid.to_string()])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(unix))]
+fn parent_process_name() -> Option<String> {
+    None
+}
+
+/// Choices that shape the code `init` generates
+struct InitOptions {
+    hook: InitHook,
+    cmd: String,
+    fallback: bool,
+    /// Database file given with --db, exported so the hooks use it too
+    db: Option<String>,
+    /// Profile given with --profile, exported the same way
+    profile: Option<String>,
+    /// The after_goto hooks, written into the goto wrapper
+    after_goto: Vec<String>,
+}
+
+/// The `init` flags that change the generated code, as they must be repeated
+/// in the startup file
+fn init_flags(shell: Shell, options: &InitOptions) -> String {
+    let mut flags = format!("--shell {}", shell.name());
+    if options.hook == InitHook::Cd {
+        flags.push_str(" --hook cd");
+    }
+    if options.cmd != "pr" {
+        flags.push_str(&format!(" --cmd {}", options.cmd));
+    }
+    if options.fallback {
+        flags.push_str(" --fallback");
+    }
+    if let Some(db) = &options.db {
+        flags.push_str(&format!(" --db {}", quote_for(shell, db)));
+    }
+    if let Some(profile) = &options.profile {
+        flags.push_str(&format!(" --profile {}", profile));
+    }
+    flags
+}
+
+/// Result of one `verify` check; failures make `verify` exit non-zero
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Check {
+    Pass,
+    Warn,
+    Fail,
+}
+
+fn report_check(theme: &Theme, check: Check, message: &str, fix: Option<&str>) {
+    let label = match check {
+        Check::Pass => theme.paint(Green.bold(), "[ok]  "),
+        Check::Warn => theme.paint(Yellow.bold(), "[warn]"),
+        Check::Fail => theme.paint(Red.bold(), "[FAIL]"),
+    };
+    println!("{} {}", label, message);
+    if let Some(fix) = fix.filter(|_| check != Check::Pass) {
+        println!("       fix: {}", fix);
+    }
+}
+
+/// Run every `verify` check and print the results. Returns false if a hard
+/// check (the database itself) failed.
+fn verify(theme: &Theme, config: &Config, location: Option<&DbLocation>, fix: bool, yes: bool) -> bool {
+    let mut ok = true;
+    
+    let Some(location) = location else {
+        report_check(theme, Check::Fail, "could not determine the data directory", Some("set $HOME or $XDG_DATA_HOME"));
+        return false;
+    };
+    let db_path = &location.path;
+    let shown = format_path(&db_path.to_string_lossy());
+    if !matches!(location.source, DbSource::Default) {
+        report_check(theme, Check::Pass, &format!("using the database set by {}", location.source.describe()), None);
+    }
+    
+    let db = if !db_path.exists() {
+        report_check(
+            theme,
+            Check::Fail,
+            &format!("database {} does not exist", shown),
+            Some("run any pathranger command (e.g. `pathranger add`) to create it"),
+        );
+        None
+    } else {
+        match Database::open_existing(db_path) {
+            Ok(db) => {
+                report_check(theme, Check::Pass, &format!("database {} is writable", shown), None);
+                Some(db)
+            }
+            Err(e) => {
+                report_check(
+                    theme,
+                    Check::Fail,
+                    &format!("database {} is not writable: {}", shown, e),
+                    Some("check the file's owner and permissions"),
+                );
+                None
+            }
+        }
+    };
+    ok &= db.is_some();
+    
+    if let Some(db) = &db {
+        if db.has_schema().unwrap_or(false) {
+            report_check(theme, Check::Pass, "schema has the directories and tags tables", None);
+            verify_timestamps(theme, db, fix);
+            let merge = fix.then_some(DestructiveArgs { yes, dry_run: false });
+            verify_path_variants(theme, db, config, merge.as_ref());
+            #[cfg(unix)]
+            verify_permissions(theme, location, fix);
+            match db.recent(1).ok().and_then(|mut entries| entries.pop()) {
+                Some(entry) => {
+                    let when = format_last_visited(entry.last_visited, &TimeFormat::Relative);
+                    report_check(theme, Check::Pass, &format!("newest record: {} ({})", format_path(&entry.path), when), None);
+                }
+                None => report_check(
+                    theme,
+                    Check::Warn,
+                    "no directories have been recorded yet",
+                    Some("add the `pathranger init` line to your shell's startup file and open a new shell"),
+                ),
+            }
+        } else {
+            report_check(
+                theme,
+                Check::Fail,
+                "schema is missing the directories or tags table",
+                Some("the file may belong to something else; move it aside and let pathranger recreate it"),
+            );
+            ok = false;
+        }
+    }
+    
+    let detected = detect_shell();
+    match std::env::var("PATHRANGER_SESSION") {
+        Ok(shell) if !shell.is_empty() => match detected {
+            Some(current) if current.name() != shell => report_check(
+                theme,
+                Check::Warn,
+                &format!("shell integration was loaded by {} but this shell is {}", shell, current.name()),
+                Some(&format!("add the line printed by `pathranger init --shell {} --help-snippet` to this shell's startup file", current.name())),
+            ),
+            _ => report_check(theme, Check::Pass, &format!("shell integration is loaded ({})", shell), None),
+        },
+        _ => {
+            let snippet = detected.map_or(String::from("pathranger init --help-snippet"), |shell| {
+                format!("pathranger init --shell {} --help-snippet", shell.name())
+            });
+            report_check(
+                theme,
+                Check::Warn,
+                "shell integration is not loaded in this shell",
+                Some(&format!("add the line printed by `{}` to your startup file", snippet)),
+            );
+        }
+    }
+    
+    match detected {
+        Some(shell) => report_check(theme, Check::Pass, &format!("detected shell: {}", shell.name()), None),
+        None => report_check(
+            theme,
+            Check::Warn,
+            "could not detect your shell",
+            Some("pass --shell to `pathranger init` explicitly"),
+        ),
+    }
+    
+    ok
+}
+
+/// Report last_visited values that aren't RFC 3339, rewriting them with `fix`
+fn verify_timestamps(theme: &Theme, db: &Database, fix: bool) {
+    let irregular = match db.irregular_timestamps() {
+        Ok(irregular) => irregular,
+        Err(e) => return report_check(theme, Check::Warn, &format!("could not check timestamps: {}", e), None),
+    };
+    if irregular.is_empty() {
+        return report_check(theme, Check::Pass, "all last-visited times are valid", None);
+    }
+    
+    let unreadable = irregular.iter().filter(|(_, time)| time.is_none()).count();
+    let summary = format!(
+        "{} directories have last-visited times in an old or unknown format ({} unreadable)",
+        irregular.len(),
+        unreadable
+    );
+    if !fix {
+        return report_check(theme, Check::Warn, &summary, Some("run `pathranger verify --fix` to rewrite them"));
+    }
+    
+    match db.repair_timestamps() {
+        Ok(count) => report_check(theme, Check::Pass, &format!("rewrote {} last-visited times", count), None),
+        Err(e) => report_check(
+            theme,
+            Check::Warn,
+            &format!("{}; rewriting them failed: {}", summary, e),
+            Some("check that the database is writable"),
+        ),
+    }
+}
+
+/// Report state files other users can read, restricting them with `fix`
+#[cfg(unix)]
+fn verify_permissions(theme: &Theme, location: &DbLocation, fix: bool) {
+    let shared: Vec<PathBuf> =
+        state_files(location).into_iter().filter(|path| pathranger::is_shared(path).unwrap_or(false)).collect();
+    if shared.is_empty() {
+        return report_check(theme, Check::Pass, "the database and its files are private to you", None);
+    }
+    
+    let names: Vec<String> = shared.iter().map(|path| format_path(&path.to_string_lossy())).collect();
+    let summary = format!("other users can read {}", names.join(", "));
+    if !fix {
+        return report_check(theme, Check::Warn, &summary, Some("run `pathranger verify --fix` to make them private"));
+    }
+    
+    let failed: Vec<String> = shared
+        .iter()
+        .filter_map(|path| pathranger::make_private(path).err().map(|e| format!("{}: {}", path.display(), e)))
+        .collect();
+    if failed.is_empty() {
+        report_check(theme, Check::Pass, &format!("made {} private", names.join(", ")), None);
+    } else {
+        report_check(
+            theme,
+            Check::Warn,
+            &format!("{}; restricting failed for {}", summary, failed.join(", ")),
+            Some("check that you own these files"),
+        );
+    }
+}
+
+/// Report directories recorded under more than one spelling of a Windows
+/// path, merging them with `fix`
+fn verify_path_variants(theme: &Theme, db: &Database, config: &Config, fix: Option<&DestructiveArgs>) {
+    let fold_case = config.fold_case.unwrap_or(true);
+    let variants = match db.path_variants(fold_case) {
+        Ok(variants) => variants,
+        Err(e) => return report_check(theme, Check::Warn, &format!("could not check path spellings: {}", e), None),
+    };
+    if variants.is_empty() {
+        return report_check(theme, Check::Pass, "every directory is recorded under one spelling", None);
+    }
+    
+    let summary = format!(
+        "{} director{} recorded under variant spellings, such as {}",
+        variants.len(),
+        if variants.len() == 1 { "y is" } else { "ies are" },
+        variants[0].join(" and ")
+    );
+    let Some(fix) = fix else {
+        return report_check(theme, Check::Warn, &summary, Some("run `pathranger verify --fix` to merge them"));
+    };
+    // Every spelling but the one kept is merged away
+    let merged: Vec<String> = variants.iter().flat_map(|group| group.iter().skip(1).cloned()).collect();
+    if let Err(e) = confirm_destructive(config, fix, "merge", "spelling", &merged) {
+        return report_check(theme, Check::Warn, &format!("{}; not merged: {}", summary, e), None);
+    }
+    
+    match db.merge_path_variants(fold_case) {
+        Ok(count) => report_check(
+            theme,
+            Check::Pass,
+            &format!(
+                "merged the spellings of {} director{} ({} rows removed)",
+                variants.len(),
+                if variants.len() == 1 { "y" } else { "ies" },
+                count
+            ),
+            None,
+        ),
+        Err(e) => report_check(
+            theme,
+            Check::Warn,
+            &format!("{}; merging them failed: {}", summary, e),
+            Some("check that the database is writable"),
+        ),
+    }
+}
+
+/// Most directories offered for one completion
+const COMPLETION_CANDIDATES: usize = 100;
+
+/// Print raw completion candidates, one per line; print nothing on errors
+/// so a broken database never breaks tab completion
+fn print_completion_candidates(db_path: Option<&Path>, config: &Config, tags: bool, prefix: &str) {
+    let Some(db) = db_path.and_then(|path| Database::open_read_only(path).ok()) else {
+        return;
+    };
+    
+    let candidates = if tags {
+        db.tags_with_prefix(prefix).map(|mut names| {
+            let files = tag_files(config);
+            let file_names = files.tags().into_iter().map(|(_, _, name, _)| name);
+            names.extend(file_names.filter(|name| name.starts_with(prefix)).map(String::from));
+            names.sort();
+            names.dedup();
+            names
+        })
+    } else {
+        db.set_exclude(config.exclude_patterns().ok().flatten());
+        let filter = Filter { hide_hidden: config.display.hide_hidden.unwrap_or(false), ..Filter::default() };
+        db.dirs_matching(&tilde(prefix), &filter, COMPLETION_CANDIDATES)
+    };
+    
+    let mut stdout = io::stdout().lock();
+    for candidate in candidates.unwrap_or_default() {
+        if writeln!(stdout, "{}", candidate).is_err() {
+            return;
+        }
+    }
+}
+
+/// Print the completion script for `shell` to stdout
+fn print_completions(shell: CompletionShell) {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    let mut script = Vec::new();
+    let out = &mut script;
+    match shell {
+        CompletionShell::Bash => clap_complete::generate(clap_complete::Shell::Bash, &mut command, name, out),
+        CompletionShell::Zsh => clap_complete::generate(clap_complete::Shell::Zsh, &mut command, name, out),
+        CompletionShell::Fish => clap_complete::generate(clap_complete::Shell::Fish, &mut command, name, out),
+        CompletionShell::Nu => clap_complete::generate(clap_complete_nushell::Nushell, &mut command, name, out),
+        CompletionShell::PowerShell => {
+            clap_complete::generate(clap_complete::Shell::PowerShell, &mut command, name, out)
+        }
+    }
+    let script = String::from_utf8_lossy(&script);
+    
+    // clap only knows static values, so hook tag and directory arguments up
+    // to `pathranger complete`
+    match shell {
+        CompletionShell::Bash => {
+            print!("{}", script.replace("complete -F _pathranger ", "complete -F _pathranger_dynamic "));
+            println!();
+            println!("# Complete tag names and tracked directories from the database");
+            println!("_pathranger_dynamic() {{");
+            println!("    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"");
+            println!("    if [[ $COMP_CWORD -eq 2 && $cur != -* ]]; then");
+            println!("        case \"${{COMP_WORDS[1]}}\" in");
+            println!("            goto|untag)");
+            println!("                mapfile -t COMPREPLY < <(pathranger complete --tags -- \"$cur\" 2>/dev/null)");
+            println!("                return 0");
+            println!("                ;;");
+            println!("        esac");
+            println!("    fi");
+            println!("    if [[ $prev == --under ]]; then");
+            println!("        mapfile -t COMPREPLY < <(pathranger complete --dirs -- \"$cur\" 2>/dev/null)");
+            println!("        if [[ ${{#COMPREPLY[@]}} -gt 0 ]]; then");
+            println!("            return 0");
+            println!("        fi");
+            println!("    fi");
+            println!("    _pathranger \"$@\"");
+            println!("}}");
+        }
+        CompletionShell::Zsh => {
+            let script = script
+                .replace("Tag name:_default", "Tag name:_pathranger_tags")
+                .replace(":PATH:_default", ":PATH:_pathranger_dirs");
+            let dispatch = script.rfind("if [ \"$funcstack[1]\"").unwrap_or(script.len());
+            print!("{}", &script[..dispatch]);
+            println!("# Complete tag names and tracked directories from the database");
+            println!("(( $+functions[_pathranger_tags] )) ||");
+            println!("_pathranger_tags() {{");
+            println!("    local -a tags");
+            println!("    tags=(${{(f)\"$(pathranger complete --tags -- \"$PREFIX\" 2>/dev/null)\"}})");
+            println!("    compadd -a tags");
+            println!("}}");
+            println!();
+            println!("(( $+functions[_pathranger_dirs] )) ||");
+            println!("_pathranger_dirs() {{");
+            println!("    local -a dirs");
+            println!("    dirs=(${{(f)\"$(pathranger complete --dirs -- \"$PREFIX\" 2>/dev/null)\"}})");
+            println!("    if (( ${{#dirs}} )); then");
+            println!("        compadd -U -V tracked -a dirs");
+            println!("    else");
+            println!("        _files -/");
+            println!("    fi");
+            println!("}}");
+            println!();
+            print!("{}", &script[dispatch..]);
+        }
+        CompletionShell::Fish => {
+            print!("{}", script);
+            println!();
+            println!("# Complete tag names and tracked directories from the database");
+            println!("complete -c pathranger -n \"__fish_pathranger_using_subcommand goto; or __fish_pathranger_using_subcommand untag\" -f -a \"(pathranger complete --tags -- (commandline -ct) 2>/dev/null)\"");
+            println!("complete -c pathranger -n \"__fish_pathranger_using_subcommand top; or __fish_pathranger_using_subcommand recent; or __fish_pathranger_using_subcommand list; or __fish_pathranger_using_subcommand search\" -l under -k -x -a \"(pathranger complete --dirs -- (commandline -ct) 2>/dev/null)\"");
+        }
+        CompletionShell::Nu | CompletionShell::PowerShell => print!("{}", script),
+    }
+}
+
+/// Print the manual page for the subcommand named by `names`, or
+/// pathranger's own page without one, or write every page into `dir`
+fn print_man_pages(names: &[String], dir: Option<&Path>) -> Result<()> {
+    let mut command = Cli::command().disable_help_subcommand(true);
+    // Building names each subcommand's page after its parents, as in
+    // pathranger-config-set
+    command.build();
+    let source = format!("pathranger {}", command.get_version().unwrap_or_default());
+    let man = |command: clap::Command| clap_mangen::Man::new(command).source(source.clone());
+    
+    if let Some(dir) = dir {
+        let failed = |e| Error::Io(format!("Could not write manual pages to {}", dir.display()), e);
+        fs::create_dir_all(dir).map_err(failed)?;
+        let mut pending = vec![command];
+        while let Some(command) = pending.pop() {
+            pending.extend(command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()).cloned());
+            let path = man(command).generate_to(dir).map_err(failed)?;
+            debug!("wrote {}", path.display());
+        }
+        return Ok(());
+    }
+    
+    for name in names {
+        let Some(subcommand) = command.find_subcommand(name).cloned() else {
+            return Err(Error::InvalidInput(format!("No manual page for '{}'", names.join(" "))));
+        };
+        command = subcommand;
+    }
+    let mut page = Vec::new();
+    man(command)
+        .render(&mut page)
+        .and_then(|()| io::stdout().write_all(&page))
+        .map_err(|e| Error::Io(String::from("Could not write the manual page"), e))
+}
+
+/// Print the after_goto hooks for the integration to evaluate, each quoted
+/// as one string so a broken hook fails alone and the others still run
+fn print_hooks(config: &Config, shell: Shell) -> Result<()> {
+    for hook in &config.hooks.after_goto {
+        match shell {
+            Shell::Bash | Shell::Zsh | Shell::Fish => println!("eval {}", quote_for(shell, hook)),
+            Shell::Xonsh => println!("execx({})", quote_for(shell, hook)),
+            // Nushell can't evaluate code it didn't parse up front, and
+            // cmd.exe has nothing to evaluate it with
+            Shell::Nu | Shell::Cmd => {
+                return Err(Error::UnsupportedShell {
+                    shell: shell.name().to_string(),
+                    feature: "hooks print",
+                    supported: "bash, zsh, fish and xonsh",
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the line that loads the integration from the shell's startup file
+fn print_init_snippet(shell: Shell, options: &InitOptions) {
+    let flags = init_flags(shell, options);
+    match shell {
+        Shell::Bash => {
+            println!("# Add this to your ~/.bashrc");
+            println!("eval \"$(pathranger init {})\"", flags);
+        }
+        Shell::Zsh => {
+            println!("# Add this to your ~/.zshrc");
+            println!("eval \"$(pathranger init {})\"", flags);
+        }
+        Shell::Fish => {
+            println!("# Add this to your ~/.config/fish/config.fish");
+            println!("pathranger init {} | source", flags);
+        }
+        Shell::Nu => {
+            println!("# Nushell can't eval generated code at startup, so save it once:");
+            println!("pathranger init {} | save --force ~/.pathranger.nu", flags);
+            println!("# and add this to your config.nu");
+            println!("source ~/.pathranger.nu");
+        }
+        Shell::Xonsh => {
+            println!("# Add this to your ~/.xonshrc");
+            println!("execx($(pathranger init {}), 'exec', __xonsh__.ctx, filename='pathranger')", flags);
+        }
+        Shell::Cmd => {
+            println!(":: cmd.exe runs the integration under Clink; save it once where Clink loads scripts:");
+            println!("pathranger init {} > \"%LOCALAPPDATA%\\clink\\pathranger.lua\"", flags);
+        }
+    }
+    
+    if let Some(completions) = shell.completions().and_then(|shell| shell.to_possible_value()) {
+        println!();
+        println!("# For tab completion, install the output of:");
+        println!("#   pathranger completions {}", completions.get_name());
+    }
+}
+
+/// Write the integration to a file the startup file can source directly
+fn write_shell_init_file(path: &Path, shell: Shell, options: &InitOptions) -> Result<()> {
+    let result = fs::File::create(path).and_then(|file| {
+        let mut out = io::BufWriter::new(file);
+        generate_shell_init(&mut out, shell, options)?;
+        out.flush()
+    });
+    result.map_err(|e| Error::Io(format!("Error writing {}", path.display()), e))?;
+    
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    note!("Wrote {} integration to {}", shell.name(), path.display());
+    if shell == Shell::Cmd {
+        note!("Let Clink load scripts from its directory:");
+        println!("clink installscripts \"{}\"", path.parent().unwrap_or(&path).display());
+    } else {
+        note!("Add this to your shell's startup file:");
+        println!("source \"{}\"", path.display());
+    }
+    Ok(())
+}
+
+fn generate_shell_init(out: &mut dyn Write, shell: Shell, options: &InitOptions) -> io::Result<()> {
+    let (hook, cmd, fallback) = (options.hook, options.cmd.as_str(), options.fallback);
+    let after_goto = &options.after_goto;
+    
+    match shell {
+        Shell::Bash => {
+            writeln!(out, "# PathRanger shell integration for bash")?;
+            writeln!(out, "export PATHRANGER_SESSION=bash")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "export PATHRANGER_DB={}", shell_quote(db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "export PATHRANGER_PROFILE={}", profile)?;
+            }
+            writeln!(out)?;
+            if hook == InitHook::Prompt {
+                writeln!(out, "# Record the directory whenever it changed since the last prompt, so cd,")?;
+                writeln!(out, "# pushd, popd and other tools' wrappers are all picked up")?;
+                writeln!(out, "__pathranger_hook() {{")?;
+                writeln!(out, "    local status=$?")?;
+                writeln!(out, "    if [[ \"${{__pathranger_oldpwd-}}\" != \"$PWD\" ]]; then")?;
+                writeln!(out, "        pathranger record --async --from \"${{__pathranger_oldpwd-}}\" \"$PWD\" >/dev/null 2>&1")?;
+                writeln!(out, "        __pathranger_oldpwd=\"$PWD\"")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "    return $status")?;
+                writeln!(out, "}}")?;
+                writeln!(out)?;
+                writeln!(out, "if [[ -n \"${{bash_preexec_imported-}}${{__bp_imported-}}\" ]]; then")?;
+                writeln!(out, "    if [[ \" ${{precmd_functions[*]}} \" != *\" __pathranger_hook \"* ]]; then")?;
+                writeln!(out, "        precmd_functions+=(__pathranger_hook)")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "elif [[ \"${{PROMPT_COMMAND-}}\" != *__pathranger_hook* ]]; then")?;
+                writeln!(out, "    PROMPT_COMMAND=\"__pathranger_hook${{PROMPT_COMMAND:+;$PROMPT_COMMAND}}\"")?;
+                writeln!(out, "fi")?;
+                writeln!(out)?;
+                writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                writeln!(out, "__pathranger_cd() {{")?;
+                writeln!(out, "    builtin cd -- \"$1\" && __pathranger_oldpwd=\"$PWD\"")?;
+                writeln!(out, "}}")?;
+            }
+            if hook == InitHook::Cd || fallback {
+                writeln!(out)?;
+                writeln!(out, "# Chain to an existing cd function or alias (such as zoxide's) instead of")?;
+                writeln!(out, "# replacing it")?;
+                writeln!(out, "__pathranger_chained=")?;
+                writeln!(out, "if [[ -n \"${{BASH_ALIASES[cd]-}}\" ]]; then")?;
+                writeln!(out, "    eval \"__pathranger_prev_cd() {{ ${{BASH_ALIASES[cd]}} \\\"\\$@\\\"; }}\"")?;
+                writeln!(out, "    __pathranger_chained=\"alias cd='${{BASH_ALIASES[cd]}}'\"")?;
+                writeln!(out, "    unalias cd")?;
+                writeln!(out, "elif declare -F cd >/dev/null && [[ \"$(declare -f cd)\" != *__pathranger_* ]]; then")?;
+                writeln!(out, "    eval \"__pathranger_prev_cd() $(declare -f cd | tail -n +2)\"")?;
+                writeln!(out, "    __pathranger_chained=\"function cd\"")?;
+                writeln!(out, "elif ! declare -F __pathranger_prev_cd >/dev/null; then")?;
+                writeln!(out, "    __pathranger_prev_cd() {{")?;
+                writeln!(out, "        builtin cd \"$@\"")?;
+                writeln!(out, "    }}")?;
+                writeln!(out, "fi")?;
+                writeln!(out, "if [[ -n \"${{__pathranger_chained-}}\" && $- == *i* ]]; then")?;
+                writeln!(out, "    echo \"pathranger: chained to existing $__pathranger_chained\" >&2")?;
+                writeln!(out, "fi")?;
+            }
+            if fallback {
+                writeln!(out)?;
+                writeln!(out, "# When cd fails on a bare name, go to pathranger's confident match for it")?;
+                writeln!(out, "# instead, or show cd's own error if there is none")?;
+                writeln!(out, "__pathranger_fallback_cd() {{")?;
+                writeln!(out, "    if [[ $# -ne 1 || -z \"$1\" || \"$1\" == */* || \"$1\" == -* ]]; then")?;
+                writeln!(out, "        __pathranger_prev_cd \"$@\"")?;
+                writeln!(out, "        return")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "    __pathranger_prev_cd \"$1\" 2>/dev/null && return")?;
+                writeln!(out, "    local dir")?;
+                writeln!(out, "    if dir=$(pathranger query -- \"$1\" 2>/dev/null && printf x); then")?;
+                writeln!(out, "        dir=${{dir%x}}")?;
+                writeln!(out, "        dir=${{dir%$'\\n'}}")?;
+                writeln!(out, "        if __pathranger_prev_cd \"$dir\"; then")?;
+                writeln!(out, "            if [[ -t 2 ]]; then")?;
+                writeln!(out, "                printf '\\033[2mpathranger: %s\\033[0m\\n' \"$dir\" >&2")?;
+                writeln!(out, "            else")?;
+                writeln!(out, "                printf 'pathranger: %s\\n' \"$dir\" >&2")?;
+                writeln!(out, "            fi")?;
+                writeln!(out, "            return 0")?;
+                writeln!(out, "        fi")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "    __pathranger_prev_cd \"$1\"")?;
+                writeln!(out, "}}")?;
+            }
+            match hook {
+                InitHook::Cd => {
+                    let change = if fallback { "__pathranger_fallback_cd" } else { "__pathranger_prev_cd" };
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd")?;
+                    writeln!(out, "cd() {{")?;
+                    writeln!(out, "    {change} \"$@\" || return")?;
+                    writeln!(out, "    pathranger record --async --from \"$OLDPWD\" \"$PWD\" >/dev/null 2>&1")?;
+                    writeln!(out, "}}")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                    writeln!(out, "__pathranger_cd() {{")?;
+                    writeln!(out, "    __pathranger_prev_cd \"$1\"")?;
+                    writeln!(out, "}}")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Record initial directory")?;
+                    writeln!(out, "pathranger record --async \"$PWD\" >/dev/null 2>&1")?;
+                }
+                InitHook::Prompt if fallback => {
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd to fall back to pathranger; the prompt hook records the visit")?;
+                    writeln!(out, "cd() {{")?;
+                    writeln!(out, "    __pathranger_fallback_cd \"$@\"")?;
+                    writeln!(out, "}}")?;
+                }
+                InitHook::Prompt => {}
+            }
+            writeln!(out)?;
+            if after_goto.is_empty() {
+                writeln!(out, "# Capture the goto result byte for byte, including trailing newlines")?;
+                writeln!(out, "__pathranger_goto() {{")?;
+                writeln!(out, "    local dir")?;
+            } else {
+                writeln!(out, "# Capture the goto result byte for byte, including trailing newlines, then")?;
+                writeln!(out, "# run the after_goto hooks unless --no-hooks was given")?;
+                writeln!(out, "__pathranger_goto() {{")?;
+                writeln!(out, "    local dir arg hooks=1")?;
+                writeln!(out, "    for arg in \"$@\"; do")?;
+                writeln!(out, "        if [[ \"$arg\" == --no-hooks ]]; then")?;
+                writeln!(out, "            hooks=")?;
+                writeln!(out, "        fi")?;
+                writeln!(out, "    done")?;
+            }
+            writeln!(out, "    dir=$(pathranger goto \"$@\" && printf x) || return")?;
+            writeln!(out, "    dir=${{dir%x}}")?;
+            writeln!(out, "    __pathranger_cd \"${{dir%$'\\n'}}\" || return")?;
+            if !after_goto.is_empty() {
+                writeln!(out, "    if [[ -n \"$hooks\" ]]; then")?;
+                writeln!(out, "        __pathranger_after_goto")?;
+                writeln!(out, "    fi")?;
+            }
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            if !after_goto.is_empty() {
+                writeln!(out, "# The after_goto hooks from the config; a failing hook doesn't fail goto")?;
+                writeln!(out, "__pathranger_after_goto() {{")?;
+                for hook in after_goto {
+                    writeln!(out, "    eval {}", quote_for(shell, hook))?;
+                }
+                writeln!(out, "    return 0")?;
+                writeln!(out, "}}")?;
+                writeln!(out)?;
+            }
+            writeln!(out, "# {cmd} goto alias")?;
+            writeln!(out, "{cmd}() {{")?;
+            writeln!(out, "    if [ \"$1\" = \"goto\" ] && [ -n \"$2\" ]; then")?;
+            writeln!(out, "        __pathranger_goto \"${{@:2}}\"")?;
+            writeln!(out, "    elif [[ \"$1\" =~ ^[0-9]+$ ]]; then")?;
+            writeln!(out, "        __pathranger_goto --top \"$@\"")?;
+            writeln!(out, "    else")?;
+            writeln!(out, "        pathranger \"$@\"")?;
+            writeln!(out, "    fi")?;
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            writeln!(out, "# Complete {cmd} like pathranger, loading its completions on first use")?;
+            writeln!(out, "__pathranger_complete() {{")?;
+            writeln!(out, "    if ! declare -F _pathranger_dynamic >/dev/null && declare -F _completion_loader >/dev/null; then")?;
+            writeln!(out, "        _completion_loader pathranger")?;
+            writeln!(out, "    fi")?;
+            writeln!(out, "    if declare -F _pathranger_dynamic >/dev/null; then")?;
+            writeln!(out, "        _pathranger_dynamic \"$@\"")?;
+            writeln!(out, "    fi")?;
+            writeln!(out, "}}")?;
+            writeln!(out, "complete -o bashdefault -o default -F __pathranger_complete {cmd}")?;
+        }
+        Shell::Zsh => {
+            writeln!(out, "# PathRanger shell integration for zsh")?;
+            writeln!(out, "export PATHRANGER_SESSION=zsh")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "export PATHRANGER_DB={}", shell_quote(db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "export PATHRANGER_PROFILE={}", profile)?;
+            }
+            writeln!(out)?;
+            if hook == InitHook::Prompt {
+                writeln!(out, "# Record the directory after every change, including pushd, popd and")?;
+                writeln!(out, "# other tools' cd wrappers")?;
+                writeln!(out, "__pathranger_hook() {{")?;
+                writeln!(out, "    if [[ -n \"${{__pathranger_skip-}}\" ]]; then")?;
+                writeln!(out, "        __pathranger_oldpwd=\"$PWD\"")?;
+                writeln!(out, "    elif [[ \"${{__pathranger_oldpwd-}}\" != \"$PWD\" ]]; then")?;
+                writeln!(out, "        pathranger record --async --from \"${{__pathranger_oldpwd-}}\" \"$PWD\" >/dev/null 2>&1")?;
+                writeln!(out, "        __pathranger_oldpwd=\"$PWD\"")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "}}")?;
+                writeln!(out)?;
+                writeln!(out, "if (( ! ${{chpwd_functions[(I)__pathranger_hook]}} )); then")?;
+                writeln!(out, "    chpwd_functions+=(__pathranger_hook)")?;
+                writeln!(out, "fi")?;
+                writeln!(out)?;
+                writeln!(out, "# Record initial directory")?;
+                writeln!(out, "__pathranger_hook")?;
+                writeln!(out)?;
+                writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                writeln!(out, "__pathranger_cd() {{")?;
+                writeln!(out, "    local __pathranger_skip=1")?;
+                writeln!(out, "    builtin cd -- \"$1\"")?;
+                writeln!(out, "}}")?;
+            }
+            if hook == InitHook::Cd || fallback {
+                writeln!(out)?;
+                writeln!(out, "# Chain to an existing cd function or alias (such as zoxide's) instead of")?;
+                writeln!(out, "# replacing it")?;
+                writeln!(out, "__pathranger_chained=")?;
+                writeln!(out, "if (( ${{+aliases[cd]}} )); then")?;
+                writeln!(out, "    eval \"__pathranger_prev_cd() {{ ${{aliases[cd]}} \\\"\\$@\\\" }}\"")?;
+                writeln!(out, "    __pathranger_chained=\"alias cd='${{aliases[cd]}}'\"")?;
+                writeln!(out, "    unalias cd")?;
+                writeln!(out, "elif (( ${{+functions[cd]}} )) && [[ \"${{functions[cd]}}\" != *__pathranger_* ]]; then")?;
+                writeln!(out, "    functions[__pathranger_prev_cd]=\"${{functions[cd]}}\"")?;
+                writeln!(out, "    __pathranger_chained=\"function cd\"")?;
+                writeln!(out, "elif (( ! ${{+functions[__pathranger_prev_cd]}} )); then")?;
+                writeln!(out, "    __pathranger_prev_cd() {{")?;
+                writeln!(out, "        builtin cd \"$@\"")?;
+                writeln!(out, "    }}")?;
+                writeln!(out, "fi")?;
+                writeln!(out, "if [[ -n \"${{__pathranger_chained-}}\" && -o interactive ]]; then")?;
+                writeln!(out, "    echo \"pathranger: chained to existing $__pathranger_chained\" >&2")?;
+                writeln!(out, "fi")?;
+            }
+            if fallback {
+                writeln!(out)?;
+                writeln!(out, "# When cd fails on a bare name, go to pathranger's confident match for it")?;
+                writeln!(out, "# instead, or show cd's own error if there is none")?;
+                writeln!(out, "__pathranger_fallback_cd() {{")?;
+                writeln!(out, "    if [[ $# -ne 1 || -z \"$1\" || \"$1\" == */* || \"$1\" == -* ]]; then")?;
+                writeln!(out, "        __pathranger_prev_cd \"$@\"")?;
+                writeln!(out, "        return")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "    __pathranger_prev_cd \"$1\" 2>/dev/null && return")?;
+                writeln!(out, "    local dir")?;
+                writeln!(out, "    if dir=$(pathranger query -- \"$1\" 2>/dev/null && printf x); then")?;
+                writeln!(out, "        dir=${{dir%x}}")?;
+                writeln!(out, "        dir=${{dir%$'\\n'}}")?;
+                writeln!(out, "        if __pathranger_prev_cd \"$dir\"; then")?;
+                writeln!(out, "            if [[ -t 2 ]]; then")?;
+                writeln!(out, "                printf '\\033[2mpathranger: %s\\033[0m\\n' \"$dir\" >&2")?;
+                writeln!(out, "            else")?;
+                writeln!(out, "                printf 'pathranger: %s\\n' \"$dir\" >&2")?;
+                writeln!(out, "            fi")?;
+                writeln!(out, "            return 0")?;
+                writeln!(out, "        fi")?;
+                writeln!(out, "    fi")?;
+                writeln!(out, "    __pathranger_prev_cd \"$1\"")?;
+                writeln!(out, "}}")?;
+            }
+            match hook {
+                InitHook::Cd => {
+                    let change = if fallback { "__pathranger_fallback_cd" } else { "__pathranger_prev_cd" };
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd")?;
+                    writeln!(out, "cd() {{")?;
+                    writeln!(out, "    {change} \"$@\" || return")?;
+                    writeln!(out, "    pathranger record --async --from \"$OLDPWD\" \"$PWD\" >/dev/null 2>&1")?;
+                    writeln!(out, "}}")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                    writeln!(out, "__pathranger_cd() {{")?;
+                    writeln!(out, "    __pathranger_prev_cd \"$1\"")?;
+                    writeln!(out, "}}")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Record initial directory")?;
+                    writeln!(out, "pathranger record --async \"$PWD\" >/dev/null 2>&1")?;
+                }
+                InitHook::Prompt if fallback => {
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd to fall back to pathranger; the chpwd hook records the visit")?;
+                    writeln!(out, "cd() {{")?;
+                    writeln!(out, "    __pathranger_fallback_cd \"$@\"")?;
+                    writeln!(out, "}}")?;
+                }
+                InitHook::Prompt => {}
+            }
+            writeln!(out)?;
+            if after_goto.is_empty() {
+                writeln!(out, "# Capture the goto result byte for byte, including trailing newlines")?;
+                writeln!(out, "__pathranger_goto() {{")?;
+                writeln!(out, "    local dir")?;
+            } else {
+                writeln!(out, "# Capture the goto result byte for byte, including trailing newlines, then")?;
+                writeln!(out, "# run the after_goto hooks unless --no-hooks was given")?;
+                writeln!(out, "__pathranger_goto() {{")?;
+                writeln!(out, "    local dir arg hooks=1")?;
+                writeln!(out, "    for arg in \"$@\"; do")?;
+                writeln!(out, "        if [[ \"$arg\" == --no-hooks ]]; then")?;
+                writeln!(out, "            hooks=")?;
+                writeln!(out, "        fi")?;
+                writeln!(out, "    done")?;
+            }
+            writeln!(out, "    dir=$(pathranger goto \"$@\" && printf x) || return")?;
+            writeln!(out, "    dir=${{dir%x}}")?;
+            writeln!(out, "    __pathranger_cd \"${{dir%$'\\n'}}\" || return")?;
+            if !after_goto.is_empty() {
+                writeln!(out, "    if [[ -n \"$hooks\" ]]; then")?;
+                writeln!(out, "        __pathranger_after_goto")?;
+                writeln!(out, "    fi")?;
+            }
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            if !after_goto.is_empty() {
+                writeln!(out, "# The after_goto hooks from the config; a failing hook doesn't fail goto")?;
+                writeln!(out, "__pathranger_after_goto() {{")?;
+                for hook in after_goto {
+                    writeln!(out, "    eval {}", quote_for(shell, hook))?;
+                }
+                writeln!(out, "    return 0")?;
+                writeln!(out, "}}")?;
+                writeln!(out)?;
+            }
+            writeln!(out, "# {cmd} goto alias")?;
+            writeln!(out, "{cmd}() {{")?;
+            writeln!(out, "    if [[ \"$1\" = \"goto\" && -n \"$2\" ]]; then")?;
+            writeln!(out, "        __pathranger_goto \"${{@:2}}\"")?;
+            writeln!(out, "    elif [[ \"$1\" =~ ^[0-9]+$ ]]; then")?;
+            writeln!(out, "        __pathranger_goto --top \"$@\"")?;
+            writeln!(out, "    else")?;
+            writeln!(out, "        pathranger \"$@\"")?;
+            writeln!(out, "    fi")?;
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            writeln!(out, "# Complete {cmd} like pathranger")?;
+            writeln!(out, "if (( $+functions[compdef] )); then")?;
+            writeln!(out, "    compdef {cmd}=pathranger")?;
+            writeln!(out, "fi")?;
+        }
+        Shell::Fish => {
+            writeln!(out, "# PathRanger shell integration for fish")?;
+            writeln!(out, "set -gx PATHRANGER_SESSION fish")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "set -gx PATHRANGER_DB {}", fish_quote(db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "set -gx PATHRANGER_PROFILE {}", profile)?;
+            }
+            writeln!(out)?;
+            if hook == InitHook::Prompt {
+                writeln!(out, "# Record the directory whenever PWD changes, whatever changed it")?;
+                writeln!(out, "function __pathranger_hook --on-variable PWD")?;
+                writeln!(out, "    if not set -q __pathranger_skip")?;
+                writeln!(out, "        pathranger record --async --from \"$__pathranger_oldpwd\" \"$PWD\" >/dev/null 2>&1")?;
+                writeln!(out, "    end")?;
+                writeln!(out, "    set -g __pathranger_oldpwd $PWD")?;
+                writeln!(out, "end")?;
+                writeln!(out)?;
+                writeln!(out, "# Record initial directory")?;
+                writeln!(out, "pathranger record --async \"$PWD\" >/dev/null 2>&1")?;
+                writeln!(out, "set -g __pathranger_oldpwd $PWD")?;
+                writeln!(out)?;
+                writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                writeln!(out, "function __pathranger_cd")?;
+                writeln!(out, "    set -g __pathranger_skip 1")?;
+                writeln!(out, "    builtin cd $argv[1]")?;
+                writeln!(out, "    set -l ret $status")?;
+                writeln!(out, "    set -e __pathranger_skip")?;
+                writeln!(out, "    return $ret")?;
+                writeln!(out, "end")?;
+            }
+            if hook == InitHook::Cd || fallback {
+                writeln!(out)?;
+                writeln!(out, "# Chain to an existing cd function (fish's own, or a wrapper such as")?;
+                writeln!(out, "# zoxide's) instead of replacing it")?;
+                writeln!(out, "if functions -q cd; and not functions cd | string match -q '*__pathranger_*'")?;
+                writeln!(out, "    functions -e __pathranger_prev_cd")?;
+                writeln!(out, "    functions -c cd __pathranger_prev_cd")?;
+                writeln!(out, "    if not string match -q \"$__fish_data_dir/*\" -- (functions --details cd); and status is-interactive")?;
+                writeln!(out, "        echo \"pathranger: chained to existing function cd\" >&2")?;
+                writeln!(out, "    end")?;
+                writeln!(out, "else if not functions -q __pathranger_prev_cd")?;
+                writeln!(out, "    function __pathranger_prev_cd")?;
+                writeln!(out, "        builtin cd $argv")?;
+                writeln!(out, "    end")?;
+                writeln!(out, "end")?;
+            }
+            if fallback {
+                writeln!(out)?;
+                writeln!(out, "# When cd fails on a bare name, go to pathranger's confident match for it")?;
+                writeln!(out, "# instead, or show cd's own error if there is none")?;
+                writeln!(out, "function __pathranger_fallback_cd")?;
+                writeln!(out, "    if test (count $argv) -ne 1; or test -z \"$argv[1]\"; or string match -q -- '*/*' \"$argv[1]\"; or string match -q -- '-*' \"$argv[1]\"")?;
+                writeln!(out, "        __pathranger_prev_cd $argv")?;
+                writeln!(out, "        return")?;
+                writeln!(out, "    end")?;
+                writeln!(out, "    __pathranger_prev_cd \"$argv[1]\" 2>/dev/null; and return")?;
+                writeln!(out, "    set -l dir")?;
+                writeln!(out, "    if set dir (pathranger query -- \"$argv[1]\" 2>/dev/null | string collect); and __pathranger_prev_cd \"$dir\"")?;
+                writeln!(out, "        set_color --dim >&2")?;
+                writeln!(out, "        echo \"pathranger: $dir\" >&2")?;
+                writeln!(out, "        set_color normal >&2")?;
+                writeln!(out, "        return 0")?;
+                writeln!(out, "    end")?;
+                writeln!(out, "    __pathranger_prev_cd \"$argv[1]\"")?;
+                writeln!(out, "end")?;
+            }
+            match hook {
+                InitHook::Cd => {
+                    let change = if fallback { "__pathranger_fallback_cd" } else { "__pathranger_prev_cd" };
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd")?;
+                    writeln!(out, "function cd")?;
+                    writeln!(out, "    set -l from $PWD")?;
+                    writeln!(out, "    {change} $argv; or return")?;
+                    writeln!(out, "    pathranger record --async --from \"$from\" \"$PWD\" >/dev/null 2>&1")?;
+                    writeln!(out, "end")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Change directory without recording it again; goto already counted the visit")?;
+                    writeln!(out, "function __pathranger_cd")?;
+                    writeln!(out, "    __pathranger_prev_cd $argv[1]")?;
+                    writeln!(out, "end")?;
+                    writeln!(out)?;
+                    writeln!(out, "# Record initial directory")?;
+                    writeln!(out, "pathranger record --async \"$PWD\" >/dev/null 2>&1")?;
+                }
+                InitHook::Prompt if fallback => {
+                    writeln!(out)?;
+                    writeln!(out, "# Override cd to fall back to pathranger; the PWD event records the visit")?;
+                    writeln!(out, "function cd")?;
+                    writeln!(out, "    __pathranger_fallback_cd $argv")?;
+                    writeln!(out, "end")?;
+                }
+                InitHook::Prompt => {}
+            }
+            writeln!(out)?;
+            if after_goto.is_empty() {
+                writeln!(out, "# Keep the goto result as one argument even if it contains newlines")?;
+            } else {
+                writeln!(out, "# Keep the goto result as one argument even if it contains newlines, then")?;
+                writeln!(out, "# run the after_goto hooks unless --no-hooks was given")?;
+            }
+            writeln!(out, "function __pathranger_goto")?;
+            writeln!(out, "    set -l dir (pathranger goto $argv | string collect); or return")?;
+            writeln!(out, "    __pathranger_cd \"$dir\"; or return")?;
+            if !after_goto.is_empty() {
+                writeln!(out, "    if not contains -- --no-hooks $argv")?;
+                writeln!(out, "        __pathranger_after_goto")?;
+                writeln!(out, "    end")?;
+            }
+            writeln!(out, "end")?;
+            writeln!(out)?;
+            if !after_goto.is_empty() {
+                writeln!(out, "# The after_goto hooks from the config; a failing hook doesn't fail goto")?;
+                writeln!(out, "function __pathranger_after_goto")?;
+                for hook in after_goto {
+                    writeln!(out, "    eval {}", quote_for(shell, hook))?;
+                }
+                writeln!(out, "    return 0")?;
+                writeln!(out, "end")?;
+                writeln!(out)?;
+            }
+            writeln!(out, "# {cmd} goto alias")?;
+            writeln!(out, "function {cmd}")?;
+            writeln!(out, "    if test \"$argv[1]\" = \"goto\"; and test -n \"$argv[2]\"")?;
+            writeln!(out, "        __pathranger_goto $argv[2..-1]")?;
+            writeln!(out, "    else if string match -qr '^[0-9]+$' -- \"$argv[1]\"")?;
+            writeln!(out, "        __pathranger_goto --top $argv")?;
+            writeln!(out, "    else")?;
+            writeln!(out, "        pathranger $argv")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "end")?;
+            writeln!(out)?;
+            writeln!(out, "# Complete {cmd} like pathranger")?;
+            writeln!(out, "complete -c {cmd} -w pathranger")?;
+        }
+        Shell::Xonsh => {
+            writeln!(out, "# PathRanger shell integration for xonsh")?;
+            writeln!(out, "import os as __pathranger_os")?;
+            writeln!(out, "import re as __pathranger_re")?;
+            writeln!(out, "import shutil as __pathranger_shutil")?;
+            writeln!(out, "import subprocess as __pathranger_subprocess")?;
+            writeln!(out, "import sys as __pathranger_sys")?;
+            writeln!(out)?;
+            writeln!(out, "$PATHRANGER_SESSION = 'xonsh'")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "$PATHRANGER_DB = {}", quote_for(Shell::Xonsh, db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "$PATHRANGER_PROFILE = '{}'", profile)?;
+            }
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "def __pathranger_bin():")?;
+            writeln!(out, "    return __pathranger_shutil.which('pathranger')")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "def __pathranger_record(path, olddir=''):")?;
+            writeln!(out, "    exe = __pathranger_bin()")?;
+            writeln!(out, "    if exe is None:")?;
+            writeln!(out, "        return")?;
+            writeln!(out, "    __pathranger_subprocess.run(")?;
+            writeln!(out, "        [exe, 'record', '--async', '--from', olddir or '', path],")?;
+            writeln!(out, "        stdout=__pathranger_subprocess.DEVNULL,")?;
+            writeln!(out, "        stderr=__pathranger_subprocess.DEVNULL,")?;
+            writeln!(out, "    )")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "# Set while goto changes directory, since goto already counted that visit")?;
+            writeln!(out, "__pathranger_state = {{'skip': False}}")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "@events.on_chdir")?;
+            writeln!(out, "def __pathranger_on_chdir(olddir, newdir, **kwargs):")?;
+            writeln!(out, "    if __pathranger_state['skip']:")?;
+            writeln!(out, "        return")?;
+            writeln!(out, "    __pathranger_record(newdir, olddir)")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "def __pathranger_goto(args):")?;
+            writeln!(out, "    exe = __pathranger_bin()")?;
+            writeln!(out, "    if exe is None:")?;
+            writeln!(out, "        print('pathranger: binary not found on PATH', file=__pathranger_sys.stderr)")?;
+            writeln!(out, "        return 1")?;
+            writeln!(out, "    result = __pathranger_subprocess.run(")?;
+            writeln!(out, "        [exe, 'goto'] + list(args),")?;
+            writeln!(out, "        stdout=__pathranger_subprocess.PIPE,")?;
+            writeln!(out, "        text=True,")?;
+            writeln!(out, "    )")?;
+            writeln!(out, "    path = result.stdout.removesuffix('\\n')")?;
+            writeln!(out, "    if result.returncode != 0 or not path:")?;
+            writeln!(out, "        return result.returncode or 1")?;
+            writeln!(out, "    # xonsh's cd keeps $PWD and dirstack in sync and fires on_chdir")?;
+            writeln!(out, "    __pathranger_state['skip'] = True")?;
+            writeln!(out, "    try:")?;
+            writeln!(out, "        aliases['cd']([path])")?;
+            writeln!(out, "    finally:")?;
+            writeln!(out, "        __pathranger_state['skip'] = False")?;
+            if !after_goto.is_empty() {
+                writeln!(out, "    if '--no-hooks' not in args:")?;
+                writeln!(out, "        __pathranger_after_goto()")?;
+            }
+            writeln!(out, "    return 0")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            if !after_goto.is_empty() {
+                let hooks: Vec<String> = after_goto.iter().map(|hook| quote_for(shell, hook)).collect();
+                writeln!(out, "def __pathranger_after_goto():")?;
+                writeln!(out, "    # The after_goto hooks from the config; a failing hook doesn't fail goto")?;
+                writeln!(out, "    for hook in [{}]:", hooks.join(", "))?;
+                writeln!(out, "        try:")?;
+                writeln!(out, "            execx(hook, 'exec', __xonsh__.ctx)")?;
+                writeln!(out, "        except Exception as e:")?;
+                writeln!(out, "            print(f'pathranger: hook failed: {{e}}', file=__pathranger_sys.stderr)")?;
+                writeln!(out)?;
+                writeln!(out)?;
+            }
+            writeln!(out, "# {cmd} goto alias")?;
+            writeln!(out, "def __pathranger_pr(args):")?;
+            writeln!(out, "    if len(args) >= 2 and args[0] == 'goto':")?;
+            writeln!(out, "        return __pathranger_goto(args[1:])")?;
+            writeln!(out, "    if len(args) >= 1 and __pathranger_re.fullmatch(r'[0-9]+', args[0]):")?;
+            writeln!(out, "        return __pathranger_goto(['--top'] + list(args))")?;
+            writeln!(out, "    exe = __pathranger_bin()")?;
+            writeln!(out, "    if exe is None:")?;
+            writeln!(out, "        print('pathranger: binary not found on PATH', file=__pathranger_sys.stderr)")?;
+            writeln!(out, "        return 1")?;
+            writeln!(out, "    return __pathranger_subprocess.run([exe] + list(args)).returncode")?;
+            writeln!(out)?;
+            writeln!(out)?;
+            writeln!(out, "aliases['{cmd}'] = __pathranger_pr")?;
+            writeln!(out)?;
+            writeln!(out, "# Record initial directory")?;
+            writeln!(out, "__pathranger_record(__pathranger_os.getcwd())")?;
+        }
+        Shell::Nu => {
+            writeln!(out, "# PathRanger shell integration for nushell")?;
+            writeln!(out, "export-env {{")?;
+            writeln!(out, "    $env.PATHRANGER_SESSION = 'nu'")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "    $env.PATHRANGER_DB = {}", nu_quote(db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "    $env.PATHRANGER_PROFILE = '{}'", profile)?;
+            }
+            writeln!(out, "    $env.config = (")?;
+            writeln!(out, "        $env.config?")?;
+            writeln!(out, "        | default {{}}")?;
+            writeln!(out, "        | upsert hooks {{ default {{}} }}")?;
+            writeln!(out, "        | upsert hooks.env_change {{ default {{}} }}")?;
+            writeln!(out, "        | upsert hooks.env_change.PWD {{ default [] }}")?;
+            writeln!(out, "    )")?;
+            writeln!(out, "    ")?;
+            writeln!(out, "    # Record every directory change, once even if this file is sourced twice,")?;
+            writeln!(out, "    # except the one goto made, which goto already counted")?;
+            writeln!(out, "    let hooked = ($env.config.hooks.env_change.PWD | any {{|hook| try {{ $hook.__pathranger_hook }} catch {{ false }} }})")?;
+            writeln!(out, "    if not $hooked {{")?;
+            writeln!(out, "        $env.config.hooks.env_change.PWD = ($env.config.hooks.env_change.PWD | append {{")?;
+            writeln!(out, "            __pathranger_hook: true,")?;
+            writeln!(out, "            code: {{|before, dir|")?;
+            writeln!(out, "                let skip = ($env.__PATHRANGER_SKIP? | default '') == $dir")?;
+            writeln!(out, "                $env.__PATHRANGER_SKIP = ''")?;
+            writeln!(out, "                if not $skip {{")?;
+            writeln!(out, "                    ^pathranger record --async --from ($before | default '') $dir | ignore")?;
+            writeln!(out, "                }}")?;
+            writeln!(out, "            }}")?;
+            writeln!(out, "        }})")?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            writeln!(out, "# Change to the directory goto printed, telling the PWD hook to leave it")?;
+            writeln!(out, "def --env __pathranger_cd [dir: string] {{")?;
+            writeln!(out, "    if ($dir | is-not-empty) and $dir != $env.PWD {{")?;
+            writeln!(out, "        $env.__PATHRANGER_SKIP = $dir")?;
+            writeln!(out, "        cd $dir")?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+            writeln!(out)?;
+            writeln!(out, "# {cmd} goto alias")?;
+            writeln!(out, "def --env --wrapped {cmd} [...args: string] {{")?;
+            writeln!(out, "    if ($args | length) >= 2 and $args.0 == \"goto\" {{")?;
+            writeln!(out, "        __pathranger_cd (^pathranger goto ...($args | skip 1) | str trim --right --char (char newline))")?;
+            writeln!(out, "    }} else if ($args | length) == 1 and ($args.0 =~ '^[0-9]+$') {{")?;
+            writeln!(out, "        __pathranger_cd (^pathranger goto --top $args.0 | str trim --right --char (char newline))")?;
+            writeln!(out, "    }} else {{")?;
+            writeln!(out, "        ^pathranger ...$args")?;
+            writeln!(out, "    }}")?;
+            writeln!(out, "}}")?;
+        }
+        Shell::Cmd => {
+            // The command name as a Lua pattern; '-' is the only magic
+            // character parse_cmd_name lets through
+            let pattern = cmd.replace('-', "%-");
+            writeln!(out, "-- PathRanger shell integration for cmd.exe")?;
+            writeln!(out, "-- Requires Clink (https://chrisant996.github.io/clink/), which loads this")?;
+            writeln!(out, "-- file from its scripts directory; plain cmd.exe has no prompt hook")?;
+            writeln!(out, "os.setenv(\"PATHRANGER_SESSION\", \"cmd\")")?;
+            if let Some(db) = &options.db {
+                writeln!(out, "os.setenv(\"PATHRANGER_DB\", {})", lua_quote(db))?;
+            }
+            if let Some(profile) = &options.profile {
+                writeln!(out, "os.setenv(\"PATHRANGER_PROFILE\", \"{}\")", profile)?;
+            }
+            writeln!(out)?;
+            writeln!(out, "-- Quote one argument for cmd.exe, keeping spaces, parentheses and &")?;
+            writeln!(out, "-- inside it")?;
+            writeln!(out, "local function pathranger_quote(arg)")?;
+            writeln!(out, "    return '\"' .. arg:gsub('\"', '\"\"') .. '\"'")?;
+            writeln!(out, "end")?;
+            writeln!(out)?;
+            writeln!(out, "-- Record the directory whenever it changed since the last prompt; case is")?;
+            writeln!(out, "-- ignored, as it is by the filesystem")?;
+            writeln!(out, "local pathranger_dir = nil")?;
+            writeln!(out, "local pathranger_prompt = clink.promptfilter(1)")?;
+            writeln!(out, "function pathranger_prompt:filter(prompt)")?;
+            writeln!(out, "    local dir = os.getcwd()")?;
+            writeln!(out, "    if pathranger_dir == nil or dir:lower() ~= pathranger_dir:lower() then")?;
+            writeln!(out, "        os.execute('pathranger record --async --from ' .. pathranger_quote(pathranger_dir or '')")?;
+            writeln!(out, "            .. ' ' .. pathranger_quote(dir) .. ' >nul 2>&1')")?;
+            writeln!(out, "        pathranger_dir = dir")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "end")?;
+            writeln!(out)?;
+            writeln!(out, "-- Turn `{cmd} goto ...` and `{cmd} N` into a cd /d to the directory goto printed,")?;
+            writeln!(out, "-- without recording it again; goto already counted the visit. Anything")?;
+            writeln!(out, "-- else {cmd} is given goes to pathranger as typed.")?;
+            writeln!(out, "local function pathranger_input(line)")?;
+            writeln!(out, "    local args = line:match('^%s*{pattern}%s+(.-)%s*$') or (line:match('^%s*{pattern}%s*$') and '')")?;
+            writeln!(out, "    if args == nil then")?;
+            writeln!(out, "        return nil")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "    local target = args:match('^goto%s+(%S.*)$')")?;
+            writeln!(out, "    if target == nil and (args:match('^%d+$') or args:match('^%d+%s')) then")?;
+            writeln!(out, "        target = '--top ' .. args")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "    if target == nil then")?;
+            writeln!(out, "        return 'pathranger ' .. args")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "    local result = io.popen('pathranger goto ' .. target)")?;
+            writeln!(out, "    local dir = result:read('*a'):gsub('\\r?\\n$', '')")?;
+            writeln!(out, "    if not result:close() or dir == '' then")?;
+            writeln!(out, "        return ''")?;
+            writeln!(out, "    end")?;
+            writeln!(out, "    pathranger_dir = dir")?;
+            writeln!(out, "    return 'cd /d ' .. pathranger_quote(dir)")?;
+            writeln!(out, "end")?;
+            writeln!(out)?;
+            writeln!(out, "if clink.onfilterinput then")?;
+            writeln!(out, "    clink.onfilterinput(pathranger_input)")?;
+            writeln!(out, "else")?;
+            writeln!(out, "    print('pathranger: {cmd} needs a newer Clink, with clink.onfilterinput')")?;
+            writeln!(out, "end")?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// Characters used to draw `--tree` views
+struct TreeGlyphs {
+    branch: &'static str,
+    last: &'static str,
+    vertical: &'static str,
+    blank: &'static str,
+    /// Between a tag and its directory in `tags --tree`
+    arrow: &'static str,
+}
+
+impl TreeGlyphs {
+    const UNICODE: TreeGlyphs = TreeGlyphs { branch: "├── ", last: "└── ", vertical: "│   ", blank: "    ", arrow: "→" };
+    const ASCII: TreeGlyphs = TreeGlyphs { branch: "|-- ", last: "`-- ", vertical: "|   ", blank: "    ", arrow: "->" };
+    
+    /// Box-drawing characters unless `--ascii` was given or the locale isn't UTF-8
+    fn detect(ascii: bool) -> TreeGlyphs {
+        if ascii || !locale_is_utf8() {
+            TreeGlyphs::ASCII
+        } else {
+            TreeGlyphs::UNICODE
+        }
+    }
+}
+
+/// Whether the terminal can be expected to render UTF-8, judged by the first
+/// of LC_ALL, LC_CTYPE and LANG that is set (Windows terminals always can)
+fn locale_is_utf8() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|value| {
+            let value = value.to_lowercase();
+            value.contains("utf-8") || value.contains("utf8")
+        })
+}
+
+/// A directory in a `--tree` view. Chains of directories that have no visits
+/// of their own and a single child are collapsed into one node.
+#[derive(Default)]
+struct TreeNode {
+    name: String,
+    /// Visits of this exact directory, if it was one of the selected rows
+    own: Option<i64>,
+    /// Visits of this directory and everything below it
+    total: i64,
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    /// Add an entry under its display path, so home directories group under `~`
+    fn insert(&mut self, entry: &DirEntry) {
+        let mut node = self;
+        for component in Path::new(&format_path(&entry.path)).components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name.clone()).or_insert_with(|| TreeNode { name, ..Default::default() });
+        }
+        node.own = Some(node.own.unwrap_or(0) + entry.visit_count);
+    }
+    
+    /// Fill in subtree totals and collapse single-child chains
+    fn finish(&mut self) {
+        for child in self.children.values_mut() {
+            child.finish();
+        }
+        
+        while self.own.is_none() && self.children.len() == 1 {
+            let (_, child) = self.children.pop_first().unwrap_or_default();
+            self.name = join_components(&self.name, &child.name);
+            self.own = child.own;
+            self.children = child.children;
+        }
+        
+        self.total = self.own.unwrap_or(0) + self.children.values().map(|child| child.total).sum::<i64>();
+    }
+    
+    /// Children with the busiest subtrees first
+    fn sorted_children(&self) -> Vec<&TreeNode> {
+        let mut children: Vec<&TreeNode> = self.children.values().collect();
+        children.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        children
+    }
+}
+
+fn join_components(parent: &str, child: &str) -> String {
+    if parent.is_empty() || parent.ends_with('/') {
+        format!("{}{}", parent, child)
+    } else {
+        format!("{}/{}", parent, child)
+    }
+}
+
+/// Render entries as an indented tree grouped by common ancestors. Each node
+/// shows its own visit count and, when it has children, the subtree total.
+fn render_tree(entries: &[DirEntry], glyphs: &TreeGlyphs, theme: &Theme) -> Vec<String> {
+    let mut root = TreeNode::default();
+    for entry in entries {
+        root.insert(entry);
+    }
+    for child in root.children.values_mut() {
+        child.finish();
+    }
+    
+    fn counts(node: &TreeNode, theme: &Theme) -> String {
+        match (node.own, node.children.is_empty()) {
+            (Some(own), true) => theme.count(own.to_string()),
+            (Some(own), false) => format!("{} ({} total)", theme.count(own.to_string()), node.total),
+            (None, _) => format!("({} total)", node.total),
+        }
+    }
+    
+    fn walk(node: &TreeNode, prefix: &str, glyphs: &TreeGlyphs, theme: &Theme, lines: &mut Vec<String>) {
+        let children = node.sorted_children();
+        for (i, child) in children.iter().enumerate() {
+            let is_last = i + 1 == children.len();
+            let branch = if is_last { glyphs.last } else { glyphs.branch };
+            lines.push(format!("{}{}{}  {}", prefix, branch, theme.path(&child.name), counts(child, theme)));
+            
+            let continuation = if is_last { glyphs.blank } else { glyphs.vertical };
+            walk(child, &format!("{}{}", prefix, continuation), glyphs, theme, lines);
+        }
+    }
+    
+    let mut lines = Vec::new();
+    let mut tops: Vec<&TreeNode> = root.children.values().collect();
+    tops.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+    for top in tops {
+        lines.push(format!("{}  {}", theme.path(&top.name), counts(top, theme)));
+        walk(top, "", glyphs, theme, &mut lines);
+    }
+    
+    lines
+}
+
+/// A directory in `tags --tree`, holding the tags that point at it
+#[derive(Default)]
+struct TagTreeNode {
+    name: String,
+    /// Tags pointing at exactly this directory, with their origin's badge
+    tags: Vec<(String, Option<&'static str>)>,
+    /// Tags pointing here or anywhere below
+    total: usize,
+    children: BTreeMap<String, TagTreeNode>,
+}
+
+impl TagTreeNode {
+    /// Add a tag under its target's display path, so home directories group under `~`
+    fn insert(&mut self, tag: &str, badge: Option<&'static str>, path: &str) {
+        let mut node = self;
+        for component in Path::new(&format_path(path)).components() {
+            let name = component.as_os_str().to_string_lossy().into_owned();
+            node = node.children.entry(name.clone()).or_insert_with(|| TagTreeNode { name, ..Default::default() });
+        }
+        node.tags.push((tag.to_string(), badge));
+    }
+    
+    /// Fill in subtree totals
+    fn finish(&mut self) {
+        for child in self.children.values_mut() {
+            child.finish();
+        }
+        self.tags.sort();
+        self.total = self.tags.len() + self.children.values().map(|child| child.total).sum::<usize>();
+    }
+    
+    /// Follow a chain of directories with no tags and a single child down
+    /// to where it branches or a tag points, returning that node and the
+    /// chain's path
+    fn collapsed(&self) -> (&TagTreeNode, String) {
+        let mut node = self;
+        let mut name = self.name.clone();
+        while node.tags.is_empty() && node.children.len() == 1 {
+            let Some(child) = node.children.values().next() else {
+                break;
+            };
+            name = join_components(&name, &child.name);
+            node = child;
+        }
+        (node, name)
+    }
+}
+
+/// Render tags as an indented tree of the directories their targets have in
+/// common. Directories show how many tags lie below them; a tag alone in its
+/// branch is a leaf, `name → rest/of/path`, and one pointing at a directory
+/// shown in the tree is listed under it as `name → .`.
+fn render_tag_tree(tags: &[(&str, Option<&'static str>, &str)], glyphs: &TreeGlyphs, theme: &Theme) -> Vec<String> {
+    let mut root = TagTreeNode::default();
+    for (tag, badge, path) in tags {
+        root.insert(tag, *badge, path);
+    }
+    root.finish();
+    
+    fn leaf(tag: &(String, Option<&'static str>), rest: &str, glyphs: &TreeGlyphs, theme: &Theme) -> String {
+        let badge = tag.1.map(|badge| format!(" {}", theme.tag(format!("[{}]", badge)))).unwrap_or_default();
+        format!("{}{} {} {}", theme.tag(&tag.0), badge, glyphs.arrow, theme.path(rest))
+    }
+    
+    fn sorted(children: &BTreeMap<String, TagTreeNode>) -> Vec<&TagTreeNode> {
+        let mut children: Vec<&TagTreeNode> = children.values().collect();
+        children.sort_by(|a, b| b.total.cmp(&a.total).then_with(|| a.name.cmp(&b.name)));
+        children
+    }
+    
+    /// The line for `node` itself, then the lines below it
+    fn render(node: &TagTreeNode, prefix: &str, glyphs: &TreeGlyphs, theme: &Theme, lines: &mut Vec<String>) {
+        let (node, name) = node.collapsed();
+        if let ([tag], true) = (node.tags.as_slice(), node.children.is_empty()) {
+            lines.push(leaf(tag, &name, glyphs, theme));
+            return;
+        }
+        lines.push(format!("{}  ({} tag{})", theme.path(&name), node.total, if node.total == 1 { "" } else { "s" }));
+        
+        let children = sorted(&node.children);
+        let count = node.tags.len() + children.len();
+        for i in 0..count {
+            let is_last = i + 1 == count;
+            let branch = if is_last { glyphs.last } else { glyphs.branch };
+            let continuation = format!("{}{}", prefix, if is_last { glyphs.blank } else { glyphs.vertical });
+            let start = lines.len();
+            match node.tags.get(i) {
+                Some(tag) => lines.push(leaf(tag, ".", glyphs, theme)),
+                None => render(children[i - node.tags.len()], &continuation, glyphs, theme, lines),
+            }
+            lines[start] = format!("{}{}{}", prefix, branch, lines[start]);
+        }
+    }
+    
+    let mut lines = Vec::new();
+    for top in sorted(&root.children) {
+        render(top, "", glyphs, theme, &mut lines);
+    }
+    lines
+}
+
+/// A pager process that stdout has been redirected into, like git does for
+/// long output. Dropping it closes the pipe and waits for the user to quit.
+struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// Start `$PATHRANGER_PAGER`, `$PAGER` or `less` and point stdout at it.
+    ///
+    /// `less` gets `LESS=FRXK` unless the user set their own: quit when the
+    /// output fits on one screen, keep colors, and exit on Ctrl-C. We ignore
+    /// SIGINT ourselves so the pager always outlives us, and die quietly from
+    /// SIGPIPE if it quits before we have finished writing.
+    #[cfg(unix)]
+    fn start() -> Option<Pager> {
+        use std::os::unix::io::AsRawFd;
+        
+        let command = std::env::var("PATHRANGER_PAGER")
+            .or_else(|_| std::env::var("PAGER"))
+            .unwrap_or_else(|_| "less".to_string());
+        let command = command.trim();
+        if command.is_empty() || command == "cat" {
+            return None;
+        }
+        
+        let mut pager = Command::new("sh");
+        pager.arg("-c").arg(command).stdin(Stdio::piped());
+        if std::env::var_os("LESS").is_none() {
+            pager.env("LESS", "FRXK");
+        }
+        
+        let mut child = pager.spawn().ok()?;
+        let stdin = child.stdin.take()?;
+        let _ = io::stdout().flush();
+        
+        // SAFETY: plain fd and signal-disposition calls with valid arguments
+        let redirected = unsafe { libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) >= 0 };
+        if !redirected {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_IGN);
+        }
+        
+        Some(Pager { child })
+    }
+    
+    #[cfg(not(unix))]
+    fn start() -> Option<Pager> {
+        None
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        
+        // Close the write end so the pager sees EOF
+        #[cfg(unix)]
+        unsafe {
+            libc::close(libc::STDOUT_FILENO);
+        }
+        
+        let _ = self.child.wait();
+    }
+}
+
+/// How a listing prints its rows
+enum OutputMode {
+    /// Aligned, colored table for people (tab-separated when piped)
+    Human,
+    /// Bare paths, each terminated by a NUL byte
+    Print0,
+    /// Bare absolute paths, one per line
+    PathsOnly,
+    /// One line per row rendered from a `--format` template
+    Template(Template),
+    /// Rows grouped under their common ancestors
+    Tree(TreeGlyphs),
+    /// Tab-separated escaped fields in a `--porcelain` version's layout
+    Porcelain(Porcelain),
+}
+
+impl OutputMode {
+    /// Pick the mode from a listing's output flags. `score_available` is false
+    /// for listings that have no search score to offer to a template.
+    fn from_args(args: &OutputArgs, score_available: bool) -> Result<Self> {
+        if let Some(version) = args.porcelain {
+            return Ok(OutputMode::Porcelain(version));
+        }
+        if args.print0 {
+            return Ok(OutputMode::Print0);
+        }
+        if args.paths_only {
+            return Ok(OutputMode::PathsOnly);
+        }
+        
+        let Some(format) = &args.format else {
+            return Ok(OutputMode::Human);
+        };
+        
+        let template = Template::parse(format)
+            .map_err(|e| Error::InvalidInput(format!("Invalid --format template: {}", e)))?;
+        if !score_available && template.uses(TemplateField::Score) {
+            return Err(Error::InvalidInput(String::from(
+                "Invalid --format template: {score} is only available in search",
+            )));
+        }
+        
+        Ok(OutputMode::Template(template))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TemplateField {
+    Rank,
+    Path,
+    PathAbs,
+    PathTilde,
+    Count,
+    LastVisited,
+    LastVisitedRelative,
+    Score,
     Tags,
-    
-    /// Remove a tag
-    Untag {
-        /// Tag to remove
-        tag: String,
-    },
-    
-    /// Record a visit to a directory (usually called from shell integration)
-    Record {
-        /// Directory path
-        path: String,
-    },
-    
-    /// Generate shell integration code
-    Init {
-        /// Shell type (bash, zsh, fish)
-        #[arg(short, long, default_value = "bash")]
-        shell: String,
-    },
 }
 
-fn setup_database() -> Result<Connection> {
-    let data_dir = match dirs::data_dir() {
-        Some(dir) => dir.join("pathranger"),
-        None => {
-            eprintln!("Could not determine data directory");
-            process::exit(1);
+impl TemplateField {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "rank" => TemplateField::Rank,
+            "path" => TemplateField::Path,
+            "path_abs" => TemplateField::PathAbs,
+            "path_tilde" => TemplateField::PathTilde,
+            "count" => TemplateField::Count,
+            "last_visited" => TemplateField::LastVisited,
+            "last_visited_relative" => TemplateField::LastVisitedRelative,
+            "score" => TemplateField::Score,
+            "tags" => TemplateField::Tags,
+            _ => return None,
+        })
+    }
+}
+
+enum TemplatePart {
+    Literal(String),
+    Field(TemplateField),
+}
+
+/// A parsed `--format` string: literal text with `{field}` placeholders.
+/// `\t`, `\n` and `\\` are unescaped, and `{{`/`}}` produce literal braces.
+struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl Template {
+    fn parse(format: &str) -> std::result::Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = format.chars().peekable();
+        
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed '{{{}'", name)),
+                        }
+                    }
+                    let field = TemplateField::from_name(&name)
+                        .ok_or_else(|| format!("unknown field {{{}}}", name))?;
+                    
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(TemplatePart::Field(field));
+                }
+                '}' => return Err("unmatched '}' (use '}}' for a literal brace)".to_string()),
+                c => literal.push(c),
+            }
         }
-    };
-    
-    fs::create_dir_all(&data_dir).map_err(|e| {
-        eprintln!("Could not create data directory: {}", e);
-        process::exit(1);
-    }).unwrap();
-    
-    let db_path = data_dir.join("pathranger.db");
-    let conn = Connection::open(&db_path)?;
+        
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        
+        Ok(Template { parts })
+    }
     
-    // Create tables if they don't exist
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS directories (
-            id INTEGER PRIMARY KEY,
-            path TEXT UNIQUE NOT NULL,
-            visit_count INTEGER NOT NULL DEFAULT 1,
-            last_visited DATETIME NOT NULL
-        )",
-        [],
-    )?;
+    fn uses(&self, field: TemplateField) -> bool {
+        self.parts.iter().any(|part| matches!(part, TemplatePart::Field(f) if *f == field))
+    }
     
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS tags (
-            id INTEGER PRIMARY KEY,
-            name TEXT UNIQUE NOT NULL,
-            path TEXT NOT NULL,
-            created_at DATETIME NOT NULL
-        )",
-        [],
-    )?;
+    /// Tag names for the listed paths, loaded only when the template shows {tags}
+    fn load_tags<'a>(
+        &self,
+        db: &Database,
+        paths: impl IntoIterator<Item = &'a str>,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        if self.uses(TemplateField::Tags) {
+            db.tags_for_paths(paths)
+        } else {
+            Ok(HashMap::new())
+        }
+    }
     
-    Ok(conn)
+    fn render(
+        &self,
+        theme: &Theme,
+        rank: usize,
+        entry: &DirEntry,
+        score: Option<i64>,
+        tags: &HashMap<String, Vec<String>>,
+    ) -> String {
+        let mut line = String::new();
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(text) => line.push_str(text),
+                TemplatePart::Field(field) => match field {
+                    TemplateField::Rank => line.push_str(&rank.to_string()),
+                    TemplateField::Path => line.push_str(&theme.display_path(&entry.path)),
+                    TemplateField::PathTilde => line.push_str(&format_path(&entry.path)),
+                    TemplateField::PathAbs => line.push_str(&entry.path),
+                    TemplateField::Count => line.push_str(&entry.visit_count.to_string()),
+                    TemplateField::LastVisited => {
+                        line.push_str(&format_last_visited(entry.last_visited, &TimeFormat::default_pattern()))
+                    }
+                    TemplateField::LastVisitedRelative => {
+                        line.push_str(&format_last_visited(entry.last_visited, &TimeFormat::Relative))
+                    }
+                    TemplateField::Score => {
+                        if let Some(score) = score {
+                            line.push_str(&score.to_string());
+                        }
+                    }
+                    TemplateField::Tags => {
+                        if let Some(names) = tags.get(&entry.path) {
+                            line.push_str(&names.join(","));
+                        }
+                    }
+                },
+            }
+        }
+        line
+    }
 }
 
-fn record_visit(conn: &Connection, path: &str) -> Result<()> {
-    let expanded_path = tilde(path).into_owned();
-    
-    // Check if the directory exists
-    if !Path::new(&expanded_path).is_dir() {
-        eprintln!("Directory does not exist: {}", expanded_path);
-        return Ok(());
+/// What a listing cell contains, which decides how the theme paints it
+#[derive(Clone, Copy)]
+enum CellKind {
+    Plain,
+    Count,
+    Tag,
+    Path,
+}
+
+struct Cell {
+    text: String,
+    kind: CellKind,
+    /// Shown after the text as ` [badge]` in tag colors
+    badge: Option<String>,
+}
+
+impl Cell {
+    fn plain(text: String) -> Self {
+        Cell { text, kind: CellKind::Plain, badge: None }
     }
     
-    // Try to update existing entry
-    let now = Local::now().to_rfc3339();
-    let rows_affected = conn.execute(
-        "UPDATE directories SET visit_count = visit_count + 1, last_visited = ?1 WHERE path = ?2",
-        params![now, expanded_path],
-    )?;
+    fn count(text: String) -> Self {
+        Cell { text, kind: CellKind::Count, badge: None }
+    }
     
-    // If no rows were affected, insert a new entry
-    if rows_affected == 0 {
-        conn.execute(
-            "INSERT INTO directories (path, visit_count, last_visited) VALUES (?1, 1, ?2)",
-            params![expanded_path, now],
-        )?;
+    fn tag(text: String) -> Self {
+        Cell { text, kind: CellKind::Tag, badge: None }
     }
     
-    Ok(())
-}
-
-fn mark_directory(conn: &Connection, tag: &str, path: Option<&str>) -> Result<()> {
-    let path = match path {
-        Some(p) => shellexpand::tilde(p).into_owned(),
-        None => std::env::current_dir()
-            .map_err(|e| {
-                eprintln!("Could not get current directory: {}", e);
-                process::exit(1);
-            })
-            .unwrap()
-            .to_string_lossy()
-            .to_string(),
-    };
+    fn path(text: String) -> Self {
+        Cell { text, kind: CellKind::Path, badge: None }
+    }
     
-    // Check if the directory exists
-    if !Path::new(&path).is_dir() {
-        eprintln!("Directory does not exist: {}", path);
-        process::exit(1);
-    }
-    
-    // Check if tag already exists
-    let mut stmt = conn.prepare("SELECT id FROM tags WHERE name = ?1")?;
-    let exists = stmt.exists(params![tag])?;
-    
-    let now = Local::now().to_rfc3339();
-    if exists {
-        // Update existing tag
-        conn.execute(
-            "UPDATE tags SET path = ?1, created_at = ?2 WHERE name = ?3",
-            params![path, now, tag],
-        )?;
-        println!("Updated tag '{}' to point to '{}'", Green.bold().paint(tag), Blue.paint(&path));
-    } else {
-        // Create new tag
-        conn.execute(
-            "INSERT INTO tags (name, path, created_at) VALUES (?1, ?2, ?3)",
-            params![tag, path, now],
-        )?;
-        println!("Created tag '{}' for '{}'", Green.bold().paint(tag), Blue.paint(&path));
+    /// A path followed by the names of the tags pointing at it, if any
+    fn tagged_path(text: String, tags: Option<&Vec<String>>) -> Self {
+        Cell { text, kind: CellKind::Path, badge: tags.map(|names| names.join(",")) }
     }
     
-    // Also record a visit
-    record_visit(conn, &path)?;
+    fn badge_width(&self) -> usize {
+        self.badge.as_ref().map_or(0, |badge| badge.width() + 3)
+    }
     
-    Ok(())
+    /// Paint `text` (the cell's text after padding or truncation) plus its badge
+    fn render(&self, theme: &Theme, text: &str) -> String {
+        let mut rendered = theme.cell(self.kind, text);
+        if let Some(badge) = &self.badge {
+            rendered.push(' ');
+            rendered.push_str(&theme.tag(format!("[{}]", badge)));
+        }
+        rendered
+    }
 }
 
-fn goto_tag(conn: &Connection, tag: &str) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT path FROM tags WHERE name = ?1")?;
-    let path: Result<String, rusqlite::Error> = stmt.query_row(params![tag], |row| row.get(0));
-    
-    match path {
-        Ok(path) => {
-            // Print the path for the shell wrapper to cd into
-            println!("{}", path);
-            record_visit(conn, &path)?;
-        }
-        Err(_) => {
-            eprintln!("Tag '{}' not found", tag);
-            process::exit(1);
+/// Rows of a listing, rendered either as aligned columns under a header
+/// (terminal output) or as tab-separated fields (pipes).
+///
+/// Columns are padded by display width rather than by `char` count, so CJK,
+/// emoji and combining characters keep later columns lined up. The last
+/// column is never padded and is truncated to the space left on the line.
+struct Table {
+    headers: Vec<&'static str>,
+    min_widths: Vec<usize>,
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    /// Columns are given as (header, minimum width) pairs
+    fn new(columns: &[(&'static str, usize)]) -> Self {
+        Table {
+            headers: columns.iter().map(|(header, _)| *header).collect(),
+            min_widths: columns.iter().map(|(_, width)| *width).collect(),
+            rows: Vec::new(),
         }
     }
     
-    Ok(())
-}
-
-fn list_top_directories(conn: &Connection, count: usize) -> Result<()> {
-    let mut stmt = conn.prepare(
-        "SELECT path, visit_count, last_visited FROM directories 
-         ORDER BY visit_count DESC LIMIT ?1",
-    )?;
+    fn push(&mut self, row: Vec<Cell>) {
+        debug_assert_eq!(row.len(), self.headers.len());
+        self.rows.push(row);
+    }
     
-    let paths = stmt.query_map(params![count], |row| {
-        let path: String = row.get(0)?;
-        let count: i64 = row.get(1)?;
-        let last_visited: String = row.get(2)?;
+    fn print(&self, theme: &Theme) {
+        if !theme.decorated {
+            for row in &self.rows {
+                let fields: Vec<String> = row.iter().map(|cell| cell.render(theme, &cell.text)).collect();
+                println!("{}", fields.join("\t"));
+            }
+            return;
+        }
         
-        // Parse the date string
-        let last_visited_date = DateTime::parse_from_rfc3339(&last_visited)
-            .map_err(|_| rusqlite::Error::InvalidQuery)?
-            .with_timezone(&Local);
+        let last = self.headers.len() - 1;
+        let texts: Vec<Vec<&str>> = self.rows.iter().map(|row| row.iter().map(|cell| cell.text.as_str()).collect()).collect();
+        let widths = column_widths(&self.headers, &self.min_widths, &texts);
+        let used: usize = widths.iter().map(|w| w + 1).sum();
         
-        Ok((path, count, last_visited_date))
-    })?;
-    
-    println!("Your most frequently visited directories:");
-    println!("{:<4} {:<8} {:<20} {}", "", "VISITS", "LAST VISITED", "PATH");
-    
-    for (i, path_result) in paths.enumerate() {
-        match path_result {
-            Ok((path, count, last_visited)) => {
-                println!(
-                    "{:<4} {:<8} {:<20} {}",
-                    i + 1,
-                    Yellow.paint(count.to_string()),
-                    last_visited.format("%Y-%m-%d %H:%M"),
-                    Blue.paint(format_path(&path))
-                );
+        let header: Vec<String> = (0..last).map(|col| pad_to_width(self.headers[col], widths[col])).collect();
+        println!("{} {}", header.join(" "), self.headers[last]);
+        
+        for row in &self.rows {
+            let mut line = String::new();
+            for (col, cell) in row.iter().enumerate() {
+                if col < last {
+                    line.push_str(&cell.render(theme, &pad_to_width(&cell.text, widths[col])));
+                    line.push(' ');
+                } else {
+                    line.push_str(&cell.render(theme, &theme.fit_path(&cell.text, used + cell.badge_width())));
+                }
             }
-            Err(e) => eprintln!("Error: {}", e),
+            println!("{}", line);
         }
     }
-    
-    Ok(())
 }
 
-fn list_recent_directories(conn: &Connection, count: usize) -> Result<()> {
-    let mut stmt = conn.prepare(
-        "SELECT path, visit_count, last_visited FROM directories 
-         ORDER BY last_visited DESC LIMIT ?1",
-    )?;
+fn print_paths_nul(paths: &[String]) {
+    let mut out = io::stdout().lock();
+    for path in paths {
+        // Stop quietly if the reader went away (e.g. `| head -z`)
+        if out.write_all(path.as_bytes()).and_then(|_| out.write_all(b"\0")).is_err() {
+            return;
+        }
+    }
+    let _ = out.flush();
+}
+
+/// Which setting chose the database file, from highest precedence to lowest
+#[derive(Clone, Copy)]
+enum DbSource {
+    Flag,
+    ProfileFlag,
+    EnvDb,
+    EnvProfile,
+    EnvDataDir,
+    Config,
+    Default,
+}
+
+impl DbSource {
+    fn describe(self) -> &'static str {
+        match self {
+            DbSource::Flag => "--db",
+            DbSource::ProfileFlag => "--profile",
+            DbSource::EnvDb => "$PATHRANGER_DB",
+            DbSource::EnvProfile => "$PATHRANGER_PROFILE",
+            DbSource::EnvDataDir => "$PATHRANGER_DATA_DIR",
+            DbSource::Config => "`database` in config.toml",
+            DbSource::Default => "default location",
+        }
+    }
+}
+
+/// The database file every command uses and the setting that chose it
+struct DbLocation {
+    path: PathBuf,
+    source: DbSource,
+    /// The profile the file belongs to, when a profile chose it
+    profile: Option<String>,
+}
+
+/// Name of the profile stored in `pathranger.db`
+const DEFAULT_PROFILE: &str = "default";
+
+/// The directory holding the profile databases: $PATHRANGER_DATA_DIR or the
+/// data directory of the configured `data_dir_style`
+fn data_dir(config: &Config) -> Result<PathBuf> {
+    match env_value("PATHRANGER_DATA_DIR") {
+        Some(dir) => Ok(PathBuf::from(resolve_user_path(&dir))),
+        None => config.data_dir_style.unwrap_or_default().data_dir().ok_or(Error::NoDataDir),
+    }
+}
+
+/// The database file of profile `name` in `dir`. Names become file names,
+/// so they are limited to letters, digits, `-` and `_`.
+fn profile_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    if name == DEFAULT_PROFILE {
+        return Ok(dir.join("pathranger.db"));
+    }
     
-    let paths = stmt.query_map(params![count], |row| {
-        let path: String = row.get(0)?;
-        let count: i64 = row.get(1)?;
-        let last_visited: String = row.get(2)?;
-        
-        // Parse the date string
-        let last_visited_date = DateTime::parse_from_rfc3339(&last_visited)
-            .map_err(|_| rusqlite::Error::InvalidQuery)?
-            .with_timezone(&Local);
-        
-        Ok((path, count, last_visited_date))
-    })?;
+    let valid = name.len() <= 64
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(Error::InvalidInput(format!(
+            "Invalid profile name '{}'\nProfile names start with a letter or digit and contain only letters, digits, '-' and '_'",
+            name
+        )));
+    }
+    if name == "pathranger" {
+        return Err(Error::InvalidInput(format!(
+            "Profile name '{}' is reserved\nUse '{}' for the default profile",
+            name, DEFAULT_PROFILE
+        )));
+    }
+    Ok(dir.join(format!("{}.db", name)))
+}
+
+/// Decide the database file once at startup: --db, then --profile, then
+/// $PATHRANGER_DB, then $PATHRANGER_PROFILE, then $PATHRANGER_DATA_DIR
+/// (holding `pathranger.db`), then the config file, then the platform
+/// default. Relative paths are made absolute against the current directory
+/// here, so a later `cd` can't change which file is used.
+fn database_location(cli: &Cli, config: &Config) -> Result<DbLocation> {
+    let profile = |name: String, source| -> Result<DbLocation> {
+        let path = profile_path(&data_dir(config)?, &name)?;
+        Ok(DbLocation { path, source, profile: Some(name) })
+    };
+    let file = |path: &str, source| DbLocation { path: PathBuf::from(resolve_user_path(path)), source, profile: None };
     
-    println!("Your recently visited directories:");
-    println!("{:<4} {:<8} {:<20} {}", "", "VISITS", "LAST VISITED", "PATH");
-    
-    for (i, path_result) in paths.enumerate() {
-        match path_result {
-            Ok((path, count, last_visited)) => {
-                println!(
-                    "{:<4} {:<8} {:<20} {}",
-                    i + 1,
-                    Yellow.paint(count.to_string()),
-                    last_visited.format("%Y-%m-%d %H:%M"),
-                    Blue.paint(format_path(&path))
-                );
+    let location = if let Some(path) = &cli.db {
+        file(&path.to_string_lossy(), DbSource::Flag)
+    } else if let Some(name) = &cli.profile {
+        profile(name.clone(), DbSource::ProfileFlag)?
+    } else if let Some(path) = env_value("PATHRANGER_DB") {
+        file(&path, DbSource::EnvDb)
+    } else if let Some(name) = env_value("PATHRANGER_PROFILE") {
+        profile(name, DbSource::EnvProfile)?
+    } else if env_value("PATHRANGER_DATA_DIR").is_some() {
+        DbLocation { path: data_dir(config)?.join("pathranger.db"), source: DbSource::EnvDataDir, profile: None }
+    } else if let Some(path) = config.database.as_deref().filter(|path| !path.is_empty()) {
+        file(path, DbSource::Config)
+    } else {
+        DbLocation { path: data_dir(config)?.join("pathranger.db"), source: DbSource::Default, profile: None }
+    };
+    debug!("database chosen by {}", location.source.describe());
+    Ok(location)
+}
+
+/// List the profile databases in the data directory with their sizes and
+/// how much each tracks, marking the one in use
+fn list_profiles(theme: &Theme, config: &Config, location: &DbLocation) -> Result<()> {
+    let dir = data_dir(config)?;
+    let mut profiles = Vec::new();
+    if let Ok(entries) = fs::read_dir(&dir) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name();
+            let Some(stem) = file_name.to_str().and_then(|name| name.strip_suffix(".db")) else {
+                continue;
+            };
+            let name = if stem == "pathranger" { DEFAULT_PROFILE } else { stem };
+            // Skip files that no profile name could have produced
+            if profile_path(&dir, name).is_ok_and(|path| path == entry.path()) {
+                profiles.push((name.to_string(), entry.path()));
             }
-            Err(e) => eprintln!("Error: {}", e),
         }
     }
+    profiles.sort();
+    
+    let mut table = Table::new(&[("PROFILE", 20), ("SIZE", 10), ("DIRS", 6), ("TAGS", 6), ("PATH", 0)]);
+    for (name, path) in &profiles {
+        let size = fs::metadata(path).map_or_else(|_| String::from("?"), |metadata| format_size(metadata.len()));
+        let db = Database::open_read_only(path).ok();
+        let count = |count: Option<Result<usize>>| match count {
+            Some(Ok(n)) => n.to_string(),
+            _ => String::from("?"),
+        };
+        let mut profile = Cell::tag(name.clone());
+        if *path == location.path {
+            profile.badge = Some(String::from("in use"));
+        }
+        table.push(vec![
+            profile,
+            Cell::plain(size),
+            Cell::count(count(db.as_ref().map(Database::directory_count))),
+            Cell::count(count(db.as_ref().map(Database::tag_count))),
+            Cell::path(theme.display_path(&path.to_string_lossy())),
+        ]);
+    }
+    
+    if theme.decorated {
+        println!("Profiles in {}:", format_path(&dir.to_string_lossy()));
+    }
+    table.print(theme);
     
     Ok(())
 }
 
-fn search_directories(conn: &Connection, query: &str) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT path FROM directories")?;
-    let paths = stmt.query_map([], |row| {
-        let path: String = row.get(0)?;
-        Ok(path)
-    })?;
-    
-    let matcher = SkimMatcherV2::default();
-    let mut matches = Vec::new();
+/// Move the database in use to `dest`, a file or a directory to keep its file
+/// name in. The copy is checked before the original is removed, so a failure
+/// part way leaves the original in place.
+fn migrate_data(theme: &Theme, config: &Config, location: &DbLocation, dest: &Path) -> Result<()> {
+    let source = &location.path;
+    if !source.exists() {
+        let shown = format_path(&source.to_string_lossy());
+        return Err(Error::InvalidInput(format!("There is no database at {} to move", shown)));
+    }
     
-    for path_result in paths {
-        match path_result {
-            Ok(path) => {
-                if let Some(score) = matcher.fuzzy_match(&path, query) {
-                    matches.push((path, score));
-                }
-            }
-            Err(e) => eprintln!("Error: {}", e),
-        }
+    // A trailing slash names a directory, even one that doesn't exist yet
+    let given = dest.to_string_lossy();
+    let mut dest = PathBuf::from(resolve_user_path(&given));
+    if given.ends_with('/') || dest.is_dir() {
+        dest.push(source.file_name().unwrap_or("pathranger.db".as_ref()));
+    }
+    let shown = format_path(&dest.to_string_lossy());
+    if dest == *source {
+        return Err(Error::InvalidInput(format!("The database is already at {}", shown)));
+    }
+    if dest.exists() {
+        return Err(Error::InvalidInput(format!(
+            "{} already exists\nMove it aside first or pick another destination",
+            shown
+        )));
+    }
+    if let Some(dir) = dest.parent() {
+        pathranger::create_private_dir(dir).map_err(|e| Error::Io(format!("Could not create {}", dir.display()), e))?;
     }
     
-    // Sort by score (descending)
-    matches.sort_by(|a, b| b.1.cmp(&a.1));
+    let db = Database::open_read_only(source)?;
+    debug!("copying {} to {}", source.display(), dest.display());
+    db.copy_to(&dest)?;
+    #[cfg(unix)]
+    pathranger::make_private(&dest).map_err(|e| Error::Io(format!("Could not restrict {}", dest.display()), e))?;
     
-    if matches.is_empty() {
-        println!("No matching directories found for '{}'", query);
-        return Ok(());
+    let copy = Database::open_read_only(&dest)?;
+    let intact = copy.integrity_check()?
+        && copy.directory_count()? == db.directory_count()?
+        && copy.tag_count()? == db.tag_count()?;
+    if !intact {
+        drop(copy);
+        let _ = fs::remove_file(&dest);
+        return Err(Error::InvalidInput(format!(
+            "The copy at {} didn't match the original, so it was removed and nothing was moved",
+            shown
+        )));
     }
+    let (directories, tags) = (copy.directory_count()?, copy.tag_count()?);
+    drop(db);
     
-    println!("Search results for '{}':", query);
-    println!("{:<4} {:<8} {}", "", "SCORE", "PATH");
+    fs::remove_file(source).map_err(|e| {
+        Error::Io(format!("Copied the database to {} but could not remove {}", shown, source.display()), e)
+    })?;
+    note!("Moved {} directories and {} tags to {}", directories, tags, theme.path(&shown));
     
-    for (i, (path, score)) in matches.iter().enumerate().take(10) {
-        println!(
-            "{:<4} {:<8} {}",
-            i + 1,
-            Yellow.paint(score.to_string()),
-            Blue.paint(format_path(path))
-        );
+    // Say how to make pathranger look there, unless it already does
+    let current = config.data_dir_style.unwrap_or_default();
+    let default_file = |style: DataDirStyle| style.data_dir().map(|dir| dir.join("pathranger.db"));
+    if default_file(current).as_ref() != Some(&dest) {
+        let styles = [DataDirStyle::Platform, DataDirStyle::Xdg];
+        match styles.into_iter().find(|style| default_file(*style).as_ref() == Some(&dest)) {
+            Some(style) => note!("Run `pathranger config set data_dir_style {}` to use it", style.name()),
+            None => note!("Run `pathranger config set database {}` to use it", shell_quote(&dest.to_string_lossy())),
+        }
     }
-    
     Ok(())
 }
 
-fn list_tags(conn: &Connection) -> Result<()> {
-    let mut stmt = conn.prepare("SELECT name, path FROM tags ORDER BY name")?;
-    let tag_rows = stmt.query_map([], |row| {
-        let name: String = row.get(0)?;
-        let path: String = row.get(1)?;
-        Ok((name, path))
-    })?;
-    
-    println!("Your tags:");
-    println!("{:<20} {}", "TAG", "PATH");
+/// Print the database in use and the setting that chose it, its size and
+/// how much it tracks, and the config file
+fn print_info(theme: &Theme, location: &DbLocation) {
+    let shown = |path: &Path| {
+        let path = path.to_string_lossy();
+        if theme.decorated { format_path(&path) } else { path.into_owned() }
+    };
     
-    for tag_result in tag_rows {
-        match tag_result {
-            Ok((name, path)) => {
-                println!(
-                    "{:<20} {}",
-                    Green.bold().paint(name),
-                    Blue.paint(format_path(&path))
-                );
+    let mut rows = vec![
+        ("database", shown(&location.path)),
+        ("source", String::from(location.source.describe())),
+    ];
+    if let Some(profile) = &location.profile {
+        rows.push(("profile", profile.clone()));
+    }
+    match fs::metadata(&location.path) {
+        Ok(metadata) => {
+            rows.push(("size", format_size(metadata.len())));
+            match Database::open_read_only(&location.path) {
+                Ok(db) => {
+                    let count = |count: Result<usize>| count.map_or_else(|e| format!("unreadable: {}", e), |n| n.to_string());
+                    rows.push(("directories", count(db.directory_count())));
+                    rows.push(("tags", count(db.tag_count())));
+                }
+                Err(e) => rows.push(("contents", format!("unreadable: {}", e))),
             }
-            Err(e) => eprintln!("Error: {}", e),
         }
+        Err(_) => rows.push(("size", String::from("not created yet"))),
+    }
+    rows.push(match config::path() {
+        Some(path) if path.exists() => ("config", shown(&path)),
+        Some(path) => ("config", format!("{} (not present)", shown(&path))),
+        None => ("config", String::from("none")),
+    });
+    if recording_disabled() {
+        rows.push(("recording", String::from("disabled by $PATHRANGER_DISABLE")));
     }
     
-    Ok(())
+    for (label, value) in rows {
+        if theme.decorated {
+            println!("{} {}", theme.paint(Style::new().bold(), format!("{:<12}", label)), value);
+        } else {
+            println!("{}\t{}", label, value);
+        }
+    }
 }
 
-fn remove_tag(conn: &Connection, tag: &str) -> Result<()> {
-    let rows_affected = conn.execute("DELETE FROM tags WHERE name = ?1", params![tag])?;
-    
-    if rows_affected > 0 {
-        println!("Tag '{}' removed", tag);
-    } else {
-        println!("Tag '{}' not found", tag);
+/// Find a database to use when `path` can't be written: the one in
+/// $PATHRANGER_FALLBACK_DIR if that's set, otherwise `path` opened read-only
+/// for commands that don't change anything. Returns None if `record` should
+/// quietly do nothing.
+fn open_read_only_fallback(path: &Path, command: Option<&Commands>, error: Error) -> Result<Option<Database>> {
+    debug!("{}", error);
+    if let Some(dir) = std::env::var_os("PATHRANGER_FALLBACK_DIR").filter(|dir| !dir.is_empty()) {
+        // Keep the file name, so each profile falls back to its own database
+        let fallback = PathBuf::from(dir).join(path.file_name().unwrap_or("pathranger.db".as_ref()));
+        debug!("using fallback database {}", fallback.display());
+        return Database::open(fallback).map(Some);
     }
     
-    Ok(())
+    match command {
+        Some(Commands::Record { .. } | Commands::RecordFile { .. }) => Ok(None),
+        Some(command) if command.is_mutating() => Err(error),
+        _ if path.exists() => {
+            debug!("opening {} read-only", path.display());
+            Database::open_read_only(path).map(Some)
+        }
+        _ => Err(error),
+    }
 }
 
-fn add_current_directory(conn: &Connection) -> Result<()> {
-    let current_dir = std::env::current_dir()
-        .map_err(|e| {
-            eprintln!("Could not get current directory: {}", e);
-            process::exit(1);
-        })
-        .unwrap();
+/// Append the visit `command` records to the journal, if it is `record`, the
+/// journal is enabled and `error` means the database can't be reached right
+/// now. Returns false if the caller has to deal with `error` itself.
+fn journal_record(config: &Config, db_path: &Path, command: Option<&Commands>, error: &Error) -> Result<bool> {
+    let Some(Commands::Record { path, from, .. }) = command else {
+        return Ok(false);
+    };
+    if !config.journal.unwrap_or(false) || !(error.is_busy() || error.is_read_only()) {
+        return Ok(false);
+    }
+    debug!("journaling the visit: {}", error);
+    record::journal_visit(db_path, &path.to_string_lossy(), utf8_arg(from.as_deref()), config.exclude_patterns()?.as_ref())
+}
+
+/// Exit status for each kind of error, so scripts can tell a missing tag
+/// from a broken database
+fn exit_code(error: &Error) -> u8 {
+    match error {
+        Error::TagNotFound(_) | Error::WorkspaceNotFound(_) | Error::RankOutOfRange { .. } | Error::Cancelled => 1,
+        Error::Database(_) | Error::ReadOnly { .. } | Error::NoDataDir => 2,
+        Error::DirectoryMissing(_) | Error::FileMissing(_) => 3,
+        Error::InvalidInput(_) | Error::UnsupportedShell { .. } => EXIT_USAGE,
+        Error::Io(..) => 74,
+    }
+}
+
+/// Exit status for command-line usage errors (sysexits' EX_USAGE)
+const EXIT_USAGE: u8 = 64;
+
+fn main() -> ExitCode {
+    // Exit quietly instead of panicking when a reader such as `head` or the
+    // pager closes the pipe early
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { ExitCode::from(EXIT_USAGE) } else { ExitCode::SUCCESS };
+        }
+    };
     
-    record_visit(conn, &current_dir.to_string_lossy())?;
-    println!("Added '{}' to tracked directories", Blue.paint(format_path(&current_dir.to_string_lossy())));
+    output::set_verbosity(if cli.quiet {
+        Verbosity::Quiet
+    } else if cli.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
     
-    Ok(())
+    // A background recorder has no terminal, so its failures go to the log
+    let background_log = match &cli.command {
+        Some(Commands::Record { path, log: Some(log), .. }) => Some((log.clone(), path.to_string_lossy().into_owned())),
+        _ => None,
+    };
+    
+    let started = Instant::now();
+    let result = run(cli);
+    debug!("finished in {:.1?}", started.elapsed());
+    
+    if let (Err(e), Some((log, path))) = (&result, background_log) {
+        record::append_log(&log, &path, e);
+    }
+    match result {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
 }
 
-fn generate_shell_init(shell: &str) -> Result<()> {
-    match shell {
-        "bash" => {
-            println!("# Add this to your ~/.bashrc");
-            println!("eval \"$(pathranger init --shell bash)\"");
-            println!("");
-            println!("# PathRanger shell integration for bash");
-            println!("__pathranger_cd() {{");
-            println!("    local dir=\"$1\"");
-            println!("    if [ -d \"$dir\" ]; then");
-            println!("        cd \"$dir\" || return");
-            println!("        pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("    fi");
-            println!("}}");
-            println!("");
-            println!("# Override cd");
-            println!("cd() {{");
-            println!("    __pathranger_cd \"$@\"");
-            println!("}}");
-            println!("");
-            println!("# Record initial directory");
-            println!("pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("");
-            println!("# pr goto alias");
-            println!("pr() {{");
-            println!("    if [ \"$1\" = \"goto\" ] && [ -n \"$2\" ]; then");
-            println!("        local dir");
-            println!("        dir=$(pathranger goto \"$2\")");
-            println!("        if [ -n \"$dir\" ]; then");
-            println!("            __pathranger_cd \"$dir\"");
-            println!("        fi");
-            println!("    else");
-            println!("        pathranger \"$@\"");
-            println!("    fi");
-            println!("}}");
+fn run(cli: Cli) -> Result<ExitCode> {
+    // Pages come from the command definitions alone
+    if let Some(Commands::Man { command, dir }) = &cli.command {
+        print_man_pages(command, dir.as_deref())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    // Nothing to do, so don't even read the config or open the database
+    if matches!(cli.command, Some(Commands::Record { .. } | Commands::RecordFile { .. })) && recording_disabled() {
+        debug!("PATHRANGER_DISABLE is set, not recording");
+        return Ok(ExitCode::SUCCESS);
+    }
+    // The database keeps paths as text, so a name that isn't valid UTF-8
+    // can't be recorded. The prompt hook runs there at every prompt, so
+    // only --verbose says so; past this point the path converts exactly.
+    if let Some(Commands::Record { path, .. } | Commands::RecordFile { path }) = &cli.command
+        && path.to_str().is_none()
+    {
+        debug!("not recording {}: not valid UTF-8", path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    
+    let config = config::load()?;
+    let theme = Theme::new(&cli, &config);
+    
+    let location = database_location(&cli, &config);
+    
+    // Completion runs on every TAB, so it skips setup and never reports errors
+    if let Some(Commands::Complete { tags, prefix, .. }) = &cli.command {
+        print_completion_candidates(location.ok().as_ref().map(|location| location.path.as_path()), &config, *tags, prefix);
+        return Ok(ExitCode::SUCCESS);
+    }
+    
+    // Verify and info inspect the database as it is, so they must not create it
+    if let Some(Commands::Verify { fix, yes }) = &cli.command {
+        let ok = verify(&theme, &config, location.as_ref().ok(), *fix, *yes);
+        return Ok(if ok { ExitCode::SUCCESS } else { ExitCode::from(2) });
+    }
+    let location = location?;
+    match &cli.command {
+        Some(Commands::Info) => {
+            print_info(&theme, &location);
+            return Ok(ExitCode::SUCCESS);
         }
-        "zsh" => {
-            println!("# Add this to your ~/.zshrc");
-            println!("eval \"$(pathranger init --shell zsh)\"");
-            println!("");
-            println!("# PathRanger shell integration for zsh");
-            println!("__pathranger_cd() {{");
-            println!("    local dir=\"$1\"");
-            println!("    if [[ -d \"$dir\" ]]; then");
-            println!("        builtin cd \"$dir\" || return");
-            println!("        pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("    fi");
-            println!("}}");
-            println!("");
-            println!("# Override cd");
-            println!("cd() {{");
-            println!("    __pathranger_cd \"$@\"");
-            println!("}}");
-            println!("");
-            println!("# Record initial directory");
-            println!("pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("");
-            println!("# pr goto alias");
-            println!("pr() {{");
-            println!("    if [[ \"$1\" = \"goto\" && -n \"$2\" ]]; then");
-            println!("        local dir");
-            println!("        dir=$(pathranger goto \"$2\")");
-            println!("        if [[ -n \"$dir\" ]]; then");
-            println!("            __pathranger_cd \"$dir\"");
-            println!("        fi");
-            println!("    else");
-            println!("        pathranger \"$@\"");
-            println!("    fi");
-            println!("}}");
+        Some(Commands::Profiles) => {
+            list_profiles(&theme, &config, &location)?;
+            return Ok(ExitCode::SUCCESS);
         }
-        "fish" => {
-            println!("# Add this to your ~/.config/fish/config.fish");
-            println!("eval (pathranger init --shell fish)");
-            println!("");
-            println!("# PathRanger shell integration for fish");
-            println!("function __pathranger_cd");
-            println!("    set dir $argv[1]");
-            println!("    if test -d \"$dir\"");
-            println!("        builtin cd \"$dir\"");
-            println!("        pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("    end");
-            println!("end");
-            println!("");
-            println!("# Override cd");
-            println!("function cd");
-            println!("    __pathranger_cd $argv");
-            println!("end");
-            println!("");
-            println!("# Record initial directory");
-            println!("pathranger record \"$PWD\" >/dev/null 2>&1");
-            println!("");
-            println!("# pr goto alias");
-            println!("function pr");
-            println!("    if test \"$argv[1]\" = \"goto\"; and test -n \"$argv[2]\"");
-            println!("        set dir (pathranger goto \"$argv[2]\")");
-            println!("        if test -n \"$dir\"");
-            println!("            __pathranger_cd \"$dir\"");
-            println!("        end");
-            println!("    else");
-            println!("        pathranger $argv");
-            println!("    end");
-            println!("end");
+        Some(Commands::MigrateData { dest }) => {
+            migrate_data(&theme, &config, &location, dest)?;
+            return Ok(ExitCode::SUCCESS);
         }
-        _ => {
-            eprintln!("Unsupported shell: {}", shell);
-            eprintln!("Supported shells: bash, zsh, fish");
-            process::exit(1);
+        Some(Commands::Daemon { stop, status, idle_timeout }) => {
+            let running = if *stop {
+                stop_daemon(&theme, &location.path)
+            } else if *status {
+                daemon_status(&theme, &location.path)
+            } else {
+                let timeout = idle_timeout.or(config.daemon.idle_timeout).unwrap_or(DEFAULT_IDLE_TIMEOUT);
+                run_daemon(&location.path, timeout, &config)?;
+                true
+            };
+            return Ok(if running { ExitCode::SUCCESS } else { ExitCode::from(1) });
+        }
+        // Without --record it is a filesystem walk, so scripts calling it
+        // don't create or replay into the database
+        Some(Commands::Root { path, record: false }) => {
+            let Some(root) = project_root(&config, path.as_deref())? else {
+                return Ok(ExitCode::from(1));
+            };
+            println!("{}", root);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Commands::Prompt { format, path, timeout_ms }) => {
+            print_prompt_segment(&location.path, format, path.as_deref(), *timeout_ms);
+            return Ok(ExitCode::SUCCESS);
+        }
+        // A running daemon is cheapest, then a detached recorder; the journal
+        // is only for when the database can't be reached, after opening it
+        Some(Commands::Record { path, from, .. })
+            if daemon::record(
+                &location.path,
+                &path.to_string_lossy(),
+                utf8_arg(from.as_deref()),
+                config.exclude_patterns()?.as_ref(),
+            ) =>
+        {
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Commands::Record { path, from, background: true, .. })
+            if record::in_background(
+                &location.path,
+                &path.to_string_lossy(),
+                utf8_arg(from.as_deref()),
+                config.exclude_patterns()?.as_ref(),
+            ) =>
+        {
+            return Ok(ExitCode::SUCCESS);
+        }
+        // The daemon protocol has no field for --exclude, and --offline must
+        // skip the daemon's own checks, so those queries open the database
+        Some(Commands::Query { name, cwd_project, exclude, explore, parent, copy, as_form, print0, offline: false })
+            if !config.journal.unwrap_or(false) && exclude.is_empty() =>
+        {
+            let cwd_project = cwd_project.or(config.cwd_project).unwrap_or_default();
+            let explore = *explore || config.query.explore.unwrap_or(false);
+            match query_cwd().and_then(|cwd| daemon::query(&location.path, &cwd, name, cwd_project)) {
+                Some(Some(path)) => {
+                    print_query_match(&config, &path, *parent, *copy, *as_form, *print0)?;
+                    return Ok(ExitCode::SUCCESS);
+                }
+                // Exploring reads directories, which the daemon doesn't do
+                Some(None) if !explore => return Ok(ExitCode::from(1)),
+                _ => {}
+            }
+        }
+        Some(Commands::Hooks { action: HooksAction::Print { shell } }) => {
+            print_hooks(&config, *shell)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(Commands::Config { action }) => {
+            match action {
+                ConfigAction::Show { sources } => show_config(&config, *sources),
+                ConfigAction::Init { force } => init_config(&theme, *force)?,
+                ConfigAction::Get { key } => {
+                    if !get_config(&config, &theme, key)? {
+                        return Ok(ExitCode::from(1));
+                    }
+                }
+                ConfigAction::Set { key, value } => set_config(&theme, key, value)?,
+            }
+            return Ok(ExitCode::SUCCESS);
         }
+        _ => {}
     }
     
-    Ok(())
-}
-
-fn format_path(path: &str) -> String {
-    let home = home_dir().unwrap_or_else(|| PathBuf::from("/"));
-    let home_str = home.to_string_lossy();
+    // The prompt hook's stderr goes nowhere, so leave the note to a command
+    // whose output is seen
+    #[cfg(unix)]
+    if !matches!(cli.command, Some(Commands::Record { .. } | Commands::RecordFile { .. })) {
+        make_state_private(&location);
+    }
     
-    if path.starts_with(&*home_str) {
-        let relative_path = path.strip_prefix(&*home_str).unwrap_or(path);
-        format!("~{}", relative_path)
-    } else {
-        path.to_string()
+    let db_path = location.path;
+    let db = match Database::open(&db_path) {
+        Ok(db) => db,
+        Err(e) if journal_record(&config, &db_path, cli.command.as_ref(), &e)? => {
+            return Ok(ExitCode::SUCCESS);
+        }
+        Err(e) if e.is_read_only() => match open_read_only_fallback(&db_path, cli.command.as_ref(), e)? {
+            Some(db) => db,
+            // Nowhere to record to; the prompt hook must stay silent
+            None => return Ok(ExitCode::SUCCESS),
+        },
+        Err(e) => return Err(e),
+    };
+    db.set_fold_case(config.fold_case.unwrap_or(true));
+    db.set_path_form(config.paths.unwrap_or_default());
+    db.set_path_checks(path_checks(&config, cli.command.as_ref()));
+    db.set_exclude(config.exclude_patterns()?);
+    
+    // Record adds to the journal rather than draining it, and runs on every prompt
+    let journal = Journal::for_database(&db_path);
+    if let Some(journal) = &journal
+        && let Some(command) = &cli.command
+        && !matches!(command, Commands::Record { .. } | Commands::Flush)
+    {
+        journal.replay(&db, command.lists_visits());
     }
-}
-
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let conn = setup_database()?;
+    
+    let paging = theme.decorated && !cli.no_pager && cli.command.as_ref().is_some_and(Commands::is_human_listing);
+    let _pager = if paging { Pager::start() } else { None };
     
     match cli.command {
-        Some(Commands::Mark { tag }) => mark_directory(&conn, &tag, None)?,
-        Some(Commands::Goto { tag }) => goto_tag(&conn, &tag)?,
-        Some(Commands::Add) => add_current_directory(&conn)?,
-        Some(Commands::Top { count }) => list_top_directories(&conn, count)?,
-        Some(Commands::Recent { count }) => list_recent_directories(&conn, count)?,
-        Some(Commands::Search { query }) => search_directories(&conn, &query)?,
-        Some(Commands::Tags) => list_tags(&conn)?,
-        Some(Commands::Untag { tag }) => remove_tag(&conn, &tag)?,
-        Some(Commands::Record { path }) => record_visit(&conn, &path)?,
-        Some(Commands::Init { shell }) => generate_shell_init(&shell)?,
+        Some(Commands::Mark { tag: Some(tag), .. }) => mark_directory(&db, &theme, &tag, None)?,
+        Some(Commands::Mark { tag: None, auto }) => {
+            if !mark_suggested(&db, &theme, auto)? {
+                return Ok(ExitCode::from(1));
+            }
+        }
+        Some(Commands::Goto { tag, top, recent, parent, no_passthrough, copy, as_form, .. }) => {
+            let target = match (tag, top, recent) {
+                (_, Some(rank), _) => rank_target(&db, &config, SortKey::Visits, rank)?,
+                (_, _, Some(rank)) => rank_target(&db, &config, SortKey::Recent, rank)?,
+                (Some(tag), _, _) => match passthrough_path(&tag).filter(|_| !no_passthrough) {
+                    Some(path) => path,
+                    None => tag_target(&db, &tag_files(&config), &tag)?,
+                },
+                (None, None, None) => unreachable!("clap requires a goto target"),
+            };
+            let path = match parent {
+                Some(levels) => parent_directory(&config, &target, levels)?,
+                None => target,
+            };
+            // Go where the visit is recorded, so `paths = "physical"` lands
+            // in the resolved directory
+            let path = db.canonical_path(&path)?;
+            let shown = path_as(&path, as_form);
+            goto_path(&db, &path, &shown)?;
+            if copy {
+                clipboard::copy(&shown)?;
+            }
+        }
+        Some(Commands::Add) => add_current_directory(&db, &theme)?,
+        Some(Commands::Scan { root, max_depth, min_depth, autotag, dry_run }) => {
+            let options = ScanOptions { max_depth, min_depth, find_repositories: autotag };
+            scan_directories(&db, &theme, &config, &root, &options, dry_run)?
+        }
+        Some(Commands::Top { count, absolute, tree, ascii, filter, output }) => {
+            let mode = if tree {
+                OutputMode::Tree(TreeGlyphs::detect(ascii))
+            } else {
+                OutputMode::from_args(&output, false)?
+            };
+            let time_format = theme.time_format(absolute);
+            let options = ListOptions {
+                sort: SortKey::Visits,
+                reverse: false,
+                count: count.or(config.top.count).unwrap_or(DEFAULT_COUNT),
+                filter: filter_from_args(&filter, &config)?,
+            };
+            list_directories(&db, &theme, &mode, &options, time_format)?
+        }
+        Some(Commands::Recent { count, absolute, filter, output }) => {
+            let mode = OutputMode::from_args(&output, false)?;
+            let time_format = theme.time_format(absolute);
+            let options = ListOptions {
+                sort: SortKey::Recent,
+                reverse: false,
+                count: count.or(config.recent.count).unwrap_or(DEFAULT_COUNT),
+                filter: filter_from_args(&filter, &config)?,
+            };
+            list_directories(&db, &theme, &mode, &options, time_format)?
+        }
+        Some(Commands::List { sort, reverse, count, absolute, tree, ascii, filter, output }) => {
+            let mode = if tree {
+                OutputMode::Tree(TreeGlyphs::detect(ascii))
+            } else {
+                OutputMode::from_args(&output, false)?
+            };
+            let time_format = theme.time_format(absolute);
+            let options = ListOptions {
+                sort: sort.or(config.list.sort).unwrap_or(SortKey::Visits),
+                reverse,
+                count: count.or(config.list.count).unwrap_or(DEFAULT_COUNT),
+                filter: filter_from_args(&filter, &config)?,
+            };
+            list_directories(&db, &theme, &mode, &options, time_format)?
+        }
+        Some(Commands::Search { query, limit, cwd_project, files, filter, output }) => {
+            let query = expand_search_query(&query);
+            let mode = OutputMode::from_args(&output, true)?;
+            let limit = limit.or(config.search.limit).unwrap_or(DEFAULT_COUNT);
+            let cwd_project = cwd_project.or(config.cwd_project).unwrap_or_default();
+            let root = cwd_project.root(query_cwd().as_deref());
+            let scope = cwd_project.scope(root.as_deref());
+            let filter = filter_from_args(&filter, &config)?;
+            let mut matches = if files {
+                db.search_files(&query, &filter, scope)?
+            } else {
+                db.search_in(&query, &filter, scope)?
+            };
+            matches.truncate(limit);
+            print_matches(&db, &theme, &mode, &query, &filter, matches, files)?
+        }
+        Some(Commands::Files { top, count, absolute, filter, output, .. }) => {
+            let mode = OutputMode::from_args(&output, false)?;
+            let time_format = theme.time_format(absolute);
+            let options = ListOptions {
+                sort: if top { SortKey::Visits } else { SortKey::Recent },
+                reverse: false,
+                count: count.unwrap_or(DEFAULT_COUNT),
+                filter: filter_from_args(&filter, &config)?,
+            };
+            let title = match options.sort {
+                SortKey::Visits => "Your most opened files",
+                _ => "Your recently opened files",
+            };
+            let title = format!("{}{}:", title, describe_filter(&options.filter));
+            print_entries(&db, &theme, &mode, &title, &db.files(&options)?, time_format)?
+        }
+        Some(Commands::Tags { clean: true, yes, dry_run, .. }) => clean_tags(&db, &theme, &config, yes, dry_run)?,
+        Some(Commands::Tags {
+            paths_only, porcelain, tree, ascii, zsh_hash, export_aliases, export_vars, fish_abbr, prefix, shell, ..
+        }) => {
+            let export = if zsh_hash {
+                Some(TagExport::ZshHash)
+            } else if export_aliases {
+                Some(TagExport::Aliases)
+            } else if export_vars {
+                Some(TagExport::Vars)
+            } else if fish_abbr {
+                Some(TagExport::FishAbbr)
+            } else {
+                None
+            };
+            match export {
+                Some(export) => export_tags(&db, export, prefix.as_deref(), shell)?,
+                None => {
+                    let time_format = theme.time_format(false);
+                    let tree = tree.then(|| TreeGlyphs::detect(ascii));
+                    list_tags(&db, &theme, &tag_files(&config), paths_only, porcelain, tree, time_format)?
+                }
+            }
+        }
+        Some(Commands::Query { name, cwd_project, exclude, explore, parent, copy, as_form, print0, offline }) => {
+            // No confident match is an answer, not an error, so say nothing
+            let cwd_project = cwd_project.or(config.cwd_project).unwrap_or_default();
+            let explore = !offline && (explore || config.query.explore.unwrap_or(false));
+            let Some(path) = query_directory(&db, &name, query_cwd().as_deref(), cwd_project, &exclude, explore)? else {
+                return Ok(ExitCode::from(1));
+            };
+            print_query_match(&config, &path, parent, copy, as_form, print0)?;
+        }
+        Some(Commands::Cdpath { top, shell }) => print_cdpath(&db, top, shell)?,
+        Some(Commands::Untag { tag, destructive }) => {
+            remove_tag(&db, &config, &tag_files(&config), &tag, &destructive)?
+        }
+        Some(Commands::Flush) => flush_journal(&db, journal.as_ref())?,
+        Some(Commands::Workspace { action }) => manage_workspace(&db, &theme, action)?,
+        Some(Commands::Stats { json, heatmap: true, scope }) => {
+            let under = scope.map(|scope| resolve_path_or_tag(&db, &config, &scope)).transpose()?;
+            print_heatmap(&db.heatmap(under.as_deref())?, &theme, json)?
+        }
+        Some(Commands::Stats { json, .. }) => print_stats(&db, &theme, json)?,
+        Some(Commands::Stat { path, json }) => {
+            let path = match path {
+                Some(path) => resolve_path_or_tag(&db, &config, &path)?,
+                None => query_cwd()
+                    .ok_or_else(|| Error::InvalidInput(String::from("Could not determine the current directory")))?
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            let time_format = theme.time_format(false);
+            print_directory_stats(&db, &theme, &path, json, time_format)?
+        }
+        Some(Commands::Report { since, until, json, markdown, .. }) => {
+            let until = until.unwrap_or_else(Local::now);
+            let since = since.unwrap_or(until - Duration::days(7));
+            if since >= until {
+                return Err(Error::InvalidInput(String::from("The report window must start before it ends")));
+            }
+            let report = db.report(since, until, REPORT_TOP)?;
+            let format = if json {
+                ReportFormat::Json
+            } else if markdown {
+                ReportFormat::Markdown
+            } else {
+                ReportFormat::Human
+            };
+            print_report(&db, &theme, &report, format)?
+        }
+        Some(Commands::Suggest { count, json, paths_only }) => suggest_directories(&db, &theme, count, json, paths_only)?,
+        Some(Commands::Projects { count, absolute, json, paths_only }) => {
+            let time_format = theme.time_format(absolute);
+            list_projects(&db, &theme, count, time_format, json, paths_only)?
+        }
+        Some(Commands::Hotspots { depth, projects, count, json }) => {
+            list_hotspots(&db, &theme, depth.into(), projects, count, json)?
+        }
+        Some(Commands::Rpc) => rpc::run(&db, &config)?,
+        #[cfg(feature = "serve")]
+        Some(Commands::Serve { addr }) => serve::serve(&db, &config, &addr)?,
+        Some(Commands::Root { path, .. }) => {
+            let Some(root) = project_root(&config, path.as_deref())? else {
+                return Ok(ExitCode::from(1));
+            };
+            println!("{}", root);
+            record::visit(&db, &root, None)?
+        }
+        Some(Commands::Record { ref path, ref from, .. }) => {
+            // Rather than wait long behind another writer
+            if config.journal.unwrap_or(false) {
+                db.set_busy_timeout(journal::BUSY_TIMEOUT)?;
+            }
+            match record::visit(&db, &path.to_string_lossy(), utf8_arg(from.as_deref())) {
+                Err(e) if journal_record(&config, &db_path, cli.command.as_ref(), &e)? => {}
+                result => result?,
+            }
+        }
+        Some(Commands::RecordFile { path }) => {
+            record::file(&db, &path.to_string_lossy())?
+        }
+        Some(Commands::Complete { .. })
+        | Some(Commands::Verify { .. })
+        | Some(Commands::Info)
+        | Some(Commands::Profiles)
+        | Some(Commands::MigrateData { .. })
+        | Some(Commands::Daemon { .. })
+        | Some(Commands::Prompt { .. })
+        | Some(Commands::Hooks { .. })
+        | Some(Commands::Config { .. })
+        | Some(Commands::Man { .. }) => {
+            unreachable!("handled before opening the database")
+        }
+        Some(Commands::Completions { shell }) => print_completions(shell),
+        Some(Commands::Init { shell, hook, cmd, fallback, help_snippet, write }) => {
+            let detected = shell.is_none();
+            let Some(shell) = shell.or_else(detect_shell) else {
+                let names: Vec<_> = Shell::value_variants().iter().map(|shell| shell.name()).collect();
+                return Err(Error::InvalidInput(format!(
+                    "Could not detect your shell\nPass it explicitly with --shell ({})",
+                    names.join(", ")
+                )));
+            };
+            
+            let db = matches!(location.source, DbSource::Flag).then(|| db_path.to_string_lossy().into_owned());
+            let profile = if matches!(location.source, DbSource::ProfileFlag) { location.profile } else { None };
+            let after_goto = config.hooks.after_goto.clone();
+            let options = InitOptions { hook, cmd, fallback, db, profile, after_goto };
+            if help_snippet {
+                print_init_snippet(shell, &options);
+            } else if let Some(path) = write {
+                write_shell_init_file(&path, shell, &options)?;
+            } else {
+                let mut stdout = io::stdout().lock();
+                let result = if detected {
+                    writeln!(stdout, "# Detected shell: {}", shell.name())
+                } else {
+                    Ok(())
+                };
+                result
+                    .and_then(|_| generate_shell_init(&mut stdout, shell, &options))
+                    .map_err(|e| Error::Io(String::from("Error writing shell integration"), e))?;
+            }
+        }
         None => {
-            eprintln!("No command specified");
-            eprintln!("Try 'pathranger --help' for more information");
+            return Err(Error::InvalidInput(String::from(
+                "No command specified\nTry 'pathranger --help' for more information",
+            )));
         }
     }
     
-    Ok(())
-}
\ No newline at end of file
+    debug!("ran {} SQL statements", db.statement_count());
+    Ok(ExitCode::SUCCESS)
+}
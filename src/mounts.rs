@@ -0,0 +1,156 @@
+//! Network filesystems. Asking whether a directory on an NFS or SMB share,
+//! or on a FUSE mount like sshfs, still exists can wait seconds on an
+//! automounter or an unreachable server, so those checks run against a
+//! deadline, or are skipped, rather than holding up a jump.
+
+use std::cell::{Cell, OnceCell};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::{debug, Database};
+
+/// Filesystem types whose answers come over the network. FUSE mounts
+/// (`fuse.sshfs`, `fuse.rclone`, ...) count too, except `fuseblk`, which
+/// serves a local disk.
+const NETWORK_TYPES: &[&str] =
+    &["nfs", "nfs4", "cifs", "smb3", "smbfs", "ncpfs", "afs", "9p", "ceph", "glusterfs", "lustre", "davfs", "autofs"];
+
+/// How long one check on a network filesystem may take before the directory
+/// is assumed to exist
+pub const NETWORK_CHECK_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// How long one command waits on network filesystems in all; once it is
+/// spent, directories on them are assumed to exist
+pub const NETWORK_CHECK_BUDGET: Duration = Duration::from_millis(500);
+
+/// How existence checks treat directories on network filesystems
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PathChecks {
+    /// Check them on a thread, giving up after NETWORK_CHECK_TIMEOUT
+    #[default]
+    Bounded,
+    /// Assume they exist without asking
+    TrustNetwork,
+    /// Assume every directory exists, so answers come from the database alone
+    Offline,
+}
+
+/// A database's policy for existence checks and what they have cost so far
+#[derive(Default)]
+pub(crate) struct Probes {
+    checks: Cell<PathChecks>,
+    /// Read from /proc/mounts on the first check that needs it
+    network_mounts: OnceCell<Vec<PathBuf>>,
+    waited: Cell<Duration>,
+}
+
+/// Where network filesystems are mounted. Read from /proc/mounts, so empty
+/// on platforms without one.
+pub fn network_mounts() -> Vec<PathBuf> {
+    match std::fs::read_to_string("/proc/mounts") {
+        Ok(mounts) => parse_mounts(&mounts),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The mount points of network filesystems in /proc/mounts text, where a
+/// line is `device mount_point type options dump pass`
+fn parse_mounts(mounts: &str) -> Vec<PathBuf> {
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (_, mount_point, kind) = (fields.next()?, fields.next()?, fields.next()?);
+            is_network_type(kind).then(|| PathBuf::from(unescape_mount_field(mount_point)))
+        })
+        .collect()
+}
+
+fn is_network_type(kind: &str) -> bool {
+    NETWORK_TYPES.contains(&kind) || kind == "fuse" || kind.starts_with("fuse.")
+}
+
+/// Undo the octal escapes /proc/mounts writes for spaces, tabs, newlines
+/// and backslashes in mount points, as in `/mnt/My\040Share`
+fn unescape_mount_field(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut unescaped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).filter(|digits| digits.iter().all(|digit| (b'0'..=b'7').contains(digit)));
+        match octal {
+            Some(digits) if bytes[i] == b'\\' => {
+                let value = digits.iter().fold(0u32, |value, digit| value * 8 + u32::from(digit - b'0'));
+                unescaped.push(value as u8);
+                i += 4;
+            }
+            _ => {
+                unescaped.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+impl Database {
+    /// How `query`, `suggest` and recording check that directories exist:
+    /// by default those on network filesystems are checked against
+    /// NETWORK_CHECK_TIMEOUT and NETWORK_CHECK_BUDGET
+    pub fn set_path_checks(&self, checks: PathChecks) {
+        self.probes.checks.set(checks);
+    }
+
+    /// Whether `path` is a directory. One on a network filesystem that
+    /// doesn't answer in time, or that `set_path_checks` says not to check,
+    /// is assumed to be.
+    pub fn dir_exists(&self, path: &Path) -> bool {
+        self.probe(path, Path::is_dir).unwrap_or(true)
+    }
+
+    /// `check` applied to `path`, or None if `set_path_checks` says to skip
+    /// it or `path` is on a network filesystem that didn't answer in time.
+    /// A check still waiting then is left behind on its thread, since a
+    /// call into a hung mount can't be interrupted.
+    pub(crate) fn probe<T: Send + 'static>(
+        &self,
+        path: &Path,
+        check: impl FnOnce(&Path) -> T + Send + 'static,
+    ) -> Option<T> {
+        let probes = &self.probes;
+        match probes.checks.get() {
+            PathChecks::Offline => return None,
+            _ if !self.on_network_mount(path) => return Some(check(path)),
+            PathChecks::TrustNetwork => return None,
+            PathChecks::Bounded => {}
+        }
+
+        let timeout = NETWORK_CHECK_TIMEOUT.min(NETWORK_CHECK_BUDGET.saturating_sub(probes.waited.get()));
+        if timeout.is_zero() {
+            debug!("not checking {}: out of time for network filesystems", path.display());
+            return None;
+        }
+        let started = Instant::now();
+        let (sender, receiver) = mpsc::channel();
+        let owned = path.to_path_buf();
+        std::thread::spawn(move || {
+            let _ = sender.send(check(&owned));
+        });
+        let result = receiver.recv_timeout(timeout).ok();
+        probes.waited.set(probes.waited.get() + started.elapsed());
+        if result.is_none() {
+            debug!("gave up checking {} after {:?}", path.display(), started.elapsed());
+        }
+        result
+    }
+
+    fn on_network_mount(&self, path: &Path) -> bool {
+        let mounts = self.probes.network_mounts.get_or_init(|| {
+            let mounts = network_mounts();
+            debug!("{} network mount(s)", mounts.len());
+            mounts
+        });
+        mounts.iter().any(|mount| path.starts_with(mount))
+    }
+}
@@ -0,0 +1,60 @@
+//! How chatty pathranger is. Messages that aren't the command's actual
+//! output go through the macros here, so `--quiet` and `--verbose` apply to
+//! all of them the same way.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Verbosity {
+    /// Only the output itself and errors
+    Quiet,
+    /// Confirmations and warnings too
+    Normal,
+    /// Also what pathranger is doing internally, on stderr
+    Verbose,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Print a confirmation such as "Created tag ..." to stdout, unless quiet
+#[macro_export]
+macro_rules! note {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Normal {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Print a warning that doesn't stop the command to stderr, unless quiet
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Print a diagnostic to stderr, only with --verbose
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Verbose {
+            eprintln!("pathranger: {}", format_args!($($arg)*));
+        }
+    };
+}
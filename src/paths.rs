@@ -0,0 +1,396 @@
+//! How directories are spelled in the database. Windows tools hand over
+//! one directory as `c:\Work\App`, `C:/work/app` or `\\?\C:\Work\App`, so
+//! Windows paths are stored in one normalized spelling, and can be matched
+//! to a tracked directory ignoring case, so their visits aren't split
+//! between rows. A directory reached through a symlink has two spellings
+//! too, the shell's and the resolved one, and one of them is recorded.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use rusqlite::types::Value;
+use rusqlite::{params, OptionalExtension};
+
+use crate::display::expand_display_path;
+use crate::{debug, parse_timestamp, Database, PathForm, Result};
+
+/// The one spelling a Windows path is stored under: separators turned into
+/// `\`, the verbatim prefix dropped where it adds nothing (`\\?\C:\src`
+/// becomes `C:\src` and `\\?\UNC\server\share` becomes `\\server\share`), the
+/// drive letter in upper case and no trailing separator except on a drive
+/// root. Plain string handling, so it behaves the same on every platform.
+pub fn normalize_windows_path(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let mut path = if let Some(share) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", share)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\")
+        && has_drive(rest)
+    {
+        rest.to_string()
+    } else {
+        path
+    };
+    
+    if has_drive(&path) {
+        path[..1].make_ascii_uppercase();
+    }
+    while path.len() > 3 && path.ends_with('\\') {
+        path.pop();
+    }
+    path
+}
+
+/// Whether `path` starts with a drive letter and colon, as in `C:\src`
+fn has_drive(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':'
+}
+
+/// Whether `path` is an absolute Windows path: on a drive, or a share or
+/// verbatim path starting with two separators
+pub fn is_windows_path(path: &str) -> bool {
+    has_drive(path) || path.starts_with(r"\\") || path.starts_with("//")
+}
+
+/// `path` as it is stored and compared: in `normalize_windows_path` form on
+/// Windows, and unchanged elsewhere
+pub fn stored_path(path: &str) -> Cow<'_, str> {
+    if cfg!(windows) {
+        Cow::Owned(normalize_windows_path(path))
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// Expand `~` and make a user-supplied path absolute against the current
+/// directory, normalizing `.` and `..` lexically
+pub fn resolve_user_path(path: &str) -> String {
+    let expanded = PathBuf::from(expand_display_path(path));
+    let absolute = if expanded.is_absolute() {
+        expanded
+    } else {
+        std::env::current_dir().unwrap_or_default().join(expanded)
+    };
+    
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    
+    stored_path(&normalized.to_string_lossy()).into_owned()
+}
+
+/// What two spellings of one directory have in common: for a Windows path
+/// its normalized form, with ASCII letters in lower case if `fold_case`,
+/// the way SQLite's NOCASE compares them. Other paths are their own key.
+pub fn path_key(path: &str, fold_case: bool) -> String {
+    if !is_windows_path(path) {
+        return path.to_string();
+    }
+    let normalized = normalize_windows_path(path);
+    if fold_case { normalized.to_ascii_lowercase() } else { normalized }
+}
+
+/// Where WSL mounts Windows drives, as `/mnt/c`
+const WSL_MOUNT_ROOT: &str = "/mnt/";
+
+/// A WSL path on a mounted drive as Windows sees it, like `wslpath -w`:
+/// `/mnt/c/Users/alex` becomes `C:\Users\alex`. None for paths outside the
+/// drive mounts.
+pub fn wsl_to_windows(path: &str) -> Option<String> {
+    let mut chars = path.strip_prefix(WSL_MOUNT_ROOT)?.chars();
+    let drive = chars.next().filter(char::is_ascii_alphabetic)?;
+    let rest = chars.as_str();
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    let rest = rest.trim_matches('/').replace('/', "\\");
+    Some(format!("{}:\\{}", drive.to_ascii_uppercase(), rest))
+}
+
+/// A Windows path on a drive as WSL sees it, like `wslpath -u`:
+/// `C:\Users\alex` becomes `/mnt/c/Users/alex`. None for shares and other
+/// paths that aren't on a drive.
+pub fn windows_to_wsl(path: &str) -> Option<String> {
+    let path = normalize_windows_path(path);
+    if !has_drive(&path) || !(path.len() == 2 || path[2..].starts_with('\\')) {
+        return None;
+    }
+    let drive = path[..1].to_ascii_lowercase();
+    let rest = path[2..].trim_matches('\\').replace('\\', "/");
+    if rest.is_empty() {
+        Some(format!("{}{}", WSL_MOUNT_ROOT, drive))
+    } else {
+        Some(format!("{}{}/{}", WSL_MOUNT_ROOT, drive, rest))
+    }
+}
+
+impl Database {
+    /// Match Windows paths to tracked directories ignoring case, as NTFS
+    /// does unless a directory is made case-sensitive. On by default.
+    pub fn set_fold_case(&self, fold_case: bool) {
+        self.fold_case.set(fold_case);
+    }
+    
+    /// Record directories reached through symlinks as the shell spells them
+    /// (logical, the default) or with symlinks resolved (physical)
+    pub fn set_path_form(&self, form: PathForm) {
+        self.path_form.set(form);
+    }
+    
+    /// The spelling `path` is recorded under: `stored_path` in the form set
+    /// with `set_path_form`, or with `set_fold_case` the spelling of the
+    /// most visited tracked directory it matches. A logical path that can't
+    /// be trusted is resolved instead. While only the other form of `path`
+    /// is tracked, that is used, so history recorded before `set_path_form`
+    /// changed isn't orphaned.
+    pub fn canonical_path(&self, path: &str) -> Result<String> {
+        let (spelling, logical) = self.spelling(path);
+        let preferred = self.matching_spelling(spelling)?;
+        let other = match self.path_form.get() {
+            PathForm::Physical => Some(stored_path(path).into_owned()),
+            // An untrusted logical path was resolved already, into `preferred`
+            PathForm::Logical if logical => self.physical_path(path),
+            PathForm::Logical => None,
+        };
+        match other.filter(|other| *other != preferred) {
+            Some(other) if !self.is_tracked(&preferred)? && self.is_tracked(&other)? => Ok(other),
+            _ => Ok(preferred),
+        }
+    }
+    
+    /// `path` in the form `canonical_path` starts from, and whether that is
+    /// the shell's logical spelling, so symlinks weren't resolved for it
+    fn spelling<'a>(&self, path: &'a str) -> (Cow<'a, str>, bool) {
+        let logical = self.path_form.get() == PathForm::Logical && self.is_trusted_logical(path);
+        let resolved = if logical { None } else { self.physical_path(path) };
+        (resolved.map_or_else(|| stored_path(path), Cow::Owned), logical)
+    }
+    
+    /// With `set_fold_case`, the spelling of the most visited tracked
+    /// directory matching the Windows path `path`
+    fn matching_spelling(&self, path: Cow<'_, str>) -> Result<String> {
+        if !self.fold_case.get() || !is_windows_path(&path) {
+            return Ok(path.into_owned());
+        }
+        let existing: Option<String> = self
+            .statement("SELECT path FROM directories WHERE path = ?1 COLLATE NOCASE ORDER BY visit_count DESC LIMIT 1")?
+            .query_row(params![path.as_ref()], |row| row.get(0))
+            .optional()?;
+        Ok(existing.unwrap_or_else(|| path.into_owned()))
+    }
+    
+    fn is_tracked(&self, path: &str) -> Result<bool> {
+        Ok(self.statement("SELECT 1 FROM directories WHERE path = ?1")?.exists(params![path])?)
+    }
+    
+    /// `path` with symlinks resolved, in its `stored_path` spelling. None if
+    /// it can't be resolved, e.g. because it doesn't exist, or isn't checked.
+    fn physical_path(&self, path: &str) -> Option<String> {
+        let resolved = self.probe(Path::new(path), |path| fs::canonicalize(path))?.ok()?;
+        Some(stored_path(&resolved.to_string_lossy()).into_owned())
+    }
+    
+    /// Whether the shell's spelling of `path` can be recorded as it is: it is
+    /// absolute, has no `.` or `..` that resolve differently after a symlink,
+    /// and leads somewhere, unless that isn't checked
+    fn is_trusted_logical(&self, path: &str) -> bool {
+        let path = Path::new(path);
+        path.is_absolute()
+            && !path.components().any(|component| matches!(component, Component::CurDir | Component::ParentDir))
+            && self.probe(path, |path| fs::metadata(path).is_ok()).unwrap_or(true)
+    }
+    
+    /// Tracked Windows paths that aren't in their `path_key` spelling or
+    /// share one key, grouped by key with the most visited first. With
+    /// `fold_case`, paths differing only in case share a key.
+    pub fn path_variants(&self, fold_case: bool) -> Result<Vec<Vec<String>>> {
+        let paths: Vec<String> = self
+            .statement("SELECT path FROM directories ORDER BY visit_count DESC, path")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        
+        let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+        for path in paths.into_iter().filter(|path| is_windows_path(path)) {
+            groups.entry(path_key(&path, fold_case)).or_default().push(path);
+        }
+        let mut variants: Vec<Vec<String>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1 || group[0] != normalize_windows_path(&group[0]))
+            .collect();
+        variants.sort();
+        Ok(variants)
+    }
+    
+    /// Merge each group of `path_variants` into the normalized spelling of
+    /// its most visited path, adding up visits, history and moves and
+    /// re-pointing tags. Returns how many rows were merged away.
+    pub fn merge_path_variants(&self, fold_case: bool) -> Result<usize> {
+        self.transaction(false, |db| db.merge_variants(fold_case))
+    }
+    
+    /// `merge_path_variants` for a caller already inside `transaction`
+    pub(crate) fn merge_variants(&self, fold_case: bool) -> Result<usize> {
+        let mut merged = 0;
+        for group in &self.path_variants(fold_case)? {
+            let into = normalize_windows_path(&group[0]);
+            for path in group.iter().filter(|path| **path != into) {
+                if self.merge_directory(path, &into)? {
+                    merged += 1;
+                }
+            }
+        }
+        Ok(merged)
+    }
+    
+    /// Move everything recorded for `from` to `into`. Returns true if `into`
+    /// was already tracked and the two rows became one, false if `from` was
+    /// only renamed.
+    fn merge_directory(&self, from: &str, into: &str) -> Result<bool> {
+        let row = |path: &str| -> Result<Option<(i64, Value)>> {
+            let row = self
+                .statement("SELECT visit_count, last_visited FROM directories WHERE path = ?1")?
+                .query_row(params![path], |row| Ok((row.get(0)?, row.get(1)?)))
+                .optional()?;
+            Ok(row)
+        };
+        let Some((count, last_visited)) = row(from)? else {
+            return Ok(false);
+        };
+        let existing = row(into)?;
+        if let Some((into_count, into_visited)) = &existing {
+            let last_visited = if parse_timestamp(&last_visited) > parse_timestamp(into_visited) {
+                last_visited
+            } else {
+                into_visited.clone()
+            };
+            self.statement("UPDATE directories SET visit_count = ?1, last_visited = ?2 WHERE path = ?3")?
+                .execute(params![count + into_count, last_visited, into])?;
+            self.statement("DELETE FROM directories WHERE path = ?1")?.execute(params![from])?;
+        } else {
+            self.statement("UPDATE directories SET path = ?2 WHERE path = ?1")?.execute(params![from, into])?;
+        }
+        
+        self.statement("UPDATE visits SET path = ?2 WHERE path = ?1")?.execute(params![from, into])?;
+        self.statement(
+            "INSERT INTO visit_hours (path, hour, count) SELECT ?2, hour, count FROM visit_hours WHERE path = ?1
+            ON CONFLICT (path, hour) DO UPDATE SET count = count + excluded.count",
+        )?
+        .execute(params![from, into])?;
+        self.statement("DELETE FROM visit_hours WHERE path = ?1")?.execute(params![from])?;
+        for (column, other) in [("to_path", "from_path"), ("from_path", "to_path")] {
+            self.statement(&format!(
+                "INSERT INTO transitions ({column}, {other}, count, last_moved)
+                SELECT ?2, {other}, count, last_moved FROM transitions WHERE {column} = ?1
+                ON CONFLICT (from_path, to_path) DO UPDATE SET
+                    count = count + excluded.count,
+                    last_moved = max(last_moved, excluded.last_moved)",
+            ))?
+            .execute(params![from, into])?;
+            self.statement(&format!("DELETE FROM transitions WHERE {column} = ?1"))?.execute(params![from])?;
+        }
+        self.statement("DELETE FROM transitions WHERE from_path = to_path")?.execute([])?;
+        self.statement("UPDATE tags SET path = ?2 WHERE path = ?1")?.execute(params![from, into])?;
+        
+        debug!("merged {} into {}", from, into);
+        Ok(existing.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn drive_paths_have_one_spelling() {
+        for path in ["C:/x", r"c:\x\", r"C:\x", "c:/x//", r"\\?\C:\x", r"\\?\c:\x\"] {
+            assert_eq!(normalize_windows_path(path), r"C:\x", "{:?}", path);
+        }
+        assert_eq!(normalize_windows_path("c:/"), r"C:\");
+        assert_eq!(normalize_windows_path(r"C:\\"), r"C:\");
+        assert_eq!(normalize_windows_path("c:"), "C:");
+        // Only the drive letter changes case
+        assert_eq!(normalize_windows_path("c:/Work/App"), r"C:\Work\App");
+    }
+    
+    #[test]
+    fn unc_paths_keep_their_server_and_share() {
+        assert_eq!(normalize_windows_path(r"\\server\share\dir\"), r"\\server\share\dir");
+        assert_eq!(normalize_windows_path("//server/share"), r"\\server\share");
+        assert_eq!(normalize_windows_path(r"\\?\UNC\server\share\dir"), r"\\server\share\dir");
+        assert_eq!(normalize_windows_path(r"\\?\UNC\Server\Share"), r"\\Server\Share");
+        // A verbatim path that isn't on a drive keeps its prefix
+        assert_eq!(normalize_windows_path(r"\\?\Volume{1234}\dir"), r"\\?\Volume{1234}\dir");
+    }
+    
+    #[test]
+    fn path_keys_fold_case_only_when_asked() {
+        assert_eq!(path_key("c:/Work/App/", false), r"C:\Work\App");
+        assert_eq!(path_key("c:/Work/App/", true), r"c:\work\app");
+        assert_eq!(path_key(r"\\?\C:\WORK\APP", true), path_key(r"c:\work\app", true));
+        assert_eq!(path_key("/home/Alex/", true), "/home/Alex/");
+    }
+    
+    #[test]
+    fn wsl_drive_mounts_round_trip() {
+        for (wsl, windows) in [
+            ("/mnt/c", r"C:\"),
+            ("/mnt/c/Users/alex", r"C:\Users\alex"),
+            ("/mnt/d/src/my app", r"D:\src\my app"),
+        ] {
+            assert_eq!(wsl_to_windows(wsl).as_deref(), Some(windows), "{}", wsl);
+            assert_eq!(windows_to_wsl(windows).as_deref(), Some(wsl), "{}", windows);
+        }
+        assert_eq!(wsl_to_windows("/mnt/c/").as_deref(), Some(r"C:\"));
+        assert_eq!(wsl_to_windows("/mnt/c/Users/alex/").as_deref(), Some(r"C:\Users\alex"));
+        assert_eq!(windows_to_wsl("c:/Users/alex/").as_deref(), Some("/mnt/c/Users/alex"));
+        assert_eq!(windows_to_wsl(r"\\?\C:\Users").as_deref(), Some("/mnt/c/Users"));
+        assert_eq!(windows_to_wsl("C:").as_deref(), Some("/mnt/c"));
+    }
+    
+    #[test]
+    fn mnt_paths_off_a_drive_stay_in_wsl() {
+        for path in ["/mnt", "/mnt/", "/mnt/cdrom", "/mnt/wsl/docker", "/mnt/1/x", "/mnt/-", "/home/alex/mnt/c"] {
+            assert_eq!(wsl_to_windows(path), None, "{}", path);
+        }
+    }
+    
+    #[test]
+    fn paths_off_a_drive_stay_in_windows() {
+        for path in [r"\\server\share\dir", r"\\?\UNC\server\share", "C:relative", "/home/alex"] {
+            assert_eq!(windows_to_wsl(path), None, "{}", path);
+        }
+    }
+    
+    #[test]
+    fn windows_paths_are_recognized() {
+        assert!(is_windows_path(r"C:\src"));
+        assert!(is_windows_path("c:/src"));
+        assert!(is_windows_path(r"\\server\share"));
+        assert!(is_windows_path("//server/share"));
+        assert!(!is_windows_path("/home/alex"));
+        assert!(!is_windows_path("src"));
+    }
+    
+    #[cfg(windows)]
+    #[test]
+    fn windows_stores_the_normalized_spelling() {
+        assert_eq!(stored_path("c:/Work/App/"), r"C:\Work\App");
+        assert_eq!(stored_path(r"\\?\UNC\server\share"), r"\\server\share");
+    }
+    
+    #[cfg(not(windows))]
+    #[test]
+    fn other_platforms_store_paths_as_they_are() {
+        assert_eq!(stored_path("/home/alex/"), "/home/alex/");
+        assert_eq!(stored_path("c:/Work/App/"), "c:/Work/App/");
+    }
+}
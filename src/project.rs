@@ -0,0 +1,284 @@
+//! Projects: the version control root a directory belongs to, found by
+//! walking up to the nearest directory holding `.git`, `.hg` or `.svn`, and
+//! for `root` also build files and other markers.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{debug, frecency, Database, Result, Scope};
+
+/// Entries whose presence marks a version control root
+const VCS_MARKERS: &[&str] = &[".git", ".hg", ".svn"];
+
+/// Whether `dir` is the root of a repository
+pub fn is_repository(dir: &Path) -> bool {
+    VCS_MARKERS.iter().any(|marker| dir.join(marker).exists())
+}
+
+/// Entries besides VCS_MARKERS that mark a project root for `root`, unless
+/// `root.markers` in the config replaces them
+pub const DEFAULT_ROOT_MARKERS: &[&str] = &["Cargo.toml", "package.json", ".project"];
+
+/// The nearest directory at or above `dir` that is a repository or holds
+/// one of `markers`. Neither the home directory nor the filesystem root
+/// counts, since a stray marker there would claim everything below it.
+pub fn find_root(dir: &Path, markers: &[String]) -> Option<PathBuf> {
+    let home = dirs::home_dir();
+    dir.ancestors()
+        .take_while(|ancestor| ancestor.parent().is_some() && home.as_deref() != Some(*ancestor))
+        .find(|ancestor| is_repository(ancestor) || markers.iter().any(|marker| ancestor.join(marker).exists()))
+        .map(Path::to_path_buf)
+}
+
+/// Finds project roots, remembering the answer for every directory it
+/// passes so a listing of many siblings stats each ancestor only once
+pub struct ProjectRoots {
+    cache: HashMap<PathBuf, Option<PathBuf>>,
+    home: Option<PathBuf>,
+}
+
+impl Default for ProjectRoots {
+    fn default() -> Self {
+        ProjectRoots { cache: HashMap::new(), home: dirs::home_dir() }
+    }
+}
+
+impl ProjectRoots {
+    /// The nearest repository root at or above `dir`. A repository in the
+    /// home directory, as some dotfile setups use, doesn't count, since it
+    /// would claim everything below it.
+    pub fn root_of(&mut self, dir: &Path) -> Option<PathBuf> {
+        let mut passed = Vec::new();
+        let mut root = None;
+        for ancestor in dir.ancestors() {
+            if let Some(cached) = self.cache.get(ancestor) {
+                root = cached.clone();
+                break;
+            }
+            passed.push(ancestor);
+            if self.home.as_deref() != Some(ancestor) && is_repository(ancestor) {
+                root = Some(ancestor.to_path_buf());
+                break;
+            }
+        }
+        for dir in passed {
+            self.cache.insert(dir.to_path_buf(), root.clone());
+        }
+        root
+    }
+}
+
+/// A version control root and the visits to directories inside it
+#[derive(Clone, Debug, Serialize)]
+pub struct Project {
+    pub path: String,
+    pub tags: Vec<String>,
+    pub visits: i64,
+    /// Distinct tracked directories in the project, the root included
+    pub directories: usize,
+    pub last_visited: Option<DateTime<Local>>,
+    pub frecency: f64,
+}
+
+impl Database {
+    /// Tracked directories grouped by their project root, highest frecency
+    /// first. Directories outside any repository are left out.
+    pub fn projects(&self) -> Result<Vec<Project>> {
+        let now = Local::now();
+        let mut roots = ProjectRoots::default();
+        let mut projects: HashMap<PathBuf, Project> = HashMap::new();
+        for entry in self.candidates()? {
+            let Some(root) = roots.root_of(Path::new(&entry.path)) else {
+                continue;
+            };
+            let project = projects.entry(root).or_insert_with_key(|root| Project {
+                path: root.to_string_lossy().into_owned(),
+                tags: Vec::new(),
+                visits: 0,
+                directories: 0,
+                last_visited: None,
+                frecency: 0.0,
+            });
+            project.visits += entry.visit_count;
+            project.directories += 1;
+            project.last_visited = project.last_visited.max(entry.last_visited);
+            project.frecency += frecency(&entry, now);
+        }
+        
+        let mut projects: Vec<Project> = projects.into_values().collect();
+        let mut tags = self.tags_for_paths(projects.iter().map(|project| project.path.as_str()))?;
+        for project in &mut projects {
+            project.tags = tags.remove(&project.path).unwrap_or_default();
+        }
+        projects.sort_by(|a, b| b.frecency.total_cmp(&a.frecency).then_with(|| a.path.cmp(&b.path)));
+        Ok(projects)
+    }
+    
+    /// Tag names for the repositories that have no tag yet: the directory
+    /// name, or when that is taken `parent/name`, then `name-2`, `name-3`
+    /// and so on. Names already in use are never reused, wherever they point.
+    pub fn autotag_names(&self, repositories: &[String]) -> Result<Vec<(String, String)>> {
+        let existing = self.tags()?;
+        let tagged: HashSet<&str> = existing.iter().map(|tag| tag.path.as_str()).collect();
+        let mut taken: HashSet<String> = existing.iter().map(|tag| tag.name.clone()).collect();
+        
+        let mut names = Vec::new();
+        for path in repositories {
+            if tagged.contains(path.as_str()) {
+                debug!("{} is already tagged", path);
+                continue;
+            }
+            let path_ref = Path::new(path);
+            let Some(name) = path_ref.file_name().map(|name| name.to_string_lossy().into_owned()) else {
+                continue;
+            };
+            let parent = path_ref
+                .parent()
+                .and_then(Path::file_name)
+                .map(|parent| format!("{}/{}", parent.to_string_lossy(), name));
+            let numbered = (2..).map(|n| format!("{}-{}", name, n));
+            let mut candidates = std::iter::once(name.clone()).chain(parent).chain(numbered);
+            let Some(tag) = candidates.find(|tag| !taken.contains(tag)) else {
+                continue;
+            };
+            taken.insert(tag.clone());
+            names.push((tag, path.clone()));
+        }
+        Ok(names)
+    }
+}
+
+/// How `search` and `query` treat the project the current directory is in
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum CwdProject {
+    /// Prefer directories inside the project, looking everywhere when none match
+    #[default]
+    Auto,
+    /// Only consider directories inside the project
+    Only,
+    /// Ignore the project
+    Off,
+}
+
+impl CwdProject {
+    /// The project root this mode narrows candidates to for a process in `cwd`
+    pub fn root(self, cwd: Option<&Path>) -> Option<PathBuf> {
+        if self == CwdProject::Off {
+            return None;
+        }
+        ProjectRoots::default().root_of(cwd?)
+    }
+    
+    /// Where candidates come from, given the root `root` found. Outside any
+    /// project that is everywhere, whatever the mode.
+    pub fn scope(self, root: Option<&Path>) -> Scope<'_> {
+        match (self, root) {
+            (CwdProject::Auto, Some(root)) => Scope::Prefer(root),
+            (CwdProject::Only, Some(root)) => Scope::Only(root),
+            _ => Scope::Everywhere,
+        }
+    }
+    
+    /// The name used on the command line, in the config file and in daemon
+    /// requests
+    pub fn name(self) -> &'static str {
+        match self {
+            CwdProject::Auto => "auto",
+            CwdProject::Only => "only",
+            CwdProject::Off => "off",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, repositories};
+    
+    /// The shared fixture with `tags` created
+    fn tagged_fixture(tags: &[(&str, &str)]) -> (tempfile::TempDir, Database) {
+        let (dir, db) = fixture();
+        for (name, path) in tags {
+            db.add_tag(name, path).expect("tag a fixture directory");
+        }
+        (dir, db)
+    }
+    
+    fn names(db: &Database, repositories: &[&str]) -> Vec<(String, String)> {
+        let repositories: Vec<String> = repositories.iter().map(|path| path.to_string()).collect();
+        db.autotag_names(&repositories).expect("name the repositories")
+    }
+    
+    fn pairs(names: &[(String, String)]) -> Vec<(&str, &str)> {
+        names.iter().map(|(name, path)| (name.as_str(), path.as_str())).collect()
+    }
+    
+    #[test]
+    fn repositories_sharing_a_name_fall_back_to_their_parent() {
+        let (_dir, db) = tagged_fixture(&[]);
+        let names = names(&db, &["/src/work/api", "/src/personal/api", "/src/other/work/api", "/src/web"]);
+        assert_eq!(
+            pairs(&names),
+            [
+                ("api", "/src/work/api"),
+                ("personal/api", "/src/personal/api"),
+                ("work/api", "/src/other/work/api"),
+                ("web", "/src/web"),
+            ]
+        );
+    }
+    
+    #[test]
+    fn numbered_names_follow_the_parent() {
+        let (_dir, db) = tagged_fixture(&[]);
+        let names = names(&db, &["/a/work/api", "/b/work/api", "/c/work/api", "/api"]);
+        assert_eq!(
+            pairs(&names),
+            [("api", "/a/work/api"), ("work/api", "/b/work/api"), ("api-2", "/c/work/api"), ("api-3", "/api")]
+        );
+    }
+    
+    #[test]
+    fn names_taken_by_unrelated_tags_are_not_reused() {
+        let (_dir, db) = tagged_fixture(&[
+            ("api", "/elsewhere"),
+            ("web", "/elsewhere/web"),
+            ("src/web", "/elsewhere/web"),
+            ("web-2", "/elsewhere/web"),
+        ]);
+        let names = names(&db, &["/src/api", "/src/web"]);
+        assert_eq!(pairs(&names), [("src/api", "/src/api"), ("web-3", "/src/web")]);
+    }
+    
+    #[test]
+    fn tagged_repositories_and_roots_are_skipped() {
+        let (_dir, db) = tagged_fixture(&[("mine", "/src/api")]);
+        assert!(names(&db, &["/src/api", "/"]).is_empty());
+        assert_eq!(pairs(&names(&db, &["/", "/src/web"])), [("web", "/src/web")]);
+    }
+    
+    #[test]
+    fn directories_belong_to_their_nearest_project() {
+        let (dir, db) = fixture();
+        let path = repositories(
+            &dir,
+            &db,
+            &["outer", "outer/vendor/inner"],
+            &[("outer", 4), ("outer/src", 3), ("outer/vendor", 1), ("outer/vendor/inner/lib", 9), ("notes", 7)],
+        );
+        db.add_tag("outer", &path("outer")).expect("tag a fixture directory");
+        
+        let projects = db.projects().expect("group the directories");
+        let found: Vec<(&str, i64, usize, &[String])> = projects
+            .iter()
+            .map(|project| (project.path.as_str(), project.visits, project.directories, project.tags.as_slice()))
+            .collect();
+        let (outer, inner) = (path("outer"), path("outer/vendor/inner"));
+        assert_eq!(found, [(inner.as_str(), 9, 1, &[][..]), (outer.as_str(), 8, 3, &[String::from("outer")][..])]);
+    }
+}
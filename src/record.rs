@@ -0,0 +1,210 @@
+//! Counting visits the way the shell hooks ask for them: straight into the
+//! database, into the journal, or from a detached process, with the exclude
+//! patterns and $PATHRANGER_DISABLE checked first
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use globset::GlobSet;
+use shellexpand::tilde;
+
+use crate::journal::Journal;
+use crate::paths::{resolve_user_path, stored_path};
+use crate::{debug, is_excluded, private_file_options, warn, Database, Error, Result};
+
+/// Once the log grows past this, only its newest half is kept
+const LOG_LIMIT: u64 = 64 * 1024;
+
+/// Whether a non-empty $PATHRANGER_DISABLE turns recording off, so scripts,
+/// containers and shells spawned by tools stay out of the history
+pub fn recording_disabled() -> bool {
+    std::env::var_os("PATHRANGER_DISABLE").is_some_and(|value| !value.is_empty())
+}
+
+/// `path` with `~` expanded, or None if it matches `exclude` or recording is
+/// disabled. Paths headed for the database are checked against its own
+/// patterns there; `exclude` saves a process or a journal line for them.
+pub fn path_to_record<'a>(path: &'a str, exclude: Option<&GlobSet>) -> Option<Cow<'a, str>> {
+    if recording_disabled() {
+        debug!("not recording {}: PATHRANGER_DISABLE is set", path);
+        return None;
+    }
+    let expanded = match tilde(path) {
+        Cow::Borrowed(path) => stored_path(path),
+        Cow::Owned(path) => Cow::Owned(stored_path(&path).into_owned()),
+    };
+    if expanded != path {
+        debug!("expanded {} to {}", path, expanded);
+    }
+    if exclude.is_some_and(|patterns| is_excluded(patterns, &expanded)) {
+        debug!("not recording {}: it matches an exclude pattern", expanded);
+        return None;
+    }
+    Some(expanded)
+}
+
+/// The directory a visit came from, if it is one `path_to_record` would
+/// record. Hooks pass an empty string before the first change.
+pub fn from_to_record<'a>(from: Option<&'a str>, exclude: Option<&GlobSet>) -> Option<Cow<'a, str>> {
+    let from = path_to_record(from.filter(|from| !from.is_empty())?, exclude)?;
+    Path::new(from.as_ref()).is_absolute().then_some(from)
+}
+
+/// Count a visit to `path` unless the database excludes it; a directory that
+/// no longer exists is reported rather than treated as an error, since the
+/// shell hooks record blindly
+pub fn visit(db: &Database, path: &str, from: Option<&str>) -> Result<()> {
+    let Some(expanded) = path_to_record(path, None) else {
+        return Ok(());
+    };
+    match db.record_visit_from(&expanded, from_to_record(from, None).as_deref()) {
+        Err(Error::DirectoryMissing(path)) => {
+            warn!("Directory does not exist: {}", path);
+            Ok(())
+        }
+        Err(e) if e.is_read_only() => {
+            debug!("not recording the visit: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Count an open of the file at `path`, like `visit` does for directories
+pub fn file(db: &Database, path: &str) -> Result<()> {
+    let Some(path) = path_to_record(&resolve_user_path(path), None).map(Cow::into_owned) else {
+        return Ok(());
+    };
+    match db.record_file(&path) {
+        Err(Error::FileMissing(path)) => {
+            warn!("File does not exist: {}", path);
+            Ok(())
+        }
+        Err(e) if e.is_read_only() => {
+            debug!("not recording the file: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Append the visit to the journal of the database at `db_path` instead of
+/// writing the database. Returns false if the caller has to record it
+/// directly.
+pub fn journal_visit(db_path: &Path, path: &str, from: Option<&str>, exclude: Option<&GlobSet>) -> Result<bool> {
+    let Some(expanded) = path_to_record(path, exclude) else {
+        return Ok(true);
+    };
+    let Some(journal) = Journal::for_database(db_path).filter(|_| Journal::accepts(&expanded)) else {
+        return Ok(false);
+    };
+    if !Path::new(expanded.as_ref()).is_dir() {
+        warn!("Directory does not exist: {}", expanded);
+        return Ok(true);
+    }
+    journal.append(&expanded, from_to_record(from, exclude).as_deref())?;
+    Ok(true)
+}
+
+/// Start a detached `pathranger record` that records the visit, for prompt
+/// hooks that mustn't wait on a busy disk. Returns false if the caller has
+/// to record synchronously instead, as on platforms that can't detach a
+/// process.
+#[cfg(unix)]
+pub fn in_background(db_path: &Path, path: &str, from: Option<&str>, exclude: Option<&GlobSet>) -> bool {
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+    
+    let Some(expanded) = path_to_record(path, exclude) else {
+        return true;
+    };
+    let Ok(exe) = std::env::current_exe() else {
+        return false;
+    };
+    let mut command = Command::new(exe);
+    command
+        .arg("--quiet")
+        .arg("--db")
+        .arg(db_path)
+        .args(["record", "--log"])
+        .arg(log_path(db_path));
+    if let Some(from) = from_to_record(from, exclude) {
+        command.arg("--from").arg(from.as_ref());
+    }
+    command
+        .arg("--")
+        .arg(expanded.as_ref())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe. A new session leaves the
+    // terminal behind, so closing it or pressing Ctrl-C can't kill the write.
+    unsafe {
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    // Not waited for: this process exits next, and init reaps the child
+    match command.spawn() {
+        Ok(child) => {
+            debug!("recording {} in process {}", expanded, child.id());
+            true
+        }
+        Err(e) => {
+            debug!("could not start a background recorder: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn in_background(_db_path: &Path, _path: &str, _from: Option<&str>, _exclude: Option<&GlobSet>) -> bool {
+    false
+}
+
+/// The background recorder's log for the database at `db_path`
+pub fn log_path(db_path: &Path) -> PathBuf {
+    db_path.with_extension("log")
+}
+
+/// Note a background recorder's failure, trimming the log so it stays small.
+/// Errors here have nowhere to go, so they are dropped.
+pub fn append_log(log: &Path, path: &str, error: &Error) {
+    if fs::metadata(log).is_ok_and(|metadata| metadata.len() > LOG_LIMIT)
+        && let Ok(contents) = fs::read(log)
+    {
+        // Measured from what was read: the log may have shrunk since its size
+        // was checked, when another recorder trimmed it first
+        let tail = &contents[contents.len().saturating_sub(LOG_LIMIT as usize / 2)..];
+        let start = tail.iter().position(|byte| *byte == b'\n').map_or(0, |newline| newline + 1);
+        let _ = fs::write(log, &tail[start..]);
+    }
+    let line = format!("{} record {}: {}\n", Local::now().to_rfc3339(), path, error.to_string().replace('\n', " "));
+    if let Ok(mut file) = private_file_options().create(true).append(true).open(log) {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn record_log_is_trimmed_to_whole_lines() {
+        let dir = tempfile::tempdir().expect("create a temporary directory");
+        let log = dir.path().join("record.log");
+        let old_line = "2024-05-01T00:00:00+00:00 record /old: failed\n";
+        fs::write(&log, old_line.repeat(LOG_LIMIT as usize / old_line.len() + 2)).expect("write the log");
+        
+        append_log(&log, "/new", &Error::InvalidInput(String::from("no\nroom")));
+        let contents = fs::read_to_string(&log).expect("read the log");
+        assert!(contents.len() <= LOG_LIMIT as usize / 2 + old_line.len(), "{} bytes left", contents.len());
+        let (kept, last) = contents.trim_end().rsplit_once('\n').expect("several lines");
+        assert!(kept.lines().all(|line| format!("{}\n", line) == old_line));
+        assert!(last.ends_with(" record /new: no room"), "{}", last);
+    }
+}
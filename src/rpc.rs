@@ -0,0 +1,281 @@
+//! `pathranger rpc`: JSON-RPC 2.0 over stdin and stdout, for editor plugins
+//! that keep one pathranger process running and talk to it over pipes. Each
+//! line on stdin is a request, and each gets one line back on stdout,
+//! flushed straight away:
+//!
+//! ```text
+//! {"jsonrpc": "2.0", "id": 1, "method": "search", "params": {"query": "api"}}
+//! {"jsonrpc":"2.0","id":1,"result":[{"path":"/home/alex/api","visit_count":12,...}]}
+//! ```
+//!
+//! ```text
+//! search       {query[, limit]}    [{path, visit_count, last_visited, score}]
+//! query        {name[, cwd]}       path | null
+//! top          {[count]}           [{path, visit_count, last_visited}]
+//! recent       {[count]}           [{path, visit_count, last_visited}]
+//! tags         {}                  [{name, path, created_at, updated_at}]
+//! record       {path[, from]}      {recorded}
+//! resolve-tag  {tag}               path | null
+//! ```
+//!
+//! A request without an id is a notification and gets no response. A line
+//! that isn't a valid request is answered with an error object rather than
+//! ending the session, which lasts until stdin is closed.
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+use pathranger::config::{Config, DEFAULT_COUNT};
+use pathranger::record;
+use pathranger::tagfile::TagFiles;
+use pathranger::{Database, DirEntry, Error, Filter, ListOptions, Result, Scope, SortKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::FilterArgs;
+
+/// JSON-RPC's codes for requests that can't be run as sent
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// The code for pathranger's own failures, such as a database error
+const SERVER_ERROR: i64 = -32000;
+
+/// A directory in a listing or search result, as `rpc` and `serve` send it
+#[derive(Serialize)]
+pub struct EntryJson {
+    path: String,
+    visit_count: i64,
+    last_visited: Option<DateTime<Local>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    score: Option<i64>,
+}
+
+impl EntryJson {
+    pub fn new(entry: DirEntry, score: Option<i64>) -> Self {
+        EntryJson { path: entry.path, visit_count: entry.visit_count, last_visited: entry.last_visited, score }
+    }
+}
+
+/// The outcome of a `record` request
+#[derive(Serialize)]
+pub struct Recorded {
+    /// False when the path is excluded or recording is disabled
+    recorded: bool,
+}
+
+/// Count a visit to `path` sent by a client, which has no current
+/// directory of ours to be relative to, so it must be absolute
+pub fn record(db: &Database, path: &str, from: Option<&str>) -> Result<Recorded> {
+    if !Path::new(path).is_absolute() && !path.starts_with('~') {
+        return Err(Error::InvalidInput(format!("{} is not an absolute path", path)));
+    }
+    let Some(path) = record::path_to_record(path, None).filter(|path| !db.excludes(path)) else {
+        return Ok(Recorded { recorded: false });
+    };
+    let from = record::from_to_record(from, None);
+    db.record_visit_from(&path, from.as_deref())?;
+    Ok(Recorded { recorded: true })
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    #[serde(default)]
+    params: Value,
+    /// Absent for a notification
+    id: Option<Value>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> Self {
+        let code = if matches!(error, Error::InvalidInput(_)) { INVALID_PARAMS } else { SERVER_ERROR };
+        RpcError { code, message: error.to_string() }
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct QueryParams {
+    name: String,
+    /// The directory to leave out and to find the project from, which is
+    /// pathranger's own current directory if not given
+    cwd: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct CountParams {
+    count: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RecordParams {
+    path: String,
+    from: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TagParams {
+    tag: String,
+}
+
+/// What requests are answered from, set up once from the config
+struct Session<'a> {
+    db: &'a Database,
+    config: &'a Config,
+    filter: Filter,
+    tag_files: TagFiles,
+}
+
+/// Answer requests from stdin until it is closed
+pub fn run(db: &Database, config: &Config) -> Result<()> {
+    let session = Session {
+        db,
+        config,
+        filter: crate::filter_from_args(&FilterArgs::default(), config)?,
+        tag_files: crate::tag_files(config),
+    };
+    
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let read = stdin
+            .read_until(b'\n', &mut line)
+            .map_err(|e| Error::Io(String::from("Could not read a request"), e))?;
+        if read == 0 {
+            return Ok(());
+        }
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        let Some(response) = session.respond(&line) else {
+            continue;
+        };
+        writeln!(stdout, "{}", response)
+            .and_then(|()| stdout.flush())
+            .map_err(|e| Error::Io(String::from("Could not write a response"), e))?;
+    }
+}
+
+/// A response line for the request `id`
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> String {
+    let response = match result {
+        Ok(result) => serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    };
+    response.to_string()
+}
+
+/// `params` as the type a method takes, where none at all means no fields
+fn params<T: DeserializeOwned>(params: Value) -> std::result::Result<T, RpcError> {
+    let params = if params.is_null() { Value::Object(serde_json::Map::new()) } else { params };
+    serde_json::from_value(params)
+        .map_err(|e| RpcError { code: INVALID_PARAMS, message: format!("Invalid params: {}", e) })
+}
+
+/// A method's result as JSON
+fn result<T: Serialize>(result: Result<T>) -> std::result::Result<Value, RpcError> {
+    serde_json::to_value(result?).map_err(|e| RpcError { code: SERVER_ERROR, message: e.to_string() })
+}
+
+impl Session<'_> {
+    /// The response to one request line, or None for a notification
+    fn respond(&self, line: &[u8]) -> Option<String> {
+        let value: Value = match serde_json::from_slice(line) {
+            Ok(value) => value,
+            Err(e) => return Some(response(Value::Null, Err(RpcError { code: PARSE_ERROR, message: e.to_string() }))),
+        };
+        let id = value.get("id").cloned().unwrap_or(Value::Null);
+        if !value.is_object() {
+            let message = String::from("A request must be a JSON object; batches aren't supported");
+            return Some(response(id, Err(RpcError { code: INVALID_REQUEST, message })));
+        }
+        let request: Request = match serde_json::from_value(value) {
+            Ok(request) => request,
+            Err(e) => {
+                let message = format!("Invalid request: {}", e);
+                return Some(response(id, Err(RpcError { code: INVALID_REQUEST, message })));
+            }
+        };
+        let result = self.call(&request.method, request.params);
+        Some(response(request.id?, result))
+    }
+    
+    fn call(&self, method: &str, params_value: Value) -> std::result::Result<Value, RpcError> {
+        match method {
+            "search" => result(self.search(params(params_value)?)),
+            "query" => result(self.query(params(params_value)?)),
+            "top" => result(self.list(SortKey::Visits, params(params_value)?)),
+            "recent" => result(self.list(SortKey::Recent, params(params_value)?)),
+            "tags" => result(self.db.tags()),
+            "record" => {
+                let RecordParams { path, from } = params(params_value)?;
+                result(record(self.db, &path, from.as_deref()))
+            }
+            "resolve-tag" => result(self.resolve_tag(params(params_value)?)),
+            _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("Unknown method '{}'", method) }),
+        }
+    }
+    
+    fn search(&self, params: SearchParams) -> Result<Vec<EntryJson>> {
+        let limit = params.limit.or(self.config.search.limit).unwrap_or(DEFAULT_COUNT);
+        let query = crate::expand_search_query(&params.query);
+        let mut matches = self.db.search_in(&query, &self.filter, Scope::Everywhere)?;
+        matches.truncate(limit);
+        Ok(matches.into_iter().map(|found| EntryJson::new(found.entry, Some(found.score))).collect())
+    }
+    
+    /// The directory `query` would print, or None without a clear winner
+    fn query(&self, params: QueryParams) -> Result<Option<String>> {
+        if let Some(cwd) = params.cwd.as_deref().filter(|cwd| !cwd.is_absolute()) {
+            return Err(Error::InvalidInput(format!("{} is not an absolute path", cwd.display())));
+        }
+        let cwd = params.cwd.or_else(crate::query_cwd);
+        let cwd_project = self.config.cwd_project.unwrap_or_default();
+        let explore = self.config.query.explore.unwrap_or(false);
+        crate::query_directory(self.db, &params.name, cwd.as_deref(), cwd_project, &[], explore)
+    }
+    
+    fn list(&self, sort: SortKey, params: CountParams) -> Result<Vec<EntryJson>> {
+        let default = match sort {
+            SortKey::Recent => self.config.recent.count,
+            _ => self.config.top.count,
+        };
+        let options = ListOptions {
+            sort,
+            reverse: false,
+            count: params.count.or(default).unwrap_or(DEFAULT_COUNT),
+            filter: self.filter.clone(),
+        };
+        Ok(self.db.list(&options)?.into_iter().map(|entry| EntryJson::new(entry, None)).collect())
+    }
+    
+    /// Where `goto` would take the tag, without counting a visit
+    fn resolve_tag(&self, params: TagParams) -> Result<Option<String>> {
+        match crate::tag_target(self.db, &self.tag_files, &params.tag) {
+            Ok(path) => Ok(Some(path)),
+            Err(Error::TagNotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! `pathranger scan`: walk a directory tree in parallel and collect the
+//! directories in it, so a fresh database has candidates for jumps and
+//! search before any have been visited.
+
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Local};
+use ignore::{WalkBuilder, WalkState};
+use pathranger::output::{self, Verbosity};
+use globset::GlobSet;
+use pathranger::{debug, is_excluded, Visit};
+
+/// How far into the tree to look
+pub struct ScanOptions {
+    /// Deepest level to descend to below the root (the root is level 0)
+    pub max_depth: Option<usize>,
+    /// Shallowest level to collect, so `--min-depth 2` skips the root and
+    /// its immediate children
+    pub min_depth: usize,
+    /// Also note which directories are version control roots
+    pub find_repositories: bool,
+}
+
+/// What a scan found
+pub struct ScanResult {
+    /// Directories to add, each with visit count 0 and its modification time
+    pub directories: Vec<Visit>,
+    /// Directories looked at, including ones above --min-depth
+    pub scanned: usize,
+    /// Entries that couldn't be read, such as permission-denied subtrees
+    pub unreadable: usize,
+    /// Version control roots, sorted, when asked for
+    pub repositories: Vec<String>,
+    /// Whether Ctrl-C cut the walk short
+    pub interrupted: bool,
+}
+
+/// Walk `root` on all cores, honouring .gitignore and .ignore files, skipping
+/// hidden directories and anything matching `exclude`. Progress goes to
+/// stderr when it is a terminal. Ctrl-C stops the walk and returns what was
+/// found.
+pub fn scan(root: &Path, options: &ScanOptions, exclude: Option<&GlobSet>, interrupted: &AtomicBool) -> ScanResult {
+    let found = Mutex::new(Vec::new());
+    let repositories = Mutex::new(Vec::new());
+    let scanned = AtomicUsize::new(0);
+    let unreadable = AtomicUsize::new(0);
+    let progress = Progress::new();
+    
+    let mut walker = WalkBuilder::new(root);
+    walker.max_depth(options.max_depth).require_git(false);
+    walker.build_parallel().run(|| {
+        Box::new(|entry| {
+            if interrupted.load(Ordering::Relaxed) {
+                return WalkState::Quit;
+            }
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    debug!("skipping: {}", e);
+                    unreadable.fetch_add(1, Ordering::Relaxed);
+                    return WalkState::Continue;
+                }
+            };
+            if !entry.file_type().is_some_and(|kind| kind.is_dir()) {
+                return WalkState::Continue;
+            }
+            let Some(path) = entry.path().to_str() else {
+                debug!("skipping non-UTF-8 path {}", entry.path().display());
+                return WalkState::Skip;
+            };
+            if exclude.is_some_and(|patterns| is_excluded(patterns, path)) {
+                debug!("skipping excluded {}", path);
+                return WalkState::Skip;
+            }
+            
+            let count = scanned.fetch_add(1, Ordering::Relaxed) + 1;
+            progress.update(count);
+            if entry.depth() >= options.min_depth {
+                let modified = entry.metadata().ok().and_then(|metadata| metadata.modified().ok());
+                let last_visited = DateTime::<Local>::from(modified.unwrap_or(SystemTime::UNIX_EPOCH));
+                let visit = Visit { path: path.to_string(), count: 0, last_visited };
+                found.lock().unwrap_or_else(|e| e.into_inner()).push(visit);
+            }
+            if options.find_repositories && pathranger::project::is_repository(entry.path()) {
+                repositories.lock().unwrap_or_else(|e| e.into_inner()).push(path.to_string());
+            }
+            WalkState::Continue
+        })
+    });
+    progress.finish();
+    
+    let mut directories = found.into_inner().unwrap_or_else(|e| e.into_inner());
+    directories.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut repositories = repositories.into_inner().unwrap_or_else(|e| e.into_inner());
+    repositories.sort();
+    ScanResult {
+        directories,
+        repositories,
+        scanned: scanned.into_inner(),
+        unreadable: unreadable.into_inner(),
+        interrupted: interrupted.load(Ordering::Relaxed),
+    }
+}
+
+/// A count of scanned directories redrawn on stderr a few times a second
+struct Progress {
+    enabled: bool,
+    last: Mutex<Instant>,
+}
+
+impl Progress {
+    fn new() -> Self {
+        let enabled = std::io::stderr().is_terminal() && output::verbosity() >= Verbosity::Normal;
+        Progress { enabled, last: Mutex::new(Instant::now()) }
+    }
+    
+    fn update(&self, scanned: usize) {
+        if !self.enabled {
+            return;
+        }
+        let Ok(mut last) = self.last.try_lock() else {
+            return;
+        };
+        if last.elapsed() >= Duration::from_millis(100) {
+            *last = Instant::now();
+            eprint!("\rScanned {} directories", scanned);
+            let _ = std::io::stderr().flush();
+        }
+    }
+    
+    /// Clear the progress line
+    fn finish(&self) {
+        if self.enabled {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! `pathranger serve`: a small JSON API over HTTP, so an editor extension or
+//! a launcher can ask pathranger on every keystroke without starting a
+//! process each time. Each connection carries one request:
+//!
+//! ```text
+//! GET  /search?q=QUERY[&n=COUNT]   [{path, visit_count, last_visited, score}]
+//! GET  /top[?n=COUNT]              [{path, visit_count, last_visited}]
+//! GET  /tags                       [{name, path, created_at, updated_at}]
+//! POST /record  {path[, from]}     {recorded}
+//! POST /goto    {tag}              {path}
+//! ```
+//!
+//! Failures come back as `{"error": message}` with a 4xx or 5xx status.
+//! The server listens on loopback unless a token is set in TOKEN_VAR, which
+//! clients then send as `Authorization: Bearer <token>`. Only built with the
+//! `serve` feature.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use pathranger::config::{Config, DEFAULT_COUNT};
+use pathranger::record;
+use pathranger::tagfile::TagFiles;
+use pathranger::{debug, Database, Error, Filter, ListOptions, Result, Scope, SortKey};
+use serde::{Deserialize, Serialize};
+
+use crate::rpc::{self, EntryJson, Recorded};
+use crate::FilterArgs;
+
+/// The environment variable holding the token every request must carry
+pub const TOKEN_VAR: &str = "PATHRANGER_SERVE_TOKEN";
+
+/// How long a client may take to send its request or read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request line and headers accepted, in bytes
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// Longest request body accepted, in bytes
+const MAX_BODY: usize = 64 * 1024;
+
+/// One parsed request
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    /// Names in lower case
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+    
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(param, _)| param == name).map(|(_, value)| value.as_str())
+    }
+    
+    /// The `n` parameter, or `default` without one
+    fn count(&self, default: usize) -> Result<usize> {
+        match self.param("n") {
+            Some(n) => n.parse().map_err(|_| Error::InvalidInput(format!("n must be a count, not '{}'", n))),
+            None => Ok(default),
+        }
+    }
+    
+    fn json<'a, T: Deserialize<'a>>(&'a self) -> Result<T> {
+        serde_json::from_slice(&self.body).map_err(|e| Error::InvalidInput(format!("Invalid request body: {}", e)))
+    }
+}
+
+/// A status and the JSON body that goes with it
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn ok<T: Serialize>(value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response { status: 200, body },
+            Err(e) => Response::error(500, &e.to_string()),
+        }
+    }
+    
+    fn error(status: u16, message: &str) -> Response {
+        Response { status, body: serde_json::json!({ "error": message }).to_string() }
+    }
+    
+    /// The response for a handler's result, with the status for its error
+    fn from_result<T: Serialize>(result: Result<T>) -> Response {
+        let error = match result {
+            Ok(value) => return Response::ok(&value),
+            Err(e) => e,
+        };
+        let status = match error {
+            Error::InvalidInput(_) => 400,
+            Error::TagNotFound(_) | Error::WorkspaceNotFound(_) | Error::DirectoryMissing(_) => 404,
+            _ => 500,
+        };
+        Response::error(status, &error.to_string())
+    }
+    
+    fn write(&self, mut stream: &TcpStream) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.body.len()
+        );
+        if self.status == 401 {
+            head.push_str("WWW-Authenticate: Bearer\r\n");
+        }
+        write!(stream, "{}\r\n{}", head, self.body)?;
+        stream.flush()
+    }
+}
+
+#[derive(Deserialize)]
+struct RecordBody {
+    path: String,
+    from: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GotoBody {
+    tag: String,
+}
+
+#[derive(Serialize)]
+struct GotoTarget {
+    path: String,
+}
+
+/// What requests are answered from, set up once from the config
+struct Server<'a> {
+    db: &'a Database,
+    config: &'a Config,
+    filter: Filter,
+    tag_files: TagFiles,
+    token: Option<String>,
+    /// Whether to turn away requests naming another host, as when a web
+    /// page points its own hostname at 127.0.0.1
+    check_host: bool,
+}
+
+/// Serve `db` on `addr` until interrupted or sent SIGTERM. A non-loopback
+/// `addr` is refused unless TOKEN_VAR is set.
+pub fn serve(db: &Database, config: &Config, addr: &str) -> Result<()> {
+    let token = std::env::var(TOKEN_VAR).ok().filter(|token| !token.is_empty());
+    let listener = TcpListener::bind(addr).map_err(|e| Error::Io(format!("Could not listen on {}", addr), e))?;
+    let local = listener
+        .local_addr()
+        .map_err(|e| Error::Io(format!("Could not listen on {}", addr), e))?;
+    if !local.ip().is_loopback() && token.is_none() {
+        return Err(Error::InvalidInput(format!(
+            "Refusing to serve on {} without a token, since it is reachable from other machines\n\
+             Set {} to the token clients must send, or serve on 127.0.0.1",
+            local, TOKEN_VAR
+        )));
+    }
+    
+    let server = Server {
+        db,
+        config,
+        filter: crate::filter_from_args(&FilterArgs::default(), config)?,
+        tag_files: crate::tag_files(config),
+        token,
+        check_host: local.ip().is_loopback(),
+    };
+    
+    let stopping = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&stopping);
+    let wake = wake_address(local);
+    let handler = move || {
+        flag.store(true, Ordering::Relaxed);
+        // accept() carries on after a signal, so connect once to return from it
+        let _ = TcpStream::connect_timeout(&wake, CLIENT_TIMEOUT);
+    };
+    if let Err(e) = ctrlc::set_handler(handler) {
+        debug!("could not catch Ctrl-C and SIGTERM: {}", e);
+    }
+    
+    // Clients started with port 0 read the port from this line
+    println!("http://{}", local);
+    for stream in listener.incoming() {
+        if stopping.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let _ = stream.set_read_timeout(Some(CLIENT_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(CLIENT_TIMEOUT));
+        let response = server.handle(&stream);
+        let _ = response.write(&stream);
+    }
+    debug!("stopped serving on {}", local);
+    Ok(())
+}
+
+/// Where to connect to reach a listener on `local`, which may be the
+/// unspecified address
+fn wake_address(local: SocketAddr) -> SocketAddr {
+    match local.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), local.port()),
+        IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), local.port()),
+        _ => local,
+    }
+}
+
+impl Server<'_> {
+    fn handle(&self, stream: &TcpStream) -> Response {
+        let request = match read_request(stream) {
+            Ok(request) => request,
+            Err(response) => return response,
+        };
+        debug!("request: {} {}", request.method, request.path);
+        
+        if self.check_host && !request.header("host").is_some_and(is_loopback_host) {
+            return Response::error(403, "The Host header must name this machine's loopback address");
+        }
+        if !self.authorized(&request) {
+            return Response::error(401, &format!("Send the token from {} as a bearer token", TOKEN_VAR));
+        }
+        // A browser has to ask before sending JSON to another origin, which
+        // this server never allows, so pages can't post visits
+        let json = request.header("content-type").is_some_and(|kind| kind.starts_with("application/json"));
+        
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/record" | "/goto") if !json => Response::error(415, "Send the request body as application/json"),
+            ("GET", "/search") => Response::from_result(self.search(&request)),
+            ("GET", "/top") => Response::from_result(self.top(&request)),
+            ("GET", "/tags") => Response::from_result(self.db.tags()),
+            ("POST", "/record") => Response::from_result(self.record(&request)),
+            ("POST", "/goto") => Response::from_result(self.goto(&request)),
+            (_, "/search" | "/top" | "/tags" | "/record" | "/goto") => {
+                Response::error(405, &format!("{} doesn't accept {}", request.path, request.method))
+            }
+            _ => Response::error(404, &format!("No endpoint at {}", request.path)),
+        }
+    }
+    
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.token else {
+            return true;
+        };
+        let sent = request.header("authorization").and_then(|value| value.strip_prefix("Bearer "));
+        sent.is_some_and(|sent| same_bytes(sent.trim().as_bytes(), token.as_bytes()))
+    }
+    
+    fn search(&self, request: &Request) -> Result<Vec<EntryJson>> {
+        let Some(query) = request.param("q") else {
+            return Err(Error::InvalidInput(String::from("Pass the search as q")));
+        };
+        let limit = request.count(self.config.search.limit.unwrap_or(DEFAULT_COUNT))?;
+        let mut matches = self.db.search_in(&crate::expand_search_query(query), &self.filter, Scope::Everywhere)?;
+        matches.truncate(limit);
+        Ok(matches.into_iter().map(|found| EntryJson::new(found.entry, Some(found.score))).collect())
+    }
+    
+    fn top(&self, request: &Request) -> Result<Vec<EntryJson>> {
+        let options = ListOptions {
+            sort: SortKey::Visits,
+            reverse: false,
+            count: request.count(self.config.top.count.unwrap_or(DEFAULT_COUNT))?,
+            filter: self.filter.clone(),
+        };
+        Ok(self.db.list(&options)?.into_iter().map(|entry| EntryJson::new(entry, None)).collect())
+    }
+    
+    fn record(&self, request: &Request) -> Result<Recorded> {
+        let body: RecordBody = request.json()?;
+        rpc::record(self.db, &body.path, body.from.as_deref())
+    }
+    
+    /// Resolve a tag as `goto` does and count the visit
+    fn goto(&self, request: &Request) -> Result<GotoTarget> {
+        let body: GotoBody = request.json()?;
+        let target = crate::tag_target(self.db, &self.tag_files, &body.tag)?;
+        let path = self.db.canonical_path(&target)?;
+        record::visit(self.db, &path, None)?;
+        Ok(GotoTarget { path })
+    }
+}
+
+/// Read one request, or the response that turns it away
+fn read_request(stream: &TcpStream) -> std::result::Result<Request, Response> {
+    let mut reader = BufReader::new(stream);
+    let mut lines = Vec::new();
+    let mut remaining = MAX_HEAD;
+    loop {
+        let mut line = String::new();
+        let read = (&mut reader)
+            .take(remaining)
+            .read_line(&mut line)
+            .map_err(|_| Response::error(400, "Could not read the request"))?;
+        if !line.ends_with('\n') {
+            return Err(Response::error(400, "The request ended early or its headers are too long"));
+        }
+        remaining -= read as u64;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line.to_string());
+    }
+    
+    let mut request_line = lines.first().map(|line| line.split(' ')).into_iter().flatten();
+    let (Some(method), Some(target), Some(_version)) = (request_line.next(), request_line.next(), request_line.next()) else {
+        return Err(Response::error(400, "Malformed request line"));
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode_component(name), decode_component(value))
+        })
+        .collect();
+    let headers = lines
+        .iter()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let mut request = Request { method: method.to_string(), path: path.to_string(), query, headers, body: Vec::new() };
+    
+    let length = match request.header("content-length") {
+        Some(length) => length.parse::<usize>().map_err(|_| Response::error(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Response::error(413, &format!("Request bodies are limited to {} bytes", MAX_BODY)));
+    }
+    request.body = vec![0; length];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|_| Response::error(400, "The request body ended early"))?;
+    Ok(request)
+}
+
+/// A query string component with `+` and percent escapes decoded
+fn decode_component(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(value)) => {
+                decoded.push(value);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(if byte == b'+' { b' ' } else { byte });
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Whether a Host header names a loopback address, as `localhost:8080`,
+/// `127.0.0.1:8080` or `[::1]:8080` do
+fn is_loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or(bracketed),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Compare a sent token with the real one in time that doesn't depend on
+/// where they differ
+fn same_bytes(sent: &[u8], token: &[u8]) -> bool {
+    sent.len() == token.len() && sent.iter().zip(token).fold(0, |differ, (a, b)| differ | (a ^ b)) == 0
+}
@@ -0,0 +1,669 @@
+//! Aggregate usage numbers for `pathranger stats`. Each directory only
+//! stores a running visit count and the time of its last visit, so numbers
+//! for a time window are estimates: a directory last visited inside the
+//! window counts with all of its visits, as in `top --since`. The heatmap,
+//! reports and per-directory visits by week read the visit history instead,
+//! which only holds visits recorded since it was added.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, Timelike};
+use rusqlite::{params, params_from_iter, OptionalExtension};
+use serde::Serialize;
+
+use crate::project::ProjectRoots;
+use crate::{debug, parse_timestamp, Database, Filter, Result, Tag, VISITED_JULIANDAY};
+
+/// A directory and how often it was visited
+#[derive(Clone, Debug, Serialize)]
+pub struct DirectoryVisits {
+    pub path: String,
+    pub visits: i64,
+}
+
+/// An overview of the whole database
+#[derive(Clone, Debug, Serialize)]
+pub struct Stats {
+    pub directories: usize,
+    pub visits: i64,
+    /// Visits to directories last visited in the past 7 days
+    pub visits_last_7_days: i64,
+    /// Visits to directories last visited in the past 30 days
+    pub visits_last_30_days: i64,
+    /// The most visited of the directories last visited in the past 7 days
+    pub busiest_this_week: Option<DirectoryVisits>,
+    pub busiest_all_time: Option<DirectoryVisits>,
+    pub tags: usize,
+    pub database_bytes: u64,
+    /// The earliest visit or tag on record. Older visits to a directory
+    /// aren't kept, so this is an estimate that only moves later.
+    pub first_record: Option<DateTime<Local>>,
+    /// Visits per day since `first_record`
+    pub visits_per_day: Option<f64>,
+    /// Whether the windowed numbers are estimates, which they are until
+    /// visits are recorded one by one
+    pub estimated: bool,
+}
+
+/// Visits in the history by day of the week and hour of the day
+#[derive(Clone, Debug, Serialize)]
+pub struct Heatmap {
+    /// The directory the visits were made in or under, if limited to one
+    pub under: Option<String>,
+    /// Visits counted
+    pub visits: i64,
+    /// The earliest visit counted
+    pub since: Option<DateTime<FixedOffset>>,
+    /// Visits per weekday (Monday first) and hour, in the local time each
+    /// visit was recorded in, so travel doesn't shift earlier visits
+    pub counts: [[i64; 24]; 7],
+}
+
+/// What happened in a window of time, from the visit history
+#[derive(Clone, Debug, Serialize)]
+pub struct Report {
+    /// Start of the window, inclusive
+    pub since: DateTime<Local>,
+    /// End of the window, exclusive
+    pub until: DateTime<Local>,
+    /// Visits in the window
+    pub visits: i64,
+    /// Distinct directories visited in the window
+    pub directories: usize,
+    /// The most visited directories in the window, with their visits there
+    pub top: Vec<DirectoryVisits>,
+    /// Directories first visited in the window, with their visits there
+    pub new: Vec<DirectoryVisits>,
+    /// Directories visited in the window of the same length just before,
+    /// but not in this one, with their visits back then
+    pub stopped: Vec<DirectoryVisits>,
+    /// Tags created in the window
+    pub tags: Vec<Tag>,
+    /// The earliest visit in the history. Directories visited before then
+    /// are never counted as new, and "stopped" only sees the history.
+    pub history_since: Option<DateTime<FixedOffset>>,
+}
+
+/// Weeks of visits `DirectoryStats` counts, most recent last
+pub const STAT_WEEKS: usize = 8;
+
+/// Everything known about one tracked directory
+#[derive(Clone, Debug, Serialize)]
+pub struct DirectoryStats {
+    pub path: String,
+    pub visits: i64,
+    /// Position among all directories by visits, 1 for the most visited.
+    /// Directories with as many visits share a rank.
+    pub rank: usize,
+    /// Tracked directories ranked
+    pub directories: usize,
+    /// The earliest visit in the history
+    pub first_visit: Option<DateTime<FixedOffset>>,
+    /// Visits in the history. When fewer than `visits`, the first visit
+    /// happened before the history was kept.
+    pub history_visits: i64,
+    pub last_visit: Option<DateTime<Local>>,
+    /// Names of the tags pointing at it
+    pub tags: Vec<String>,
+    /// Visits in each of the last `STAT_WEEKS` weeks, oldest first
+    pub weekly_visits: [i64; STAT_WEEKS],
+    /// Directories most often left straight for this one
+    pub came_from: Vec<DirectoryVisits>,
+    /// Directories most often gone to straight from this one
+    pub went_to: Vec<DirectoryVisits>,
+}
+
+/// A subtree and the visits to directories in it
+#[derive(Clone, Debug, Serialize)]
+pub struct Hotspot {
+    pub path: String,
+    pub visits: i64,
+    /// Percentage of all visits
+    pub share: f64,
+    /// Visited directories in the subtree, its root included
+    pub directories: usize,
+}
+
+/// The first `depth` components of `path`, or all of it when shallower
+fn subtree_root(path: &Path, depth: usize) -> PathBuf {
+    let mut root = PathBuf::new();
+    let mut normal = 0;
+    for component in path.components() {
+        if matches!(component, Component::Normal(_)) {
+            if normal == depth {
+                break;
+            }
+            normal += 1;
+        }
+        root.push(component);
+    }
+    root
+}
+
+/// A visit time in the history as a Julian day, which the history's index
+/// is built on
+const HISTORY_JULIANDAY: &str = "julianday(visited_at)";
+
+impl Database {
+    /// Gather `Stats` as of now
+    pub fn stats(&self) -> Result<Stats> {
+        let now = Local::now();
+        let (directories, visits): (i64, i64) = self
+            .statement("SELECT COUNT(*), COALESCE(SUM(visit_count), 0) FROM directories")?
+            .query_row([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        
+        let since = |days: i64| (now - Duration::days(days)).to_rfc3339();
+        let mut windowed = self.statement(&format!(
+            "SELECT COALESCE(SUM(visit_count), 0) FROM directories WHERE {} >= julianday(?1)",
+            VISITED_JULIANDAY
+        ))?;
+        let visits_last_7_days: i64 = windowed.query_row(params![since(7)], |row| row.get(0))?;
+        let visits_last_30_days: i64 = windowed.query_row(params![since(30)], |row| row.get(0))?;
+        
+        let busiest_this_week = self.busiest(Some(&since(7)))?;
+        let busiest_all_time = self.busiest(None)?;
+        
+        let database_bytes: i64 = self
+            .statement("SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()")?
+            .query_row([], |row| row.get(0))?;
+        
+        // Directories added by `scan` carry their modification time but were
+        // never visited, so they don't date the first record
+        let first_record: Option<String> = self
+            .statement(&format!(
+                "SELECT strftime('%Y-%m-%dT%H:%M:%SZ', MIN(day)) FROM (
+                    SELECT MIN({}) AS day FROM directories WHERE visit_count > 0
+                    UNION ALL SELECT MIN(julianday(created_at)) FROM tags
+                )",
+                VISITED_JULIANDAY
+            ))?
+            .query_row([], |row| row.get(0))?;
+        let first_record = first_record
+            .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+            .map(|time| time.with_timezone(&Local));
+        // A database started today has been in use for one day, not zero
+        let visits_per_day = first_record.map(|first| {
+            let days = (now - first).num_seconds() as f64 / 86_400.0;
+            visits as f64 / days.max(1.0)
+        });
+        
+        Ok(Stats {
+            directories: directories as usize,
+            visits,
+            visits_last_7_days,
+            visits_last_30_days,
+            busiest_this_week,
+            busiest_all_time,
+            tags: self.tag_count()?,
+            database_bytes: database_bytes as u64,
+            first_record,
+            visits_per_day,
+            estimated: true,
+        })
+    }
+    
+    /// Bucket the visit history, or the part of it in or under the directory
+    /// `under`, by weekday and hour
+    pub fn heatmap(&self, under: Option<&str>) -> Result<Heatmap> {
+        let filter = Filter { under: under.map(str::to_string), ..Filter::default() };
+        let (condition, values) = filter.where_clause();
+        let mut stmt = self.statement(&format!("SELECT visited_at FROM visits WHERE {}", condition))?;
+        let rows = stmt.query_map(params_from_iter(values), |row| row.get::<_, String>(0))?;
+        
+        let mut heatmap = Heatmap { under: filter.under, visits: 0, since: None, counts: [[0; 24]; 7] };
+        for row in rows {
+            let text = row?;
+            let Ok(time) = DateTime::parse_from_rfc3339(&text) else {
+                debug!("skipping unreadable visit time {}", text);
+                continue;
+            };
+            heatmap.counts[time.weekday().num_days_from_monday() as usize][time.hour() as usize] += 1;
+            heatmap.visits += 1;
+            if heatmap.since.is_none_or(|since| time < since) {
+                heatmap.since = Some(time);
+            }
+        }
+        Ok(heatmap)
+    }
+    
+    /// Summarize visits from `since` up to `until`, listing at most `top`
+    /// of the most visited directories
+    pub fn report(&self, since: DateTime<Local>, until: DateTime<Local>, top: usize) -> Result<Report> {
+        let (start, end) = (since.to_rfc3339(), until.to_rfc3339());
+        let previous = (since - (until - since)).to_rfc3339();
+        let visits_of = |row: &rusqlite::Row| Ok(DirectoryVisits { path: row.get(0)?, visits: row.get(1)? });
+        
+        let (visits, directories): (i64, i64) = self
+            .statement(&format!(
+                "SELECT COUNT(*), COUNT(DISTINCT path) FROM visits WHERE {0} >= julianday(?1) AND {0} < julianday(?2)",
+                HISTORY_JULIANDAY
+            ))?
+            .query_row(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        
+        let top = self
+            .statement(&format!(
+                "SELECT path, COUNT(*) AS visits FROM visits WHERE {0} >= julianday(?1) AND {0} < julianday(?2)
+                GROUP BY path ORDER BY visits DESC, path ASC LIMIT ?3",
+                HISTORY_JULIANDAY
+            ))?
+            .query_map(params![start, end, top as i64], visits_of)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        // New means no visit before the window, in the history or before it:
+        // a directory whose total exceeds its history was visited earlier
+        let new = self
+            .statement(&format!(
+                "SELECT visits.path, SUM({0} >= julianday(?1)) AS recent FROM visits
+                LEFT JOIN directories ON directories.path = visits.path
+                WHERE {0} < julianday(?2)
+                GROUP BY visits.path
+                HAVING MIN({0}) >= julianday(?1)
+                    AND (SELECT COUNT(*) FROM visits AS every WHERE every.path = visits.path)
+                        >= COALESCE(MAX(directories.visit_count), 0)
+                ORDER BY recent DESC, visits.path ASC",
+                HISTORY_JULIANDAY
+            ))?
+            .query_map(params![start, end], visits_of)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        let stopped = self
+            .statement(&format!(
+                "SELECT path, COUNT(*) AS visits FROM visits WHERE {0} >= julianday(?1) AND {0} < julianday(?2)
+                    AND path NOT IN (SELECT path FROM visits WHERE {0} >= julianday(?2) AND {0} < julianday(?3))
+                GROUP BY path ORDER BY visits DESC, path ASC",
+                HISTORY_JULIANDAY
+            ))?
+            .query_map(params![previous, start, end], visits_of)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        
+        let mut tags: Vec<Tag> = self
+            .tags()?
+            .into_iter()
+            .filter(|tag| tag.created_at.is_some_and(|created| created >= since && created < until))
+            .collect();
+        tags.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+        
+        let history_since: Option<String> = self
+            .statement(&format!(
+                "SELECT visited_at FROM visits ORDER BY {} ASC LIMIT 1",
+                HISTORY_JULIANDAY
+            ))?
+            .query_row([], |row| row.get(0))
+            .optional()?;
+        let history_since = history_since.and_then(|time| DateTime::parse_from_rfc3339(&time).ok());
+        
+        Ok(Report {
+            since,
+            until,
+            visits,
+            directories: directories as usize,
+            top,
+            new,
+            stopped,
+            tags,
+            history_since,
+        })
+    }
+    
+    /// Visited directories grouped into subtrees `depth` components deep,
+    /// each directory in exactly one, heaviest first. With `projects`, a
+    /// directory inside a repository belongs to the repository's root.
+    pub fn hotspots(&self, depth: usize, projects: bool) -> Result<Vec<Hotspot>> {
+        let mut roots = ProjectRoots::default();
+        let mut hotspots: HashMap<PathBuf, Hotspot> = HashMap::new();
+        let mut total = 0;
+        // Directories added by `scan` and never visited carry no weight
+        for entry in self.candidates()?.into_iter().filter(|entry| entry.visit_count > 0) {
+            let path = Path::new(&entry.path);
+            let root = match projects.then(|| roots.root_of(path)).flatten() {
+                Some(project) => project,
+                None => subtree_root(path, depth),
+            };
+            let hotspot = hotspots.entry(root).or_insert_with_key(|root| Hotspot {
+                path: root.to_string_lossy().into_owned(),
+                visits: 0,
+                share: 0.0,
+                directories: 0,
+            });
+            hotspot.visits += entry.visit_count;
+            hotspot.directories += 1;
+            total += entry.visit_count;
+        }
+        
+        let mut hotspots: Vec<Hotspot> = hotspots.into_values().collect();
+        for hotspot in &mut hotspots {
+            hotspot.share = hotspot.visits as f64 * 100.0 / total as f64;
+        }
+        hotspots.sort_by(|a, b| b.visits.cmp(&a.visits).then_with(|| a.path.cmp(&b.path)));
+        Ok(hotspots)
+    }
+    
+    /// Gather `DirectoryStats` for `path`, spelled as `canonical_path` finds
+    /// it, with at most `neighbours` of the directories moved to and from
+    /// it, or None if it isn't tracked
+    pub fn directory_stats(&self, path: &str, neighbours: usize) -> Result<Option<DirectoryStats>> {
+        let path = &*self.canonical_path(path)?;
+        let Some((visits, last_visit)) = self
+            .statement("SELECT visit_count, last_visited FROM directories WHERE path = ?1")?
+            .query_row(params![path], |row| Ok((row.get::<_, i64>(0)?, parse_timestamp(&row.get(1)?))))
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        let rank: i64 = self
+            .statement("SELECT COUNT(*) + 1 FROM directories WHERE visit_count > ?1")?
+            .query_row(params![visits], |row| row.get(0))?;
+        
+        let now = Local::now();
+        let mut weekly_visits = [0; STAT_WEEKS];
+        let mut first_visit = None;
+        let mut history_visits = 0;
+        let mut stmt = self.statement("SELECT visited_at FROM visits WHERE path = ?1")?;
+        for row in stmt.query_map(params![path], |row| row.get::<_, String>(0))? {
+            let text = row?;
+            let Ok(time) = DateTime::parse_from_rfc3339(&text) else {
+                debug!("skipping unreadable visit time {}", text);
+                continue;
+            };
+            history_visits += 1;
+            if first_visit.is_none_or(|first| time < first) {
+                first_visit = Some(time);
+            }
+            let weeks_ago = (now.fixed_offset() - time).num_weeks();
+            if (0..STAT_WEEKS as i64).contains(&weeks_ago) {
+                weekly_visits[STAT_WEEKS - 1 - weeks_ago as usize] += 1;
+            }
+        }
+        
+        let neighbours_of = |sql: &str| -> Result<Vec<DirectoryVisits>> {
+            let rows = self
+                .statement(sql)?
+                .query_map(params![path, neighbours as i64], |row| Ok(DirectoryVisits { path: row.get(0)?, visits: row.get(1)? }))?
+                .collect::<rusqlite::Result<_>>()?;
+            Ok(rows)
+        };
+        let came_from = neighbours_of(
+            "SELECT from_path, count FROM transitions WHERE to_path = ?1 ORDER BY count DESC, from_path ASC LIMIT ?2",
+        )?;
+        let went_to = neighbours_of(
+            "SELECT to_path, count FROM transitions WHERE from_path = ?1 ORDER BY count DESC, to_path ASC LIMIT ?2",
+        )?;
+        
+        Ok(Some(DirectoryStats {
+            path: path.to_string(),
+            visits,
+            rank: rank as usize,
+            directories: self.directory_count()?,
+            first_visit,
+            history_visits,
+            last_visit,
+            tags: self.tags_for_paths([path])?.remove(path).unwrap_or_default(),
+            weekly_visits,
+            came_from,
+            went_to,
+        }))
+    }
+    
+    /// The most visited directory, among those last visited at or after
+    /// `since` if given
+    fn busiest(&self, since: Option<&str>) -> Result<Option<DirectoryVisits>> {
+        let condition = match since {
+            Some(_) => format!("visit_count > 0 AND {} >= julianday(?1)", VISITED_JULIANDAY),
+            None => String::from("visit_count > 0 AND ?1 IS NULL"),
+        };
+        let mut stmt = self.statement(&format!(
+            "SELECT path, visit_count FROM directories WHERE {} ORDER BY visit_count DESC, path ASC LIMIT 1",
+            condition
+        ))?;
+        let mut rows = stmt.query(params![since])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(DirectoryVisits { path: row.get(0)?, visits: row.get(1)? })),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, repositories};
+    use crate::{Visit, VisitLog, VisitTime};
+    
+    /// Record each directory's total visits, last visited `days` ago
+    fn seed(db: &Database, now: DateTime<Local>, visits: &[(&str, i64, i64)]) {
+        let visits = visits
+            .iter()
+            .map(|(path, count, days)| Visit { path: path.to_string(), count: *count, last_visited: now - Duration::days(*days) })
+            .collect();
+        db.record_visits(&VisitLog { visits, ..VisitLog::default() }).expect("seed the fixture database");
+    }
+    
+    #[test]
+    fn empty_database() {
+        let (_dir, db) = fixture();
+        let stats = db.stats().expect("gather stats");
+        assert_eq!((stats.directories, stats.visits, stats.tags), (0, 0, 0));
+        assert_eq!((stats.visits_last_7_days, stats.visits_last_30_days), (0, 0));
+        assert!(stats.busiest_this_week.is_none() && stats.busiest_all_time.is_none());
+        assert!(stats.first_record.is_none() && stats.visits_per_day.is_none());
+        assert!(stats.database_bytes > 0);
+    }
+    
+    #[test]
+    fn totals_windows_and_busiest() {
+        let (_dir, db) = fixture();
+        let now = Local::now();
+        seed(
+            &db,
+            now,
+            &[
+                ("/fixture/api", 10, 2),
+                ("/fixture/web", 4, 10),
+                ("/fixture/thesis", 20, 100),
+                // Added by a scan and never visited: no visits, and too old
+                // to count as the first record
+                ("/fixture/scanned", 0, 1000),
+            ],
+        );
+        db.add_tag("api", "/fixture/api").expect("tag a fixture directory");
+        db.add_tag("web", "/fixture/web").expect("tag a fixture directory");
+        
+        let stats = db.stats().expect("gather stats");
+        assert_eq!((stats.directories, stats.visits, stats.tags), (4, 34, 2));
+        assert_eq!(stats.visits_last_7_days, 10);
+        assert_eq!(stats.visits_last_30_days, 14);
+        let busiest = |busiest: Option<DirectoryVisits>| busiest.map(|entry| (entry.path, entry.visits));
+        assert_eq!(busiest(stats.busiest_this_week), Some((String::from("/fixture/api"), 10)));
+        assert_eq!(busiest(stats.busiest_all_time), Some((String::from("/fixture/thesis"), 20)));
+        
+        let first = stats.first_record.expect("a first record");
+        assert!((first - (now - Duration::days(100))).num_seconds().abs() <= 1, "first record {}", first);
+        let per_day = stats.visits_per_day.expect("visits per day");
+        assert!((per_day - 0.34).abs() < 0.001, "{} visits per day", per_day);
+        assert!(stats.estimated);
+    }
+    
+    #[test]
+    fn busiest_ties_go_to_the_first_path() {
+        let (_dir, db) = fixture();
+        let now = Local::now();
+        seed(&db, now, &[("/fixture/b", 5, 1), ("/fixture/a", 5, 3), ("/fixture/c", 2, 0)]);
+        let stats = db.stats().expect("gather stats");
+        assert_eq!(stats.busiest_this_week.map(|entry| entry.path).as_deref(), Some("/fixture/a"));
+        assert_eq!(stats.busiest_all_time.map(|entry| entry.path).as_deref(), Some("/fixture/a"));
+    }
+    
+    #[test]
+    fn a_database_started_today_counts_one_day() {
+        let (_dir, db) = fixture();
+        let now = Local::now();
+        seed(&db, now, &[("/fixture/today", 6, 0)]);
+        assert_eq!(db.stats().expect("gather stats").visits_per_day, Some(6.0));
+        
+        // A tag older than every visit dates the first record
+        db.add_tag("old", "/fixture/today").expect("tag a fixture directory");
+        let created = (now - Duration::days(3)).to_rfc3339();
+        db.conn.execute("UPDATE tags SET created_at = ?1", params![created]).expect("backdate the tag");
+        let per_day = db.stats().expect("gather stats").visits_per_day.expect("visits per day");
+        assert!((per_day - 2.0).abs() < 0.001, "{} visits per day", per_day);
+    }
+    
+    /// A time in the history, in the offset it was recorded in
+    fn at(time: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(time).expect("a valid fixture time")
+    }
+    
+    /// Record every visit in `history`, with directory totals of their own
+    /// or of `totals` where a directory was visited before the history
+    fn seed_history(db: &Database, history: &[(&str, &str)], totals: &[(&str, i64)]) {
+        let mut visits: Vec<Visit> = Vec::new();
+        for (path, time) in history {
+            let last_visited = at(time).with_timezone(&Local);
+            match visits.iter_mut().find(|visit| visit.path == *path) {
+                Some(visit) => {
+                    visit.count += 1;
+                    visit.last_visited = visit.last_visited.max(last_visited);
+                }
+                None => visits.push(Visit { path: path.to_string(), count: 1, last_visited }),
+            }
+        }
+        for (path, total) in totals {
+            if let Some(visit) = visits.iter_mut().find(|visit| visit.path == *path) {
+                visit.count = *total;
+            }
+        }
+        let history = history.iter().map(|(path, time)| VisitTime { path: path.to_string(), visited_at: at(time) }).collect();
+        db.record_visits(&VisitLog { visits, history, ..VisitLog::default() }).expect("seed the fixture database");
+    }
+    
+    /// The week of Monday 2024-05-06 and the week before it, in UTC
+    const HISTORY: [(&str, &str); 12] = [
+        ("/work/api", "2024-05-01T10:00:00Z"),
+        ("/work/api", "2024-05-06T09:00:00Z"),
+        ("/work/api", "2024-05-07T09:30:00Z"),
+        ("/work/api", "2024-05-10T16:00:00Z"),
+        ("/work/web", "2024-05-08T11:00:00Z"),
+        ("/work/web", "2024-05-12T23:59:59Z"),
+        ("/old/thesis", "2024-04-29T00:00:00Z"),
+        ("/old/thesis", "2024-05-05T23:59:59Z"),
+        ("/home/dotfiles", "2024-05-09T20:00:00Z"),
+        // Outside both windows
+        ("/work/api", "2024-05-13T00:00:00Z"),
+        ("/later", "2024-05-20T08:00:00Z"),
+        ("/earlier", "2024-04-28T23:59:59Z"),
+    ];
+    
+    fn week() -> (DateTime<Local>, DateTime<Local>) {
+        (at("2024-05-06T00:00:00Z").with_timezone(&Local), at("2024-05-13T00:00:00Z").with_timezone(&Local))
+    }
+    
+    fn visits(entries: &[DirectoryVisits]) -> Vec<(&str, i64)> {
+        entries.iter().map(|entry| (entry.path.as_str(), entry.visits)).collect()
+    }
+    
+    #[test]
+    fn report_aggregates_a_week() {
+        let (_dir, db) = fixture();
+        // dotfiles was visited before the history was kept, so it isn't new
+        seed_history(&db, &HISTORY, &[("/home/dotfiles", 7)]);
+        let (since, until) = week();
+        
+        let report = db.report(since, until, 10).expect("build the report");
+        assert_eq!((report.visits, report.directories), (6, 3));
+        assert_eq!(visits(&report.top), [("/work/api", 3), ("/work/web", 2), ("/home/dotfiles", 1)]);
+        assert_eq!(visits(&report.new), [("/work/web", 2)]);
+        assert_eq!(visits(&report.stopped), [("/old/thesis", 2)]);
+        assert_eq!(report.history_since, Some(at("2024-04-28T23:59:59Z")));
+        assert!(report.tags.is_empty());
+        
+        let report = db.report(since, until, 2).expect("build the report");
+        assert_eq!(visits(&report.top), [("/work/api", 3), ("/work/web", 2)]);
+    }
+    
+    #[test]
+    fn report_lists_tags_created_in_the_window() {
+        let (_dir, db) = fixture();
+        seed_history(&db, &HISTORY, &[]);
+        for (name, created) in [("api", "2024-05-07T12:00:00Z"), ("web", "2024-05-06T00:00:00Z"), ("old", "2024-05-05T12:00:00Z")] {
+            db.add_tag(name, "/work/api").expect("tag a fixture directory");
+            db.conn
+                .execute("UPDATE tags SET created_at = ?1 WHERE name = ?2", params![created, name])
+                .expect("date the tag");
+        }
+        let (since, until) = week();
+        let report = db.report(since, until, 10).expect("build the report");
+        let tags: Vec<&str> = report.tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(tags, ["web", "api"]);
+    }
+    
+    #[test]
+    fn heatmap_buckets_by_weekday_and_hour() {
+        let (_dir, db) = fixture();
+        seed_history(
+            &db,
+            &[
+                // Monday 09:00 where each was recorded, whatever the offset
+                ("/work/api", "2024-05-06T09:15:00+02:00"),
+                ("/work/api", "2024-05-13T09:59:59-07:00"),
+                ("/work/web", "2024-05-06T09:00:00Z"),
+                // Sunday 23:00
+                ("/work/web", "2024-05-12T23:30:00Z"),
+                ("/workshop", "2024-05-08T14:00:00Z"),
+            ],
+            &[],
+        );
+        
+        let heatmap = db.heatmap(None).expect("build the heatmap");
+        assert_eq!(heatmap.visits, 5);
+        assert_eq!(heatmap.since, Some(at("2024-05-06T09:15:00+02:00")));
+        assert_eq!(heatmap.counts[0][9], 3);
+        assert_eq!(heatmap.counts[6][23], 1);
+        assert_eq!(heatmap.counts[2][14], 1);
+        assert_eq!(heatmap.counts.iter().flatten().sum::<i64>(), 5);
+        
+        // Only the directory and those under it, not its siblings sharing a prefix
+        let heatmap = db.heatmap(Some("/work")).expect("build the heatmap");
+        assert_eq!(heatmap.under.as_deref(), Some("/work"));
+        assert_eq!((heatmap.visits, heatmap.counts[0][9], heatmap.counts[2][14]), (4, 3, 0));
+        let heatmap = db.heatmap(Some("/work/api")).expect("build the heatmap");
+        assert_eq!((heatmap.visits, heatmap.counts[0][9]), (2, 2));
+    }
+    
+    #[test]
+    fn hotspots_count_each_directory_once() {
+        let (dir, db) = fixture();
+        let visited = [
+            ("work/outer", 4),
+            ("work/outer/src", 3),
+            ("work/outer/vendor", 1),
+            ("work/outer/vendor/inner", 5),
+            ("work/outer/vendor/inner/lib", 2),
+            ("notes/a", 7),
+        ];
+        // A repository vendored inside another one is a project of its own
+        let path = repositories(&dir, &db, &["work/outer", "work/outer/vendor/inner"], &visited);
+        seed(&db, Local::now(), &[(&path("work/outer/scanned"), 0, 1)]);
+        
+        let depth = Path::new(&path("")).components().filter(|component| matches!(component, Component::Normal(_))).count() + 1;
+        let hotspots = db.hotspots(depth, true).expect("group the visits");
+        let found: Vec<(&str, i64, usize)> =
+            hotspots.iter().map(|hotspot| (hotspot.path.as_str(), hotspot.visits, hotspot.directories)).collect();
+        let (outer, inner, notes) = (path("work/outer"), path("work/outer/vendor/inner"), path("notes"));
+        assert_eq!(found, [(outer.as_str(), 8, 3), (notes.as_str(), 7, 1), (inner.as_str(), 7, 2)]);
+        
+        // Without projects, the same directories land in their subtrees
+        let hotspots = db.hotspots(depth, false).expect("group the visits");
+        let found: Vec<(&str, i64, usize)> =
+            hotspots.iter().map(|hotspot| (hotspot.path.as_str(), hotspot.visits, hotspot.directories)).collect();
+        let work = path("work");
+        assert_eq!(found, [(work.as_str(), 15, 5), (notes.as_str(), 7, 1)]);
+        
+        for projects in [true, false] {
+            let hotspots = db.hotspots(depth, projects).expect("group the visits");
+            assert_eq!(hotspots.iter().map(|hotspot| hotspot.directories).sum::<usize>(), visited.len());
+            assert_eq!(hotspots.iter().map(|hotspot| hotspot.visits).sum::<i64>(), 22);
+            assert!((hotspots.iter().map(|hotspot| hotspot.share).sum::<f64>() - 100.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Tags defined in files rather than the database: a repository's
+//! `.pathranger.toml` and a system-wide file shipped by administrators.
+//! pathranger only reads these files; `mark` and `untag` never write to them.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::{debug, warn};
+use shellexpand::tilde;
+
+/// File name that defines tags for the directory tree it sits in
+pub const PROJECT_FILE: &str = ".pathranger.toml";
+
+/// Where the system-wide tags are read from unless the config says otherwise
+pub const SYSTEM_FILE: &str = "/etc/pathranger/tags.toml";
+
+/// Which kind of file a tag came from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Project,
+    System,
+}
+
+impl Origin {
+    /// Shown next to the tag in `tags`
+    pub fn badge(self) -> &'static str {
+        match self {
+            Origin::Project => "project",
+            Origin::System => "system",
+        }
+    }
+}
+
+/// Tags read from one file, pointing at absolute paths
+pub struct TagFile {
+    /// The file the tags were read from
+    pub path: PathBuf,
+    /// Tag names and the directories they point at
+    pub tags: BTreeMap<String, String>,
+}
+
+impl TagFile {
+    /// Read `name = "dir"` pairs from `path`. Relative directories are taken
+    /// from the file's own directory. A file that can't be parsed is
+    /// reported and treated as empty, like the config file.
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        let entries: BTreeMap<String, String> = match toml::from_str(&contents) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Ignoring invalid tags file {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        debug!("tags file: {} ({} tags)", path.display(), entries.len());
+        
+        let base = path.parent().unwrap_or(Path::new("/"));
+        let tags = entries
+            .into_iter()
+            .map(|(name, dir)| (name, normalize(&base.join(tilde(&dir).as_ref()))))
+            .collect();
+        Some(TagFile { path: path.to_path_buf(), tags })
+    }
+    
+    /// The project file in `start` or the nearest directory above it
+    pub fn find_project(start: &Path) -> Option<Self> {
+        let file = start.ancestors().map(|dir| dir.join(PROJECT_FILE)).find(|file| file.is_file())?;
+        TagFile::load(&file)
+    }
+    
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.tags.get(name).map(String::as_str)
+    }
+}
+
+/// Drop `.` and resolve `..` lexically, as the shell does for `cd`
+fn normalize(path: &Path) -> String {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized.to_string_lossy().into_owned()
+}
+
+/// Every tag file in effect, highest precedence first. The user's own tags
+/// in the database rank above all of them.
+pub struct TagFiles {
+    files: Vec<(Origin, TagFile)>,
+}
+
+impl TagFiles {
+    /// The project file above `cwd`, if any, then the system file
+    pub fn load(cwd: Option<&Path>, system: &Path) -> Self {
+        let project = cwd.and_then(TagFile::find_project).map(|file| (Origin::Project, file));
+        let system = TagFile::load(system).map(|file| (Origin::System, file));
+        TagFiles { files: project.into_iter().chain(system).collect() }
+    }
+    
+    /// The file that decides what `name` points at, and the path
+    pub fn resolve(&self, name: &str) -> Option<(Origin, &TagFile, &str)> {
+        self.files.iter().find_map(|(origin, file)| Some((*origin, file, file.get(name)?)))
+    }
+    
+    /// Every file's tags, each name only from the file that decides it
+    pub fn tags(&self) -> Vec<(Origin, &TagFile, &str, &str)> {
+        let mut seen = HashSet::new();
+        let mut tags = Vec::new();
+        for (origin, file) in &self.files {
+            for (name, path) in &file.tags {
+                if seen.insert(name.as_str()) {
+                    tags.push((*origin, file, name.as_str(), path.as_str()));
+                }
+            }
+        }
+        tags
+    }
+}
@@ -0,0 +1,128 @@
+//! Workspaces: named, ordered groups of tags for directories used together,
+//! such as a service's repository, its infrastructure and a notes directory.
+//! Members refer to tags, so re-pointing a tag moves it in every workspace,
+//! and deleting one drops it from them.
+
+use chrono::Local;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+
+use crate::{debug, parse_timestamp, Database, Error, Result, Tag};
+
+/// A workspace and its member tags, in the order they were added
+#[derive(Clone, Debug, Serialize)]
+pub struct Workspace {
+    pub name: String,
+    pub members: Vec<Tag>,
+}
+
+impl Database {
+    /// Create the workspace `name` with `tags` as its members, in order.
+    /// Every tag must exist; a workspace by that name must not.
+    pub fn create_workspace(&self, name: &str, tags: &[String]) -> Result<()> {
+        let exists = self.statement("SELECT 1 FROM workspaces WHERE name = ?1")?.exists(params![name])?;
+        if exists {
+            return Err(Error::InvalidInput(format!("Workspace '{}' already exists", name)));
+        }
+        self.statement("INSERT INTO workspaces (name, created_at) VALUES (?1, ?2)")?
+            .execute(params![name, Local::now().to_rfc3339()])?;
+        for tag in tags {
+            self.add_to_workspace(name, tag)?;
+        }
+        Ok(())
+    }
+
+    /// Every workspace with its members, sorted by name
+    pub fn workspaces(&self) -> Result<Vec<Workspace>> {
+        let names: Vec<String> = self
+            .statement("SELECT name FROM workspaces ORDER BY name")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        names.into_iter().map(|name| self.workspace_members(name)).collect()
+    }
+
+    /// The workspace `name`, if there is one
+    pub fn workspace(&self, name: &str) -> Result<Option<Workspace>> {
+        let exists = self.statement("SELECT 1 FROM workspaces WHERE name = ?1")?.exists(params![name])?;
+        if !exists {
+            return Ok(None);
+        }
+        self.workspace_members(name.to_string()).map(Some)
+    }
+
+    /// Append `tag` to the workspace `name`. Returns false if it was already
+    /// a member.
+    pub fn add_to_workspace(&self, name: &str, tag: &str) -> Result<bool> {
+        let workspace = self.workspace_id(name)?;
+        let tag_id: i64 = self
+            .statement("SELECT id FROM tags WHERE name = ?1")?
+            .query_row(params![tag], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| Error::TagNotFound(tag.to_string()))?;
+        let added = self
+            .statement(
+                "INSERT OR IGNORE INTO workspace_tags (workspace_id, tag_id, position)
+                SELECT ?1, ?2, COALESCE(MAX(position), 0) + 1 FROM workspace_tags WHERE workspace_id = ?1",
+            )?
+            .execute(params![workspace, tag_id])?;
+        debug!("added {} member(s) to workspace '{}'", added, name);
+        Ok(added > 0)
+    }
+
+    /// Take `tag` out of the workspace `name`, leaving the tag itself alone.
+    /// Returns false if it wasn't a member.
+    pub fn remove_from_workspace(&self, name: &str, tag: &str) -> Result<bool> {
+        let workspace = self.workspace_id(name)?;
+        let removed = self
+            .statement(
+                "DELETE FROM workspace_tags
+                WHERE workspace_id = ?1 AND tag_id = (SELECT id FROM tags WHERE name = ?2)",
+            )?
+            .execute(params![workspace, tag])?;
+        Ok(removed > 0)
+    }
+
+    /// Delete the workspace `name`, leaving its tags alone. Returns false if
+    /// there was no such workspace.
+    pub fn delete_workspace(&self, name: &str) -> Result<bool> {
+        let Some(workspace) = self.find_workspace_id(name)? else {
+            return Ok(false);
+        };
+        self.statement("DELETE FROM workspace_tags WHERE workspace_id = ?1")?.execute(params![workspace])?;
+        self.statement("DELETE FROM workspaces WHERE id = ?1")?.execute(params![workspace])?;
+        Ok(true)
+    }
+
+    fn workspace_members(&self, name: String) -> Result<Workspace> {
+        let members = self
+            .statement(
+                "SELECT tags.name, tags.path, tags.created_at, tags.updated_at FROM workspace_tags
+                JOIN workspaces ON workspaces.id = workspace_tags.workspace_id
+                JOIN tags ON tags.id = workspace_tags.tag_id
+                WHERE workspaces.name = ?1
+                ORDER BY workspace_tags.position",
+            )?
+            .query_map(params![name], |row| {
+                Ok(Tag {
+                    name: row.get(0)?,
+                    path: row.get(1)?,
+                    created_at: parse_timestamp(&row.get(2)?),
+                    updated_at: parse_timestamp(&row.get(3)?),
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(Workspace { name, members })
+    }
+
+    fn find_workspace_id(&self, name: &str) -> Result<Option<i64>> {
+        let id = self
+            .statement("SELECT id FROM workspaces WHERE name = ?1")?
+            .query_row(params![name], |row| row.get(0))
+            .optional()?;
+        Ok(id)
+    }
+
+    fn workspace_id(&self, name: &str) -> Result<i64> {
+        self.find_workspace_id(name)?.ok_or_else(|| Error::WorkspaceNotFound(name.to_string()))
+    }
+}
@@ -0,0 +1,120 @@
+//! What the integration tests share: a throwaway home directory and database
+//! for each test, and pathranger run against them with nothing leaking in
+//! from the environment of whoever runs the tests.
+
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use chrono::{DateTime, Local};
+use pathranger::{Database, Visit, VisitLog};
+use tempfile::TempDir;
+
+/// The binary under test
+pub const PATHRANGER: &str = env!("CARGO_BIN_EXE_pathranger");
+
+/// A home directory, config and database of its own, removed when dropped
+pub struct TestEnv {
+    root: TempDir,
+    /// The temporary directory with symlinks resolved, so it matches $PWD
+    pub dir: PathBuf,
+    pub home: PathBuf,
+    pub db: PathBuf,
+}
+
+impl TestEnv {
+    pub fn new() -> TestEnv {
+        // Not tempfile's ".tmp" prefix, which would make every path hidden
+        let root = tempfile::Builder::new().prefix("pathranger").tempdir().expect("create a temporary directory");
+        let dir = root.path().canonicalize().expect("resolve the temporary directory");
+        let home = dir.join("home");
+        fs::create_dir_all(home.join(".config")).expect("create the home directory");
+        let db = dir.join("pathranger.db");
+        TestEnv { root, dir, home, db }
+    }
+    
+    /// Make a directory, with its parents, under the temporary directory
+    pub fn mkdir(&self, name: impl AsRef<Path>) -> PathBuf {
+        let path = self.dir.join(name);
+        fs::create_dir_all(&path).expect("create a test directory");
+        path
+    }
+    
+    /// `program` with only this environment's variables set, and pathranger
+    /// first on PATH for shell integration code that calls it by name
+    pub fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        let bin_dir = Path::new(PATHRANGER).parent().expect("the binary is in a directory");
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let mut dirs = vec![bin_dir.to_path_buf()];
+        dirs.extend(std::env::split_paths(&path));
+        
+        let mut command = Command::new(program);
+        command
+            .env_clear()
+            .env("PATH", std::env::join_paths(dirs).expect("join PATH"))
+            .env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .env("XDG_DATA_HOME", self.home.join(".local/share"))
+            .env("XDG_STATE_HOME", self.home.join(".local/state"))
+            .env("PATHRANGER_DB", &self.db)
+            .env("PATHRANGER_SYSTEM_TAGS", self.dir.join("system-tags.toml"))
+            .env("LC_ALL", "C.UTF-8")
+            .current_dir(&self.dir);
+        command
+    }
+    
+    /// Open the test database through the library, to seed fixtures
+    pub fn database(&self) -> Database {
+        Database::open(&self.db).expect("open the test database")
+    }
+    
+    /// Make each directory in `visits` and record its visit count, last
+    /// visited at the given Unix time
+    pub fn seed(&self, visits: &[(&str, i64, i64)]) -> Vec<PathBuf> {
+        let paths: Vec<PathBuf> = visits.iter().map(|(name, _, _)| self.mkdir(name)).collect();
+        let log = VisitLog {
+            visits: visits
+                .iter()
+                .zip(&paths)
+                .map(|((_, count, time), path)| Visit {
+                    path: path.to_string_lossy().into_owned(),
+                    count: *count,
+                    last_visited: unix_time(*time),
+                })
+                .collect(),
+            ..VisitLog::default()
+        };
+        self.database().record_visits(&log).expect("seed the test database");
+        paths
+    }
+    
+    /// pathranger with `args`, which must succeed
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.run_in(&self.dir, args)
+    }
+    
+    /// pathranger with `args` from the directory `cwd`, which must succeed
+    pub fn run_in(&self, cwd: &Path, args: &[&str]) -> Output {
+        let output = self.command(PATHRANGER).args(args).current_dir(cwd).output().expect("run pathranger");
+        assert!(
+            output.status.success(),
+            "pathranger {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+}
+
+/// Whether `shell` is installed, so tests for shells that aren't can be skipped
+pub fn has_shell(shell: &str) -> bool {
+    Command::new(shell).arg("-c").arg("exit 0").output().is_ok_and(|output| output.status.success())
+}
+
+/// A Unix time as a local time, the way visits are recorded
+pub fn unix_time(seconds: i64) -> DateTime<Local> {
+    DateTime::from_timestamp(seconds, 0).expect("a valid Unix time").with_timezone(&Local)
+}
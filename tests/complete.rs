@@ -0,0 +1,51 @@
+//! Directory completion offers tracked directories starting with what was
+//! typed, best first, leaving out what listings leave out.
+
+mod common;
+
+use globset::{Glob, GlobSetBuilder};
+use pathranger::Filter;
+
+use common::TestEnv;
+
+/// The paths `dirs_matching` offers for `prefix`, relative to the test directory
+fn matching(env: &TestEnv, prefix: &str, filter: &Filter, count: usize) -> Vec<String> {
+    let db = env.database();
+    let prefix = format!("{}/{}", env.dir.display(), prefix);
+    db.set_exclude(Some(
+        GlobSetBuilder::new().add(Glob::new("**/scratch").expect("a valid glob")).build().expect("build the globs"),
+    ));
+    let paths = db.dirs_matching(&prefix, filter, count).expect("complete directories");
+    let root = format!("{}/", env.dir.display());
+    paths.into_iter().map(|path| path.strip_prefix(&root).expect("a test directory").to_string()).collect()
+}
+
+#[test]
+fn directories_complete_by_prefix_and_frecency() {
+    let env = TestEnv::new();
+    env.seed(&[
+        ("src/alpha", 2, 1_714_564_800),
+        ("src/alps", 9, 1_714_564_800),
+        ("src/beta", 50, 1_714_564_800),
+        ("src/a_b", 1, 1_714_564_800),
+        ("src/axb", 1, 1_714_564_800),
+    ]);
+    
+    assert_eq!(matching(&env, "src/al", &Filter::default(), 10), ["src/alps", "src/alpha"]);
+    assert_eq!(matching(&env, "SRC/AL", &Filter::default(), 10), ["src/alps", "src/alpha"]);
+    assert_eq!(matching(&env, "src/al", &Filter::default(), 1), ["src/alps"]);
+    // LIKE wildcards in what was typed are matched literally
+    assert_eq!(matching(&env, "src/a_", &Filter::default(), 10), ["src/a_b"]);
+    assert_eq!(matching(&env, "src/", &Filter::default(), 2), ["src/beta", "src/alps"]);
+}
+
+#[test]
+fn directory_completion_applies_the_filter() {
+    let env = TestEnv::new();
+    env.seed(&[("work/.cache", 50, 1_714_564_800), ("work/scratch", 40, 1_714_564_800), ("work/app", 1, 1_714_564_800)]);
+    
+    assert_eq!(matching(&env, "work/", &Filter::default(), 10), ["work/.cache", "work/app"]);
+    assert_eq!(matching(&env, "work/", &Filter { hide_hidden: true, ..Filter::default() }, 1), ["work/app"]);
+    let all = Filter { show_excluded: true, ..Filter::default() };
+    assert_eq!(matching(&env, "work/", &all, 10), ["work/.cache", "work/scratch", "work/app"]);
+}
@@ -0,0 +1,69 @@
+//! Destructive commands ask before changing more than `confirm_threshold`
+//! rows, and refuse without --yes when there is no terminal to ask on.
+
+mod common;
+
+use std::fs;
+use std::process::Output;
+
+use common::{TestEnv, PATHRANGER};
+
+/// pathranger with `args`, stdin not a terminal and the threshold at zero,
+/// so any change at all needs confirming
+fn run_strict(env: &TestEnv, args: &[&str]) -> Output {
+    env.command(PATHRANGER)
+        .env("PATHRANGER_CONFIRM_THRESHOLD", "0")
+        .args(args)
+        .output()
+        .expect("run pathranger")
+}
+
+fn tag_names(env: &TestEnv) -> Vec<String> {
+    env.database().tags().expect("read the tags").into_iter().map(|tag| tag.name).collect()
+}
+
+#[test]
+fn untag_past_the_threshold_needs_yes() {
+    let env = TestEnv::new();
+    let work = env.mkdir("work");
+    env.database().add_tag("work", &work.to_string_lossy()).expect("create the tag");
+    
+    let output = run_strict(&env, &["untag", "work"]);
+    assert!(!output.status.success(), "untag ran without confirmation");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pass --yes"));
+    assert_eq!(tag_names(&env), ["work"]);
+    
+    let output = run_strict(&env, &["untag", "work", "--dry-run"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Would remove tag 'work'"));
+    assert_eq!(tag_names(&env), ["work"]);
+    
+    assert!(run_strict(&env, &["untag", "work", "--yes"]).status.success());
+    assert!(tag_names(&env).is_empty());
+}
+
+#[test]
+fn untag_within_the_threshold_needs_no_confirmation() {
+    let env = TestEnv::new();
+    let work = env.mkdir("work");
+    env.database().add_tag("work", &work.to_string_lossy()).expect("create the tag");
+    
+    env.run(&["untag", "work"]);
+    assert!(tag_names(&env).is_empty());
+}
+
+#[test]
+fn cleaning_tags_past_the_threshold_needs_yes() {
+    let env = TestEnv::new();
+    let gone = env.mkdir("gone");
+    env.database().add_tag("gone", &gone.to_string_lossy()).expect("create the tag");
+    fs::remove_dir(&gone).expect("remove the tagged directory");
+    
+    let output = run_strict(&env, &["tags", "--clean"]);
+    assert!(!output.status.success(), "tags --clean ran without confirmation");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pass --yes"));
+    assert_eq!(tag_names(&env), ["gone"]);
+    
+    assert!(run_strict(&env, &["tags", "--clean", "--yes"]).status.success());
+    assert!(tag_names(&env).is_empty());
+}
@@ -0,0 +1,87 @@
+//! Which spelling of the current directory is recorded: $PWD, which keeps
+//! the symlinks the shell went through, when it is the directory pathranger
+//! runs in, and the resolved directory otherwise.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::fs::symlink;
+use std::path::Path;
+
+use common::TestEnv;
+
+/// Run `add` in `cwd` with $PWD set to `pwd` (unset if None) and return the
+/// one directory it recorded
+fn added(env: &TestEnv, cwd: &Path, pwd: Option<&Path>) -> String {
+    let _ = std::fs::remove_file(&env.db);
+    let mut command = env.command(common::PATHRANGER);
+    command.args(["add"]).current_dir(cwd);
+    if let Some(pwd) = pwd {
+        command.env("PWD", pwd);
+    }
+    let output = command.output().expect("run pathranger");
+    assert!(output.status.success(), "add failed: {}", String::from_utf8_lossy(&output.stderr));
+    
+    let output = env.run(&["list", "--porcelain"]);
+    let list = String::from_utf8(output.stdout).expect("output is UTF-8");
+    let paths: Vec<&str> = list.lines().filter_map(|line| line.splitn(4, '\t').nth(3)).collect();
+    assert_eq!(paths.len(), 1, "add recorded:\n{}", list);
+    paths[0].to_string()
+}
+
+#[test]
+fn pwd_through_a_symlink_comes_first() {
+    let env = TestEnv::new();
+    let real = env.mkdir("real/project");
+    let link = env.dir.join("link");
+    symlink(&real, &link).expect("create a symlink");
+    
+    assert_eq!(added(&env, &real, Some(&link)), link.to_string_lossy());
+    // Through a symlinked parent too
+    let parent_link = env.dir.join("parent");
+    symlink(env.dir.join("real"), &parent_link).expect("create a symlink");
+    assert_eq!(added(&env, &real, Some(&parent_link.join("project"))), parent_link.join("project").to_string_lossy());
+}
+
+#[test]
+fn stale_pwd_is_ignored() {
+    let env = TestEnv::new();
+    let real = env.mkdir("real");
+    let other = env.mkdir("other");
+    let link_elsewhere = env.dir.join("elsewhere");
+    symlink(&other, &link_elsewhere).expect("create a symlink");
+    
+    // $PWD left over from another directory, or a link to one
+    assert_eq!(added(&env, &real, Some(&other)), real.to_string_lossy());
+    assert_eq!(added(&env, &real, Some(&link_elsewhere)), real.to_string_lossy());
+    // Gone, relative or unset
+    assert_eq!(added(&env, &real, Some(&env.dir.join("missing"))), real.to_string_lossy());
+    assert_eq!(added(&env, &real, Some(Path::new("."))), real.to_string_lossy());
+    assert_eq!(added(&env, &real, None), real.to_string_lossy());
+}
+
+#[test]
+fn history_under_the_resolved_spelling_is_kept() {
+    let env = TestEnv::new();
+    let real = env.seed(&[("real/project", 5, 1_714_564_800)]).remove(0);
+    let link = env.dir.join("link");
+    symlink(&real, &link).expect("create a symlink");
+    let (real, link) = (real.to_string_lossy().into_owned(), link.to_string_lossy().into_owned());
+    let db = env.database();
+    db.add_tag("proj", &real).expect("create the tag");
+    
+    // Visited through the link, the row recorded with symlinks resolved
+    // goes on counting, rather than being renamed
+    db.record_visit(&link).expect("record a visit");
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    let mut stmt = conn.prepare("SELECT path, visit_count FROM directories").expect("prepare a query");
+    let totals: Vec<(String, i64)> =
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).expect("run a query").map(|row| row.expect("read a row")).collect();
+    assert_eq!(totals, [(real.clone(), 6)]);
+    assert_eq!(db.resolve_tag("proj").expect("resolve the tag").as_deref(), Some(real.as_str()));
+    for path in [&real, &link] {
+        let stats = db.directory_stats(path, 3).expect("gather stats").expect("the directory is tracked");
+        assert_eq!((stats.path, stats.visits), (real.clone(), 6), "{}", path);
+    }
+}
@@ -0,0 +1,104 @@
+//! Directories matching the config's `exclude` patterns aren't recorded,
+//! however they are reached, and aren't offered back by `query`.
+
+mod common;
+
+use std::fs;
+
+use common::TestEnv;
+
+/// Write a config that excludes node_modules and any `scratch` directory
+fn exclude_node_modules(env: &TestEnv) {
+    let config = env.home.join(".config/pathranger");
+    fs::create_dir_all(&config).expect("create the config directory");
+    fs::write(config.join("config.toml"), "exclude = [\"**/node_modules/**\", \"**/scratch\"]\n").expect("write the config");
+}
+
+/// Visits recorded for `path`, or None if it isn't tracked
+fn visits(env: &TestEnv, path: &str) -> Option<i64> {
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    conn.query_row("SELECT visit_count FROM directories WHERE path = ?1", [path], |row| row.get(0)).ok()
+}
+
+#[test]
+fn goto_skips_excluded_directories() {
+    let env = TestEnv::new();
+    exclude_node_modules(&env);
+    
+    let kept = env.mkdir("src/app").to_string_lossy().into_owned();
+    let excluded = env.mkdir("src/app/node_modules/left-pad").to_string_lossy().into_owned();
+    let scratch = env.mkdir("scratch").to_string_lossy().into_owned();
+    let db = env.database();
+    for (tag, path) in [("app", &kept), ("pad", &excluded), ("scratch", &scratch)] {
+        db.add_tag(tag, path).expect("tag a test directory");
+    }
+    drop(db);
+    
+    for (tag, path) in [("pad", &excluded), ("scratch", &scratch)] {
+        let output = env.run(&["goto", tag]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", path));
+        assert_eq!(visits(&env, path), None, "goto {} recorded {}", tag, path);
+        // As the prompt hook would
+        env.run(&["record", path]);
+        assert_eq!(visits(&env, path), None, "record recorded {}", path);
+    }
+    
+    env.run(&["goto", "app"]);
+    assert_eq!(visits(&env, &kept), Some(1));
+}
+
+#[test]
+fn query_skips_excluded_directories() {
+    let env = TestEnv::new();
+    // Recorded before the patterns were set up
+    let paths = env.seed(&[("src/app/node_modules", 50, 1_700_000_000), ("src/nodes", 1, 1_700_000_000)]);
+    exclude_node_modules(&env);
+    
+    let output = env.run(&["query", "node"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}\n", paths[1].display()));
+}
+
+#[cfg(feature = "serve")]
+#[test]
+fn serve_goto_skips_excluded_directories() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+    use std::process::Stdio;
+    
+    let env = TestEnv::new();
+    exclude_node_modules(&env);
+    let excluded = env.mkdir("src/app/node_modules/left-pad").to_string_lossy().into_owned();
+    env.database().add_tag("pad", &excluded).expect("tag a test directory");
+    
+    let mut server = env
+        .command(common::PATHRANGER)
+        .args(["serve", "--addr", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("start pathranger serve");
+    let mut url = String::new();
+    BufReader::new(server.stdout.take().expect("the server's stdout"))
+        .read_line(&mut url)
+        .expect("read the server's address");
+    let addr = url.trim().trim_start_matches("http://").to_string();
+    
+    let body = r#"{"tag": "pad"}"#;
+    let mut stream = TcpStream::connect(&addr).expect("connect to the server");
+    write!(
+        stream,
+        "POST /goto HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        addr,
+        body.len(),
+        body
+    )
+    .expect("send the request");
+    let mut response = String::new();
+    stream.read_to_string(&mut response).expect("read the response");
+    let _ = server.kill();
+    let _ = server.wait();
+    
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.contains(&excluded), "{}", response);
+    assert_eq!(visits(&env, &excluded), None, "serve /goto recorded {}", excluded);
+}
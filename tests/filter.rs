@@ -0,0 +1,56 @@
+//! Listing filters on the paths as stored, whichever platform stored them:
+//! Windows paths are kept with backslashes.
+
+mod common;
+
+use pathranger::{Filter, ListOptions, SortKey};
+
+use common::TestEnv;
+
+/// Store `paths` as they are, without checking that they exist here
+fn store(env: &TestEnv, paths: &[&str]) {
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    for path in paths {
+        conn.execute(
+            "INSERT INTO directories (path, visit_count, last_visited) VALUES (?1, 1, '2024-05-01T12:00:00+00:00')",
+            [path],
+        )
+        .expect("store a path");
+    }
+}
+
+fn listed(env: &TestEnv, filter: Filter) -> Vec<String> {
+    let options = ListOptions { sort: SortKey::Alpha, reverse: false, count: 100, filter };
+    env.database().list(&options).expect("list directories").into_iter().map(|entry| entry.path).collect()
+}
+
+#[test]
+fn filters_understand_windows_paths() {
+    let env = TestEnv::new();
+    drop(env.database());
+    store(
+        &env,
+        &[
+            r"C:\src",
+            r"C:\src\.git",
+            r"C:\src\app",
+            r"C:\srcs",
+            r"\\server\share\docs",
+            "/home/me/src",
+            r"/home/me/a\.b",
+        ],
+    );
+    
+    let under = |under: &str| Filter { under: Some(under.to_string()), ..Filter::default() };
+    assert_eq!(listed(&env, under(r"C:\src")), [r"C:\src", r"C:\src\.git", r"C:\src\app"]);
+    assert_eq!(listed(&env, under(r"C:\")), [r"C:\src", r"C:\src\.git", r"C:\src\app", r"C:\srcs"]);
+    assert_eq!(listed(&env, under(r"\\server\share")), [r"\\server\share\docs"]);
+    assert_eq!(listed(&env, under("/home/me")), [r"/home/me/a\.b", "/home/me/src"]);
+    
+    let hidden = Filter { hide_hidden: true, ..Filter::default() };
+    // A backslash is an ordinary character in a Unix path
+    assert_eq!(
+        listed(&env, hidden),
+        [r"/home/me/a\.b", "/home/me/src", r"C:\src", r"C:\src\app", r"C:\srcs", r"\\server\share\docs"]
+    );
+}
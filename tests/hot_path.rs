@@ -0,0 +1,86 @@
+//! What runs on every prompt and every jump stays fast as the database
+//! grows: opening a database that is already set up, and `query_in` over
+//! every tracked directory. Budgets are generous, for unoptimized builds on
+//! slow machines; they catch work that grows out of proportion, not
+//! ordinary noise.
+
+mod common;
+
+use std::time::{Duration, Instant};
+
+use common::{unix_time, TestEnv};
+use pathranger::{Database, Scope, Visit, VisitLog};
+
+/// Tracked directories in the benchmark database
+const ROWS: i64 = 50_000;
+
+/// The longest opening an initialized database may take
+const OPEN_BUDGET: Duration = Duration::from_millis(50);
+
+/// The longest a query over `ROWS` directories may take
+const QUERY_BUDGET: Duration = Duration::from_secs(1);
+
+/// The fastest of `runs` timed calls of `f`, which shrugs off a run slowed
+/// by something else on the machine, with the last result
+fn fastest<T>(runs: usize, mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut result = None;
+    for _ in 0..runs {
+        let start = Instant::now();
+        result = Some(f());
+        best = best.min(start.elapsed());
+    }
+    (best, result.expect("at least one run"))
+}
+
+/// A database of `ROWS` directories spread over projects. Only the one the
+/// test looks for exists, since `query_in` skips directories that are gone.
+fn large_database(env: &TestEnv) {
+    env.mkdir(format!("src/project{}/module{}", (ROWS - 1) % 500, ROWS - 1));
+    let visits = (0..ROWS)
+        .map(|i| Visit {
+            path: env.dir.join(format!("src/project{}/module{}", i % 500, i)).to_string_lossy().into_owned(),
+            count: i % 97 + 1,
+            last_visited: unix_time(1_714_564_800 + i),
+        })
+        .collect();
+    env.database().record_visits(&VisitLog { visits, ..VisitLog::default() }).expect("seed the test database");
+}
+
+#[test]
+fn large_database_stays_within_budget() {
+    let env = TestEnv::new();
+    large_database(&env);
+    
+    let (open, db) = fastest(5, || Database::open(&env.db).expect("open the test database"));
+    assert!(open <= OPEN_BUDGET, "opening took {:?}", open);
+    
+    let (query, found) = fastest(3, || db.query_in("module49999", Some(&env.dir), Scope::Everywhere, &[]).expect("query"));
+    assert!(query <= QUERY_BUDGET, "query_in over {} directories took {:?}", ROWS, query);
+    assert!(found.is_some_and(|path| path.ends_with("module49999")));
+    eprintln!("open: {:?}, query_in over {} directories: {:?}", open, ROWS, query);
+}
+
+/// Opening with the schema current against opening with the schema DDL run
+/// every time, as it was before `user_version` was checked first. Prints the
+/// timings; run with `cargo test --test hot_path -- --ignored --nocapture`.
+#[test]
+#[ignore = "a benchmark that only prints its timings"]
+fn open_compared_with_ddl_on_every_open() {
+    const RUNS: usize = 50;
+    let env = TestEnv::new();
+    drop(env.database());
+    
+    let (current, _) = fastest(RUNS, || Database::open(&env.db).expect("open the test database"));
+    
+    // A zero user_version makes the next open run all of the DDL again
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    let mut every = Duration::MAX;
+    for _ in 0..RUNS {
+        conn.execute_batch("PRAGMA user_version = 0").expect("reset the schema version");
+        let (elapsed, _) = fastest(1, || Database::open(&env.db).expect("open the test database"));
+        every = every.min(elapsed);
+    }
+    
+    eprintln!("open with the schema current: {:?}; with DDL on every open: {:?}", current, every);
+}
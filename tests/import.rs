@@ -0,0 +1,51 @@
+//! Writing many visits at once, as a journal replay does: one transaction,
+//! with statements prepared once rather than per row.
+
+mod common;
+
+use common::{unix_time, TestEnv};
+use pathranger::{Visit, VisitLog, VisitTime};
+
+/// A log of `rows` directories visited i + 1 times each, with one history
+/// entry apiece
+fn visit_log(env: &TestEnv, rows: i64) -> VisitLog {
+    let path = |i: i64| env.dir.join(format!("imported/{}", i)).to_string_lossy().into_owned();
+    VisitLog {
+        visits: (0..rows).map(|i| Visit { path: path(i), count: i + 1, last_visited: unix_time(1_714_564_800 + i) }).collect(),
+        history: (0..rows)
+            .map(|i| VisitTime { path: path(i), visited_at: unix_time(1_714_564_800 + i).fixed_offset() })
+            .collect(),
+        ..VisitLog::default()
+    }
+}
+
+/// Import `rows` directories into a fresh database. Returns the number of
+/// directories, the visit_count total, the history rows, and how many
+/// statements the import ran.
+fn import(rows: i64) -> (i64, i64, i64, usize) {
+    let env = TestEnv::new();
+    let db = env.database();
+    let before = db.statement_count();
+    db.record_visits(&visit_log(&env, rows)).expect("import the visits");
+    let statements = db.statement_count() - before;
+    drop(db);
+    
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    let (directories, total): (i64, i64) = conn
+        .query_row("SELECT COUNT(*), SUM(visit_count) FROM directories", [], |row| Ok((row.get(0)?, row.get(1)?)))
+        .expect("count the directories");
+    let history: i64 = conn.query_row("SELECT COUNT(*) FROM visits", [], |row| row.get(0)).expect("count the history");
+    (directories, total, history, statements)
+}
+
+#[test]
+fn ten_thousand_rows_import_in_one_transaction() {
+    let (directories, total, history, statements) = import(10_000);
+    assert_eq!(directories, 10_000);
+    assert_eq!(total, 10_000 * 10_001 / 2);
+    assert_eq!(history, 10_000);
+    
+    // BEGIN, COMMIT and each statement prepared once, however many rows
+    let (.., few) = import(10);
+    assert_eq!(statements, few, "the import ran more statements for more rows");
+}
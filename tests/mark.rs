@@ -0,0 +1,53 @@
+//! `mark` points a tag and records a visit as one change.
+
+mod common;
+
+use common::TestEnv;
+
+/// Rows of `sql`, two columns each, read straight from the test database
+fn rows<T: rusqlite::types::FromSql>(env: &TestEnv, sql: &str) -> Vec<(String, T)> {
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    let mut stmt = conn.prepare(sql).expect("prepare a query");
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?))).expect("run a query");
+    rows.map(|row| row.expect("read a row")).collect()
+}
+
+fn tags(env: &TestEnv) -> Vec<(String, String)> {
+    rows(env, "SELECT name, path FROM tags ORDER BY name")
+}
+
+fn totals(env: &TestEnv) -> Vec<(String, i64)> {
+    rows(env, "SELECT path, visit_count FROM directories ORDER BY path")
+}
+
+#[test]
+fn mark_tags_and_visits_together() {
+    let env = TestEnv::new();
+    let paths = env.seed(&[("old", 2, 1_714_564_800), ("new", 1, 1_711_972_800)]);
+    let (old, new) = (paths[0].to_string_lossy().into_owned(), paths[1].to_string_lossy().into_owned());
+    let db = env.database();
+    
+    assert!(!db.mark("work", &old).expect("create the tag"));
+    assert!(db.mark("work", &new).expect("move the tag"));
+    assert_eq!(tags(&env), [(String::from("work"), new.clone())]);
+    assert_eq!(totals(&env), [(new, 2), (old, 3)]);
+}
+
+#[test]
+fn failed_mark_changes_nothing() {
+    let env = TestEnv::new();
+    let paths = env.seed(&[("old", 2, 1_714_564_800), ("new", 1, 1_711_972_800)]);
+    let (old, new) = (paths[0].to_string_lossy().into_owned(), paths[1].to_string_lossy().into_owned());
+    let db = env.database();
+    db.mark("work", &old).expect("create the tag");
+    let before = (tags(&env), totals(&env));
+    
+    // Fail the visit after the tag has been written
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    conn.execute_batch("CREATE TRIGGER fail_visits BEFORE INSERT ON visits BEGIN SELECT RAISE(ABORT, 'no visits'); END")
+        .expect("create a failing trigger");
+    
+    assert!(db.mark("work", &new).is_err(), "moving the tag succeeded");
+    assert!(db.mark("other", &new).is_err(), "creating a tag succeeded");
+    assert_eq!((tags(&env), totals(&env)), before);
+}
@@ -0,0 +1,67 @@
+//! How results are printed: NUL-separated output, colors and control
+//! characters.
+
+mod common;
+
+use common::TestEnv;
+
+#[test]
+fn print0_keeps_newlines_in_paths() {
+    let env = TestEnv::new();
+    let paths = env.seed(&[("a\nb", 2, 1_714_564_800), ("plain", 1, 1_711_972_800)]);
+    let expected: Vec<u8> = paths.iter().flat_map(|path| [path.as_os_str().as_encoded_bytes(), b"\0"].concat()).collect();
+    
+    for args in [["top", "-0"], ["recent", "--print0"], ["list", "-0"]] {
+        assert_eq!(env.run(&args).stdout, expected, "{:?}", args);
+    }
+    let newline = [paths[0].as_os_str().as_encoded_bytes(), b"\0"].concat();
+    assert_eq!(env.run(&["search", "a\nb", "-0"]).stdout, newline);
+    assert_eq!(env.run(&["query", "a\nb", "-0"]).stdout, newline);
+    assert_eq!(env.run(&["query", "a\nb", "--print0", "--offline"]).stdout, newline);
+}
+
+/// Whether `output` holds an ESC byte, which starts every color sequence
+fn has_escape(output: &[u8]) -> bool {
+    output.contains(&0x1b)
+}
+
+#[test]
+fn no_color_drops_escape_sequences() {
+    let env = TestEnv::new();
+    env.seed(&[("alpha", 2, 1_714_564_800), ("beta", 1, 1_711_972_800)]);
+    env.database().add_tag("a", &env.dir.join("alpha").to_string_lossy()).expect("tag a fixture directory");
+    
+    for command in ["top", "list"] {
+        let colored = env.run(&[command, "--color=always"]).stdout;
+        assert!(has_escape(&colored), "{} --color=always printed no colors", command);
+        
+        // The color setting would turn colors on even though stdout is a
+        // pipe, and NO_COLOR overrides it
+        let with_env = |vars: &[(&str, &str)]| {
+            let output = env.command(common::PATHRANGER).arg(command).envs(vars.iter().copied()).output();
+            let output = output.expect("run pathranger");
+            assert!(output.status.success());
+            assert!(!output.stdout.is_empty(), "{} printed nothing", command);
+            output.stdout
+        };
+        assert!(has_escape(&with_env(&[("PATHRANGER_COLOR", "always")])), "{} ignored the color setting", command);
+        let output = with_env(&[("PATHRANGER_COLOR", "always"), ("NO_COLOR", "1")]);
+        assert!(!has_escape(&output), "{} printed colors with NO_COLOR set", command);
+        assert!(!has_escape(&env.run(&[command, "--no-color"]).stdout), "{} --no-color printed colors", command);
+    }
+}
+
+#[test]
+fn human_output_escapes_control_characters() {
+    let env = TestEnv::new();
+    env.seed(&[("\x1b[2Jclear", 3, 1_714_564_800), ("csi\u{9b}31m next\u{85}line", 2, 1_711_972_800), ("del\x7f", 1, 1_709_294_400)]);
+    
+    for command in ["top", "list", "recent"] {
+        let output = String::from_utf8(env.run(&[command, "--no-color"]).stdout).expect("output is UTF-8");
+        let raw = output.chars().find(|c| c.is_control() && !matches!(c, '\n' | '\t'));
+        assert_eq!(raw, None, "{} printed a control character:\n{}", command, output);
+        for escaped in [r"\x1b[2Jclear", r"csi\x9b31m next\x85line", r"del\x7f"] {
+            assert!(output.contains(escaped), "{} didn't print {}:\n{}", command, escaped, output);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! The `--porcelain=v1` format is promised never to change, so its bytes are
+//! pinned here for a fixed database.
+
+mod common;
+
+use std::path::PathBuf;
+
+use common::TestEnv;
+
+/// Directories with their visits and last visit, one needing escapes
+const FIXTURE: [(&str, i64, i64); 3] = [
+    ("alpha", 5, 1_714_564_800),
+    ("tab\there\\and back", 3, 1_711_972_800),
+    ("beta", 1, 1_709_294_400),
+];
+
+fn stdout(env: &TestEnv, args: &[&str]) -> String {
+    String::from_utf8(env.run(args).stdout).expect("porcelain output is UTF-8")
+}
+
+/// The fixture paths as a porcelain field, and the test environment
+fn fixture() -> (TestEnv, Vec<String>) {
+    let env = TestEnv::new();
+    let paths: Vec<PathBuf> = env.seed(&FIXTURE);
+    let fields = paths.iter().map(|path| path.to_string_lossy().replace('\\', "\\\\").replace('\t', "\\t")).collect();
+    (env, fields)
+}
+
+#[test]
+fn list_porcelain_v1_bytes() {
+    let (env, paths) = fixture();
+    let expected = format!(
+        "1\t5\t1714564800\t{}\n2\t3\t1711972800\t{}\n3\t1\t1709294400\t{}\n",
+        paths[0], paths[1], paths[2]
+    );
+    assert_eq!(stdout(&env, &["list", "--sort", "visits", "--porcelain"]), expected);
+    assert_eq!(stdout(&env, &["list", "--sort", "visits", "--porcelain=v1"]), expected);
+}
+
+#[test]
+fn search_porcelain_v1_bytes() {
+    let (env, paths) = fixture();
+    // The backslash keeps characters of the random temporary directory
+    // name from matching the other fixtures
+    let output = stdout(&env, &["search", "here\\and", "--porcelain"]);
+    assert_eq!(output, format!("1\t3\t1711972800\t159\t{}\n", paths[1]));
+}
+
+#[test]
+fn tags_porcelain_v1_bytes() {
+    let (env, paths) = fixture();
+    let db = env.database();
+    for (name, path) in [("one", &env.dir.join(FIXTURE[0].0)), ("two", &env.dir.join(FIXTURE[1].0))] {
+        db.add_tag(name, &path.to_string_lossy()).expect("tag a fixture directory");
+    }
+    assert_eq!(
+        stdout(&env, &["tags", "--porcelain"]),
+        format!("one\tuser\t{}\ntwo\tuser\t{}\n", paths[0], paths[1])
+    );
+}
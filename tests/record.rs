@@ -0,0 +1,103 @@
+//! Where `record` sends a visit: to a running daemon first, then to a
+//! detached recorder with --async, and to the journal only when the
+//! database can't be reached.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{TestEnv, PATHRANGER};
+
+/// Visits recorded for `path`, or None if it isn't tracked
+fn visits(env: &TestEnv, path: &Path) -> Option<i64> {
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    conn.query_row("SELECT visit_count FROM directories WHERE path = ?1", [path.to_string_lossy()], |row| row.get(0))
+        .ok()
+}
+
+/// Every line of every journal in the test's state directory
+fn journal_lines(env: &TestEnv) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(env.home.join(".local/state/pathranger")) else {
+        return Vec::new();
+    };
+    entries
+        .map(|entry| entry.expect("read the state directory").path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("journal-")))
+        .flat_map(|path| fs::read_to_string(path).expect("read a journal").lines().map(String::from).collect::<Vec<_>>())
+        .collect()
+}
+
+/// pathranger with the daemon's socket kept in the test directory
+fn command(env: &TestEnv) -> Command {
+    let mut command = env.command(PATHRANGER);
+    command.env("XDG_RUNTIME_DIR", env.mkdir("run"));
+    command
+}
+
+/// The daemon's request count, or None if it isn't running
+fn daemon_requests(env: &TestEnv) -> Option<u64> {
+    let output = command(env).args(["daemon", "--status"]).output().expect("ask the daemon");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (_, requests) = stdout.rsplit_once(", ")?;
+    requests.split(' ').next()?.parse().ok()
+}
+
+#[cfg(unix)]
+#[test]
+fn record_prefers_the_daemon_over_a_detached_recorder() {
+    let env = TestEnv::new();
+    let dir = env.mkdir("project");
+    drop(env.database());
+    let mut daemon = command(&env)
+        .arg("daemon")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("start the daemon");
+    let started = Instant::now();
+    let before = loop {
+        if let Some(requests) = daemon_requests(&env) {
+            break requests;
+        }
+        assert!(started.elapsed() < Duration::from_secs(5), "the daemon didn't start");
+        thread::sleep(Duration::from_millis(20));
+    };
+    
+    let output = command(&env).args(["record", "--async"]).arg(&dir).output().expect("run pathranger");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let after = daemon_requests(&env).expect("the daemon is still running");
+    command(&env).args(["daemon", "--stop"]).output().expect("stop the daemon");
+    daemon.wait().expect("wait for the daemon");
+    
+    // The second status request, and the visit
+    assert_eq!(after - before, 2, "the daemon didn't record the visit");
+    assert_eq!(visits(&env, &dir), Some(1));
+}
+
+#[test]
+fn record_journals_only_when_the_database_is_locked() {
+    let env = TestEnv::new();
+    let (first, second) = (env.mkdir("first"), env.mkdir("second"));
+    drop(env.database());
+    let record = |dir: &Path| {
+        let output = command(&env).env("PATHRANGER_JOURNAL", "true").arg("record").arg(dir).output().expect("run pathranger");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+    
+    record(&first);
+    assert_eq!(visits(&env, &first), Some(1));
+    assert!(journal_lines(&env).is_empty(), "a reachable database was journaled to");
+    
+    let lock = rusqlite::Connection::open(&env.db).expect("open the test database");
+    lock.execute_batch("BEGIN IMMEDIATE").expect("lock the database");
+    record(&second);
+    lock.execute_batch("COMMIT").expect("unlock the database");
+    assert_eq!(visits(&env, &second), None);
+    let lines = journal_lines(&env);
+    assert_eq!(lines.len(), 1, "{:?}", lines);
+    assert!(lines[0].contains(&*second.to_string_lossy()), "{}", lines[0]);
+}
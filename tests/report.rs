@@ -0,0 +1,51 @@
+//! `report` output for a fixed visit history.
+
+mod common;
+
+use chrono::{DateTime, Local};
+use common::TestEnv;
+use pathranger::{Visit, VisitLog, VisitTime};
+
+/// One visit to each path at each time, with the directory totals to match
+fn history_log(history: &[(&str, &str)]) -> VisitLog {
+    let history: Vec<VisitTime> = history
+        .iter()
+        .map(|(path, time)| VisitTime {
+            path: path.to_string(),
+            visited_at: DateTime::parse_from_rfc3339(time).expect("a valid fixture time"),
+        })
+        .collect();
+    let mut visits: Vec<Visit> = Vec::new();
+    for visit in &history {
+        let last_visited = visit.visited_at.with_timezone(&Local);
+        match visits.iter_mut().find(|total| total.path == visit.path) {
+            Some(total) => {
+                total.count += 1;
+                total.last_visited = total.last_visited.max(last_visited);
+            }
+            None => visits.push(Visit { path: visit.path.clone(), count: 1, last_visited }),
+        }
+    }
+    VisitLog { visits, history, ..VisitLog::default() }
+}
+
+#[test]
+fn plain_report_lists_each_section() {
+    let env = TestEnv::new();
+    let log = history_log(&[
+        ("/work/api", "2024-04-30T10:00:00Z"),
+        ("/work/api", "2024-05-06T09:00:00Z"),
+        ("/work/api", "2024-05-10T16:00:00Z"),
+        ("/work/web", "2024-05-08T11:00:00Z"),
+        ("/old/thesis", "2024-05-02T08:00:00Z"),
+        ("/old/thesis", "2024-05-03T08:00:00Z"),
+        ("/later", "2024-05-13T00:00:00Z"),
+    ]);
+    env.database().record_visits(&log).expect("seed the test database");
+    
+    let output = env.run(&["report", "--since", "2024-05-06T00:00:00Z", "--until", "2024-05-13T00:00:00Z"]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "top\t2\t/work/api\ntop\t1\t/work/web\nnew\t1\t/work/web\nstopped\t2\t/old/thesis\n"
+    );
+}
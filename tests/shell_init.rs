@@ -0,0 +1,270 @@
+//! Run the code `pathranger init` generates in a real shell and check that
+//! its cd wrapper behaves exactly like the builtin it replaces, and that
+//! `pr goto` lands in directories whatever their names.
+
+#![cfg(unix)]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use common::{has_shell, TestEnv};
+
+/// The `init` flags that make the integration wrap cd
+const CD_WRAPPERS: [&[&str]; 3] = [&["--hook", "cd"], &["--hook", "cd", "--fallback"], &["--fallback"]];
+
+/// Prints a status with where the shell is and was, one line per step
+const REPORT: &str = "report() { printf '%s|%s|%s\\n' \"$1\" \"$PWD\" \"$OLDPWD\"; }\n";
+
+/// Run `body` in `shell`, after evaluating `pathranger init` with `init`
+/// flags unless they are None, and return its stdout
+fn run_script(env: &TestEnv, shell: &str, init: Option<&[&str]>, body: &str) -> String {
+    let mut script = String::new();
+    if let Some(flags) = init {
+        script.push_str(&format!("eval \"$(pathranger init --shell {} {})\"\n", shell, flags.join(" ")));
+        script.push_str("typeset -f __pathranger_goto >/dev/null || { echo 'init failed' >&2; exit 99; }\n");
+    }
+    script.push_str(REPORT);
+    script.push_str(body);
+    
+    let mut command = env.command(shell);
+    match shell {
+        "zsh" => command.arg("-f"),
+        _ => command.args(["--norc", "--noprofile"]),
+    };
+    let Output { status, stdout, stderr } = command.arg("-c").arg(&script).output().expect("run the shell");
+    let stderr = String::from_utf8_lossy(&stderr);
+    assert_ne!(status.code(), Some(99), "{} {:?}: {}", shell, init, stderr);
+    String::from_utf8(stdout).expect("shell output is UTF-8")
+}
+
+/// The `report` lines of a script's output
+fn reports(output: &str) -> Vec<&str> {
+    output.lines().filter(|line| line.contains('|')).collect()
+}
+
+fn check_cd_basics(shell: &str) {
+    let env = TestEnv::new();
+    let a = env.mkdir("a");
+    let (dir, home) = (env.dir.display(), env.home.display());
+    let body = "cd a; report $?\n\
+                cd; report $?\n\
+                cd -; report $?\n\
+                cd nonexistent 2>/dev/null; report $?\n";
+    let builtin = run_script(&env, shell, None, body);
+    
+    for flags in CD_WRAPPERS {
+        let wrapped = run_script(&env, shell, Some(flags), body);
+        assert_eq!(wrapped, builtin, "{} with {:?}", shell, flags);
+        assert_eq!(
+            reports(&wrapped),
+            [
+                format!("0|{}|{}", a.display(), dir),
+                format!("0|{}|{}", home, a.display()),
+                format!("0|{}|{}", a.display(), home),
+                format!("1|{}|{}", a.display(), home),
+            ],
+            "{} with {:?}",
+            shell,
+            flags
+        );
+    }
+}
+
+#[test]
+fn bash_cd_wrapper_keeps_builtin_behavior() {
+    check_cd_basics("bash");
+}
+
+#[test]
+fn zsh_cd_wrapper_keeps_builtin_behavior() {
+    if !has_shell("zsh") {
+        eprintln!("zsh isn't installed, skipping");
+        return;
+    }
+    check_cd_basics("zsh");
+}
+
+/// -P, -L and zsh's `cd old new` reach the builtin untouched
+fn check_cd_arguments(shell: &str) {
+    let env = TestEnv::new();
+    let real = env.mkdir("real");
+    let one = env.mkdir("one/x");
+    let two = env.mkdir("two/x");
+    let link = env.dir.join("link");
+    std::os::unix::fs::symlink(&real, &link).expect("create a symlink");
+    let body = format!(
+        "cd -P '{link}'; report $?\n\
+         cd -L '{link}'; report $?\n\
+         cd '{one}'; report $?\n\
+         cd one two 2>/dev/null; report $?\n",
+        link = link.display(),
+        one = one.display(),
+    );
+    let builtin = run_script(&env, shell, None, &body);
+    
+    // zsh replaces `one` with `two` in $PWD; bash has no such form
+    let substituted = match shell {
+        "zsh" => format!("0|{}|{}", two.display(), one.display()),
+        _ => format!("1|{}|{}", one.display(), link.display()),
+    };
+    for flags in CD_WRAPPERS {
+        let wrapped = run_script(&env, shell, Some(flags), &body);
+        assert_eq!(wrapped, builtin, "{} with {:?}", shell, flags);
+        assert_eq!(
+            reports(&wrapped),
+            [
+                format!("0|{}|{}", real.display(), env.dir.display()),
+                format!("0|{}|{}", link.display(), real.display()),
+                format!("0|{}|{}", one.display(), link.display()),
+                substituted.clone(),
+            ],
+            "{} with {:?}",
+            shell,
+            flags
+        );
+    }
+}
+
+#[test]
+fn bash_cd_wrapper_passes_arguments_through() {
+    check_cd_arguments("bash");
+}
+
+#[test]
+fn zsh_cd_wrapper_passes_arguments_through() {
+    if !has_shell("zsh") {
+        eprintln!("zsh isn't installed, skipping");
+        return;
+    }
+    check_cd_arguments("zsh");
+}
+
+/// Directory names that break unquoted or word-split shell code
+const ADVERSARIAL_NAMES: [&str; 6] = [
+    "trailing newline\n",
+    "-leading dash",
+    "with  spaces",
+    "it's \"quoted\"",
+    "$(touch pwned)",
+    "glob [a] *",
+];
+
+/// Tag a directory for each of `names` as t0, t1 and so on, returning them
+fn tag_adversarial(env: &TestEnv, names: &[&str]) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = names.iter().map(|name| env.mkdir(name)).collect();
+    for (i, dir) in dirs.iter().enumerate() {
+        env.run_in(dir, &["mark", &format!("t{}", i)]);
+    }
+    dirs
+}
+
+/// The NUL-terminated directories a script printed
+fn nul_separated(output: &str) -> Vec<PathBuf> {
+    output.split_terminator('\0').map(PathBuf::from).collect()
+}
+
+fn check_goto_adversarial(shell: &str) {
+    let env = TestEnv::new();
+    let dirs = tag_adversarial(&env, &ADVERSARIAL_NAMES);
+    let body: String = (0..dirs.len())
+        .map(|i| format!("cd '{}' && pr goto t{} && printf '%s\\0' \"$PWD\"\n", env.dir.display(), i))
+        .collect();
+    
+    for flags in [&[][..], &["--hook", "cd"]] {
+        let output = run_script(&env, shell, Some(flags), &body);
+        assert_eq!(nul_separated(&output), dirs, "{} with {:?}", shell, flags);
+    }
+    assert!(!env.dir.join("pwned").exists(), "a directory name was run as a command");
+}
+
+#[test]
+fn bash_goto_handles_adversarial_names() {
+    check_goto_adversarial("bash");
+}
+
+#[test]
+fn zsh_goto_handles_adversarial_names() {
+    if !has_shell("zsh") {
+        eprintln!("zsh isn't installed, skipping");
+        return;
+    }
+    check_goto_adversarial("zsh");
+}
+
+#[test]
+fn fish_goto_handles_adversarial_names() {
+    if !has_shell("fish") {
+        eprintln!("fish isn't installed, skipping");
+        return;
+    }
+    // fish's command substitution drops trailing newlines, so that name
+    // can't survive the capture
+    let names: Vec<&str> = ADVERSARIAL_NAMES.into_iter().filter(|name| !name.ends_with('\n')).collect();
+    let env = TestEnv::new();
+    let dirs = tag_adversarial(&env, &names);
+    let mut script = String::from("pathranger init --shell fish | source\n");
+    for i in 0..dirs.len() {
+        script.push_str(&format!("cd '{}'; and pr goto t{}; and printf '%s\\0' $PWD\n", env.dir.display(), i));
+    }
+    let output = env.command("fish").arg("--no-config").arg("-c").arg(&script).output().expect("run fish");
+    let stdout = String::from_utf8(output.stdout).expect("shell output is UTF-8");
+    assert_eq!(nul_separated(&stdout), dirs, "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!env.dir.join("pwned").exists(), "a directory name was run as a command");
+}
+
+/// Visits recorded for `path`, once any background recorder has had time to
+/// write
+fn visits_settled(env: &TestEnv, path: &Path) -> i64 {
+    std::thread::sleep(std::time::Duration::from_millis(500));
+    let conn = rusqlite::Connection::open(&env.db).expect("open the test database");
+    conn.query_row("SELECT visit_count FROM directories WHERE path = ?1", [path.to_string_lossy()], |row| row.get(0))
+        .expect("the directory is tracked")
+}
+
+fn check_goto_counts_once(shell: &str) {
+    for flags in [&[][..], &["--hook", "cd"], &["--hook", "cd", "--fallback"]] {
+        let env = TestEnv::new();
+        let proj = env.mkdir("proj");
+        env.run_in(&proj, &["mark", "proj"]);
+        let output = run_script(&env, shell, Some(flags), "pr goto proj; report $?\npr 1; report $?\n");
+        assert_eq!(reports(&output)[0].split('|').next(), Some("0"), "{} with {:?}: {}", shell, flags, output);
+        // The mark, and one for each jump
+        assert_eq!(visits_settled(&env, &proj), 3, "{} with {:?}", shell, flags);
+    }
+}
+
+#[test]
+fn bash_goto_counts_each_jump_once() {
+    check_goto_counts_once("bash");
+}
+
+#[test]
+fn zsh_goto_counts_each_jump_once() {
+    if !has_shell("zsh") {
+        eprintln!("zsh isn't installed, skipping");
+        return;
+    }
+    check_goto_counts_once("zsh");
+}
+
+#[test]
+fn bash_goto_runs_the_hooks_written_into_the_integration() {
+    let env = TestEnv::new();
+    let work = env.mkdir("work");
+    env.run_in(&work, &["mark", "work"]);
+    let init = env.run(&["init", "--shell", "bash"]);
+    let script = String::from_utf8_lossy(&init.stdout);
+    assert!(!script.contains("__pathranger_after_goto"), "hooks called with none configured:\n{}", script);
+    
+    let config = env.home.join(".config/pathranger");
+    std::fs::create_dir_all(&config).expect("create the config directory");
+    std::fs::write(
+        config.join("config.toml"),
+        "[hooks]\nafter_goto = [\"echo \\\"hooked in $PWD\\\"\", \"false\", \"echo 'it'\\\\''s second'\"]\n",
+    )
+    .expect("write the config");
+    let output = run_script(&env, "bash", Some(&[]), "pr goto work; echo \"status $?\"\npr goto work --no-hooks\n");
+    assert_eq!(output, format!("hooked in {}\nit's second\nstatus 0\n", work.display()));
+}
@@ -0,0 +1,40 @@
+//! How many SQL statements listings run, so per-row queries don't creep back
+//! in as the number of rows grows.
+
+mod common;
+
+use common::TestEnv;
+
+/// Seed `rows` directories, each with a tag, and return how many statements
+/// `top -c 100` ran on them, as reported by --verbose
+fn top_statements(rows: usize) -> usize {
+    let env = TestEnv::new();
+    let names: Vec<String> = (0..rows).map(|i| format!("dir{}", i)).collect();
+    let visits: Vec<(&str, i64, i64)> =
+        names.iter().enumerate().map(|(i, name)| (name.as_str(), i as i64 + 1, 1_714_564_800 + i as i64)).collect();
+    let paths = env.seed(&visits);
+    let db = env.database();
+    for (name, path) in names.iter().zip(&paths) {
+        db.add_tag(&format!("badge-{}", name), &path.to_string_lossy()).expect("tag a fixture directory");
+    }
+    drop(db);
+    
+    let output = env.run(&["top", "-c", "100", "--verbose"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.lines().count(), rows.min(100), "top printed:\n{}", stdout);
+    assert!(stdout.contains("badge-dir0"), "top printed no tag badges:\n{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr
+        .lines()
+        .find_map(|line| line.strip_prefix("pathranger: ran ")?.strip_suffix(" SQL statements")?.parse().ok())
+        .unwrap_or_else(|| panic!("no statement count in:\n{}", stderr))
+}
+
+#[test]
+fn top_with_badges_runs_a_fixed_number_of_statements() {
+    let few = top_statements(3);
+    let many = top_statements(100);
+    assert_eq!(few, many, "top ran more statements for more rows");
+    // The listing and one query for the badges of every row
+    assert!(many <= 2, "top -c 100 ran {} statements", many);
+}